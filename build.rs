@@ -0,0 +1,24 @@
+//! Embeds the git commit the game is built from, read back by `build_info`.
+
+use std::process::Command;
+
+fn main() {
+    // Release pipelines building from a source archive can pass the hash in instead.
+    println!("cargo:rerun-if-env-changed=SWARM_HEAVEN_GIT_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    let hash = std::env::var("SWARM_HEAVEN_GIT_HASH").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|hash| hash.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=SWARM_HEAVEN_GIT_HASH={}",
+        hash.filter(|hash| !hash.is_empty()).unwrap_or_else(|| "unknown".to_string())
+    );
+}
@@ -0,0 +1,20 @@
+#import bevy_ui::ui_vertex_output::UiVertexOutput
+
+struct WipeMaterial {
+    color: vec4<f32>,
+    remaining: f32,
+}
+@group(1) @binding(0) var<uniform> material: WipeMaterial;
+
+const TAU: f32 = 6.28318530718;
+
+@fragment
+fn fragment(in: UiVertexOutput) -> @location(0) vec4<f32> {
+    let from_center = in.uv - vec2<f32>(0.5, 0.5);
+    // 0 at twelve o'clock, rising clockwise to 1.
+    let sweep = fract(atan2(from_center.x, -from_center.y) / TAU + 1.0);
+    if sweep < 1.0 - material.remaining {
+        return material.color;
+    }
+    return vec4<f32>(0.0);
+}
@@ -2,6 +2,9 @@
 use bevy::{
     prelude::*,
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    app::PluginGroupBuilder,
+    log::LogPlugin,
+    ecs::schedule::ScheduleLabel,
     window::PresentMode,
 };
 use rand::Rng;
@@ -9,12 +12,28 @@ use rand::Rng;
 // Game constants
 const PLAYER_SPEED: f32 = 500.0;
 const PLAYER_SIZE: f32 = 30.0;
+const PLAYER_HURTBOX_RADIUS: f32 = 9.0; // Well inside the sprite, so grazes feel fair
 const ENEMY_SIZE: f32 = 20.0;
 const ENEMY_SPEED: f32 = 200.0;
 const ENEMY_SPAWN_INTERVAL: f32 = 0.1;
 const XP_GEM_SIZE: f32 = 10.0;
+const XP_PER_GEM: u32 = 10;
+const XP_PICKUP_RADIUS: f32 = 50.0;
 const ORBITING_BLADE_RADIUS: f32 = 100.0;
 const ORBITING_BLADE_ROTATION_SPEED: f32 = 2.0;
+const PLAYER_MAX_HEALTH: f32 = 100.0;
+const ENEMY_CONTACT_DAMAGE: f32 = 10.0; // Per second, per touching enemy
+const ENEMY_HEALTH: f32 = 10.0;
+const BASE_WEAPON_DAMAGE: f32 = 10.0;
+const BASE_FIRE_COOLDOWN: f32 = 0.5;
+const MIN_FIRE_COOLDOWN: f32 = 0.1;
+const BASE_PROJECTILE_SPREAD: f32 = 0.15; // Radians between multishot projectiles
+const PROJECTILE_JITTER: f32 = 0.25; // Random deviation, as a fraction of spread
+const RECOIL_KICK: f32 = 4.0;
+const ENEMY_PROJECTILE_DAMAGE: f32 = 8.0;
+const DASH_SPEED: f32 = 1600.0;
+const DASH_DURATION: f32 = 0.15;
+const DASH_COOLDOWN: f32 = 1.0;
 
 // Game state
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, States, Default)]
@@ -23,46 +42,216 @@ enum GameState {
     MainMenu,
     Running,
     Paused,
+    /// The kill cam between the player's death and the game-over screen.
+    Dying,
+    GameOver,
+    RunHistory,
+    Stats,
+    Settings,
+    Heat,
+    /// Picking the player's colour and dash trail.
+    Character,
+}
+
+impl GameState {
+    const ALL: [GameState; 10] = [
+        GameState::MainMenu,
+        GameState::Running,
+        GameState::Paused,
+        GameState::Dying,
+        GameState::GameOver,
+        GameState::RunHistory,
+        GameState::Stats,
+        GameState::Settings,
+        GameState::Heat,
+        GameState::Character,
+    ];
+}
+
+/// Gameplay runs during a real run, on in slow motion through the kill cam, and behind
+/// the main menu, where a bot plays an attract-mode game. Input, run bookkeeping and HUD
+/// systems stay on `Running`.
+fn in_play(state: Res<State<GameState>>) -> bool {
+    matches!(state.get(), GameState::Running | GameState::Dying | GameState::MainMenu)
+}
+
+/// Despawns everything a game left in the world and resets per-run resources.
+/// Run when leaving the game-over screen and when the attract-mode game ends.
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+struct ResetRun;
+
+/// Despawns an entity, children and all, when the game leaves the given state. Only
+/// tag root entities; `despawn_recursive` takes care of the rest.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+struct DespawnOnExit(GameState);
+
+impl DespawnOnExit {
+    /// Anything belonging to a game: the world's contents and the HUD. A run ends on
+    /// leaving the game-over screen, but the attract-mode game behind the menu ends
+    /// without a state change, so this scope is cleared on `ResetRun` instead.
+    const RUN: Self = Self(GameState::GameOver);
+}
+
+struct CleanupPlugin;
+
+impl Plugin for CleanupPlugin {
+    fn build(&self, app: &mut App) {
+        for state in GameState::ALL {
+            if state == DespawnOnExit::RUN.0 {
+                app.add_systems(ResetRun, despawn_scope(state));
+            } else {
+                app.add_systems(OnExit(state), despawn_scope(state));
+            }
+        }
+    }
+}
+
+fn despawn_scope(state: GameState) -> impl Fn(Commands, Query<(Entity, &DespawnOnExit)>) {
+    move |mut commands, query| {
+        for (entity, scope) in query.iter() {
+            if scope.0 == state {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+/// Everything that makes up a game, without rendering effects or save files, so the
+/// headless balance simulation runs exactly the same rules as the real game.
+struct GameplayPlugins;
+
+impl PluginGroup for GameplayPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(CleanupPlugin)
+            .add(player::PlayerPlugin)
+            .add(enemy::EnemyPlugin)
+            .add(combat::CombatPlugin)
+            .add(budget::BudgetPlugin)
+            .add(hibernation::HibernationPlugin)
+            .add(patterns::PatternsPlugin)
+            .add(leveling::LevelingPlugin)
+            .add(drops::DropsPlugin)
+            .add(ui::UiPlugin)
+            .add(ui_anim::UiAnimPlugin)
+            .add(buttons::ButtonsPlugin)
+            .add(confirm::ConfirmPlugin)
+            .add(toasts::ToastsPlugin)
+            .add(waves::WavePlugin)
+            .add(run::RunPlugin)
+            .add(game_speed::GameSpeedPlugin)
+            .add(last_stand::LastStandPlugin)
+            .add(kill_cam::KillCamPlugin)
+            .add(damage_log::DamageLogPlugin)
+            .add(taunt::TauntPlugin)
+            .add(energy::EnergyPlugin)
+            .add(buffs::BuffsPlugin)
+            .add(settings::SettingsPlugin)
+            .add(triggers::TriggersPlugin)
+            .add(passives::PassivesPlugin)
+            .add(gems::GemsPlugin)
+            .add(quests::QuestsPlugin)
+            .add(synergies::SynergiesPlugin)
+            .add(attributes::AttributesPlugin)
+            .add(build_code::BuildCodePlugin)
+            .add(railgun::RailgunPlugin)
+            .add(mines::MinesPlugin)
+            .add(whip::WhipPlugin)
+            .add(turrets::TurretsPlugin)
+            .add(vortex::VortexPlugin)
+            .add(shield::ShieldPlugin)
+            .add(obstacles::ObstaclesPlugin)
+            .add(arena::ArenaPlugin)
+            .add(altars::AltarsPlugin)
+            .add(decals::DecalsPlugin)
+            .add(ambience::AmbiencePlugin)
+            .add(flow_field::FlowFieldPlugin)
+            .add(attract::AttractPlugin)
+            .add(input::PlayerInputPlugin)
+            .add(rumble::RumblePlugin)
+            .add(bot::BotPlugin)
+            .add(pet::PetPlugin)
+            .add(heat::HeatPlugin)
+            .add(daily::DailyPlugin)
+            .add(dummy::DummyPlugin)
+            .add(sandbox::SandboxPlugin)
+    }
 }
 
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            primary_window: Some(Window {
-                title: "Swarm Heaven".into(),
-                resolution: (1280.0, 720.0).into(),
-                present_mode: PresentMode::AutoVsync,
+    if let Some(runs) = simulate::runs_from_args() {
+        simulate::run(runs);
+        return;
+    }
+    crash::install_panic_hook();
+
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "Swarm Heaven".into(),
+                    resolution: (1280.0, 720.0).into(),
+                    present_mode: PresentMode::AutoVsync,
+                    ..default()
+                }),
+                ..default()
+            })
+            .set(LogPlugin {
+                update_subscriber: Some(diagnostics::capture_recent_logs),
                 ..default()
             }),
-            ..default()
-        }))
+    )
         .insert_resource(ClearColor(Color::rgb(0.05, 0.05, 0.1))) // Dark space theme
         .add_plugins((
             LogDiagnosticsPlugin::default(),
-            FrameTimeDiagnosticsPlugin::default(),
+            FrameTimeDiagnosticsPlugin,
         ))
         .init_state::<GameState>()
+        .add_plugins(GameplayPlugins)
         .add_plugins((
-            player::PlayerPlugin,
-            enemy::EnemyPlugin,
-            combat::CombatPlugin,
-            leveling::LevelingPlugin,
-            ui::UiPlugin,
-            waves::WavePlugin,
+            history::HistoryPlugin,
+            stats::StatsPlugin,
+            cosmetics::CosmeticsPlugin,
+            diagnostics::DiagnosticsPlugin,
+            crash::CrashPlugin,
+            post_process::PostProcessPlugin,
+            buff_tray::BuffTrayPlugin,
+            weapon_strip::WeaponStripPlugin,
+            frame_limit::FrameLimitPlugin,
+            world_view::WorldViewPlugin,
         ))
-        .add_systems(Startup, setup)
         .add_systems(OnEnter(GameState::MainMenu), setup_main_menu)
-        .add_systems(Update, main_menu_input.run_if(in_state(GameState::MainMenu)))
-        .add_systems(OnExit(GameState::MainMenu), despawn_main_menu)
-        .run();
+        .add_systems(Update, main_menu_input.run_if(in_state(GameState::MainMenu)));
+
+    #[cfg(feature = "audio")]
+    app.add_plugins((music::MusicPlugin, sfx::SfxPlugin));
+
+    #[cfg(feature = "discord")]
+    app.add_plugins(presence::PresencePlugin);
+
+    #[cfg(feature = "twitch")]
+    app.add_plugins(twitch::TwitchPlugin);
+
+    #[cfg(feature = "dev")]
+    app.add_plugins(console::ConsolePlugin);
+
+    if let Some(enemy_count) = stress::enemy_count_from_args() {
+        app.add_plugins(stress::StressPlugin { enemy_count });
+    }
+
+    if let Some(role) = relay::role_from_args() {
+        app.add_plugins(relay::RelayPlugin { role });
+    }
+
+    app.run();
 }
 
 #[derive(Component)]
 struct MainMenu;
 
-fn setup(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
-}
+#[derive(Component)]
+struct MainMenuStatusText;
 
 fn setup_main_menu(mut commands: Commands) {
     commands.spawn((
@@ -75,9 +264,13 @@ fn setup_main_menu(mut commands: Commands) {
                 flex_direction: FlexDirection::Column,
                 ..default()
             },
+            // Dims the attract-mode game playing behind the menu.
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
             ..default()
         },
         MainMenu,
+        DespawnOnExit(GameState::MainMenu),
+        ui_anim::Tween::fade_in(),
     )).with_children(|parent| {
         parent.spawn(TextBundle::from_section(
             "Swarm Heaven",
@@ -93,188 +286,1179 @@ fn setup_main_menu(mut commands: Commands) {
                 ..default()
             },
         ));
+        parent.spawn(TextBundle::from_section(
+            "Press H to browse run history, S for lifetime stats, O for options",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn(TextBundle::from_section(
+            "Press D for today's Daily run",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn(TextBundle::from_section(
+            "Press C to turn up the Heat",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn(TextBundle::from_section(
+            "Press P to change your look",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn(TextBundle::from_section(
+            "Press T to try builds on the test range",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn(TextBundle::from_section(
+            "Press V to paste a build code",
+            TextStyle {
+                font_size: 20.0,
+                color: Color::GRAY,
+                ..default()
+            },
+        ));
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.0,
+                    color: Color::rgb(1.0, 0.5, 0.5),
+                    ..default()
+                },
+            ),
+            MainMenuStatusText,
+        ));
+        parent.spawn(
+            TextBundle::from_section(
+                build_info::label(),
+                TextStyle {
+                    font_size: 16.0,
+                    color: Color::GRAY,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            }),
+        );
     });
 }
 
 fn main_menu_input(
     mut next_state: ResMut<NextState<GameState>>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut next_seed: ResMut<run::NextRunSeed>,
+    mut planned_build: ResMut<build_code::PlannedBuild>,
+    mut pending_daily: ResMut<daily::PendingDaily>,
+    mut pending_sandbox: ResMut<sandbox::PendingSandbox>,
+    mut status_query: Query<&mut Text, With<MainMenuStatusText>>,
 ) {
     if keyboard_input.any_just_pressed([
         KeyCode::Space,
         KeyCode::Enter,
     ]) {
         next_state.set(GameState::Running);
+    } else if keyboard_input.just_pressed(KeyCode::KeyH) {
+        next_state.set(GameState::RunHistory);
+    } else if keyboard_input.just_pressed(KeyCode::KeyS) {
+        next_state.set(GameState::Stats);
+    } else if keyboard_input.just_pressed(KeyCode::KeyO) {
+        next_state.set(GameState::Settings);
+    } else if keyboard_input.just_pressed(KeyCode::KeyC) {
+        next_state.set(GameState::Heat);
+    } else if keyboard_input.just_pressed(KeyCode::KeyP) {
+        next_state.set(GameState::Character);
+    } else if keyboard_input.just_pressed(KeyCode::KeyD) {
+        pending_daily.0 = Some(daily::today());
+        next_state.set(GameState::Running);
+    } else if keyboard_input.just_pressed(KeyCode::KeyT) {
+        pending_sandbox.0 = true;
+        next_state.set(GameState::Running);
+    } else if keyboard_input.just_pressed(KeyCode::KeyV) {
+        let pasted = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text());
+        let result = match pasted {
+            Ok(text) => build_code::BuildCode::decode(&text).map_err(|err| err.to_string()),
+            Err(err) => Err(format!("Could not read the clipboard: {}", err)),
+        };
+        match result {
+            Ok(code) => {
+                info!("Replaying build code with seed {} and {} picks", code.seed, code.upgrades.len());
+                next_seed.0 = Some(code.seed);
+                planned_build.0 = code.upgrades;
+                next_state.set(GameState::Running);
+            }
+            Err(message) => {
+                for mut text in status_query.iter_mut() {
+                    text.sections[0].value = message.clone();
+                }
+            }
+        }
     }
 }
 
-fn despawn_main_menu(mut commands: Commands, query: Query<Entity, With<MainMenu>>) {
-    for entity in query.iter() {
-        commands.entity(entity).despawn_recursive();
+
+mod render_layer {
+    use super::*;
+
+    /// Draw order for the world, back to front. Everything takes its z from one of these
+    /// when it is spawned, so sprites on different layers never share a depth and
+    /// flicker through each other. UI draws in its own pass, above all of them.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum RenderLayer {
+        Background,
+        Decals,
+        Gems,
+        Enemies,
+        Player,
+        Projectiles,
+        Vfx,
     }
-}
 
+    impl RenderLayer {
+        /// Layers sit a whole unit apart. Anything ordered within a layer stays less than
+        /// half a unit either side of it.
+        pub fn z(self) -> f32 {
+            self as u8 as f32
+        }
+
+        /// `position` moved onto this layer.
+        pub fn at(self, position: Vec3) -> Vec3 {
+            position.truncate().extend(self.z())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_layers_stack_back_to_front() {
+            let layers = [
+                RenderLayer::Background,
+                RenderLayer::Decals,
+                RenderLayer::Gems,
+                RenderLayer::Enemies,
+                RenderLayer::Player,
+                RenderLayer::Projectiles,
+                RenderLayer::Vfx,
+            ];
+            assert!(layers.windows(2).all(|pair| pair[0].z() + 1.0 == pair[1].z()));
+            assert_eq!(RenderLayer::Gems.at(Vec3::new(5.0, -3.0, 40.0)), Vec3::new(5.0, -3.0, RenderLayer::Gems.z()));
+        }
+    }
+}
 
 mod player {
     use super::*;
-    use crate::combat::BladeOrbit;
+    use crate::combat::{BladeOrbit, Health};
+    use bevy::sprite::Anchor;
 
     pub struct PlayerPlugin;
 
     impl Plugin for PlayerPlugin {
         fn build(&self, app: &mut App) {
-            app.add_systems(OnEnter(GameState::Running), spawn_player)
-                .add_systems(Update, player_movement.run_if(in_state(GameState::Running)));
+            app.add_event::<PlayerDashed>()
+                .add_event::<PlayerHurt>()
+                .add_systems(OnEnter(GameState::Running), spawn_player)
+                .add_systems(Update, spawn_player.run_if(in_state(GameState::MainMenu)))
+                .add_systems(
+                    Update,
+                    (
+                        player_movement.in_set(combat::CombatSet::Move),
+                        enemy_contact_damage.in_set(combat::CombatSet::Collide),
+                        settle_recoil,
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(
+                    Update,
+                    check_player_death
+                        .in_set(combat::CombatSet::Death)
+                        .run_if(in_state(GameState::Running)),
+                );
         }
     }
 
     #[derive(Component)]
     pub struct Player;
 
-    fn spawn_player(mut commands: Commands, query: Query<&Player>) {
+    /// The part of the player that takes damage. Smaller than the sprite, and only used
+    /// for incoming hits; pickups and visuals still go by `PLAYER_SIZE`. Each character
+    /// can be spawned with its own radius.
+    #[derive(Component, Clone, Copy, Debug)]
+    pub struct Hurtbox {
+        pub radius: f32,
+    }
+
+    impl Default for Hurtbox {
+        fn default() -> Self {
+            Self {
+                radius: PLAYER_HURTBOX_RADIUS,
+            }
+        }
+    }
+
+    /// Last direction the player moved in, kept while standing still.
+    #[derive(Component)]
+    pub struct Facing(pub Vec3);
+
+    impl Default for Facing {
+        fn default() -> Self {
+            Self(Vec3::X)
+        }
+    }
+
+    /// Visual-only kick applied to the sprite when firing; gameplay position is untouched.
+    #[derive(Component, Default)]
+    pub struct Recoil {
+        offset: Vec2,
+    }
+
+    impl Recoil {
+        pub fn kick(&mut self, offset: Vec2) {
+            self.offset += offset;
+        }
+    }
+
+    #[derive(Component)]
+    struct Dash {
+        cooldown: Timer,
+        active: Timer,
+        direction: Vec3,
+    }
+
+    impl Default for Dash {
+        fn default() -> Self {
+            let mut cooldown = Timer::from_seconds(DASH_COOLDOWN, TimerMode::Once);
+            cooldown.tick(cooldown.duration());
+            let mut active = Timer::from_seconds(DASH_DURATION, TimerMode::Once);
+            active.tick(active.duration());
+            Self {
+                cooldown,
+                active,
+                direction: Vec3::ZERO,
+            }
+        }
+    }
+
+    #[derive(Event, Debug)]
+    pub struct PlayerDashed {
+        pub position: Vec3,
+    }
+
+    /// Sent for every hit the player takes; contact damage sends one per touching
+    /// enemy every frame.
+    #[derive(Event, Debug)]
+    pub struct PlayerHurt {
+        pub amount: f32,
+        pub source: enemy::EnemyKind,
+        pub kind: DamageKind,
+    }
+
+    /// How a hit on the player was dealt.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DamageKind {
+        Contact,
+        Explosion,
+        Projectile,
+    }
+
+    impl DamageKind {
+        pub fn label(&self) -> &'static str {
+            match self {
+                DamageKind::Contact => "contact",
+                DamageKind::Explosion => "explosion",
+                DamageKind::Projectile => "projectile",
+            }
+        }
+    }
+
+    fn spawn_player(mut commands: Commands, query: Query<&Player>, wardrobe: Option<Res<cosmetics::Wardrobe>>) {
         if !query.is_empty() {
             return;
         }
+        let color = cosmetics::skin_color(wardrobe.as_deref());
         commands.spawn((
             SpriteBundle {
                 sprite: Sprite {
-                    color: Color::rgb(0.2, 0.7, 0.9),
+                    color,
                     custom_size: Some(Vec2::new(PLAYER_SIZE, PLAYER_SIZE)),
                     ..default()
                 },
-                transform: Transform::from_xyz(0.0, 0.0, 10.0),
+                transform: Transform::from_xyz(0.0, 0.0, render_layer::RenderLayer::Player.z()),
                 ..default()
             },
             Player,
+            Health::new(PLAYER_MAX_HEALTH),
+            Hurtbox::default(),
+            energy::Energy::default(),
+            Dash::default(),
+            Facing::default(),
+            Recoil::default(),
+            collision::PreviousPosition::default(),
+            last_stand::LastStand::default(),
+            DespawnOnExit::RUN,
         )).with_children(|parent| {
             // Glow effect
             parent.spawn(SpriteBundle {
                 sprite: Sprite {
-                    color: Color::rgba(0.2, 0.7, 0.9, 0.3),
+                    color: color.with_a(0.3),
                     custom_size: Some(Vec2::new(PLAYER_SIZE * 2.0, PLAYER_SIZE * 2.0)),
                     ..default()
                 },
-                transform: Transform::from_xyz(0.0, 0.0, -1.0),
+                transform: Transform::from_xyz(0.0, 0.0, -0.3),
                 ..default()
             });
 
+            // Lifts the blades from the player's layer onto the projectiles'.
             parent.spawn((
-                SpatialBundle::default(),
+                SpatialBundle::from_transform(Transform::from_xyz(
+                    0.0,
+                    0.0,
+                    render_layer::RenderLayer::Projectiles.z() - render_layer::RenderLayer::Player.z(),
+                )),
                 BladeOrbit,
             ));
         });
     }
 
     fn player_movement(
-        keyboard_input: Res<ButtonInput<KeyCode>>,
-        mut query: Query<&mut Transform, With<Player>>,
+        mut input: ResMut<input::PlayerInput>,
+        mut query: Query<
+            (&mut Transform, &mut Dash, &mut energy::Energy, &mut Facing, &mut collision::PreviousPosition),
+            With<Player>,
+        >,
+        mut dash_events: EventWriter<PlayerDashed>,
+        effective_stats: Res<attributes::EffectiveStats>,
         time: Res<Time>,
     ) {
-        if let Ok(mut transform) = query.get_single_mut() {
-            let mut direction = Vec3::ZERO;
+        if let Ok((mut transform, mut dash, mut energy, mut facing, mut previous)) = query.get_single_mut() {
+            previous.0 = Some(transform.translation);
 
-            if keyboard_input.pressed(KeyCode::KeyA) || keyboard_input.pressed(KeyCode::ArrowLeft) {
-                direction.x -= 1.0;
-            }
-            if keyboard_input.pressed(KeyCode::KeyD) || keyboard_input.pressed(KeyCode::ArrowRight) {
-                direction.x += 1.0;
+            let direction = input.movement;
+
+            if direction.length() > 0.0 {
+                facing.0 = direction.normalize();
             }
-            if keyboard_input.pressed(KeyCode::KeyW) || keyboard_input.pressed(KeyCode::ArrowUp) {
-                direction.y += 1.0;
+
+            dash.cooldown.tick(time.delta());
+            dash.active.tick(time.delta());
+            if dash.cooldown.finished()
+                && direction != Vec3::ZERO
+                && energy.can_afford(energy::DASH_COST)
+                && input.consume(input::Action::Dash)
+            {
+                energy.spend(energy::DASH_COST);
+                dash.cooldown.reset();
+                dash.active.reset();
+                dash.direction = facing.0;
+                dash_events.send(PlayerDashed {
+                    position: transform.translation,
+                });
             }
-            if keyboard_input.pressed(KeyCode::KeyS) || keyboard_input.pressed(KeyCode::ArrowDown) {
-                direction.y -= 1.0;
+
+            if dash.active.finished() {
+                transform.translation += direction * effective_stats.move_speed * time.delta_seconds();
+            } else {
+                transform.translation += dash.direction * DASH_SPEED * time.delta_seconds();
             }
+        }
+    }
 
-            if direction.length() > 0.0 {
-                direction = direction.normalize();
+    fn settle_recoil(mut query: Query<(&mut Recoil, &mut Sprite)>, time: Res<Time>) {
+        for (mut recoil, mut sprite) in query.iter_mut() {
+            recoil.offset *= (-20.0 * time.delta_seconds()).exp();
+            // Anchor is in sprite-size units and points the opposite way of the visual shift.
+            sprite.anchor = Anchor::Custom(-recoil.offset / PLAYER_SIZE);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn enemy_contact_damage(
+        mut player_query: Query<(&Transform, &mut Health, &Hurtbox), (With<Player>, Without<buffs::Invulnerable>)>,
+        enemy_query: Query<(&Transform, &enemy::EnemyKind), (With<enemy::Enemy>, Without<Player>, Without<enemy::Spawning>)>,
+        mut hurt_events: EventWriter<PlayerHurt>,
+        time: Res<Time>,
+    ) {
+        if let Ok((player_transform, mut health, hurtbox)) = player_query.get_single_mut() {
+            let amount = ENEMY_CONTACT_DAMAGE * time.delta_seconds();
+            for (enemy_transform, kind) in enemy_query.iter() {
+                if player_transform.translation.distance(enemy_transform.translation)
+                    < hurtbox.radius + enemy::radius(enemy_transform)
+                {
+                    health.current -= amount;
+                    hurt_events.send(PlayerHurt {
+                        amount,
+                        source: *kind,
+                        kind: DamageKind::Contact,
+                    });
+                }
             }
+        }
+    }
 
-            transform.translation += direction * PLAYER_SPEED * time.delta_seconds();
+    fn check_player_death(
+        query: Query<&Health, With<Player>>,
+        mut current_run: ResMut<run::CurrentRun>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        if let Ok(health) = query.get_single() {
+            if health.current <= 0.0 {
+                current_run.death_cause = Some(run::DeathCause::Swarmed);
+                game_state.set(GameState::Dying);
+            }
         }
     }
 }
 
 mod enemy {
     use super::*;
+    use bevy::{
+        sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+        utils::Instant,
+    };
+    use serde::{Deserialize, Serialize};
+    use std::collections::VecDeque;
 
     pub struct EnemyPlugin;
 
     impl Plugin for EnemyPlugin {
         fn build(&self, app: &mut App) {
-            app.insert_resource(EnemySpawnTimer(Timer::from_seconds(
-                ENEMY_SPAWN_INTERVAL,
-                TimerMode::Repeating,
-            )))
-            .add_systems(
-                Update,
-                (
-                    enemy_spawner,
-                    (enemy_movement, boid_steering).chain(),
+            app.add_event::<EnemySpawned>()
+                .add_event::<EnemyDamaged>()
+                .add_event::<EnemyDied>()
+                .insert_resource(EnemySpawnTimer(Timer::from_seconds(
+                    ENEMY_SPAWN_INTERVAL,
+                    TimerMode::Repeating,
+                )))
+                .init_resource::<SpawnSectors>()
+                .insert_resource(AmbientSpawnTimer(Timer::from_seconds(
+                    AMBIENT_SPAWN_INTERVAL,
+                    TimerMode::Repeating,
+                )))
+                .add_systems(
+                    Update,
+                    (
+                        announce_spawned_enemies,
+                        attach_steering_lod,
+                        attach_armor,
+                        apply_enemy_damage.in_set(combat::CombatSet::ApplyDamage),
+                    ),
                 )
-                    .run_if(in_state(GameState::Running)),
-            );
+                .add_systems(
+                    Update,
+                    (
+                        enemy_spawner.run_if(run::not_sandbox),
+                        spawn_in,
+                        ((enemy_movement, boid_steering).chain(), thief_behavior, ambient_behavior)
+                            .in_set(combat::CombatSet::Move),
+                        detonate_exploders.in_set(combat::CombatSet::Collide),
+                        spawn_ambient_mobs.before(ambient_behavior).run_if(run::not_sandbox),
+                        wear_off_shred.before(combat::CombatSet::ApplyDamage),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_spawners);
         }
     }
 
     #[derive(Component)]
     pub struct Enemy;
 
+    /// What spawned an enemy, used to look up what it drops.
+    #[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    pub enum EnemyKind {
+        Grunt,
+        Spitter,
+        Exploder,
+        Broodmother,
+        Brood,
+        Thief,
+        Ambient,
+        Elite,
+        Boss,
+    }
+
+    impl EnemyKind {
+        pub fn label(&self) -> &'static str {
+            match self {
+                EnemyKind::Grunt => "Grunt",
+                EnemyKind::Spitter => "Spitter",
+                EnemyKind::Exploder => "Exploder",
+                EnemyKind::Broodmother => "Broodmother",
+                EnemyKind::Brood => "Brood",
+                EnemyKind::Thief => "Thief",
+                EnemyKind::Ambient => "Wanderer",
+                EnemyKind::Elite => "Elite",
+                EnemyKind::Boss => "Boss",
+            }
+        }
+
+        /// Fraction of each hit the kind shrugs off before any shred.
+        fn armor(&self) -> f32 {
+            match self {
+                EnemyKind::Broodmother => 0.2,
+                EnemyKind::Elite => 0.4,
+                EnemyKind::Boss => 0.5,
+                EnemyKind::Grunt
+                | EnemyKind::Spitter
+                | EnemyKind::Exploder
+                | EnemyKind::Brood
+                | EnemyKind::Thief
+                | EnemyKind::Ambient => 0.0,
+            }
+        }
+    }
+
+    /// Armor each shred stack strips off.
+    const SHRED_PER_STACK: f32 = 0.1;
+    const MAX_SHRED_STACKS: u32 = 5;
+    /// Seconds without a shredding hit before an enemy's stacks fall off.
+    const SHRED_DURATION: f32 = 4.0;
+    /// Grunts spawned this far into a run come armored.
+    const ARMORED_GRUNTS_AFTER: f32 = 600.0;
+    const ARMORED_GRUNT_ARMOR: f32 = 0.3;
+
+    /// Fraction of each hit an enemy shrugs off. Given to armored enemies as they spawn,
+    /// along with an `ArmorShred` to wear it down.
+    #[derive(Component, Clone, Copy, Debug, PartialEq)]
+    pub struct Armor(pub f32);
+
+    impl Armor {
+        /// What's left of `amount` after the armor, with `shred` stacks stripping some of it.
+        pub fn mitigate(&self, amount: f32, shred: u32) -> f32 {
+            let armor = (self.0 - shred.min(MAX_SHRED_STACKS) as f32 * SHRED_PER_STACK).max(0.0);
+            amount * (1.0 - armor)
+        }
+    }
+
+    /// Shred stacks on an armored enemy, from hits that send `EnemyDamaged::shred`. They
+    /// all fall off once `SHRED_DURATION` passes without another shredding hit.
+    #[derive(Component, Debug)]
+    pub struct ArmorShred {
+        pub stacks: u32,
+        timer: Timer,
+    }
+
+    impl Default for ArmorShred {
+        fn default() -> Self {
+            Self { stacks: 0, timer: Timer::from_seconds(SHRED_DURATION, TimerMode::Once) }
+        }
+    }
+
+    impl ArmorShred {
+        fn add(&mut self, stacks: u32) {
+            if stacks > 0 {
+                self.stacks = (self.stacks + stacks).min(MAX_SHRED_STACKS);
+                self.timer.reset();
+            }
+        }
+    }
+
+    /// Sent once for every enemy that enters the world, whichever system spawned it.
+    #[derive(Event, Debug)]
+    pub struct EnemySpawned {
+        pub entity: Entity,
+        pub position: Vec3,
+    }
+
+    /// A hit landing on an enemy. Weapons only send these; health is applied
+    /// in `apply_enemy_damage`, which also decides whether the enemy died.
+    #[derive(Event, Clone, Copy, Debug)]
+    pub struct EnemyDamaged {
+        pub entity: Entity,
+        pub amount: f32,
+        /// Armor shred stacks the hit leaves on the enemy, after its own damage is worked out.
+        pub shred: u32,
+        pub split: Option<gems::SplitShot>,
+    }
+
+    /// Sent exactly once per enemy killed, right before it is despawned.
+    #[derive(Event, Debug)]
+    pub struct EnemyDied {
+        pub entity: Entity,
+        pub position: Vec3,
+        pub kind: EnemyKind,
+        /// The killing hit's split, if it was a shot that splits on kills.
+        pub split: Option<gems::SplitShot>,
+    }
+
     #[derive(Resource)]
     struct EnemySpawnTimer(Timer);
 
+    const SPAWN_SECTORS: usize = 12;
+    const SPAWN_SECTOR_DECAY: f32 = 0.97;
+
+    /// Recent spawn counts per angular sector around the player, decaying with
+    /// every spawn, so new enemies fill in the sides that have been quiet.
+    #[derive(Resource, Default)]
+    struct SpawnSectors {
+        recent: [f32; SPAWN_SECTORS],
+    }
+
+    impl SpawnSectors {
+        fn next_angle(&mut self, rng: &mut impl Rng) -> f32 {
+            let least = self.recent.iter().copied().fold(f32::MAX, f32::min);
+            // Anything within one spawn of the emptiest sector is fair game, which keeps it from
+            // sweeping around the circle in a predictable order.
+            let candidates = (0..SPAWN_SECTORS)
+                .filter(|&sector| self.recent[sector] < least + 1.0)
+                .collect::<Vec<_>>();
+            let sector = candidates[rng.gen_range(0..candidates.len())];
+
+            for count in self.recent.iter_mut() {
+                *count *= SPAWN_SECTOR_DECAY;
+            }
+            self.recent[sector] += 1.0;
+
+            let width = std::f32::consts::TAU / SPAWN_SECTORS as f32;
+            (sector as f32 + rng.gen::<f32>()) * width
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn enemy_spawner(
         mut commands: Commands,
         time: Res<Time>,
         mut timer: ResMut<EnemySpawnTimer>,
+        mut sectors: ResMut<SpawnSectors>,
+        mut run_rng: ResMut<run::RunRng>,
+        settings: Res<settings::Settings>,
+        arena: Res<arena::BossArena>,
         player_query: Query<&Transform, With<player::Player>>,
     ) {
-        if timer.0.tick(time.delta()).just_finished() {
+        if timer.0.tick(time.delta()).just_finished() && !arena.is_active() {
             if let Ok(player_transform) = player_query.get_single() {
-                let mut rng = rand::thread_rng();
-                let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+                let rng = &mut run_rng.0;
+                let angle = sectors.next_angle(rng);
                 let distance = 1000.0;
-                let spawn_pos = player_transform.translation
-                    + Vec3::new(angle.cos() * distance, angle.sin() * distance, 0.0);
+                let offset = Vec3::new(angle.cos() * distance, angle.sin() * distance, 0.0);
+                let spawn_pos = outside_safe_zone(
+                    player_transform.translation + offset,
+                    player_transform.translation,
+                    settings.gameplay.spawn_safe_radius,
+                );
 
                 // Vary enemy color slightly
                 let r = rng.gen_range(0.8..1.0);
                 let g = rng.gen_range(0.1..0.3);
                 let b = rng.gen_range(0.1..0.3);
 
-                commands.spawn((
-                    SpriteBundle {
-                        sprite: Sprite {
-                            color: Color::rgb(r, g, b),
-                            custom_size: Some(Vec2::new(ENEMY_SIZE, ENEMY_SIZE)),
-                            ..default()
-                        },
-                        transform: Transform::from_translation(spawn_pos),
-                        ..default()
-                    },
-                    Enemy,
-                ));
+                let roll = rng.gen::<f64>();
+                if roll < SPITTER_CHANCE {
+                    commands.spawn(enemy_bundle(spawn_pos, Color::rgb(0.3, 1.0, 0.2), EnemyKind::Spitter));
+                } else if roll < SPITTER_CHANCE + EXPLODER_CHANCE {
+                    commands.spawn((enemy_bundle(spawn_pos, Color::rgb(2.0, 0.9, 0.1), EnemyKind::Exploder), OnDeath::Explode));
+                } else if roll < SPITTER_CHANCE + EXPLODER_CHANCE + BROODMOTHER_CHANCE {
+                    commands.spawn((
+                        champion_bundle(spawn_pos, Color::rgb(0.6, 0.2, 0.9), 1.5, ENEMY_HEALTH * 3.0, EnemyKind::Broodmother),
+                        OnDeath::Brood,
+                        AvoidsObstacles,
+                    ));
+                } else if roll < SPITTER_CHANCE + EXPLODER_CHANCE + BROODMOTHER_CHANCE + THIEF_CHANCE {
+                    commands.spawn((enemy_bundle(spawn_pos, Color::rgb(0.2, 0.8, 1.6), EnemyKind::Thief), Thief::default()));
+                } else {
+                    commands.spawn(enemy_bundle(spawn_pos, Color::rgb(r, g, b), EnemyKind::Grunt));
+                }
             }
         }
     }
 
-    fn enemy_movement(
-        mut enemy_query: Query<&mut Transform, (With<Enemy>, Without<player::Player>)>,
-        player_query: Query<&Transform, With<player::Player>>,
-        time: Res<Time>,
-    ) {
-        if let Ok(player_transform) = player_query.get_single() {
-            enemy_query.par_iter_mut().for_each(|mut transform| {
-                let direction = (player_transform.translation - transform.translation).normalize_or_zero();
-                transform.translation += direction * ENEMY_SPEED * time.delta_seconds();
-            });
-        }
-    }
-    
-    fn boid_steering(
-        mut enemy_query: Query<(Entity, &mut Transform), With<Enemy>>,
-        time: Res<Time>,
-    ) {
+    /// Every enemy is an untextured sprite, so they all share Bevy's default
+    /// image and land in a single sprite batch regardless of tint.
+    /// Starts invisible and is grown in by `spawn_in`.
+    pub fn enemy_bundle(
+        position: Vec3,
+        color: Color,
+        kind: EnemyKind,
+    ) -> (SpriteBundle, Enemy, EnemyKind, combat::Health, Spawning, DespawnOnExit) {
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color: color.with_a(0.0),
+                    custom_size: Some(Vec2::ZERO),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Enemies.at(position)),
+                ..default()
+            },
+            Enemy,
+            kind,
+            combat::Health::new(ENEMY_HEALTH),
+            Spawning(Timer::from_seconds(SPAWN_IN_DURATION, TimerMode::Once)),
+            DespawnOnExit::RUN,
+        )
+    }
+
+    const SPAWN_IN_DURATION: f32 = 0.4;
+
+    /// An enemy still appearing. Until the timer runs out it doesn't move, hurt the
+    /// player or take damage, so nothing pops into existence already attacking.
+    #[derive(Component)]
+    pub struct Spawning(pub Timer);
+
+    /// Grows and fades new enemies in, then lets them loose.
+    fn spawn_in(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Spawning, &mut Sprite)>) {
+        for (entity, mut spawning, mut sprite) in query.iter_mut() {
+            let progress = spawning.0.tick(time.delta()).fraction();
+            sprite.custom_size = Some(Vec2::splat(ENEMY_SIZE * progress));
+            sprite.color.set_a(progress);
+            if spawning.0.finished() {
+                commands.entity(entity).remove::<Spawning>();
+            }
+        }
+    }
+
+    /// A single tougher enemy, drawn `scale` times the normal size.
+    pub fn champion_bundle(
+        position: Vec3,
+        color: Color,
+        scale: f32,
+        health: f32,
+        kind: EnemyKind,
+    ) -> (SpriteBundle, Enemy, EnemyKind, combat::Health, Spawning, DespawnOnExit) {
+        let mut bundle = enemy_bundle(position, color, kind);
+        bundle.0.transform.scale = Vec3::splat(scale);
+        bundle.3 = combat::Health::new(health);
+        bundle
+    }
+
+    /// Moves a spawn position that landed within `radius` of the player straight out to
+    /// the edge of that circle. Every spawner runs its positions through this, so a
+    /// formation that happens to overlap the player is pushed apart rather than spawned
+    /// on top of them.
+    pub fn outside_safe_zone(position: Vec3, player: Vec3, radius: f32) -> Vec3 {
+        let offset = (position - player).truncate();
+        if offset.length() >= radius {
+            return position;
+        }
+        let direction = offset.try_normalize().unwrap_or(Vec2::Y);
+        (player.truncate() + direction * radius).extend(position.z)
+    }
+
+    /// Spitters lob slow shots at the player from the emitter in `patterns::EmitterTables`.
+    const SPITTER_CHANCE: f64 = 0.05;
+
+    const EXPLODER_CHANCE: f64 = 0.04;
+    const EXPLODER_FUSE: f32 = 0.8;
+    const EXPLODER_RADIUS: f32 = 110.0;
+    const EXPLODER_DAMAGE: f32 = 20.0;
+    const BROODMOTHER_CHANCE: f64 = 0.03;
+    const BROOD_SIZE: usize = 4;
+
+    /// What an enemy leaves behind when it dies, handled by `apply_enemy_damage`.
+    #[derive(Component, Clone, Copy, Debug)]
+    pub enum OnDeath {
+        /// Leaves a telegraphed blast that hurts the player if they're still inside when it goes off.
+        Explode,
+        /// Releases a handful of small, fast enemies.
+        Brood,
+    }
+
+    const THIEF_CHANCE: f64 = 0.02;
+    const THIEF_SPEED: f32 = 1.4;
+    const THIEF_APPETITE: u32 = 5;
+    const THIEF_ESCAPE_DISTANCE: f32 = 1400.0;
+
+    /// Goes for gems instead of the player and runs off with them once full.
+    /// Killing it drops everything it has eaten as a single gem.
+    #[derive(Component, Default)]
+    pub struct Thief {
+        stolen: u32,
+        eaten: u32,
+    }
+
+    impl Thief {
+        fn fleeing(&self) -> bool {
+            self.eaten >= THIEF_APPETITE
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn thief_behavior(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut thief_query: Query<
+            (Entity, &mut Transform, &mut Thief, &mut combat::Health, Has<taunt::Taunted>),
+            Without<Spawning>,
+        >,
+        gem_query: Query<
+            (Entity, &Transform, &leveling::XpGem),
+            (Without<Thief>, Without<combat::MarkedForDeath>, Without<hibernation::Hibernating>),
+        >,
+        player_query: Query<&Transform, (With<player::Player>, Without<Thief>)>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let mut taken = Vec::new();
+        for (entity, mut transform, mut thief, mut health, taunted) in thief_query.iter_mut() {
+            let position = transform.translation;
+            let nearest_gem = gem_query
+                .iter()
+                .filter(|(gem, _, _)| !taken.contains(gem))
+                .min_by(|a, b| {
+                    a.1.translation
+                        .distance_squared(position)
+                        .total_cmp(&b.1.translation.distance_squared(position))
+                });
+
+            let direction = match nearest_gem {
+                _ if taunted => player_transform.translation - position,
+                Some((gem, gem_transform, xp_gem)) if !thief.fleeing() => {
+                    if gem_transform.translation.distance(position) < radius(&transform) + XP_GEM_SIZE {
+                        taken.push(gem);
+                        commands.entity(gem).try_insert(combat::MarkedForDeath);
+                        thief.stolen += xp_gem.value;
+                        thief.eaten += 1;
+                        // Every gem makes it a little bigger and harder to bring down.
+                        transform.scale *= 1.1;
+                        health.max += ENEMY_HEALTH;
+                        health.current += ENEMY_HEALTH;
+                    }
+                    gem_transform.translation - position
+                }
+                // Nothing left to take: run with what it has, or fall back to chasing the player.
+                _ if thief.stolen > 0 => position - player_transform.translation,
+                _ => player_transform.translation - position,
+            };
+            transform.translation +=
+                (direction.truncate().normalize_or_zero() * ENEMY_SPEED * THIEF_SPEED * time.delta_seconds()).extend(0.0);
+
+            if thief.stolen > 0 && position.distance(player_transform.translation) > THIEF_ESCAPE_DISTANCE {
+                commands.entity(entity).try_insert(combat::MarkedForDeath);
+            }
+        }
+    }
+
+    const AMBIENT_SPAWN_INTERVAL: f32 = 4.0;
+    const AMBIENT_MAX: usize = 12;
+    const AMBIENT_WANDER_RADIUS: f32 = 150.0;
+    const AMBIENT_AGGRO_RADIUS: f32 = 300.0;
+    const AMBIENT_LEASH_RADIUS: f32 = 900.0;
+
+    #[derive(Resource)]
+    struct AmbientSpawnTimer(Timer);
+
+    /// A mob that idles around where it spawned until the player comes close,
+    /// then gives chase until the player drags it too far from home.
+    #[derive(Component)]
+    pub struct Ambient {
+        home: Vec3,
+        wander_target: Vec3,
+        state: AmbientState,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum AmbientState {
+        Wandering,
+        Aggro,
+        Returning,
+    }
+
+    fn spawn_ambient_mobs(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut timer: ResMut<AmbientSpawnTimer>,
+        mut run_rng: ResMut<run::RunRng>,
+        settings: Res<settings::Settings>,
+        ambient_query: Query<(), With<Ambient>>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        if !timer.0.tick(time.delta()).just_finished() || ambient_query.iter().count() >= AMBIENT_MAX {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let rng = &mut run_rng.0;
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = rng.gen_range(600.0..900.0);
+        let home = outside_safe_zone(
+            player_transform.translation + Vec3::new(angle.cos(), angle.sin(), 0.0) * distance,
+            player_transform.translation,
+            settings.gameplay.spawn_safe_radius,
+        );
+        commands.spawn((
+            enemy_bundle(home, Color::rgb(0.6, 0.5, 0.3), EnemyKind::Ambient),
+            Ambient {
+                home,
+                wander_target: home,
+                state: AmbientState::Wandering,
+            },
+        ));
+    }
+
+    fn ambient_behavior(
+        time: Res<Time>,
+        mut ambient_query: Query<(&mut Transform, &mut Ambient, &mut combat::Health, Has<taunt::Taunted>), Without<Spawning>>,
+        player_query: Query<&Transform, (With<player::Player>, Without<Ambient>)>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player_position = player_transform.translation;
+        for (mut transform, mut ambient, mut health, taunted) in ambient_query.iter_mut() {
+            let position = transform.translation;
+            ambient.state = match ambient.state {
+                _ if taunted => AmbientState::Aggro,
+                AmbientState::Wandering if position.distance(player_position) < AMBIENT_AGGRO_RADIUS => {
+                    AmbientState::Aggro
+                }
+                AmbientState::Aggro if player_position.distance(ambient.home) > AMBIENT_LEASH_RADIUS => {
+                    AmbientState::Returning
+                }
+                AmbientState::Returning if position.distance(ambient.home) < AMBIENT_WANDER_RADIUS => {
+                    // Back on its patch: shake off the fight.
+                    health.current = health.max;
+                    AmbientState::Wandering
+                }
+                state => state,
+            };
+
+            let (target, speed) = match ambient.state {
+                AmbientState::Wandering => {
+                    if position.distance(ambient.wander_target) < ENEMY_SIZE {
+                        let angle = rand::thread_rng().gen_range(0.0..std::f32::consts::TAU);
+                        let reach = rand::thread_rng().gen_range(0.0..AMBIENT_WANDER_RADIUS);
+                        ambient.wander_target = ambient.home + Vec3::new(angle.cos(), angle.sin(), 0.0) * reach;
+                    }
+                    (ambient.wander_target, 0.3)
+                }
+                AmbientState::Aggro => (player_position, 1.0),
+                AmbientState::Returning => (ambient.home, 1.2),
+            };
+            transform.translation +=
+                ((target - position).truncate().normalize_or_zero() * ENEMY_SPEED * speed * time.delta_seconds()).extend(0.0);
+        }
+    }
+
+    /// Steers around obstacles instead of pushing straight through them.
+    /// Only the big enemies pay for this; the swarm just gets pushed out.
+    #[derive(Component)]
+    pub struct AvoidsObstacles;
+
+    /// Scales an enemy's chase speed.
+    #[derive(Component)]
+    pub struct Speed(pub f32);
+
+    /// Within this many collision grid cells of the camera, enemies steer every frame.
+    const LOD_NEAR_CELLS: u32 = 12;
+    /// Out to this many cells they steer at 20 Hz, and beyond it at 10 Hz.
+    const LOD_MIDDLE_CELLS: u32 = 20;
+
+    /// How often an enemy's steering is worked out, by how far it is from the camera.
+    /// In between it keeps moving along its last velocity.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum LodBand {
+        Near,
+        Middle,
+        Far,
+    }
+
+    impl LodBand {
+        pub fn of(position: Vec3, camera: Vec3) -> Self {
+            match collision::cell_distance(position, camera) {
+                cells if cells <= LOD_NEAR_CELLS => LodBand::Near,
+                cells if cells <= LOD_MIDDLE_CELLS => LodBand::Middle,
+                _ => LodBand::Far,
+            }
+        }
+
+        /// Seconds between steering updates.
+        fn interval(&self) -> f32 {
+            match self {
+                LodBand::Near => 0.0,
+                LodBand::Middle => 1.0 / 20.0,
+                LodBand::Far => 1.0 / 10.0,
+            }
+        }
+    }
+
+    /// The velocity an enemy extrapolates along between steering updates.
+    #[derive(Component, Debug)]
+    struct SteeringLod {
+        velocity: Vec3,
+        since_update: f32,
+    }
+
+    fn attach_steering_lod(mut commands: Commands, query: Query<Entity, Added<Enemy>>) {
+        for entity in query.iter() {
+            // Stagger the first update so a wave spawned together doesn't steer in lockstep.
+            let since_update = (entity.index() % 10) as f32 * 0.01;
+            commands.entity(entity).try_insert(SteeringLod {
+                velocity: Vec3::ZERO,
+                since_update,
+            });
+        }
+    }
+
+    #[derive(Component)]
+    struct Detonation {
+        fuse: Timer,
+        material: Handle<ColorMaterial>,
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn detonate_exploders(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut detonation_query: Query<(Entity, &mut Detonation, &Transform)>,
+        mut player_query: Query<
+            (&Transform, &mut combat::Health, &player::Hurtbox),
+            (With<player::Player>, Without<Detonation>, Without<buffs::Invulnerable>),
+        >,
+        mut hurt_events: EventWriter<player::PlayerHurt>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        mut screen_effects: ResMut<post_process::ScreenEffects>,
+    ) {
+        for (entity, mut detonation, transform) in detonation_query.iter_mut() {
+            detonation.fuse.tick(time.delta());
+            // Brightens as the fuse burns down so the player can see it coming.
+            if let Some(material) = materials.get_mut(&detonation.material) {
+                material.color.set_a(0.15 + 0.45 * detonation.fuse.fraction());
+            }
+            if !detonation.fuse.finished() {
+                continue;
+            }
+            screen_effects.chromatic_flash(post_process::EXPLOSION_FLASH);
+            if let Ok((player_transform, mut health, hurtbox)) = player_query.get_single_mut() {
+                if player_transform.translation.truncate().distance(transform.translation.truncate())
+                    < EXPLODER_RADIUS + hurtbox.radius
+                {
+                    health.current -= EXPLODER_DAMAGE;
+                    hurt_events.send(player::PlayerHurt {
+                        amount: EXPLODER_DAMAGE,
+                        source: EnemyKind::Exploder,
+                        kind: player::DamageKind::Explosion,
+                    });
+                }
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+
+    /// Collision radius, which grows with the sprite's scale.
+    pub fn radius(transform: &Transform) -> f32 {
+        ENEMY_SIZE * transform.scale.x / 2.0
+    }
+
+    /// Pulls enemies within `radius` toward the entity's position, strongest at the centre.
+    #[derive(Component)]
+    pub struct GravityWell {
+        pub radius: f32,
+        pub strength: f32,
+    }
+
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    fn enemy_movement(
+        mut enemy_query: Query<
+            (Entity, &mut Transform, Option<&mut SteeringLod>),
+            (With<Enemy>, Without<player::Player>, Without<Thief>, Without<Ambient>, Without<Spawning>),
+        >,
+        speed_query: Query<&Speed>,
+        avoider_query: Query<(), With<AvoidsObstacles>>,
+        taunted_query: Query<(), With<taunt::Taunted>>,
+        player_query: Query<&Transform, With<player::Player>>,
+        camera_query: Query<&Transform, (With<world_view::WorldCamera>, Without<Enemy>)>,
+        well_query: Query<(&Transform, &GravityWell), (Without<Enemy>, Without<hibernation::Hibernating>)>,
+        obstacle_query: Query<(&Transform, &obstacles::Obstacle), Without<Enemy>>,
+        flow_field: Res<flow_field::FlowField>,
+        time: Res<Time>,
+    ) {
+        let wells = well_query
+            .iter()
+            .map(|(transform, well)| (transform.translation, well))
+            .collect::<Vec<_>>();
+        let obstacles = obstacle_query
+            .iter()
+            .map(|(transform, obstacle)| (transform.translation, obstacle.radius))
+            .collect::<Vec<_>>();
+        if let Ok(player_transform) = player_query.get_single() {
+            // Headless runs have no camera, and it follows the player anyway.
+            let camera = camera_query
+                .get_single()
+                .map_or(player_transform.translation, |transform| transform.translation);
+            let delta = time.delta_seconds();
+            enemy_query.par_iter_mut().for_each(|(entity, mut transform, mut lod)| {
+                if let Some(lod) = lod.as_mut() {
+                    lod.since_update += delta;
+                    if lod.since_update < LodBand::of(transform.translation, camera).interval() {
+                        transform.translation += lod.velocity * delta;
+                        return;
+                    }
+                    lod.since_update = 0.0;
+                }
+                let taunted = taunted_query.contains(entity);
+                let straight_at_player = || {
+                    (player_transform.translation - transform.translation).truncate().normalize_or_zero().extend(0.0)
+                };
+                // Taunted enemies charge in a straight line rather than following the flow field.
+                let mut direction = if taunted {
+                    straight_at_player()
+                } else {
+                    flow_field.sample(transform.translation).unwrap_or_else(straight_at_player)
+                };
+                if avoider_query.contains(entity) {
+                    direction = obstacles::avoidance(transform.translation, direction, radius(&transform), &obstacles);
+                }
+                let mut speed = speed_query.get(entity).map_or(1.0, |speed| speed.0);
+                if taunted {
+                    speed *= taunt::TAUNTED_SPEED;
+                }
+                let mut velocity = direction * ENEMY_SPEED * speed;
+                for (center, well) in wells.iter() {
+                    let offset = (*center - transform.translation).truncate();
+                    let distance = offset.length();
+                    if distance < well.radius {
+                        velocity += (offset.normalize_or_zero() * well.strength * (1.0 - distance / well.radius))
+                            .extend(0.0);
+                    }
+                }
+                transform.translation += velocity * delta;
+                if let Some(lod) = lod.as_mut() {
+                    lod.velocity = velocity;
+                }
+            });
+        }
+    }
+    
+    /// Pushes overlapping enemies apart. Under a reduced frame budget it only runs every
+    /// few frames, with the push scaled up to cover the frames it skipped.
+    #[allow(clippy::type_complexity)]
+    fn boid_steering(
+        mut enemy_query: Query<(Entity, &mut Transform), (With<Enemy>, Without<Spawning>)>,
+        time: Res<Time>,
+        mut budget: ResMut<budget::FrameBudget>,
+        mut frame: Local<u32>,
+    ) {
+        let interval = budget.fidelity.boid_interval();
+        *frame = frame.wrapping_add(1);
+        if !frame.is_multiple_of(interval) {
+            return;
+        }
+        let started = Instant::now();
+        let delta = time.delta_seconds() * interval as f32;
         let mut combinations = enemy_query.iter_combinations_mut();
         while let Some([(_, mut t1), (_, mut t2)]) = combinations.fetch_next() {
             let distance = t1.translation.distance(t2.translation);
@@ -284,15 +1468,312 @@ mod enemy {
                 let separation_vector = (t1.translation - t2.translation).normalize();
                 let separation_force = (separation_threshold - distance) / separation_threshold;
 
-                t1.translation += separation_vector * separation_force * ENEMY_SPEED * time.delta_seconds() / 2.0;
-                t2.translation -= separation_vector * separation_force * ENEMY_SPEED * time.delta_seconds() / 2.0;
+                t1.translation += separation_vector * separation_force * ENEMY_SPEED * delta / 2.0;
+                t2.translation -= separation_vector * separation_force * ENEMY_SPEED * delta / 2.0;
+            }
+        }
+        budget.boids = started.elapsed();
+    }
+
+    fn announce_spawned_enemies(
+        query: Query<(Entity, &Transform), Added<Enemy>>,
+        mut events: EventWriter<EnemySpawned>,
+    ) {
+        for (entity, transform) in query.iter() {
+            events.send(EnemySpawned {
+                entity,
+                position: transform.translation,
+            });
+        }
+    }
+
+    /// Armors champions, and grunts once the run is late enough.
+    fn attach_armor(
+        mut commands: Commands,
+        query: Query<(Entity, &EnemyKind), Added<Enemy>>,
+        current_run: Res<run::CurrentRun>,
+    ) {
+        for (entity, kind) in query.iter() {
+            let armor = match kind {
+                EnemyKind::Grunt if current_run.elapsed >= ARMORED_GRUNTS_AFTER => ARMORED_GRUNT_ARMOR,
+                kind => kind.armor(),
+            };
+            if armor > 0.0 {
+                commands.entity(entity).try_insert((Armor(armor), ArmorShred::default()));
+            }
+        }
+    }
+
+    fn wear_off_shred(mut query: Query<&mut ArmorShred>, time: Res<Time>) {
+        for mut shred in query.iter_mut() {
+            if shred.stacks > 0 && shred.timer.tick(time.delta()).just_finished() {
+                shred.stacks = 0;
+            }
+        }
+    }
+
+    /// How far overkill damage can carry to the next enemy.
+    const OVERKILL_RADIUS: f32 = 150.0;
+
+    /// Applies hits, resolving deaths as they happen. With the Overkill upgrade, whatever a
+    /// killing hit had left over goes on to the nearest living enemy in `OVERKILL_RADIUS`,
+    /// and can carry on again from there.
+    #[allow(clippy::type_complexity)]
+    fn apply_enemy_damage(
+        mut commands: Commands,
+        mut damage_events: EventReader<EnemyDamaged>,
+        mut death_events: EventWriter<EnemyDied>,
+        mut health_query: Query<
+            (
+                Entity,
+                &mut combat::Health,
+                &Transform,
+                &EnemyKind,
+                Option<&OnDeath>,
+                Option<&Thief>,
+                Option<&Armor>,
+                Option<&mut ArmorShred>,
+            ),
+            Without<Spawning>,
+        >,
+        weapon_stats: Res<combat::WeaponStats>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+    ) {
+        let mut hits = damage_events.read().copied().collect::<VecDeque<_>>();
+        while let Some(EnemyDamaged { entity, amount, shred: shred_stacks, split }) = hits.pop_front() {
+            let Ok((_, mut health, transform, &kind, on_death, thief, armor, shred)) = health_query.get_mut(entity)
+            else {
+                continue;
+            };
+            // Several hits can land in the same frame; only the first lethal one counts.
+            if health.current <= 0.0 {
+                continue;
+            }
+            let amount = match (armor, shred) {
+                (Some(armor), Some(mut shred)) => {
+                    let amount = armor.mitigate(amount, shred.stacks);
+                    shred.add(shred_stacks);
+                    amount
+                }
+                (Some(armor), None) => armor.mitigate(amount, 0),
+                (None, _) => amount,
+            };
+            health.current -= amount;
+            if health.current <= 0.0 {
+                death_events.send(EnemyDied {
+                    entity,
+                    position: transform.translation,
+                    kind,
+                    split,
+                });
+                commands.entity(entity).try_insert(combat::MarkedForDeath);
+
+                let position = transform.translation;
+                match on_death {
+                    Some(OnDeath::Explode) => {
+                        let material = materials.add(Color::rgba(2.0, 0.5, 0.1, 0.15));
+                        commands.spawn((
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(meshes.add(Circle::new(EXPLODER_RADIUS))),
+                                material: material.clone(),
+                                transform: Transform::from_translation(position.truncate().extend(render_layer::RenderLayer::Decals.z() + 0.2)),
+                                ..default()
+                            },
+                            Detonation {
+                                fuse: Timer::from_seconds(EXPLODER_FUSE, TimerMode::Once),
+                                material,
+                            },
+                            DespawnOnExit::RUN,
+                        ));
+                    }
+                    Some(OnDeath::Brood) => {
+                        for i in 0..BROOD_SIZE {
+                            let angle = i as f32 * std::f32::consts::TAU / BROOD_SIZE as f32;
+                            let offset = Vec3::new(angle.cos(), angle.sin(), 0.0) * ENEMY_SIZE;
+                            commands.spawn((
+                                champion_bundle(position + offset, Color::rgb(0.8, 0.4, 1.0), 0.5, ENEMY_HEALTH * 0.3, EnemyKind::Brood),
+                                Speed(1.8),
+                            ));
+                        }
+                    }
+                    None => {}
+                }
+                if let Some(thief) = thief.filter(|thief| thief.stolen > 0) {
+                    commands.spawn(leveling::xp_gem_bundle(position + Vec3::X * ENEMY_SIZE, thief.stolen));
+                }
+
+                let overkill = -health.current;
+                if weapon_stats.overkill && overkill > 0.0 {
+                    let next = health_query
+                        .iter()
+                        .filter(|(_, health, ..)| health.current > 0.0)
+                        .map(|(next, _, transform, ..)| (next, transform.translation.distance(position)))
+                        .filter(|&(_, distance)| distance < OVERKILL_RADIUS)
+                        .min_by(|a, b| a.1.total_cmp(&b.1));
+                    if let Some((next, _)) = next {
+                        hits.push_back(EnemyDamaged {
+                            entity: next,
+                            amount: overkill,
+                            shred: 0,
+                            split: None,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    fn reset_spawners(
+        mut timer: ResMut<EnemySpawnTimer>,
+        mut ambient_timer: ResMut<AmbientSpawnTimer>,
+        mut sectors: ResMut<SpawnSectors>,
+    ) {
+        timer.0.reset();
+        ambient_timer.0.reset();
+        *sectors = SpawnSectors::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        #[test]
+        fn test_spawn_angles_cover_every_sector_evenly() {
+            let mut sectors = SpawnSectors::default();
+            let mut rng = StdRng::seed_from_u64(7);
+            let width = std::f32::consts::TAU / SPAWN_SECTORS as f32;
+            let mut counts = [0u32; SPAWN_SECTORS];
+            for _ in 0..SPAWN_SECTORS * 20 {
+                let angle = sectors.next_angle(&mut rng);
+                counts[(angle / width) as usize % SPAWN_SECTORS] += 1;
+            }
+            let min = *counts.iter().min().unwrap();
+            let max = *counts.iter().max().unwrap();
+            assert!(max - min <= 4, "uneven spawn sectors: {:?}", counts);
+        }
+
+        #[test]
+        fn test_lod_bands_grow_coarser_away_from_the_camera() {
+            let camera = Vec3::new(1000.0, -300.0, 0.0);
+            assert_eq!(LodBand::of(camera, camera), LodBand::Near);
+            // The corner of the 1280x720 window is still steered every frame.
+            assert_eq!(LodBand::of(camera + Vec3::new(640.0, 360.0, 0.0), camera), LodBand::Near);
+            assert_eq!(LodBand::of(camera + Vec3::new(0.0, -1000.0, 0.0), camera), LodBand::Middle);
+            assert_eq!(LodBand::of(camera + Vec3::new(-1600.0, 0.0, 0.0), camera), LodBand::Far);
+            assert!(LodBand::Far.interval() > LodBand::Middle.interval());
+        }
+
+        #[test]
+        fn test_outside_safe_zone_pushes_out_to_the_radius() {
+            let player = Vec3::new(100.0, 100.0, 0.0);
+            let far = Vec3::new(900.0, 100.0, 0.0);
+            assert_eq!(outside_safe_zone(far, player, 450.0), far);
+
+            let close = outside_safe_zone(Vec3::new(200.0, 100.0, 0.0), player, 450.0);
+            assert!((close - Vec3::new(550.0, 100.0, 0.0)).length() < 1e-3);
+
+            let on_top = outside_safe_zone(player, player, 450.0);
+            assert!((on_top.distance(player) - 450.0).abs() < 1e-3);
+        }
+
+        /// An app running just `apply_enemy_damage`, with the weapon stats it reads.
+        fn damage_app(weapon_stats: combat::WeaponStats) -> App {
+            let mut app = App::new();
+            app.add_event::<EnemyDamaged>()
+               .add_event::<EnemyDied>()
+               .init_resource::<Assets<Mesh>>()
+               .init_resource::<Assets<ColorMaterial>>()
+               .insert_resource(weapon_stats)
+               .add_systems(Update, apply_enemy_damage);
+            app
+        }
+
+        #[test]
+        fn test_enemies_take_no_damage_while_spawning_in() {
+            let mut app = damage_app(combat::WeaponStats::default());
+
+            let spawning = app.world.spawn(enemy_bundle(Vec3::ZERO, Color::RED, EnemyKind::Grunt)).id();
+            let active = app.world.spawn(enemy_bundle(Vec3::ZERO, Color::RED, EnemyKind::Grunt)).remove::<Spawning>().id();
+            for entity in [spawning, active] {
+                app.world.send_event(EnemyDamaged { entity, amount: 5.0, shred: 0, split: None });
             }
+            app.update();
+
+            let health = |entity| app.world.get::<combat::Health>(entity).unwrap().current;
+            assert_eq!(health(spawning), ENEMY_HEALTH);
+            assert_eq!(health(active), ENEMY_HEALTH - 5.0);
+        }
+
+        #[test]
+        fn test_shred_stacks_strip_armor_up_to_the_cap() {
+            let mut app = damage_app(combat::WeaponStats::default());
+
+            let elite = app
+                .world
+                .spawn((champion_bundle(Vec3::ZERO, Color::RED, 1.0, 100.0, EnemyKind::Elite), Armor(0.4), ArmorShred::default()))
+                .remove::<Spawning>()
+                .id();
+            let mut hit = |shred| {
+                app.world.send_event(EnemyDamaged { entity: elite, amount: 10.0, shred, split: None });
+                app.update();
+                app.world.get::<combat::Health>(elite).unwrap().current
+            };
+
+            // The shredding hit itself still meets the full armor.
+            assert!((hit(3) - 94.0).abs() < 1e-3);
+            assert!((hit(3) - 85.0).abs() < 1e-3);
+            // Five stacks strip the 0.4 with room to spare.
+            assert!((hit(0) - 75.0).abs() < 1e-3);
+            assert_eq!(app.world.get::<ArmorShred>(elite).unwrap().stacks, MAX_SHRED_STACKS);
+        }
+
+        #[test]
+        fn test_only_a_hit_that_kills_through_armor_carries_its_split() {
+            let mut app = damage_app(combat::WeaponStats::default());
+
+            let armored = app
+                .world
+                .spawn((enemy_bundle(Vec3::ZERO, Color::RED, EnemyKind::Grunt), Armor(0.5), ArmorShred::default()))
+                .remove::<Spawning>()
+                .id();
+            let split = gems::SplitShot { weapon: combat::Weapon::Gun, direction: Vec3::X, gems: 1 };
+            let mut hit = |amount| {
+                app.world.send_event(EnemyDamaged { entity: armored, amount, shred: 0, split: Some(split) });
+                app.update();
+                app.world.resource_mut::<Events<EnemyDied>>().drain().map(|died| died.split).collect::<Vec<_>>()
+            };
+
+            // Enough to kill a bare grunt, but the armor halves it.
+            assert_eq!(hit(ENEMY_HEALTH), vec![]);
+            assert_eq!(hit(ENEMY_HEALTH), vec![Some(split)]);
+        }
+
+        #[test]
+        fn test_overkill_carries_to_the_nearest_enemy_in_range() {
+            let mut app = damage_app(combat::WeaponStats { overkill: true, ..default() });
+
+            let mut grunt = |x| {
+                app.world.spawn(enemy_bundle(Vec3::new(x, 0.0, 0.0), Color::RED, EnemyKind::Grunt)).remove::<Spawning>().id()
+            };
+            let (killed, near, nearer, far) = (grunt(0.0), grunt(100.0), grunt(60.0), grunt(-OVERKILL_RADIUS - 1.0));
+            // Enough to kill the first, then the nearer one, leaving 5 for the one behind it.
+            app.world.send_event(EnemyDamaged { entity: killed, amount: 25.0, shred: 0, split: None });
+            app.update();
+
+            let health = |entity| app.world.get::<combat::Health>(entity).unwrap().current;
+            assert!(health(killed) <= 0.0 && health(nearer) <= 0.0);
+            assert_eq!(health(near), ENEMY_HEALTH - 5.0);
+            assert_eq!(health(far), ENEMY_HEALTH);
+            assert_eq!(app.world.resource::<Events<EnemyDied>>().len(), 2);
         }
     }
 }
 
 mod combat {
     use super::*;
+    use bevy::{time::common_conditions::on_timer, utils::HashMap};
     use std::time::Duration;
 
     pub struct CombatPlugin;
@@ -300,26 +1781,107 @@ mod combat {
     #[derive(Component)]
     pub struct BladeOrbit;
 
+    #[derive(Component, Debug)]
+    pub struct Health {
+        pub current: f32,
+        pub max: f32,
+    }
+
+    impl Health {
+        pub fn new(max: f32) -> Self {
+            Self { current: max, max }
+        }
+    }
+
     impl Plugin for CombatPlugin {
         fn build(&self, app: &mut App) {
             app.insert_resource(WeaponStats::default())
+                .init_resource::<ProjectileCap>()
+                .init_resource::<collision::EnemyGrid>()
+                .add_event::<ChainHit>()
                 .insert_resource(FireRateTimer(Timer::from_seconds(
-                    0.5,
+                    BASE_FIRE_COOLDOWN,
                     TimerMode::Repeating,
                 )))
+                .configure_sets(
+                    Update,
+                    (
+                        CombatSet::Targeting,
+                        CombatSet::Fire,
+                        CombatSet::Move,
+                        CombatSet::Collide,
+                        CombatSet::ApplyDamage,
+                        CombatSet::Death,
+                        CombatSet::Drops,
+                    )
+                        .chain(),
+                )
+                .configure_sets(Update, input::InputSet.before(CombatSet::Targeting))
                 .add_systems(
                     Update,
                     (
-                        fire_projectiles,
-                        move_projectiles,
-                        projectile_collision,
-                        rotate_orbiting_blades,
-                        orbiting_blade_collision,
-                        update_blade_count,
-                        spawn_initial_blades,
+                        (fire_projectiles, enforce_projectile_caps, update_blade_count, spawn_initial_blades)
+                            .chain()
+                            .in_set(CombatSet::Fire),
+                        ((extend_range, move_projectiles).chain(), rotate_orbiting_blades).in_set(CombatSet::Move),
+                        (projectile_collision, enemy_projectile_collision).in_set(CombatSet::Collide),
+                        split_on_kill.in_set(CombatSet::Death),
+                        fade_graze_sparks,
+                        orbiting_blade_collision
+                            .in_set(CombatSet::Collide)
+                            .run_if(on_timer(Duration::from_secs_f32(1.0 / BLADE_HIT_RATE))),
+                        chain_hits
+                            .in_set(CombatSet::Collide)
+                            .after(projectile_collision)
+                            .after(orbiting_blade_collision),
+                        collision::build_enemy_grid
+                            .after(CombatSet::Move)
+                            .before(CombatSet::Collide),
                     )
-                        .run_if(in_state(GameState::Running)),
-                );
+                        .run_if(in_play),
+                )
+                .add_systems(Last, despawn_marked)
+                .add_systems(ResetRun, reset_combat);
+        }
+    }
+
+    /// The stages a frame of combat runs in, in order. Each stage only reads what the
+    /// stages before it wrote this frame:
+    ///
+    /// - `Targeting`: the flow field and taunts decide where enemies are heading.
+    /// - `Fire`: weapons spawn projectiles and hitscan attacks; hitscan weapons send
+    ///   `EnemyDamaged` straight away. Triggered weapons react to last frame's kills,
+    ///   dashes and hurts here.
+    /// - `Move`: the player, enemies, projectiles and orbiting blades move.
+    /// - `Collide`: overlaps at the new positions send `EnemyDamaged` and hurt the player.
+    ///   `collision::EnemyGrid` is rebuilt just before this stage.
+    /// - `ApplyDamage`: every `EnemyDamaged` is applied; lethal hits send `EnemyDied` and
+    ///   mark the enemy for death.
+    /// - `Death`: kills are counted, last stand arms and the player's death ends the run.
+    /// - `Drops`: gems and decals spawn where enemies died, and gems are collected.
+    ///
+    /// Marked entities are despawned in `Last`, after every stage has seen them.
+    #[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum CombatSet {
+        Targeting,
+        Fire,
+        Move,
+        Collide,
+        ApplyDamage,
+        Death,
+        Drops,
+    }
+
+    /// Removes the entity at the end of the frame. Gameplay systems insert this instead
+    /// of despawning directly, so an entity two systems finish off in the same frame (a
+    /// projectile hitting two things, a gem picked up twice) is only despawned once. Queries
+    /// that could act on an entity twice filter it out with `Without<MarkedForDeath>`.
+    #[derive(Component)]
+    pub struct MarkedForDeath;
+
+    fn despawn_marked(mut commands: Commands, query: Query<Entity, With<MarkedForDeath>>) {
+        for entity in query.iter() {
+            commands.entity(entity).despawn_recursive();
         }
     }
 
@@ -328,9 +1890,10 @@ mod combat {
         pub multishot: u32,
         pub chain_lightning: u32,
         pub blade_count: u32,
-        pub fire_rate: f32,
         pub shotgun_count: u32, // New weapon
         pub homing_count: u32,  // New weapon
+        /// Killing blows carry their excess damage to the next enemy over.
+        pub overkill: bool,
     }
 
     impl Default for WeaponStats {
@@ -339,38 +1902,196 @@ mod combat {
                 multishot: 1,
                 chain_lightning: 0,
                 blade_count: 3,
-                fire_rate: 0.5,
                 shotgun_count: 0,
                 homing_count: 0,
+                overkill: false,
             }
         }
     }
 
     #[derive(Component)]
-    struct Projectile {
+    pub struct Projectile {
         direction: Vec3,
         speed: f32,
+        radius: f32,
         ttl: Timer,
         homing: bool,
     }
 
-    #[derive(Component)]
-    pub struct OrbitingBlade;
+    impl Projectile {
+        pub fn radius(&self) -> f32 {
+            self.radius
+        }
 
-    #[derive(Resource)]
-    struct FireRateTimer(Timer);
+        /// Turns the projectile around toward `target`, or straight back if there is none,
+        /// with a fresh lifetime for the return trip.
+        pub fn reflect(&mut self, from: Vec3, target: Option<Vec3>) {
+            self.direction = target
+                .map(|target| (target - from).truncate().normalize_or_zero().extend(0.0))
+                .filter(|direction| *direction != Vec3::ZERO)
+                .unwrap_or(-self.direction);
+            self.ttl.reset();
+        }
 
-    fn fire_projectiles(
-        mut commands: Commands,
-        time: Res<Time>,
-        mut timer: ResMut<FireRateTimer>,
-        weapon_stats: Res<WeaponStats>,
-        player_query: Query<&Transform, With<player::Player>>,
+        /// Bends the flight path toward `direction` by `amount`, keeping the speed.
+        pub fn turn_toward(&mut self, direction: Vec3, amount: f32) {
+            self.direction = (self.direction + direction * amount).normalize_or_zero();
+        }
+    }
+
+    /// How hard an orbiting projectile is pulled back onto its circle, per pixel off it.
+    const ORBIT_PULL: f32 = 10.0;
+
+    /// The path a projectile flies along, set from `Weapon::motion` when it's fired.
+    #[derive(Component, Clone, Copy, Debug, PartialEq)]
+    pub enum Motion {
+        Straight,
+        /// Weaves up to `amplitude` either side of its heading, `frequency` times a second.
+        Sine { amplitude: f32, frequency: f32 },
+        /// Turns at `turn_rate` radians a second, easing off as it ages so the curl
+        /// opens out into a spiral.
+        Spiral { turn_rate: f32 },
+        /// Circles the player at `radius` for `duration` seconds, then flies off along
+        /// the tangent it was on when let go.
+        OrbitThenRelease { radius: f32, duration: f32 },
+    }
+
+    impl Motion {
+        /// Whether the motion still has hold of the projectile, so it shouldn't home yet.
+        fn holding(&self, age: f32) -> bool {
+            matches!(*self, Motion::OrbitThenRelease { duration, .. } if age < duration)
+        }
+
+        /// Velocity for this frame of a projectile `age` seconds into its flight and
+        /// `from_player` away from the player. Curving motions turn `direction` as they go.
+        fn velocity(&self, direction: &mut Vec3, speed: f32, age: f32, delta: f32, from_player: Vec3) -> Vec3 {
+            match *self {
+                Motion::Straight => *direction * speed,
+                Motion::Sine { amplitude, frequency } => {
+                    // The derivative of `amplitude * sin`, so the sideways offset stays in bounds.
+                    let angular = std::f32::consts::TAU * frequency;
+                    let sideways = Vec3::new(-direction.y, direction.x, 0.0);
+                    *direction * speed + sideways * amplitude * angular * (angular * age).cos()
+                }
+                Motion::Spiral { turn_rate } => {
+                    *direction = Quat::from_rotation_z(turn_rate * delta / (1.0 + age)).mul_vec3(*direction);
+                    *direction * speed
+                }
+                Motion::OrbitThenRelease { radius, duration } => {
+                    if age >= duration {
+                        return *direction * speed;
+                    }
+                    let outward = from_player.truncate().try_normalize().unwrap_or(direction.truncate());
+                    *direction = outward.perp().extend(0.0);
+                    let correction = (radius - from_player.truncate().length()) * ORBIT_PULL;
+                    *direction * speed + outward.extend(0.0) * correction
+                }
+            }
+        }
+    }
+
+    /// What fired a projectile, so each source gets its own share of the projectile budget.
+    #[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub enum Weapon {
+        Gun,
+        Shotgun,
+        Homing,
+        Turret,
+        Spitter,
+    }
+
+    impl Weapon {
+        /// Most live projectiles this source may have before the oldest are recycled.
+        fn cap(&self) -> usize {
+            match self {
+                Weapon::Gun => 800,
+                Weapon::Shotgun => 600,
+                Weapon::Homing => 200,
+                Weapon::Turret => 400,
+                Weapon::Spitter => 300,
+            }
+        }
+
+        /// Seconds a shot flies before it fizzles, before the player's Range stat.
+        /// The shotgun's pellets are meant to fall off early.
+        fn lifetime(&self) -> f32 {
+            match self {
+                Weapon::Gun | Weapon::Turret => 2.0,
+                Weapon::Shotgun => 0.8,
+                Weapon::Homing => 3.0,
+                Weapon::Spitter => 4.0,
+            }
+        }
+
+        /// Armor shred stacks each hit leaves. Pellets land in bunches, so the shotgun is the
+        /// projectile weapon that strips armor.
+        pub fn shred(&self) -> u32 {
+            match self {
+                Weapon::Shotgun => 1,
+                Weapon::Gun | Weapon::Turret | Weapon::Homing | Weapon::Spitter => 0,
+            }
+        }
+
+        /// Pellets wobble, and missiles circle the player once before going after targets.
+        pub fn motion(&self) -> Motion {
+            match self {
+                Weapon::Shotgun => Motion::Sine { amplitude: 10.0, frequency: 5.0 },
+                Weapon::Homing => Motion::OrbitThenRelease { radius: 60.0, duration: 0.5 },
+                Weapon::Gun | Weapon::Turret | Weapon::Spitter => Motion::Straight,
+            }
+        }
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                Weapon::Gun => "Gun",
+                Weapon::Shotgun => "Shotgun",
+                Weapon::Homing => "Homing Missiles",
+                Weapon::Turret => "Turret",
+                Weapon::Spitter => "Spitter",
+            }
+        }
+    }
+
+    /// Seconds left on the HUD's "capped" warning; topped up every frame a cap is hit.
+    #[derive(Resource, Default)]
+    pub struct ProjectileCap {
+        warning: f32,
+    }
+
+    impl ProjectileCap {
+        pub fn is_capped(&self) -> bool {
+            self.warning > 0.0
+        }
+    }
+
+    /// Which side a projectile hurts. Enemy shots remember who fired them so they
+    /// can be sent back; flipping a shot to `Player` makes it hit enemies instead.
+    #[derive(Component, Clone, Copy, Debug, PartialEq)]
+    pub enum Team {
+        Player,
+        /// `source` is the shooter's kind, kept so the shot can be blamed after the
+        /// shooter dies.
+        Enemy { shooter: Entity, source: enemy::EnemyKind },
+    }
+
+    #[derive(Component)]
+    pub struct OrbitingBlade;
+
+    #[derive(Resource)]
+    struct FireRateTimer(Timer);
+
+    fn fire_projectiles(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut timer: ResMut<FireRateTimer>,
+        weapon_stats: Res<WeaponStats>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        mut player_query: Query<(&Transform, &mut player::Recoil), With<player::Player>>,
         enemy_query: Query<&Transform, With<enemy::Enemy>>,
     ) {
-        timer.0.set_duration(Duration::from_secs_f32(weapon_stats.fire_rate));
+        timer.0.set_duration(Duration::from_secs_f32(effective_stats.fire_cooldown));
         if timer.0.tick(time.delta()).just_finished() {
-            if let Ok(player_transform) = player_query.get_single() {
+            if let Ok((player_transform, mut recoil)) = player_query.get_single_mut() {
                 let mut closest_enemy: Option<Vec3> = None;
                 let mut min_dist = f32::MAX;
 
@@ -385,35 +2106,24 @@ mod combat {
                 }
 
                 let target_dir = if let Some(target_pos) = closest_enemy {
-                    (target_pos - player_transform.translation).normalize_or_zero()
+                    (target_pos - player_transform.translation).truncate().normalize_or_zero().extend(0.0)
                 } else {
                     // Default fire direction if no enemies? Or just don't fire.
                     // Let's fire right if no enemies, or random.
                     Vec3::X
                 };
 
+                recoil.kick(-target_dir.truncate() * RECOIL_KICK);
+
                 // Base Multishot
+                let spread = effective_stats.spread;
+                let jitter = spread * PROJECTILE_JITTER;
                 for i in 0..weapon_stats.multishot {
-                    let angle_offset = (i as f32 - (weapon_stats.multishot - 1) as f32 / 2.0) * 0.15;
+                    let angle_offset = (i as f32 - (weapon_stats.multishot - 1) as f32 / 2.0) * spread
+                        + rand::thread_rng().gen_range(-jitter..=jitter);
                     let rotated_direction = Quat::from_rotation_z(angle_offset).mul_vec3(target_dir);
 
-                    commands.spawn((
-                        SpriteBundle {
-                            sprite: Sprite {
-                                color: Color::rgb(0.9, 0.9, 0.1),
-                                custom_size: Some(Vec2::new(10.0, 10.0)),
-                                ..default()
-                            },
-                            transform: Transform::from_translation(player_transform.translation),
-                            ..default()
-                        },
-                        Projectile {
-                            direction: rotated_direction,
-                            speed: 800.0,
-                            ttl: Timer::from_seconds(2.0, TimerMode::Once),
-                            homing: false,
-                        },
-                    ));
+                    commands.spawn(bullet_bundle(player_transform.translation, rotated_direction, Weapon::Gun));
                 }
 
                 // Shotgun
@@ -426,19 +2136,25 @@ mod combat {
                         commands.spawn((
                             SpriteBundle {
                                 sprite: Sprite {
-                                    color: Color::rgb(1.0, 0.5, 0.0),
+                                    color: Color::rgb(2.0, 1.0, 0.0),
                                     custom_size: Some(Vec2::new(8.0, 8.0)),
                                     ..default()
                                 },
-                                transform: Transform::from_translation(player_transform.translation),
+                                transform: Transform::from_translation(render_layer::RenderLayer::Projectiles.at(player_transform.translation)),
                                 ..default()
                             },
                             Projectile {
                                 direction: rotated_direction,
                                 speed: 700.0 + rand::thread_rng().gen_range(-50.0..50.0),
-                                ttl: Timer::from_seconds(0.8, TimerMode::Once), // Shorter range
+                                radius: 4.0,
+                                ttl: Timer::from_seconds(Weapon::Shotgun.lifetime(), TimerMode::Once),
                                 homing: false,
                             },
+                            Weapon::Shotgun.motion(),
+                            collision::PreviousPosition::default(),
+                            Team::Player,
+                            Weapon::Shotgun,
+                            DespawnOnExit::RUN,
                         ));
                     }
                 }
@@ -451,33 +2167,117 @@ mod combat {
                      commands.spawn((
                         SpriteBundle {
                             sprite: Sprite {
-                                color: Color::rgb(0.5, 0.2, 1.0),
+                                color: Color::rgb(1.0, 0.4, 2.0),
                                 custom_size: Some(Vec2::new(12.0, 12.0)),
                                 ..default()
                             },
-                            transform: Transform::from_translation(player_transform.translation),
+                            transform: Transform::from_translation(render_layer::RenderLayer::Projectiles.at(player_transform.translation)),
                             ..default()
                         },
                         Projectile {
                             direction: rotated_direction, // Start moving in random direction
                             speed: 400.0, // Slower but homing
-                            ttl: Timer::from_seconds(3.0, TimerMode::Once),
+                            radius: 6.0,
+                            ttl: Timer::from_seconds(Weapon::Homing.lifetime(), TimerMode::Once),
                             homing: true,
                         },
+                        Weapon::Homing.motion(),
+                        collision::PreviousPosition::default(),
+                        Team::Player,
+                        Weapon::Homing,
+                        DespawnOnExit::RUN,
                     ));
                 }
             }
         }
     }
 
+    /// The main gun's bullet, also fired by anything else that shoots on the player's behalf.
+    pub fn bullet_bundle(origin: Vec3, direction: Vec3, weapon: Weapon) -> impl Bundle {
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(2.0, 2.0, 0.2),
+                    custom_size: Some(Vec2::new(10.0, 10.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Projectiles.at(origin)),
+                ..default()
+            },
+            Projectile {
+                direction,
+                speed: 800.0,
+                radius: 5.0,
+                ttl: Timer::from_seconds(weapon.lifetime(), TimerMode::Once),
+                homing: false,
+            },
+            weapon.motion(),
+            collision::PreviousPosition::default(),
+            Team::Player,
+            weapon,
+            DespawnOnExit::RUN,
+        )
+    }
+
+    pub fn enemy_bullet_bundle(
+        origin: Vec3,
+        direction: Vec3,
+        speed: f32,
+        shooter: Entity,
+        source: enemy::EnemyKind,
+    ) -> impl Bundle {
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1.8, 0.3, 1.2),
+                    custom_size: Some(Vec2::new(9.0, 9.0)),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Projectiles.at(origin)),
+                ..default()
+            },
+            Projectile {
+                direction,
+                speed,
+                radius: 4.5,
+                ttl: Timer::from_seconds(Weapon::Spitter.lifetime(), TimerMode::Once),
+                homing: false,
+            },
+            Weapon::Spitter.motion(),
+            collision::PreviousPosition::default(),
+            Team::Enemy { shooter, source },
+            Weapon::Spitter,
+            DespawnOnExit::RUN,
+        )
+    }
+
+    /// Stretches the lifetime of the player's new shots by their Range stat, so they fly further.
+    fn extend_range(
+        mut query: Query<(&mut Projectile, &Team), Added<Projectile>>,
+        effective_stats: Res<attributes::EffectiveStats>,
+    ) {
+        for (mut projectile, team) in query.iter_mut() {
+            if *team == Team::Player {
+                let lifetime = projectile.ttl.duration().mul_f32(effective_stats.range);
+                projectile.ttl.set_duration(lifetime);
+            }
+        }
+    }
+
     fn move_projectiles(
         mut commands: Commands,
-        mut query: Query<(Entity, &mut Transform, &mut Projectile)>,
+        mut query: Query<
+            (Entity, &mut Transform, &mut Projectile, &Motion, &mut collision::PreviousPosition),
+            Without<MarkedForDeath>,
+        >,
         enemy_query: Query<&Transform, (With<enemy::Enemy>, Without<Projectile>)>,
+        player_query: Query<&Transform, (With<player::Player>, Without<Projectile>)>,
         time: Res<Time>,
     ) {
-        for (entity, mut transform, mut projectile) in query.iter_mut() {
-            if projectile.homing {
+        let player = player_query.get_single().map_or(Vec3::ZERO, |transform| transform.translation);
+        for (entity, mut transform, mut projectile, motion, mut previous) in query.iter_mut() {
+            let age = projectile.ttl.elapsed_secs();
+            if projectile.homing && !motion.holding(age) {
                 // Find closest enemy
                 let mut closest_enemy: Option<Vec3> = None;
                 let mut min_dist = 500.0; // Homing range
@@ -491,73 +2291,339 @@ mod combat {
                 }
 
                 if let Some(target_pos) = closest_enemy {
-                    let direction_to_target = (target_pos - transform.translation).normalize_or_zero();
+                    let direction_to_target = (target_pos - transform.translation).truncate().normalize_or_zero().extend(0.0);
                     // Steer towards target
                     let steer_strength = 5.0;
                     projectile.direction = (projectile.direction + direction_to_target * steer_strength * time.delta_seconds()).normalize_or_zero();
                 }
             }
 
-            transform.translation += projectile.direction * projectile.speed * time.delta_seconds();
+            previous.0 = Some(transform.translation);
+            let delta = time.delta_seconds();
+            let from_player = transform.translation - player;
+            let speed = projectile.speed;
+            let velocity = motion.velocity(&mut projectile.direction, speed, age, delta, from_player);
+            transform.translation += velocity * delta;
             if projectile.ttl.tick(time.delta()).finished() {
-                commands.entity(entity).despawn();
+                commands.entity(entity).try_insert(MarkedForDeath);
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
     fn projectile_collision(
         mut commands: Commands,
-        projectile_query: Query<(Entity, &Transform), With<Projectile>>,
-        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
-        mut xp_events: EventWriter<leveling::XpDropEvent>,
+        mut projectile_query: Query<
+            (
+                Entity,
+                &Transform,
+                &Projectile,
+                &collision::PreviousPosition,
+                &Team,
+                &Weapon,
+                Option<&mut gems::Pierced>,
+                Has<gems::SplitShard>,
+            ),
+            Without<MarkedForDeath>,
+        >,
+        enemy_query: Query<
+            (Entity, &Transform),
+            (With<enemy::Enemy>, Without<enemy::Spawning>, Without<MarkedForDeath>),
+        >,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+        mut chain_events: EventWriter<ChainHit>,
         weapon_stats: Res<WeaponStats>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        gem_pouch: Res<gems::GemPouch>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
     ) {
-        for (proj_entity, proj_transform) in projectile_query.iter() {
-            for (enemy_entity, enemy_transform) in enemy_query.iter() {
-                if proj_transform
-                    .translation
-                    .distance(enemy_transform.translation)
-                    < (ENEMY_SIZE / 2.0)
-                {
-                    commands.entity(proj_entity).despawn();
-                    commands.entity(enemy_entity).despawn();
-                    xp_events.send(leveling::XpDropEvent(enemy_transform.translation));
-
-                    // Chain lightning
-                    if weapon_stats.chain_lightning > 0 {
-                        let mut chained_targets = vec![enemy_entity];
-                        let mut last_pos = enemy_transform.translation;
-
-                        for _ in 0..weapon_stats.chain_lightning {
-                            let mut closest_new_target: Option<(Entity, Vec3)> = None;
-                            let mut min_dist = 300.0; // Max chain distance
-
-                            for (next_enemy_entity, next_enemy_transform) in enemy_query.iter() {
-                                if !chained_targets.contains(&next_enemy_entity) {
-                                    let dist = last_pos.distance(next_enemy_transform.translation);
-                                    if dist < min_dist {
-                                        min_dist = dist;
-                                        closest_new_target = Some((next_enemy_entity, next_enemy_transform.translation));
-                                    }
-                                }
-                            }
+        let damage = effective_stats.damage * damage_multiplier(&last_stand_query);
+        for (proj_entity, proj_transform, projectile, previous, team, weapon, mut pierced, is_shard) in
+            projectile_query.iter_mut()
+        {
+            if *team != Team::Player {
+                continue;
+            }
+            let (start, end) = previous.segment_to(proj_transform.translation);
+            // The first enemy along this frame's path takes the hit, not whichever the query yields first.
+            let first_hit = enemy_query
+                .iter()
+                .filter(|(enemy_entity, ..)| pierced.as_ref().is_none_or(|pierced| !pierced.0.contains(enemy_entity)))
+                .filter_map(|(enemy_entity, enemy_transform)| {
+                    collision::segment_circle_entry(
+                        start,
+                        end,
+                        enemy_transform.translation,
+                        enemy::radius(enemy_transform) + projectile.radius,
+                    )
+                    .map(|t| (t, enemy_entity, enemy_transform))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
 
-                            if let Some((target_entity, target_pos)) = closest_new_target {
-                                commands.entity(target_entity).despawn();
-                                xp_events.send(leveling::XpDropEvent(target_pos));
-                                chained_targets.push(target_entity);
-                                last_pos = target_pos;
-                            } else {
-                                break;
-                            }
-                        }
+            if let Some((_, enemy_entity, enemy_transform)) = first_hit {
+                let pierce = gem_pouch.count(*weapon, gems::ModifierGem::Pierce);
+                let hits = match pierced.as_mut() {
+                    Some(pierced) => {
+                        pierced.0.push(enemy_entity);
+                        pierced.0.len()
+                    }
+                    None => {
+                        commands.entity(proj_entity).try_insert(gems::Pierced(vec![enemy_entity]));
+                        1
                     }
-                    return; 
+                };
+                if hits > pierce {
+                    commands.entity(proj_entity).try_insert(MarkedForDeath);
+                }
+                let split_gems = gem_pouch.count(*weapon, gems::ModifierGem::SplitOnKill);
+                damage_events.send(enemy::EnemyDamaged {
+                    entity: enemy_entity,
+                    amount: damage,
+                    shred: weapon.shred(),
+                    split: (!is_shard && split_gems > 0).then_some(gems::SplitShot {
+                        weapon: *weapon,
+                        direction: projectile.direction,
+                        gems: split_gems,
+                    }),
+                });
+
+                if weapon_stats.chain_lightning > 0 {
+                    chain_events.send(ChainHit {
+                        first: enemy_entity,
+                        position: enemy_transform.translation,
+                        damage,
+                        chain: ChainBehavior::lightning(weapon_stats.chain_lightning),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Bursts killing shots with Split on Kill gems into shards where the enemy died.
+    fn split_on_kill(mut commands: Commands, mut died_events: EventReader<enemy::EnemyDied>) {
+        for event in died_events.read() {
+            let Some(split) = event.split else {
+                continue;
+            };
+            for shard in gems::split_directions(split.direction, split.gems) {
+                // Shards curl away from the kill whatever fired them.
+                commands
+                    .spawn((
+                        bullet_bundle(event.position, shard, split.weapon),
+                        gems::SplitShard,
+                        gems::Pierced(vec![event.entity]),
+                    ))
+                    .insert(Motion::Spiral { turn_rate: 4.0 });
+            }
+        }
+    }
+
+    /// Hops from a hit enemy to the nearest one not yet hit, up to `jumps` times, each
+    /// within `range` of the last and dealing `falloff` times the previous hop's damage.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ChainBehavior {
+        pub jumps: u32,
+        pub range: f32,
+        pub falloff: f32,
+    }
+
+    impl ChainBehavior {
+        /// Chain lightning: one jump per level at full damage.
+        pub fn lightning(level: u32) -> Self {
+            Self { jumps: level, range: 300.0, falloff: 1.0 }
+        }
+
+        /// The enemies the chain jumps to after hitting `first`, with where they are and
+        /// the damage each takes.
+        pub fn hops(
+            &self,
+            first: (Entity, Vec3),
+            damage: f32,
+            candidates: &[(Entity, Vec3)],
+        ) -> Vec<(Entity, Vec3, f32)> {
+            let mut hit = vec![first.0];
+            let mut hops = Vec::new();
+            let (mut last_position, mut damage) = (first.1, damage);
+            for _ in 0..self.jumps {
+                let next = candidates
+                    .iter()
+                    .filter(|(entity, _)| !hit.contains(entity))
+                    .map(|&(entity, position)| (entity, position, last_position.distance(position)))
+                    .filter(|&(.., distance)| distance < self.range)
+                    .min_by(|a, b| a.2.total_cmp(&b.2));
+                let Some((entity, position, _)) = next else {
+                    break;
+                };
+                damage *= self.falloff;
+                hit.push(entity);
+                hops.push((entity, position, damage));
+                last_position = position;
+            }
+            hops
+        }
+    }
+
+    /// A hit that carries on to nearby enemies. Any weapon can send one; `first` has
+    /// already taken its own damage.
+    #[derive(Event, Clone, Copy, Debug)]
+    pub struct ChainHit {
+        pub first: Entity,
+        pub position: Vec3,
+        pub damage: f32,
+        pub chain: ChainBehavior,
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn chain_hits(
+        mut chain_events: EventReader<ChainHit>,
+        enemy_query: Query<(Entity, &Transform), (With<enemy::Enemy>, Without<MarkedForDeath>)>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+    ) {
+        if chain_events.is_empty() {
+            return;
+        }
+        let candidates = enemy_query
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation))
+            .collect::<Vec<_>>();
+        for hit in chain_events.read() {
+            for (entity, _, amount) in hit.chain.hops((hit.first, hit.position), hit.damage, &candidates) {
+                damage_events.send(enemy::EnemyDamaged { entity, amount, shred: 0, split: None });
+            }
+        }
+    }
+
+    fn enforce_projectile_caps(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut cap: ResMut<ProjectileCap>,
+        query: Query<(Entity, &Projectile, &Weapon), Without<MarkedForDeath>>,
+    ) {
+        cap.warning = (cap.warning - time.delta_seconds()).max(0.0);
+        let mut by_weapon = HashMap::<Weapon, Vec<(Duration, Entity)>>::new();
+        for (entity, projectile, weapon) in query.iter() {
+            by_weapon.entry(*weapon).or_default().push((projectile.ttl.elapsed(), entity));
+        }
+        for (weapon, mut projectiles) in by_weapon {
+            let excess = projectiles.len().saturating_sub(weapon.cap());
+            if excess == 0 {
+                continue;
+            }
+            // Oldest first: the ones that have been flying longest are closest to expiring anyway.
+            projectiles.select_nth_unstable_by(excess - 1, |a, b| b.0.cmp(&a.0));
+            for (_, entity) in &projectiles[..excess] {
+                commands.entity(*entity).try_insert(MarkedForDeath);
+            }
+            cap.warning = 1.0;
+        }
+    }
+
+    /// Enemy shots passing this close to the player's hurtbox without hitting count as a graze.
+    const GRAZE_MARGIN: f32 = 18.0;
+    const GRAZE_XP: u32 = 2;
+    const GRAZE_SPARK_LIFETIME: f32 = 0.25;
+
+    /// An enemy shot that has come within grazing distance of the player. It pays out
+    /// once it has left again without hitting, and never twice.
+    #[derive(Component, Clone, Copy, PartialEq)]
+    enum Graze {
+        Close,
+        Counted,
+    }
+
+    #[derive(Component)]
+    struct GrazeSpark(Timer);
+
+    #[allow(clippy::type_complexity)]
+    fn enemy_projectile_collision(
+        mut commands: Commands,
+        projectile_query: Query<
+            (Entity, &Transform, &Projectile, &collision::PreviousPosition, &Team, Option<&Graze>),
+            Without<MarkedForDeath>,
+        >,
+        mut player_query: Query<
+            (&Transform, &mut Health, &player::Hurtbox),
+            (With<player::Player>, Without<Projectile>, Without<buffs::Invulnerable>),
+        >,
+        mut hurt_events: EventWriter<player::PlayerHurt>,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+    ) {
+        let Ok((player_transform, mut health, hurtbox)) = player_query.get_single_mut() else {
+            return;
+        };
+        for (entity, transform, projectile, previous, team, graze) in projectile_query.iter() {
+            let Team::Enemy { source, .. } = *team else {
+                continue;
+            };
+            let (start, end) = previous.segment_to(transform.translation);
+            let hit_radius = hurtbox.radius + projectile.radius;
+            if collision::segment_hits_circle(start, end, player_transform.translation, hit_radius) {
+                commands.entity(entity).try_insert(MarkedForDeath);
+                health.current -= ENEMY_PROJECTILE_DAMAGE;
+                hurt_events.send(player::PlayerHurt {
+                    amount: ENEMY_PROJECTILE_DAMAGE,
+                    source,
+                    kind: player::DamageKind::Projectile,
+                });
+                continue;
+            }
+
+            let close = collision::segment_hits_circle(
+                start,
+                end,
+                player_transform.translation,
+                hit_radius + GRAZE_MARGIN,
+            );
+            match (close, graze) {
+                (true, None) => {
+                    commands.entity(entity).try_insert(Graze::Close);
+                }
+                (false, Some(Graze::Close)) => {
+                    commands.entity(entity).try_insert(Graze::Counted);
+                    player_stats.xp += GRAZE_XP;
+                    let toward_shot = (transform.translation - player_transform.translation).truncate();
+                    let spark_at = player_transform.translation.truncate()
+                        + toward_shot.normalize_or_zero() * PLAYER_SIZE / 2.0;
+                    commands.spawn((
+                        SpriteBundle {
+                            sprite: Sprite {
+                                color: Color::rgb(2.5, 2.5, 1.5),
+                                custom_size: Some(Vec2::splat(6.0)),
+                                ..default()
+                            },
+                            transform: Transform::from_translation(spark_at.extend(render_layer::RenderLayer::Vfx.z())),
+                            ..default()
+                        },
+                        GrazeSpark(Timer::from_seconds(GRAZE_SPARK_LIFETIME, TimerMode::Once)),
+                        DespawnOnExit::RUN,
+                    ));
                 }
+                _ => {}
+            }
+        }
+    }
+
+    fn fade_graze_sparks(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut GrazeSpark, &mut Sprite, &mut Transform)>,
+    ) {
+        for (entity, mut spark, mut sprite, mut transform) in query.iter_mut() {
+            spark.0.tick(time.delta());
+            sprite.color.set_a(spark.0.fraction_remaining());
+            transform.scale = Vec3::splat(1.0 + spark.0.fraction() * 2.0);
+            if spark.0.finished() {
+                commands.entity(entity).despawn();
             }
         }
     }
 
+    pub fn damage_multiplier(last_stand_query: &Query<&last_stand::LastStand, With<player::Player>>) -> f32 {
+        last_stand_query
+            .get_single()
+            .map_or(1.0, |last_stand| last_stand.damage_multiplier())
+    }
+
     fn spawn_initial_blades(
         mut commands: Commands,
         orbit_query: Query<Entity, Added<BladeOrbit>>,
@@ -582,6 +2648,7 @@ mod combat {
                             ..default()
                         },
                         OrbitingBlade,
+                        collision::PreviousPosition::default(),
                     ));
                 }
             });
@@ -597,24 +2664,42 @@ mod combat {
         }
     }
 
+    /// Blades hit this many times a second whatever the frame rate.
+    const BLADE_HIT_RATE: f32 = 30.0;
+    /// Reach past the orbit radius that still counts: half a blade plus the largest enemy.
+    const BLADE_HIT_MARGIN: f32 = 60.0;
+
     fn orbiting_blade_collision(
-        mut commands: Commands,
-        blade_query: Query<&GlobalTransform, With<OrbitingBlade>>,
-        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
-        mut xp_events: EventWriter<leveling::XpDropEvent>,
+        mut blade_query: Query<(&GlobalTransform, &mut collision::PreviousPosition), With<OrbitingBlade>>,
+        player_query: Query<&Transform, With<player::Player>>,
+        enemy_grid: Res<collision::EnemyGrid>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
         mut hit_enemies: Local<Vec<Entity>>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
     ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let damage = effective_stats.damage * damage_multiplier(&last_stand_query);
+        let candidates = enemy_grid
+            .0
+            .near(player_transform.translation, ORBITING_BLADE_RADIUS + BLADE_HIT_MARGIN)
+            .collect::<Vec<_>>();
         hit_enemies.clear();
-        for blade_global_transform in blade_query.iter() {
-            for (enemy_entity, enemy_transform) in enemy_query.iter() {
+        for (blade_global_transform, mut previous) in blade_query.iter_mut() {
+            // Sweep along the arc the blade covered since the last check, approximated by its chord.
+            let (start, end) = previous.segment_to(blade_global_transform.translation());
+            previous.0 = Some(end);
+            for &&(enemy_entity, position, radius) in candidates.iter() {
                 if hit_enemies.contains(&enemy_entity) { continue; }
-                if blade_global_transform
-                    .translation()
-                    .distance(enemy_transform.translation)
-                    < (ENEMY_SIZE / 2.0 + 15.0)
-                {
-                    commands.entity(enemy_entity).despawn();
-                    xp_events.send(leveling::XpDropEvent(enemy_transform.translation));
+                if collision::segment_hits_circle(start, end, position, radius + 15.0) {
+                    damage_events.send(enemy::EnemyDamaged {
+                        entity: enemy_entity,
+                        amount: damage,
+                        shred: 0,
+                        split: None,
+                    });
                     hit_enemies.push(enemy_entity);
                 }
             }
@@ -650,420 +2735,13947 @@ mod combat {
                                 ..default()
                             },
                             OrbitingBlade,
+                            collision::PreviousPosition::default(),
                         ));
                     }
                 });
             }
         }
     }
+
+    fn reset_combat(
+        mut weapon_stats: ResMut<WeaponStats>,
+        mut timer: ResMut<FireRateTimer>,
+        mut cap: ResMut<ProjectileCap>,
+    ) {
+        *weapon_stats = WeaponStats::default();
+        timer.0.reset();
+        *cap = ProjectileCap::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_chain_hops_to_the_nearest_new_enemy_in_range() {
+            let enemy = |index| Entity::from_raw(index);
+            let candidates = [
+                (enemy(0), Vec3::ZERO),
+                (enemy(1), Vec3::new(100.0, 0.0, 0.0)),
+                (enemy(2), Vec3::new(50.0, 0.0, 0.0)),
+                (enemy(3), Vec3::new(1000.0, 0.0, 0.0)),
+            ];
+            let chain = ChainBehavior { jumps: 5, range: 300.0, falloff: 0.5 };
+            let hops = chain.hops(candidates[0], 40.0, &candidates);
+            assert_eq!(
+                hops,
+                vec![(enemy(2), candidates[2].1, 20.0), (enemy(1), candidates[1].1, 10.0)]
+            );
+        }
+
+        /// Steps `motion` for `seconds` from the player's position, returning where the
+        /// projectile ends up and its heading.
+        fn fly(motion: Motion, seconds: f32) -> (Vec3, Vec3, f32) {
+            let (delta, speed) = (1.0 / 1000.0, 400.0);
+            let (mut position, mut direction, mut widest) = (Vec3::ZERO, Vec3::X, 0.0_f32);
+            for step in 0..(seconds / delta).round() as u32 {
+                position += motion.velocity(&mut direction, speed, step as f32 * delta, delta, position) * delta;
+                widest = widest.max(position.y.abs());
+            }
+            (position, direction, widest)
+        }
+
+        #[test]
+        fn test_motions_weave_curl_and_orbit_then_release() {
+            let (position, direction, widest) = fly(Motion::Sine { amplitude: 10.0, frequency: 5.0 }, 1.0);
+            assert!((position.x - 400.0).abs() < 1.0);
+            assert!((widest - 10.0).abs() < 0.5, "weaves {} wide", widest);
+            assert_eq!(direction, Vec3::X);
+
+            let (_, direction, _) = fly(Motion::Spiral { turn_rate: 4.0 }, 1.0);
+            assert!(direction.angle_between(Vec3::X) > 1.0);
+
+            let orbit = Motion::OrbitThenRelease { radius: 60.0, duration: 0.5 };
+            let (position, _, _) = fly(orbit, 0.4);
+            assert!((position.length() - 60.0).abs() < 5.0);
+            assert!(orbit.holding(0.4) && !orbit.holding(0.5));
+            let (released, heading, _) = fly(orbit, 0.5);
+            let (later, _, _) = fly(orbit, 1.0);
+            assert!((later - released).normalize().distance(heading) < 0.01);
+        }
+    }
 }
 
-mod leveling {
+mod patterns {
     use super::*;
+    use enemy::EnemyKind;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+    use std::f32::consts::TAU;
 
-    pub struct LevelingPlugin;
+    /// Per-kind bullet emitters. Kinds missing from the file keep their built-in emitters.
+    const EMITTERS_PATH: &str = "assets/data/emitters.ron";
+    /// How hard a homing shot turns toward the player once it wakes up, per second.
+    const HOMING_TURN_RATE: f32 = 3.0;
+    /// Homing shots stop steering after this long, so they can still be dodged.
+    const HOMING_DURATION: f32 = 1.5;
 
-    impl Plugin for LevelingPlugin {
+    pub struct PatternsPlugin;
+
+    impl Plugin for PatternsPlugin {
         fn build(&self, app: &mut App) {
-            app.add_event::<XpDropEvent>()
-                .insert_resource(PlayerStats::default())
-                .add_systems(
-                    Update,
-                    (
-                        spawn_xp_gems,
-                        collect_xp_gems,
-                        check_level_up,
-                    )
-                        .run_if(in_state(GameState::Running)),
-                );
+            app.insert_resource(EmitterTables::load()).add_systems(
+                Update,
+                (
+                    attach_emitters,
+                    fire_emitters.in_set(combat::CombatSet::Fire),
+                    steer_homing_shots.in_set(combat::CombatSet::Move),
+                )
+                    .run_if(in_play),
+            );
         }
     }
 
-    #[derive(Event)]
-    pub struct XpDropEvent(pub Vec3);
+    /// The shape of one volley.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub enum Pattern {
+        /// `count` shots evenly spaced around the shooter, wherever the player is.
+        Ring { count: u32 },
+        /// `arms` evenly spaced shots, rotated a further `turn` radians every volley.
+        Spiral { arms: u32, turn: f32 },
+        /// `count` shots fanned `spread` radians apart, centred on the player.
+        Aimed { count: u32, spread: f32 },
+        /// An aimed fan whose shots coast for `delay` seconds, then curve toward the player.
+        DelayedHoming { count: u32, spread: f32, delay: f32 },
+    }
 
-    #[derive(Component)]
-    struct XpGem;
+    impl Pattern {
+        /// Directions to fire volley number `volley` in, given the direction to the player.
+        pub fn directions(&self, aim: Vec2, volley: u32) -> Vec<Vec2> {
+            let ring = |count: u32, offset: f32| {
+                (0..count)
+                    .map(|i| Vec2::from_angle(offset + i as f32 / count as f32 * TAU))
+                    .collect()
+            };
+            let fan = |count: u32, spread: f32| {
+                (0..count)
+                    .map(|i| Vec2::from_angle((i as f32 - (count as f32 - 1.0) / 2.0) * spread).rotate(aim))
+                    .collect()
+            };
+            match *self {
+                Pattern::Ring { count } => ring(count, 0.0),
+                Pattern::Spiral { arms, turn } => ring(arms, turn * volley as f32),
+                Pattern::Aimed { count, spread } | Pattern::DelayedHoming { count, spread, .. } => {
+                    fan(count, spread)
+                }
+            }
+        }
+    }
 
-    #[derive(Resource, Debug)]
-    pub struct PlayerStats {
-        pub xp: u32,
-        pub level: u32,
-        pub xp_to_next_level: u32,
+    /// One emitter as configured in the data file.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    pub struct EmitterSpec {
+        pub pattern: Pattern,
+        /// Seconds between volleys.
+        pub interval: f32,
+        pub speed: f32,
+        /// Holds fire while the player is further away than this.
+        pub range: f32,
     }
 
-    impl Default for PlayerStats {
+    #[derive(Resource, Debug, PartialEq)]
+    pub struct EmitterTables(HashMap<EnemyKind, Vec<EmitterSpec>>);
+
+    impl Default for EmitterTables {
         fn default() -> Self {
+            let emitter = |pattern, interval, speed, range| EmitterSpec {
+                pattern,
+                interval,
+                speed,
+                range,
+            };
+            Self(HashMap::from([
+                (
+                    EnemyKind::Spitter,
+                    vec![emitter(Pattern::Aimed { count: 1, spread: 0.0 }, 2.5, 300.0, 600.0)],
+                ),
+                (
+                    EnemyKind::Elite,
+                    vec![emitter(Pattern::Aimed { count: 5, spread: 0.25 }, 2.0, 280.0, 700.0)],
+                ),
+                (
+                    EnemyKind::Boss,
+                    vec![
+                        emitter(Pattern::Ring { count: 16 }, 3.0, 260.0, 900.0),
+                        emitter(Pattern::Spiral { arms: 3, turn: 0.3 }, 0.25, 220.0, 900.0),
+                        emitter(
+                            Pattern::DelayedHoming { count: 4, spread: 0.8, delay: 0.6 },
+                            5.0,
+                            240.0,
+                            900.0,
+                        ),
+                    ],
+                ),
+            ]))
+        }
+    }
+
+    impl EmitterTables {
+        fn load() -> Self {
+            let mut tables = Self::default();
+            tables
+                .0
+                .extend(persistence::load_ron::<HashMap<EnemyKind, Vec<EmitterSpec>>>(EMITTERS_PATH));
+            tables
+        }
+    }
+
+    pub struct Emitter {
+        spec: EmitterSpec,
+        timer: Timer,
+        volley: u32,
+    }
+
+    impl Emitter {
+        pub fn new(spec: EmitterSpec) -> Self {
             Self {
-                xp: 0,
-                level: 1,
-                xp_to_next_level: 100,
+                spec,
+                timer: Timer::from_seconds(spec.interval, TimerMode::Repeating),
+                volley: 0,
             }
         }
     }
 
-    fn spawn_xp_gems(mut commands: Commands, mut events: EventReader<XpDropEvent>) {
-        for event in events.read() {
-            commands.spawn((
-                SpriteBundle {
-                    sprite: Sprite {
-                        color: Color::rgb(0.1, 0.9, 0.1),
-                        custom_size: Some(Vec2::new(XP_GEM_SIZE, XP_GEM_SIZE)),
-                        ..default()
-                    },
-                    transform: Transform::from_translation(event.0),
-                    ..default()
-                },
-                XpGem,
-            ));
+    /// The bullet emitters an enemy fires from, attached from its kind's table when it
+    /// spawns. Boss phase scripts compose attacks by inserting a different set.
+    #[derive(Component)]
+    pub struct Emitters(pub Vec<Emitter>);
+
+    /// An enemy shot that coasts until `delay` runs out, then steers toward the player
+    /// until `steering` does.
+    #[derive(Component)]
+    struct HomingShot {
+        delay: Timer,
+        steering: Timer,
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn attach_emitters(
+        mut commands: Commands,
+        tables: Res<EmitterTables>,
+        query: Query<(Entity, &EnemyKind), (Added<enemy::Enemy>, Without<Emitters>)>,
+    ) {
+        for (entity, kind) in query.iter() {
+            let Some(specs) = tables.0.get(kind) else {
+                continue;
+            };
+            commands
+                .entity(entity)
+                .insert(Emitters(specs.iter().copied().map(Emitter::new).collect()));
+        }
+    }
+
+    fn fire_emitters(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut emitter_query: Query<(Entity, &Transform, &EnemyKind, &mut Emitters), Without<enemy::Spawning>>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        for (entity, transform, kind, mut emitters) in emitter_query.iter_mut() {
+            let offset = (player_transform.translation - transform.translation).truncate();
+            let aim = offset.try_normalize().unwrap_or(Vec2::X);
+            for emitter in emitters.0.iter_mut() {
+                if !emitter.timer.tick(time.delta()).just_finished() || offset.length() > emitter.spec.range {
+                    continue;
+                }
+                for direction in emitter.spec.pattern.directions(aim, emitter.volley) {
+                    let bullet = commands
+                        .spawn(combat::enemy_bullet_bundle(
+                            transform.translation,
+                            direction.extend(0.0),
+                            emitter.spec.speed,
+                            entity,
+                            *kind,
+                        ))
+                        .id();
+                    if let Pattern::DelayedHoming { delay, .. } = emitter.spec.pattern {
+                        commands.entity(bullet).insert(HomingShot {
+                            delay: Timer::from_seconds(delay, TimerMode::Once),
+                            steering: Timer::from_seconds(HOMING_DURATION, TimerMode::Once),
+                        });
+                    }
+                }
+                emitter.volley += 1;
+            }
+        }
+    }
+
+    fn steer_homing_shots(
+        time: Res<Time>,
+        mut shot_query: Query<(&Transform, &combat::Team, &mut combat::Projectile, &mut HomingShot)>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        for (transform, team, mut projectile, mut homing) in shot_query.iter_mut() {
+            // A reflected shot belongs to the player now and flies straight.
+            if !matches!(team, combat::Team::Enemy { .. }) {
+                continue;
+            }
+            if !homing.delay.tick(time.delta()).finished() {
+                continue;
+            }
+            if homing.steering.tick(time.delta()).finished() {
+                continue;
+            }
+            let to_player = (player_transform.translation - transform.translation).truncate().normalize_or_zero().extend(0.0);
+            projectile.turn_toward(to_player, HOMING_TURN_RATE * time.delta_seconds());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_shipped_emitters_match_the_built_in_ones() {
+            let shipped: HashMap<EnemyKind, Vec<EmitterSpec>> =
+                ron::from_str(include_str!("../assets/data/emitters.ron")).unwrap();
+            assert_eq!(shipped, EmitterTables::default().0);
+        }
+
+        #[test]
+        fn test_pattern_directions() {
+            let aim = Vec2::Y;
+            let aimed = Pattern::Aimed { count: 3, spread: 0.5 }.directions(aim, 0);
+            assert_eq!(aimed.len(), 3);
+            assert!(aimed[1].distance(aim) < 1e-5);
+            assert!((aimed[0].angle_between(aimed[2]).abs() - 1.0).abs() < 1e-5);
+
+            let ring = Pattern::Ring { count: 4 }.directions(aim, 0);
+            assert!(ring[0].distance(Vec2::X) < 1e-5);
+            assert!(ring[1].distance(Vec2::Y) < 1e-5);
+
+            let spiral = Pattern::Spiral { arms: 2, turn: 0.25 };
+            let first = spiral.directions(aim, 0);
+            let third = spiral.directions(aim, 2);
+            assert!((first[0].angle_between(third[0]) - 0.5).abs() < 1e-5);
+        }
+    }
+}
+
+mod leveling {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    pub struct LevelingPlugin;
+
+    impl Plugin for LevelingPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(PlayerStats::default())
+                .add_event::<XpGemCollected>()
+                .add_systems(
+                    Update,
+                    collect_xp_gems.in_set(combat::CombatSet::Drops).run_if(in_play),
+                )
+                .add_systems(Update, check_level_up.run_if(in_state(GameState::Running)))
+                .add_systems(OnExit(GameState::Paused), ease_back_in)
+                .add_systems(ResetRun, reset_leveling);
+        }
+    }
+
+    #[derive(Component)]
+    pub struct XpGem {
+        pub value: u32,
+    }
+
+    /// Sent for every XP gem picked up, by the player or their pet.
+    #[derive(Event, Debug)]
+    pub struct XpGemCollected;
+
+    /// A gem worth `value` XP; gems carrying more than one kill's worth are drawn larger.
+    pub fn xp_gem_bundle(position: Vec3, value: u32) -> impl Bundle {
+        let size = XP_GEM_SIZE * (value as f32 / XP_PER_GEM as f32).sqrt().max(1.0);
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.2, 1.8, 0.2), // Above 1.0 so it blooms
+                    custom_size: Some(Vec2::new(size, size)),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Gems.at(position)),
+                ..default()
+            },
+            XpGem { value },
+            hibernation::Hibernates,
+            DespawnOnExit::RUN,
+        )
+    }
+
+    /// How much XP each level costs. Configured per difficulty in the settings file.
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    pub enum XpCurve {
+        Linear { base: u32, step: u32 },
+        Exponential { base: u32, growth: f32 },
+        /// XP for each level in turn; the last entry repeats once the table runs out.
+        Table(Vec<u32>),
+    }
+
+    impl Default for XpCurve {
+        fn default() -> Self {
+            XpCurve::Exponential {
+                base: 100,
+                growth: 1.5,
+            }
+        }
+    }
+
+    impl XpCurve {
+        /// XP needed to go from `level` to the next one.
+        pub fn xp_to_advance(&self, level: u32) -> u32 {
+            let steps = level.saturating_sub(1);
+            let xp = match self {
+                XpCurve::Linear { base, step } => base + step * steps,
+                XpCurve::Exponential { base, growth } => (*base as f32 * growth.powi(steps as i32)) as u32,
+                XpCurve::Table(table) => table
+                    .get(steps as usize)
+                    .or(table.last())
+                    .copied()
+                    .unwrap_or(100),
+            };
+            xp.max(1)
+        }
+    }
+
+    #[derive(Resource, Debug)]
+    pub struct PlayerStats {
+        pub xp: u32,
+        pub level: u32,
+        pub xp_to_next_level: u32,
+        pub curve: XpCurve,
+    }
+
+    impl PlayerStats {
+        pub fn new(curve: XpCurve) -> Self {
+            Self {
+                xp: 0,
+                level: 1,
+                xp_to_next_level: curve.xp_to_advance(1),
+                curve,
+            }
+        }
+    }
+
+    impl Default for PlayerStats {
+        fn default() -> Self {
+            Self::new(XpCurve::default())
+        }
+    }
+
+    impl PlayerStats {
+        /// Spends XP on the next level if there's enough. One level per call: whatever is
+        /// left over carries into the next level and pays for another pick later.
+        pub fn advance(&mut self) -> bool {
+            if self.xp < self.xp_to_next_level {
+                return false;
+            }
+            self.level += 1;
+            self.xp -= self.xp_to_next_level;
+            self.xp_to_next_level = self.curve.xp_to_advance(self.level);
+            true
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub fn collect_xp_gems(
+        mut commands: Commands,
+        player_query: Query<(&Transform, &collision::PreviousPosition), With<player::Player>>,
+        gem_query: Query<
+            (Entity, &Transform, &XpGem),
+            (Without<combat::MarkedForDeath>, Without<hibernation::Hibernating>),
+        >,
+        mut player_stats: ResMut<PlayerStats>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        mut collected_events: EventWriter<XpGemCollected>,
+    ) {
+        if let Ok((player_transform, previous)) = player_query.get_single() {
+            // Sweep the player's path so a dash through gems still picks them up.
+            let (start, end) = previous.segment_to(player_transform.translation);
+            for (gem_entity, gem_transform, gem) in gem_query.iter() {
+                if collision::segment_hits_circle(
+                    start,
+                    end,
+                    gem_transform.translation,
+                    PLAYER_SIZE / 2.0 + effective_stats.pickup_radius,
+                ) {
+                    commands.entity(gem_entity).try_insert(combat::MarkedForDeath);
+                    player_stats.xp += gem.value;
+                    collected_events.send(XpGemCollected);
+                }
+            }
+        }
+    }
+
+    fn check_level_up(
+        mut player_stats: ResMut<PlayerStats>,
+        mut game_state: ResMut<NextState<GameState>>,
+        bot_query: Query<(), With<bot::Bot>>,
+    ) {
+        // A bot picks its own upgrades without stopping the game.
+        if !bot_query.is_empty() {
+            return;
+        }
+        // Leftover XP triggers another pick as soon as the game resumes.
+        if player_stats.advance() {
+            game_state.set(GameState::Paused);
+        }
+    }
+
+    // Brief slow-mo after picking an upgrade so the player can re-orient.
+    fn ease_back_in(mut game_speed: ResMut<game_speed::GameSpeed>) {
+        game_speed.slow_motion(0.4, 0.5);
+    }
+
+    fn reset_leveling(mut player_stats: ResMut<PlayerStats>, settings: Res<settings::Settings>) {
+        // Every run starts here, so this also picks up a difficulty changed in the options.
+        *player_stats = PlayerStats::new(settings.xp_curve().clone());
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_level_up_logic() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .init_state::<GameState>()
+               .insert_resource(PlayerStats {
+                   xp: 100,
+                   level: 1,
+                   xp_to_next_level: 100,
+                   curve: XpCurve::default(),
+               })
+               .add_systems(Update, check_level_up);
+
+            app.update();
+
+            let stats = app.world.resource::<PlayerStats>();
+            assert_eq!(stats.level, 2);
+            assert_eq!(stats.xp, 0);
+            assert_eq!(stats.xp_to_next_level, 150); // 100 * 1.5
+
+            let _state = app.world.resource::<State<GameState>>();
+            // State transitions are applied at the start of the next frame.
+            // But next_state is in NextState resource.
+            let next_state = app.world.resource::<NextState<GameState>>();
+
+            // To verify state transition, we need to apply state transitions.
+            // But we can just check if NextState was set.
+            if let Some(s) = next_state.0 {
+                assert_eq!(s, GameState::Paused);
+            }
+        }
+
+        #[test]
+        fn test_overflow_xp_carries_across_level_ups() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .init_state::<GameState>()
+               .insert_resource(PlayerStats {
+                   xp: 300,
+                   ..PlayerStats::new(XpCurve::Linear { base: 100, step: 50 })
+               })
+               .add_systems(Update, check_level_up);
+
+            app.update();
+            app.update();
+            app.update();
+
+            let stats = app.world.resource::<PlayerStats>();
+            assert_eq!(stats.level, 3);
+            assert_eq!(stats.xp, 50); // 300 - 100 - 150
+            assert_eq!(stats.xp_to_next_level, 200);
+        }
+
+        #[test]
+        fn test_table_curve_repeats_last_entry() {
+            let curve = XpCurve::Table(vec![50, 80, 120]);
+            assert_eq!(curve.xp_to_advance(1), 50);
+            assert_eq!(curve.xp_to_advance(3), 120);
+            assert_eq!(curve.xp_to_advance(10), 120);
+        }
+    }
+}
+
+mod drops {
+    use super::*;
+    use enemy::EnemyKind;
+    use serde::{Deserialize, Serialize};
+    use std::collections::HashMap;
+
+    /// Per-kind drop tables. Kinds missing from the file keep their built-in table.
+    const DROP_TABLES_PATH: &str = "assets/data/enemies.ron";
+    const GOLD_SIZE: f32 = 8.0;
+    const HEAL_SIZE: f32 = 14.0;
+    const CHEST_SIZE: f32 = 26.0;
+    const POWER_UP_SIZE: f32 = 18.0;
+    const WEAPON_SIZE: f32 = 30.0;
+    const GEM_SIZE: f32 = 16.0;
+    const HEAL_AMOUNT: f32 = 25.0;
+    /// How far from the corpse extra drops land, so they don't hide under the XP gem.
+    const DROP_SCATTER: f32 = 18.0;
+    /// Gold starts flying to the player from this many times their pickup radius.
+    const GOLD_MAGNET_RANGE: f32 = 3.0;
+    const GOLD_MAGNET_SPEED: f32 = 650.0;
+    /// Interest pays one gold per this much banked gold, up to `INTEREST_CAP`.
+    const INTEREST_STEP: u32 = 10;
+    const INTEREST_CAP: u32 = 5;
+    /// Weapons that never show up on a level-up until a boss has dropped them, one per
+    /// boss killed, in this order.
+    pub const BOSS_LOOT: [ui::Upgrade; 1] = [ui::Upgrade::BlackHole];
+
+    pub struct DropsPlugin;
+
+    impl Plugin for DropsPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(DropTables::load())
+                .init_resource::<Purse>()
+                .init_resource::<BossLoot>()
+                .add_event::<WeaponLooted>()
+                .add_systems(
+                    Update,
+                    (roll_drops, attract_gold, collect_pickups)
+                        .chain()
+                        .in_set(combat::CombatSet::Drops)
+                        .run_if(in_play),
+                )
+                .add_systems(Update, pay_interest.run_if(in_play))
+                .add_systems(ResetRun, reset_drops);
+        }
+    }
+
+    /// What one kill of an enemy kind is worth. Chances are rolled independently.
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct DropTable {
+        pub xp: u32,
+        pub gold_chance: f64,
+        pub heal_chance: f64,
+        pub chest_chance: f64,
+        /// A random timed buff: double damage, haste or invulnerability.
+        pub power_up_chance: f64,
+        /// A random modifier gem to socket into a weapon.
+        pub gem_chance: f64,
+    }
+
+    impl Default for DropTable {
+        fn default() -> Self {
+            Self {
+                xp: XP_PER_GEM,
+                gold_chance: 0.05,
+                heal_chance: 0.005,
+                chest_chance: 0.0,
+                power_up_chance: 0.001,
+                gem_chance: 0.0,
+            }
+        }
+    }
+
+    #[derive(Resource, Debug, PartialEq)]
+    pub struct DropTables(HashMap<EnemyKind, DropTable>);
+
+    impl Default for DropTables {
+        fn default() -> Self {
+            let table = |xp, gold_chance, heal_chance, chest_chance, power_up_chance, gem_chance| DropTable {
+                xp,
+                gold_chance,
+                heal_chance,
+                chest_chance,
+                power_up_chance,
+                gem_chance,
+            };
+            Self(HashMap::from([
+                (EnemyKind::Grunt, DropTable::default()),
+                (EnemyKind::Spitter, table(15, 0.1, 0.01, 0.0, 0.003, 0.0)),
+                (EnemyKind::Exploder, table(15, 0.1, 0.0, 0.0, 0.003, 0.0)),
+                (EnemyKind::Broodmother, table(30, 0.3, 0.02, 0.0, 0.02, 0.01)),
+                (EnemyKind::Brood, table(3, 0.0, 0.0, 0.0, 0.0, 0.0)),
+                (EnemyKind::Thief, table(20, 0.5, 0.0, 0.0, 0.05, 0.05)),
+                (EnemyKind::Ambient, table(10, 0.15, 0.02, 0.0, 0.01, 0.0)),
+                (EnemyKind::Elite, table(250, 1.0, 0.5, 1.0, 0.25, 1.0)),
+                (EnemyKind::Boss, table(1000, 1.0, 1.0, 1.0, 0.0, 1.0)),
+            ]))
+        }
+    }
+
+    impl DropTables {
+        fn load() -> Self {
+            let mut tables = Self::default();
+            tables
+                .0
+                .extend(persistence::load_ron::<HashMap<EnemyKind, DropTable>>(DROP_TABLES_PATH));
+            tables
+        }
+
+        pub fn get(&self, kind: EnemyKind) -> DropTable {
+            self.0.get(&kind).copied().unwrap_or_default()
+        }
+    }
+
+    /// Gold picked up this run.
+    #[derive(Resource, Default, Debug)]
+    pub struct Purse {
+        pub gold: u32,
+    }
+
+    /// How many of `BOSS_LOOT` have dropped this run.
+    #[derive(Resource, Default, Debug)]
+    pub struct BossLoot {
+        dropped: usize,
+    }
+
+    impl BossLoot {
+        /// The next unique weapon for a boss to drop, if there are any left.
+        fn next(&mut self) -> Option<ui::Upgrade> {
+            let loot = BOSS_LOOT.get(self.dropped).copied();
+            self.dropped += loot.is_some() as usize;
+            loot
+        }
+    }
+
+    /// A boss weapon was picked up; the UI announces it.
+    #[derive(Event, Clone, Copy, Debug)]
+    pub struct WeaponLooted(pub ui::Upgrade);
+
+    /// Something other than XP lying on the floor, collected by walking over it.
+    #[derive(Component, Clone, Copy, Debug)]
+    pub enum Pickup {
+        Gold(u32),
+        Heal(f32),
+        /// Fills the XP bar, so the next upgrade pick comes straight away.
+        Chest,
+        PowerUp(buffs::BuffKind),
+        /// One of `BOSS_LOOT`, picked as if it had been chosen on a level-up.
+        Weapon(ui::Upgrade),
+        /// Goes into the gem pouch until socketed from the pause screen.
+        Gem(gems::ModifierGem),
+    }
+
+    impl Pickup {
+        fn bundle(self, position: Vec3) -> impl Bundle {
+            let (color, size) = match self {
+                Pickup::Gold(_) => (Color::rgb(1.8, 1.4, 0.2), GOLD_SIZE),
+                Pickup::Heal(_) => (Color::rgb(1.6, 0.3, 0.4), HEAL_SIZE),
+                Pickup::Chest => (Color::rgb(0.7, 0.45, 0.15), CHEST_SIZE),
+                Pickup::PowerUp(kind) => (kind.color(), POWER_UP_SIZE),
+                Pickup::Weapon(_) => (Color::rgb(1.6, 0.6, 2.0), WEAPON_SIZE),
+                Pickup::Gem(gem) => (gem.color(), GEM_SIZE),
+            };
+            (
+                SpriteBundle {
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::splat(size)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(render_layer::RenderLayer::Gems.at(position)),
+                    ..default()
+                },
+                self,
+                DespawnOnExit::RUN,
+            )
+        }
+    }
+
+    fn roll_drops(
+        mut commands: Commands,
+        mut events: EventReader<enemy::EnemyDied>,
+        tables: Res<DropTables>,
+        mut boss_loot: ResMut<BossLoot>,
+        mut run_rng: ResMut<run::RunRng>,
+    ) {
+        let rng = &mut run_rng.0;
+        for event in events.read() {
+            let table = tables.get(event.kind);
+            if table.xp > 0 {
+                commands.spawn(leveling::xp_gem_bundle(event.position, table.xp));
+            }
+            if event.kind == EnemyKind::Boss {
+                if let Some(upgrade) = boss_loot.next() {
+                    commands.spawn(Pickup::Weapon(upgrade).bundle(event.position));
+                }
+            }
+            let extras = [
+                (table.gold_chance, Pickup::Gold(1)),
+                (table.heal_chance, Pickup::Heal(HEAL_AMOUNT)),
+                (table.chest_chance, Pickup::Chest),
+            ];
+            for (chance, pickup) in extras {
+                if rng.gen_bool(chance.clamp(0.0, 1.0)) {
+                    commands.spawn(pickup.bundle(event.position + scatter(rng)));
+                }
+            }
+            // Only pick which buff once one drops, so the roll costs nothing otherwise.
+            if rng.gen_bool(table.power_up_chance.clamp(0.0, 1.0)) {
+                let kind = buffs::BuffKind::ALL[rng.gen_range(0..buffs::BuffKind::ALL.len())];
+                commands.spawn(Pickup::PowerUp(kind).bundle(event.position + scatter(rng)));
+            }
+            if rng.gen_bool(table.gem_chance.clamp(0.0, 1.0)) {
+                let gem = gems::ModifierGem::ALL[rng.gen_range(0..gems::ModifierGem::ALL.len())];
+                commands.spawn(Pickup::Gem(gem).bundle(event.position + scatter(rng)));
+            }
+        }
+    }
+
+    fn scatter(rng: &mut impl Rng) -> Vec3 {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        Vec3::new(angle.cos(), angle.sin(), 0.0) * DROP_SCATTER
+    }
+
+    /// Pulls nearby gold toward the player, faster the closer it gets.
+    fn attract_gold(
+        time: Res<Time>,
+        player_query: Query<&Transform, With<player::Player>>,
+        mut pickup_query: Query<(&mut Transform, &Pickup), Without<player::Player>>,
+        effective_stats: Res<attributes::EffectiveStats>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let range = (PLAYER_SIZE / 2.0 + effective_stats.pickup_radius) * GOLD_MAGNET_RANGE;
+        for (mut transform, pickup) in pickup_query.iter_mut() {
+            if !matches!(pickup, Pickup::Gold(_)) {
+                continue;
+            }
+            let offset = (player_transform.translation - transform.translation).truncate();
+            let distance = offset.length();
+            if distance >= range || distance <= f32::EPSILON {
+                continue;
+            }
+            let pull = GOLD_MAGNET_SPEED * (1.0 - distance / range).max(0.25) * time.delta_seconds();
+            transform.translation += (offset / distance * pull.min(distance)).extend(0.0);
+        }
+    }
+
+    /// Gold paid at the end of each wave for `banked` gold held.
+    pub fn interest(banked: u32) -> u32 {
+        (banked / INTEREST_STEP).min(INTEREST_CAP)
+    }
+
+    /// Each mega wave closes out the one before it, so that's when interest is paid.
+    fn pay_interest(mut events: EventReader<waves::ScriptedEventStarted>, mut purse: ResMut<Purse>) {
+        for event in events.read() {
+            if event.event == waves::ScriptedEvent::MegaWave {
+                purse.gold += interest(purse.gold);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn collect_pickups(
+        mut commands: Commands,
+        mut player_query: Query<(&Transform, &collision::PreviousPosition, &mut combat::Health), With<player::Player>>,
+        pickup_query: Query<(Entity, &Transform, &Pickup), Without<combat::MarkedForDeath>>,
+        mut purse: ResMut<Purse>,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        mut buff_events: EventWriter<buffs::BuffGained>,
+        mut upgrade_events: EventWriter<ui::UpgradeChosen>,
+        mut looted_events: EventWriter<WeaponLooted>,
+        mut gem_pouch: ResMut<gems::GemPouch>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        let Ok((player_transform, previous, mut health)) = player_query.get_single_mut() else {
+            return;
+        };
+        let (start, end) = previous.segment_to(player_transform.translation);
+        for (entity, transform, pickup) in pickup_query.iter() {
+            let reach = PLAYER_SIZE / 2.0 + effective_stats.pickup_radius;
+            if !collision::segment_hits_circle(start, end, transform.translation, reach) {
+                continue;
+            }
+            commands.entity(entity).try_insert(combat::MarkedForDeath);
+            match *pickup {
+                Pickup::Gold(amount) => purse.gold += amount,
+                Pickup::Heal(amount) => health.current = (health.current + amount).min(health.max),
+                Pickup::Chest => player_stats.xp = player_stats.xp.max(player_stats.xp_to_next_level),
+                Pickup::PowerUp(kind) => {
+                    buff_events.send(buffs::BuffGained(kind));
+                }
+                Pickup::Weapon(upgrade) => {
+                    upgrade_events.send(ui::UpgradeChosen(upgrade));
+                    looted_events.send(WeaponLooted(upgrade));
+                }
+                Pickup::Gem(gem) => {
+                    gem_pouch.loose.push(gem);
+                    toasts.send(toasts::ToastEvent::new(
+                        toasts::ToastKind::Pickup,
+                        format!("{} gem: socket it on the next level-up", gem.label()),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn reset_drops(mut purse: ResMut<Purse>, mut boss_loot: ResMut<BossLoot>) {
+        *purse = Purse::default();
+        *boss_loot = BossLoot::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_shipped_drop_tables_match_the_built_in_ones() {
+            let shipped: HashMap<EnemyKind, DropTable> =
+                ron::from_str(include_str!("../assets/data/enemies.ron")).unwrap();
+            assert_eq!(shipped, DropTables::default().0);
+        }
+
+        #[test]
+        fn test_interest_pays_per_step_up_to_the_cap() {
+            assert_eq!(interest(0), 0);
+            assert_eq!(interest(9), 0);
+            assert_eq!(interest(34), 3);
+            assert_eq!(interest(50), 5);
+            assert_eq!(interest(400), 5);
+        }
+
+        #[test]
+        fn test_boss_loot_drops_once_and_only_then_levels_up() {
+            let mut boss_loot = BossLoot::default();
+            let first = boss_loot.next().unwrap();
+            assert!(!first.is_offered(&[]));
+            assert!(first.is_offered(&[first]));
+            for _ in 1..BOSS_LOOT.len() {
+                assert!(boss_loot.next().is_some());
+            }
+            assert_eq!(boss_loot.next(), None);
+        }
+    }
+}
+
+mod ui {
+    use super::*;
+    use bevy::diagnostic::DiagnosticsStore;
+    use rand::seq::SliceRandom;
+    use serde::{Deserialize, Serialize};
+
+    pub struct UiPlugin;
+
+    impl Plugin for UiPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<UpgradeChosen>()
+                .init_resource::<LevelUpOffer>()
+                .add_systems(OnEnter(GameState::Running), setup_game_ui)
+                .add_systems(
+                    Update,
+                    (update_game_ui, update_energy_bar, update_projectile_cap_warning, update_fidelity_warning, (handle_upgrade_buttons, apply_upgrades).chain())
+                )
+                .add_systems(
+                    Update,
+                    (update_wave_timeline, show_loot_banner, fade_loot_banners).run_if(in_state(GameState::Running)),
+                )
+                .add_systems(
+                    Update,
+                    choose_with_number_keys.before(apply_upgrades).run_if(in_state(GameState::Paused)),
+                )
+                .add_systems(OnEnter(GameState::Paused), show_level_up_menu)
+                .add_systems(OnExit(GameState::Paused), hide_level_up_menu)
+                .add_systems(OnExit(GameState::Running), hide_level_up_menu);
+        }
+    }
+
+    #[derive(Component)]
+    struct FpsText;
+    #[derive(Component)]
+    struct EnemyCountText;
+    #[derive(Component)]
+    struct TimerText;
+    #[derive(Component)]
+    struct HealthText;
+    #[derive(Component)]
+    struct GoldText;
+    #[derive(Component)]
+    struct ProjectileCapText;
+    #[derive(Component)]
+    struct FidelityText;
+    #[derive(Component)]
+    struct EnergyBar;
+    #[derive(Component)]
+    struct LevelUpMenu;
+    #[derive(Component)]
+    struct StatsPanel;
+    #[derive(Component)]
+    struct WaveTimeline;
+    #[derive(Component)]
+    struct TimelineMarker {
+        at: f32,
+        event: waves::ScriptedEvent,
+    }
+
+    /// How far ahead the wave timeline looks, in seconds.
+    const TIMELINE_HORIZON: f32 = 90.0;
+    #[derive(Component)]
+    struct GameUi;
+
+    fn setup_game_ui(mut commands: Commands, query: Query<&GameUi>) {
+        if !query.is_empty() {
+            return;
+        }
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::SpaceBetween,
+                    ..default()
+                },
+                ..default()
+            },
+            GameUi,
+            settings::SafeArea,
+            DespawnOnExit::RUN,
+        )).with_children(|parent| {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    margin: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                ..default()
+            }).with_children(|parent| {
+                parent.spawn((TextBundle::from_section(
+                    "FPS: ",
+                    TextStyle { font_size: 20.0, ..default() },
+                ), FpsText));
+                parent.spawn((TextBundle::from_section(
+                    "Enemies: ",
+                    TextStyle { font_size: 20.0, ..default() },
+                ), EnemyCountText));
+                parent.spawn((TextBundle::from_section(
+                    "HP: ",
+                    TextStyle { font_size: 20.0, ..default() },
+                ), HealthText));
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(120.0),
+                        height: Val::Px(8.0),
+                        margin: UiRect::vertical(Val::Px(2.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                    ..default()
+                }).with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(100.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.3, 0.8, 1.0).into(),
+                            ..default()
+                        },
+                        EnergyBar,
+                    ));
+                });
+                parent.spawn((TextBundle::from_section(
+                    "Gold: 0",
+                    TextStyle { font_size: 20.0, color: Color::GOLD, ..default() },
+                ), GoldText));
+                parent.spawn((
+                    TextBundle {
+                        visibility: Visibility::Hidden,
+                        ..TextBundle::from_section(
+                            "Projectile cap reached",
+                            TextStyle { font_size: 16.0, color: Color::ORANGE, ..default() },
+                        )
+                    },
+                    ProjectileCapText,
+                ));
+                parent.spawn((
+                    TextBundle {
+                        visibility: Visibility::Hidden,
+                        ..TextBundle::from_section(
+                            "",
+                            TextStyle { font_size: 16.0, color: Color::ORANGE, ..default() },
+                        )
+                    },
+                    FidelityText,
+                ));
+            });
+            parent.spawn((
+                TextBundle::from_section(
+                    "Time: 0.0",
+                    TextStyle { font_size: 30.0, ..default() },
+                ).with_style(Style {
+                    margin: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                }),
+                TimerText,
+            ));
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(8.0),
+                        left: Val::Percent(25.0),
+                        width: Val::Percent(50.0),
+                        height: Val::Px(4.0),
+                        ..default()
+                    },
+                    background_color: Color::rgba(1.0, 1.0, 1.0, 0.25).into(),
+                    ..default()
+                },
+                WaveTimeline,
+            ));
+        });
+
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    display: Display::None,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            LevelUpMenu,
+            DespawnOnExit::RUN,
+        )).with_children(|parent| {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            }).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Level Up!",
+                    TextStyle { font_size: 50.0, color: Color::WHITE, ..default() },
+                ));
+            });
+        });
+    }
+
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    fn update_game_ui(
+        diagnostics: Res<DiagnosticsStore>,
+        mut fps_query: Query<&mut Text, With<FpsText>>,
+        mut enemy_query: Query<&mut Text, (With<EnemyCountText>, Without<FpsText>)>,
+        enemy_count_query: Query<(), With<enemy::Enemy>>,
+        current_run: Res<run::CurrentRun>,
+        mut timer_query: Query<&mut Text, (With<TimerText>, Without<FpsText>, Without<EnemyCountText>)>,
+        mut health_query: Query<&mut Text, (With<HealthText>, Without<FpsText>, Without<EnemyCountText>, Without<TimerText>)>,
+        player_query: Query<&combat::Health, With<player::Player>>,
+        mut gold_query: Query<&mut Text, (With<GoldText>, Without<FpsText>, Without<EnemyCountText>, Without<TimerText>, Without<HealthText>)>,
+        purse: Res<drops::Purse>,
+    ) {
+        if let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS) {
+            if let Some(value) = fps.smoothed() {
+                for mut text in fps_query.iter_mut() {
+                    text.sections[0].value = format!("FPS: {:.0}", value);
+                }
+            }
+        }
+
+        for mut text in enemy_query.iter_mut() {
+            text.sections[0].value = format!("Enemies: {}", enemy_count_query.iter().count());
+        }
+
+        for mut text in timer_query.iter_mut() {
+            text.sections[0].value = format!("Time: {:.1}", current_run.elapsed);
+        }
+
+        if let Ok(health) = player_query.get_single() {
+            for mut text in health_query.iter_mut() {
+                text.sections[0].value = format!("HP: {:.0}/{:.0}", health.current.max(0.0), health.max);
+            }
+        }
+
+        for mut text in gold_query.iter_mut() {
+            text.sections[0].value = format!("Gold: {}", purse.gold);
+        }
+    }
+
+    fn update_projectile_cap_warning(
+        cap: Res<combat::ProjectileCap>,
+        settings: Res<settings::Settings>,
+        mut query: Query<&mut Visibility, With<ProjectileCapText>>,
+    ) {
+        let shown = settings.graphics.projectile_cap_warning && cap.is_capped();
+        for mut visibility in query.iter_mut() {
+            visibility.set_if_neq(if shown { Visibility::Inherited } else { Visibility::Hidden });
+        }
+    }
+
+    fn update_energy_bar(
+        player_query: Query<&energy::Energy, With<player::Player>>,
+        mut bar_query: Query<&mut Style, With<EnergyBar>>,
+    ) {
+        let Ok(energy) = player_query.get_single() else {
+            return;
+        };
+        for mut style in bar_query.iter_mut() {
+            style.width = Val::Percent(energy.fraction() * 100.0);
+        }
+    }
+
+    /// How long the boss loot announcement stays up; it fades over the last second.
+    const LOOT_BANNER_DURATION: f32 = 4.0;
+
+    #[derive(Component)]
+    struct LootBanner(Timer);
+
+    fn show_loot_banner(mut commands: Commands, mut events: EventReader<drops::WeaponLooted>) {
+        for drops::WeaponLooted(upgrade) in events.read() {
+            commands.spawn((
+                TextBundle::from_section(
+                    format!("New weapon: {}", upgrade.label()),
+                    TextStyle { font_size: 40.0, color: Color::rgb(0.9, 0.6, 1.0), ..default() },
+                )
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Percent(25.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                })
+                .with_text_justify(JustifyText::Center),
+                LootBanner(Timer::from_seconds(LOOT_BANNER_DURATION, TimerMode::Once)),
+                ui_anim::Tween::pop(),
+                DespawnOnExit::RUN,
+            ));
+        }
+    }
+
+    fn fade_loot_banners(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut LootBanner, &mut Text)>,
+    ) {
+        for (entity, mut banner, mut text) in query.iter_mut() {
+            if banner.0.tick(time.delta()).finished() {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+            let alpha = banner.0.remaining_secs().min(1.0);
+            for section in text.sections.iter_mut() {
+                section.style.color.set_a(alpha);
+            }
+        }
+    }
+
+    fn update_fidelity_warning(
+        budget: Res<budget::FrameBudget>,
+        mut query: Query<(&mut Text, &mut Visibility), With<FidelityText>>,
+    ) {
+        let label = budget.fidelity.label();
+        for (mut text, mut visibility) in query.iter_mut() {
+            text.sections[0].value = format!("{} detail to keep up", label.unwrap_or_default());
+            visibility.set_if_neq(if label.is_some() { Visibility::Inherited } else { Visibility::Hidden });
+        }
+    }
+
+    /// Slides markers for upcoming scripted events toward the left ("now") end of the bar.
+    fn update_wave_timeline(
+        mut commands: Commands,
+        current_run: Res<run::CurrentRun>,
+        director: Res<waves::WaveDirector>,
+        timeline_query: Query<Entity, With<WaveTimeline>>,
+        mut marker_query: Query<(Entity, &TimelineMarker, &mut Style)>,
+    ) {
+        let Ok(timeline) = timeline_query.get_single() else {
+            return;
+        };
+        let now = current_run.elapsed;
+        let percent_for = |at: f32| (at - now) / TIMELINE_HORIZON * 100.0;
+
+        let mut shown = Vec::new();
+        for (entity, marker, mut style) in marker_query.iter_mut() {
+            if marker.at <= now {
+                commands.entity(entity).despawn_recursive();
+            } else {
+                style.left = Val::Percent(percent_for(marker.at));
+                shown.push((marker.at, marker.event));
+            }
+        }
+
+        for (at, event) in director.upcoming(now, now + TIMELINE_HORIZON) {
+            if shown.contains(&(at, event)) {
+                continue;
+            }
+            let marker = commands
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            left: Val::Percent(percent_for(at)),
+                            top: Val::Px(-7.0),
+                            width: Val::Px(18.0),
+                            height: Val::Px(18.0),
+                            margin: UiRect::left(Val::Px(-9.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: event.color().into(),
+                        ..default()
+                    },
+                    TimelineMarker { at, event },
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        event.icon(),
+                        TextStyle { font_size: 14.0, color: Color::BLACK, ..default() },
+                    ));
+                })
+                .id();
+            commands.entity(timeline).add_child(marker);
+        }
+    }
+
+    #[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Upgrade {
+        Multishot,
+        ChainLightning,
+        BladeCount,
+        AttackSpeed,
+        Shotgun,
+        HomingMissile,
+        DashBlast,
+        RetaliationNova,
+        Passive(passives::PassiveItem),
+        Evolve(passives::Evolution),
+        Railgun,
+        RailgunDamage,
+        RailgunFireRate,
+        MineLayer,
+        Whip,
+        WhipWidth,
+        WhipReach,
+        Turret,
+        BlackHole,
+        ReflectiveShield,
+        MaxEnergy,
+        EnergyRegen,
+        Range,
+        Overkill,
+        CorpseBlast,
+        PulseNova,
+        ReaperNova,
+    }
+
+    /// Each Range pick adds this much to the Range stat...
+    const RANGE_STEP: f32 = 0.2;
+    /// ...so this many picks take it to `attributes::MAX_RANGE`.
+    const MAX_RANGE_PICKS: usize = 5;
+
+    impl Upgrade {
+        /// Everything that can show up on a level-up; evolutions are added once unlocked.
+        pub const ALL: [Upgrade; 29] = [
+            Upgrade::Multishot,
+            Upgrade::ChainLightning,
+            Upgrade::BladeCount,
+            Upgrade::AttackSpeed,
+            Upgrade::Shotgun,
+            Upgrade::HomingMissile,
+            Upgrade::DashBlast,
+            Upgrade::RetaliationNova,
+            Upgrade::Passive(passives::PassiveItem::HollowHeart),
+            Upgrade::Passive(passives::PassiveItem::Wings),
+            Upgrade::Passive(passives::PassiveItem::Spinach),
+            Upgrade::Passive(passives::PassiveItem::EmptyTome),
+            Upgrade::Railgun,
+            Upgrade::RailgunDamage,
+            Upgrade::RailgunFireRate,
+            Upgrade::MineLayer,
+            Upgrade::Whip,
+            Upgrade::WhipWidth,
+            Upgrade::WhipReach,
+            Upgrade::Turret,
+            Upgrade::BlackHole,
+            Upgrade::ReflectiveShield,
+            Upgrade::MaxEnergy,
+            Upgrade::EnergyRegen,
+            Upgrade::Range,
+            Upgrade::Overkill,
+            Upgrade::CorpseBlast,
+            Upgrade::PulseNova,
+            Upgrade::ReaperNova,
+        ];
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                Upgrade::Multishot => "More Projectiles",
+                Upgrade::ChainLightning => "Chain Lightning",
+                Upgrade::BladeCount => "More Blades",
+                Upgrade::AttackSpeed => "Faster Attacks",
+                Upgrade::Shotgun => "Shotgun",
+                Upgrade::HomingMissile => "Homing Missiles",
+                Upgrade::DashBlast => "Dash Blast",
+                Upgrade::RetaliationNova => "Retaliation Nova",
+                Upgrade::Passive(item) => item.label(),
+                Upgrade::Evolve(evolution) => evolution.label(),
+                Upgrade::Railgun => "Railgun",
+                Upgrade::RailgunDamage => "Railgun: +50% Damage",
+                Upgrade::RailgunFireRate => "Railgun: Faster Charge",
+                Upgrade::MineLayer => "Mine Layer",
+                Upgrade::Whip => "Whip",
+                Upgrade::WhipWidth => "Whip: Wider Arc",
+                Upgrade::WhipReach => "Whip: Longer Reach",
+                Upgrade::Turret => "Turret",
+                Upgrade::BlackHole => "Black Hole",
+                Upgrade::ReflectiveShield => "Reflective Shield",
+                Upgrade::MaxEnergy => "Capacitor: +25% Max Energy",
+                Upgrade::EnergyRegen => "Dynamo: Faster Recharge",
+                Upgrade::Range => "Scope: +20% Range & Area",
+                Upgrade::Overkill => "Overkill: Excess Damage Carries On",
+                Upgrade::CorpseBlast => "Volatile Corpses: Kills May Explode",
+                Upgrade::PulseNova => "Pulse Nova",
+                Upgrade::ReaperNova => "Reaper Nova",
+            }
+        }
+
+        /// Whether this can be offered given what has been picked so far.
+        pub fn is_offered(&self, build: &[Upgrade]) -> bool {
+            match self {
+                Upgrade::Railgun => !build.contains(&Upgrade::Railgun),
+                Upgrade::RailgunDamage | Upgrade::RailgunFireRate => build.contains(&Upgrade::Railgun),
+                Upgrade::Whip => !build.contains(&Upgrade::Whip),
+                Upgrade::Overkill => !build.contains(&Upgrade::Overkill),
+                Upgrade::CorpseBlast => {
+                    build.iter().filter(|upgrade| **upgrade == Upgrade::CorpseBlast).count()
+                        < triggers::MAX_CORPSE_BLAST_LEVEL as usize
+                }
+                Upgrade::WhipWidth | Upgrade::WhipReach => build.contains(&Upgrade::Whip),
+                // The first copy only ever comes from a boss.
+                upgrade if drops::BOSS_LOOT.contains(upgrade) => build.contains(upgrade),
+                Upgrade::Range => build.iter().filter(|upgrade| **upgrade == Upgrade::Range).count() < MAX_RANGE_PICKS,
+                _ => true,
+            }
+        }
+    }
+
+    /// The cards on the open level-up menu, left to right.
+    #[derive(Resource, Default, Debug)]
+    pub struct LevelUpOffer(pub Vec<Upgrade>);
+
+    /// Level-up cards fly up into place from this far below, one after another.
+    const CARD_FLY_IN: f32 = 80.0;
+    const CARD_STAGGER: f32 = 0.06;
+
+    fn show_level_up_menu(
+        mut commands: Commands,
+        mut menu_query: Query<(Entity, &mut Style), With<LevelUpMenu>>,
+        mut offer: ResMut<LevelUpOffer>,
+        mut current_run: ResMut<run::CurrentRun>,
+        planned_build: Res<build_code::PlannedBuild>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
+    ) {
+        if let Ok((menu_entity, mut style)) = menu_query.get_single_mut() {
+            style.display = Display::Flex;
+            commands.entity(menu_entity).insert(ui_anim::Tween::fade_in());
+
+            let mut rng = current_run.next_draft_rng();
+            let chosen_upgrades = draft_upgrades(&current_run.build, &current_run.heat, &planned_build, &mut rng);
+            offer.0.clone_from(&chosen_upgrades);
+
+            commands.entity(menu_entity).with_children(|parent| {
+                for (index, upgrade) in chosen_upgrades.into_iter().enumerate() {
+                    parent.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(250.0),
+                                height: Val::Px(70.0),
+                                margin: UiRect::all(Val::Px(10.0)),
+                                flex_direction: FlexDirection::Column,
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                            ..default()
+                        },
+                        upgrade,
+                        ui_anim::Tween::slide_in(Vec2::new(0.0, CARD_FLY_IN)).with_delay(index as f32 * CARD_STAGGER),
+                    )).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            upgrade.label(),
+                            TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                        ));
+                        for synergy in synergies::tags(upgrade) {
+                            parent.spawn(NodeBundle {
+                                style: Style {
+                                    margin: UiRect::top(Val::Px(4.0)),
+                                    padding: UiRect::horizontal(Val::Px(6.0)),
+                                    ..default()
+                                },
+                                background_color: synergy.color().into(),
+                                ..default()
+                            }).with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    format!(
+                                        "{} {}/{}",
+                                        synergy.label(),
+                                        synergies::owned(&current_run.build, *synergy),
+                                        synergies::SYNERGY_THRESHOLD,
+                                    ),
+                                    TextStyle { font_size: 14.0, color: Color::BLACK, ..default() },
+                                ));
+                            });
+                        }
+                    });
+                }
+                parent.spawn((
+                    TextBundle::from_section(
+                        stats_panel_text(
+                            &effective_stats,
+                            combat::damage_multiplier(&last_stand_query),
+                            &current_run.build,
+                        ),
+                        TextStyle { font_size: 18.0, color: Color::GRAY, ..default() },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::left(Val::Px(30.0)),
+                        ..default()
+                    }),
+                    StatsPanel,
+                ));
+            });
+        }
+    }
+
+    /// The three upgrades offered on a level-up.
+    pub fn draft_upgrades(
+        build: &[Upgrade],
+        heat: &[heat::HeatModifier],
+        planned_build: &build_code::PlannedBuild,
+        rng: &mut impl Rng,
+    ) -> Vec<Upgrade> {
+        // A replayed build code's next pick, or else an unlocked evolution, takes the first slot.
+        let mut chosen_upgrades = match planned_build.next_pick(build.len()) {
+            Some(planned) => vec![planned],
+            None => passives::available_evolutions(build)
+                .into_iter()
+                .map(Upgrade::Evolve)
+                .filter(|upgrade| heat::allows(heat, *upgrade))
+                .take(1)
+                .collect::<Vec<_>>(),
+        };
+        let pool = Upgrade::ALL
+            .iter()
+            .filter(|upgrade| {
+                upgrade.is_offered(build) && heat::allows(heat, **upgrade) && !chosen_upgrades.contains(upgrade)
+            })
+            .collect::<Vec<_>>();
+        let remaining = 3 - chosen_upgrades.len();
+        chosen_upgrades.extend(pool.choose_multiple(rng, remaining).copied());
+        chosen_upgrades
+    }
+
+    /// What the player's upgrades add up to, straight from the resolved stats,
+    /// followed by progress toward each synergy the build has started on.
+    fn stats_panel_text(
+        stats: &attributes::EffectiveStats,
+        damage_multiplier: f32,
+        build: &[Upgrade],
+    ) -> String {
+        let mut text = format!(
+            "Damage: {:.0}%\nFire rate: {:.1}/s\nMove speed: {:.0}\nRange: {:.0}%\nPickup radius: {:.0}\nMax HP: {:.0}\nEnergy: {:.0} (+{:.1}/s)",
+            stats.damage / BASE_WEAPON_DAMAGE * damage_multiplier * 100.0,
+            1.0 / stats.fire_cooldown,
+            stats.move_speed,
+            stats.range * 100.0,
+            stats.pickup_radius,
+            stats.max_health,
+            stats.max_energy,
+            stats.energy_regen,
+        );
+        let synergy_summary = synergies::summary(build);
+        if !synergy_summary.is_empty() {
+            text.push_str("\n\nSynergies\n");
+            text.push_str(&synergy_summary);
+        }
+        text
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn hide_level_up_menu(
+        mut commands: Commands,
+        menu_query: Query<Entity, With<LevelUpMenu>>,
+        button_query: Query<Entity, Or<(With<Button>, With<StatsPanel>)>>,
+    ) {
+        if let Ok(menu_entity) = menu_query.get_single() {
+            commands.entity(menu_entity).insert(ui_anim::Tween::fade_out());
+            for entity in button_query.iter() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    /// Sent when an upgrade is picked, whether from the level-up menu, by a bot or off a boss.
+    #[derive(Event, Debug)]
+    pub struct UpgradeChosen(pub Upgrade);
+
+    /// 1, 2 and 3 pick the cards left to right.
+    fn choose_with_number_keys(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        offer: Res<LevelUpOffer>,
+        mut chosen_events: EventWriter<UpgradeChosen>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        let keys = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3];
+        let picked = keys
+            .iter()
+            .zip(&offer.0)
+            .find(|(key, _)| keyboard_input.just_pressed(**key));
+        if let Some((_, upgrade)) = picked {
+            chosen_events.send(UpgradeChosen(*upgrade));
+            game_state.set(GameState::Running);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_upgrade_buttons(
+        interaction_query: Query<(&Interaction, &Upgrade), (Changed<Interaction>, With<Button>)>,
+        mut chosen_events: EventWriter<UpgradeChosen>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        for (interaction, upgrade) in interaction_query.iter() {
+            if *interaction == Interaction::Pressed {
+                chosen_events.send(UpgradeChosen(*upgrade));
+                game_state.set(GameState::Running);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn apply_upgrades(
+        mut commands: Commands,
+        mut chosen_events: EventReader<UpgradeChosen>,
+        mut weapon_stats: ResMut<combat::WeaponStats>,
+        mut triggered_weapons: ResMut<triggers::TriggeredWeapons>,
+        mut stat_modifiers: ResMut<attributes::StatModifiers>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+        mut railgun: ResMut<railgun::Railgun>,
+        mut mine_layer: ResMut<mines::MineLayer>,
+        mut whip: ResMut<whip::Whip>,
+        mut turret_deployer: ResMut<turrets::TurretDeployer>,
+        mut black_hole: ResMut<vortex::BlackHole>,
+        mut reflective_shield: ResMut<shield::ReflectiveShield>,
+        mut corpse_blast: ResMut<triggers::CorpseBlast>,
+        mut current_run: ResMut<run::CurrentRun>,
+    ) {
+        for UpgradeChosen(upgrade) in chosen_events.read() {
+            current_run.build.push(*upgrade);
+            match upgrade {
+                Upgrade::Multishot => {
+                    weapon_stats.multishot += 1;
+                    // Each level tightens the fan so wide volleys still connect.
+                    stat_modifiers.0.push(attributes::StatModifier {
+                        stat: attributes::Stat::Spread,
+                        op: attributes::ModifierOp::Multiply(0.9),
+                    });
+                    stats_changed.send(attributes::StatsChanged);
+                }
+                Upgrade::ChainLightning => weapon_stats.chain_lightning += 1,
+                Upgrade::BladeCount => weapon_stats.blade_count += 1,
+                Upgrade::AttackSpeed => {
+                    stat_modifiers.0.push(attributes::StatModifier {
+                        stat: attributes::Stat::FireCooldown,
+                        op: attributes::ModifierOp::Multiply(0.9),
+                    });
+                    stats_changed.send(attributes::StatsChanged);
+                }
+                Upgrade::Shotgun => weapon_stats.shotgun_count += 1,
+                Upgrade::HomingMissile => weapon_stats.homing_count += 1,
+                Upgrade::DashBlast => triggered_weapons.add(triggers::DASH_BLAST),
+                Upgrade::RetaliationNova => triggered_weapons.add(triggers::RETALIATION_NOVA),
+                Upgrade::PulseNova => triggered_weapons.add(triggers::PULSE_NOVA),
+                Upgrade::ReaperNova => triggered_weapons.add(triggers::REAPER_NOVA),
+                Upgrade::Passive(item) => {
+                    commands.spawn((*item, DespawnOnExit::RUN));
+                }
+                Upgrade::Evolve(passives::Evolution::StormCaller) => {
+                    weapon_stats.chain_lightning += 3
+                }
+                Upgrade::Evolve(passives::Evolution::RazorHalo) => weapon_stats.blade_count += 4,
+                Upgrade::Railgun => railgun.unlocked = true,
+                Upgrade::RailgunDamage => railgun.damage *= 1.5,
+                Upgrade::RailgunFireRate => railgun.faster_charge(),
+                Upgrade::MineLayer => mine_layer.level += 1,
+                Upgrade::Whip => whip.unlocked = true,
+                Upgrade::WhipWidth => whip.widen(),
+                Upgrade::WhipReach => whip.reach *= 1.25,
+                Upgrade::Turret => turret_deployer.level += 1,
+                Upgrade::BlackHole => black_hole.level += 1,
+                Upgrade::ReflectiveShield => reflective_shield.level += 1,
+                Upgrade::MaxEnergy => {
+                    stat_modifiers.0.push(attributes::StatModifier {
+                        stat: attributes::Stat::MaxEnergy,
+                        op: attributes::ModifierOp::Percent(0.25),
+                    });
+                    stats_changed.send(attributes::StatsChanged);
+                }
+                Upgrade::EnergyRegen => {
+                    stat_modifiers.0.push(attributes::StatModifier {
+                        stat: attributes::Stat::EnergyRegen,
+                        op: attributes::ModifierOp::Percent(0.3),
+                    });
+                    stats_changed.send(attributes::StatsChanged);
+                }
+                Upgrade::Range => {
+                    stat_modifiers.0.push(attributes::StatModifier {
+                        stat: attributes::Stat::Range,
+                        op: attributes::ModifierOp::Percent(RANGE_STEP),
+                    });
+                    stats_changed.send(attributes::StatsChanged);
+                }
+                Upgrade::Overkill => weapon_stats.overkill = true,
+                Upgrade::CorpseBlast => corpse_blast.level += 1,
+            }
+        }
+    }
+
+}
+
+mod ui_anim {
+    use super::*;
+    use std::time::Duration;
+
+    const FADE_DURATION: f32 = 0.2;
+    const SLIDE_DURATION: f32 = 0.35;
+    const POP_DURATION: f32 = 0.3;
+
+    /// Eased transitions for UI nodes: menus fade in and out, cards slide into place and
+    /// banners pop. Runs on real time so pauses and slow motion don't drag them out.
+    pub struct UiAnimPlugin;
+
+    impl Plugin for UiAnimPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(PostUpdate, animate_tweens.before(bevy::ui::UiSystem::Layout));
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Ease {
+        OutCubic,
+        /// Overshoots a little before settling.
+        OutBack,
+    }
+
+    impl Ease {
+        /// Maps linear progress from 0.0 to 1.0 onto the curve.
+        pub fn apply(self, t: f32) -> f32 {
+            let t = t.clamp(0.0, 1.0);
+            match self {
+                Ease::OutCubic => 1.0 - (1.0 - t).powi(3),
+                Ease::OutBack => {
+                    const OVERSHOOT: f32 = 1.70158;
+                    let u = t - 1.0;
+                    1.0 + (OVERSHOOT + 1.0) * u.powi(3) + OVERSHOOT * u.powi(2)
+                }
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum TweenEffect {
+        /// The node and everything under it fade up from transparent.
+        FadeIn,
+        /// Fades the node and everything under it out, then hides it with `Display::None`.
+        FadeOut,
+        /// Slides a relatively positioned node in from this many pixels away.
+        SlideIn(Vec2),
+        /// Text under the node grows from half size, overshooting slightly.
+        Pop,
+    }
+
+    /// Plays `effect` on the node once, then removes itself. Inserting a new one replaces
+    /// whatever was playing.
+    #[derive(Component, Debug)]
+    pub struct Tween {
+        effect: TweenEffect,
+        ease: Ease,
+        delay: f32,
+        timer: Timer,
+    }
+
+    impl Tween {
+        pub fn fade_in() -> Self {
+            Self::new(TweenEffect::FadeIn, Ease::OutCubic, FADE_DURATION)
+        }
+
+        pub fn fade_out() -> Self {
+            Self::new(TweenEffect::FadeOut, Ease::OutCubic, FADE_DURATION)
+        }
+
+        pub fn slide_in(from: Vec2) -> Self {
+            Self::new(TweenEffect::SlideIn(from), Ease::OutBack, SLIDE_DURATION)
+        }
+
+        pub fn pop() -> Self {
+            Self::new(TweenEffect::Pop, Ease::OutBack, POP_DURATION)
+        }
+
+        /// Holds the starting pose for `seconds` first, to stagger a row of nodes.
+        pub fn with_delay(mut self, seconds: f32) -> Self {
+            self.delay = seconds;
+            self
+        }
+
+        fn new(effect: TweenEffect, ease: Ease, duration: f32) -> Self {
+            Self { effect, ease, delay: 0.0, timer: Timer::from_seconds(duration, TimerMode::Once) }
+        }
+    }
+
+    /// A node's colours and font sizes as they were built, so fades and pops have something
+    /// to return to however often they are interrupted.
+    #[derive(Component, Clone, Debug)]
+    struct UiBase {
+        background: f32,
+        text_alphas: Vec<f32>,
+        font_sizes: Vec<f32>,
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn animate_tweens(
+        mut commands: Commands,
+        time: Res<Time<Real>>,
+        mut tween_query: Query<(Entity, &mut Tween)>,
+        children_query: Query<&Children>,
+        base_query: Query<&UiBase>,
+        mut style_query: Query<&mut Style>,
+        mut background_query: Query<&mut BackgroundColor>,
+        mut text_query: Query<&mut Text>,
+    ) {
+        for (root, mut tween) in tween_query.iter_mut() {
+            let mut seconds = time.delta_seconds();
+            if tween.delay > 0.0 {
+                let waited = seconds.min(tween.delay);
+                tween.delay -= waited;
+                seconds -= waited;
+            }
+            tween.timer.tick(Duration::from_secs_f32(seconds));
+            let progress = tween.ease.apply(tween.timer.fraction());
+            let finished = tween.timer.finished();
+
+            match tween.effect {
+                TweenEffect::SlideIn(from) => {
+                    if let Ok(mut style) = style_query.get_mut(root) {
+                        let (left, top) = if finished {
+                            (Val::Auto, Val::Auto)
+                        } else {
+                            (Val::Px(from.x * (1.0 - progress)), Val::Px(from.y * (1.0 - progress)))
+                        };
+                        style.left = left;
+                        style.top = top;
+                    }
+                }
+                effect => {
+                    let (alpha, scale) = match effect {
+                        TweenEffect::FadeIn => (progress, 1.0),
+                        // Put the colours back once hidden, ready for the next time it is shown.
+                        TweenEffect::FadeOut if finished => (1.0, 1.0),
+                        TweenEffect::FadeOut => (1.0 - progress, 1.0),
+                        _ => (1.0, 0.5 + 0.5 * progress),
+                    };
+                    for entity in std::iter::once(root).chain(children_query.iter_descendants(root)) {
+                        let base = base_query.get(entity).cloned().unwrap_or_else(|_| {
+                            let base = UiBase {
+                                background: background_query.get(entity).map_or(0.0, |color| color.0.a()),
+                                text_alphas: text_query.get(entity).map_or(Vec::new(), |text| {
+                                    text.sections.iter().map(|section| section.style.color.a()).collect()
+                                }),
+                                font_sizes: text_query.get(entity).map_or(Vec::new(), |text| {
+                                    text.sections.iter().map(|section| section.style.font_size).collect()
+                                }),
+                            };
+                            commands.entity(entity).try_insert(base.clone());
+                            base
+                        });
+                        if let Ok(mut background) = background_query.get_mut(entity) {
+                            background.0.set_a(base.background * alpha);
+                        }
+                        if let Ok(mut text) = text_query.get_mut(entity) {
+                            for (index, section) in text.sections.iter_mut().enumerate() {
+                                if let Some(&base_alpha) = base.text_alphas.get(index) {
+                                    section.style.color.set_a(base_alpha * alpha);
+                                }
+                                if let Some(&base_size) = base.font_sizes.get(index) {
+                                    section.style.font_size = base_size * scale;
+                                }
+                            }
+                        }
+                    }
+                    if finished && effect == TweenEffect::FadeOut {
+                        if let Ok(mut style) = style_query.get_mut(root) {
+                            style.display = Display::None;
+                        }
+                    }
+                }
+            }
+            if finished {
+                commands.entity(root).remove::<Tween>();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_eases_run_from_zero_to_one() {
+            for ease in [Ease::OutCubic, Ease::OutBack] {
+                assert!(ease.apply(0.0).abs() < 1e-5);
+                assert!((ease.apply(1.0) - 1.0).abs() < 1e-5);
+            }
+            assert!(Ease::OutBack.apply(0.7) > 1.0);
+            assert!(Ease::OutCubic.apply(0.7) < 1.0);
+        }
+    }
+}
+
+mod buttons {
+    use super::*;
+
+    /// How much lighter a hovered button gets.
+    const HOVER_TINT: f32 = 0.12;
+    const PRESSED_SCALE: f32 = 0.95;
+    const FOCUS_OUTLINE: Color = Color::rgb(1.0, 0.85, 0.3);
+
+    /// Shared look and feel for every `Button`: a tint on hover, a slight squeeze while
+    /// pressed and an outline on the one with keyboard or gamepad focus. The arrow keys,
+    /// Tab and the d-pad move focus through the visible buttons in reading order, and
+    /// Enter or the gamepad's south button presses the focused one.
+    pub struct ButtonsPlugin;
+
+    impl Plugin for ButtonsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<ButtonFocus>()
+                .add_systems(Update, (move_focus, press_focused, style_buttons).chain());
+        }
+    }
+
+    /// The button keyboard and gamepad input acts on, if any.
+    #[derive(Resource, Default, Debug)]
+    pub struct ButtonFocus(pub Option<Entity>);
+
+    /// While one of these is on screen, focus only moves between the buttons under it.
+    #[derive(Component)]
+    pub struct FocusTrap;
+
+    /// A button's background as it was built, before any tint.
+    #[derive(Component)]
+    struct BaseColor(Color);
+
+    /// The button after (or before, going `back`) `current` in `order`, wrapping round.
+    /// With nothing focused yet, the first (or last) one.
+    fn step(order: &[Entity], current: Option<Entity>, back: bool) -> Option<Entity> {
+        let len = order.len();
+        if len == 0 {
+            return None;
+        }
+        let next = match current.and_then(|current| order.iter().position(|&entity| entity == current)) {
+            Some(index) if back => (index + len - 1) % len,
+            Some(index) => (index + 1) % len,
+            None if back => len - 1,
+            None => 0,
+        };
+        Some(order[next])
+    }
+
+    fn move_focus(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+        button_query: Query<(Entity, &Node, &GlobalTransform), With<Button>>,
+        trap_query: Query<Entity, With<FocusTrap>>,
+        parent_query: Query<&Parent>,
+        mut focus: ResMut<ButtonFocus>,
+    ) {
+        if focus.0.is_some_and(|focused| !button_query.contains(focused)) {
+            focus.0 = None;
+        }
+        let trap = trap_query.iter().next();
+        let mut visible = button_query
+            .iter()
+            .filter(|(_, node, _)| node.size() != Vec2::ZERO)
+            .filter(|(entity, ..)| trap.is_none_or(|trap| parent_query.iter_ancestors(*entity).any(|ancestor| ancestor == trap)))
+            .map(|(entity, _, transform)| (entity, transform.translation().truncate()))
+            .collect::<Vec<_>>();
+
+        let pad_pressed = |button_type| {
+            gamepads.iter().any(|gamepad| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+        };
+        let back = keyboard_input.any_just_pressed([KeyCode::ArrowUp, KeyCode::ArrowLeft])
+            || pad_pressed(GamepadButtonType::DPadUp)
+            || pad_pressed(GamepadButtonType::DPadLeft);
+        let forward = keyboard_input.any_just_pressed([KeyCode::ArrowDown, KeyCode::ArrowRight, KeyCode::Tab])
+            || pad_pressed(GamepadButtonType::DPadDown)
+            || pad_pressed(GamepadButtonType::DPadRight);
+        if !back && !forward {
+            return;
+        }
+        // Reading order: rows top to bottom, then left to right within a row.
+        visible.sort_by(|a, b| a.1.y.total_cmp(&b.1.y).then(a.1.x.total_cmp(&b.1.x)));
+        let order = visible.iter().map(|&(entity, _)| entity).collect::<Vec<_>>();
+        focus.0 = step(&order, focus.0, back);
+    }
+
+    /// Presses the focused button the way a click would, and lets it go a frame later.
+    fn press_focused(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+        focus: Res<ButtonFocus>,
+        mut interaction_query: Query<&mut Interaction, With<Button>>,
+        mut pressed: Local<Option<Entity>>,
+    ) {
+        if let Some(entity) = pressed.take() {
+            if let Ok(mut interaction) = interaction_query.get_mut(entity) {
+                interaction.set_if_neq(Interaction::None);
+            }
+        }
+        let Some(focused) = focus.0 else {
+            return;
+        };
+        let activated = keyboard_input.just_pressed(KeyCode::Enter)
+            || gamepads.iter().any(|gamepad| {
+                gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::South))
+            });
+        if activated {
+            if let Ok(mut interaction) = interaction_query.get_mut(focused) {
+                *interaction = Interaction::Pressed;
+                *pressed = Some(focused);
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn style_buttons(
+        mut commands: Commands,
+        focus: Res<ButtonFocus>,
+        mut button_query: Query<
+            (Entity, &Interaction, &mut BackgroundColor, &mut Transform, Option<&BaseColor>, Has<Outline>),
+            With<Button>,
+        >,
+    ) {
+        for (entity, interaction, mut background, mut transform, base, outlined) in button_query.iter_mut() {
+            let Some(base) = base else {
+                commands.entity(entity).try_insert(BaseColor(background.0));
+                continue;
+            };
+            let tint = match interaction {
+                Interaction::None => 0.0,
+                Interaction::Hovered | Interaction::Pressed => HOVER_TINT,
+            };
+            // Keep whatever alpha a fade has set; only the shade changes.
+            let color = Color::rgba(
+                base.0.r() + tint,
+                base.0.g() + tint,
+                base.0.b() + tint,
+                background.0.a(),
+            );
+            if background.0 != color {
+                background.0 = color;
+            }
+            let scale = if *interaction == Interaction::Pressed { PRESSED_SCALE } else { 1.0 };
+            if transform.scale.x != scale {
+                transform.scale = Vec3::new(scale, scale, 1.0);
+            }
+
+            let focused = focus.0 == Some(entity);
+            if focused && !outlined {
+                commands.entity(entity).try_insert(Outline::new(Val::Px(2.0), Val::Px(2.0), FOCUS_OUTLINE));
+            } else if !focused && outlined {
+                commands.entity(entity).remove::<Outline>();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_focus_steps_wrap_round_both_ways() {
+            let order = [Entity::from_raw(0), Entity::from_raw(1), Entity::from_raw(2)];
+            assert_eq!(step(&order, None, false), Some(order[0]));
+            assert_eq!(step(&order, None, true), Some(order[2]));
+            assert_eq!(step(&order, Some(order[2]), false), Some(order[0]));
+            assert_eq!(step(&order, Some(order[0]), true), Some(order[2]));
+            assert_eq!(step(&[], Some(order[0]), false), None);
+        }
+    }
+}
+
+mod confirm {
+    use super::*;
+    use bevy::ui::FocusPolicy;
+
+    /// A modal yes/no question in front of whatever screen asked it, for actions that
+    /// can't be taken back. Send `ConfirmRequested` to ask; a `Confirmed` comes back only
+    /// if the player says yes. Game time stands still while it is open. Y confirms, N or
+    /// Escape (or the gamepad's east button) cancels, and "No" starts with focus, so
+    /// Enter on its own is always safe.
+    pub struct ConfirmPlugin;
+
+    impl Plugin for ConfirmPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<ConfirmRequested>()
+                .add_event::<Confirmed>()
+                .add_systems(Update, (answer_dialog, open_dialog).chain().in_set(ConfirmSet))
+                .add_systems(PostUpdate, resume_time);
+        }
+    }
+
+    /// Input gated on `dialog_closed` runs before this, so the key that closes a
+    /// dialog can't also act on the screen behind it in the same frame.
+    #[derive(SystemSet, Clone, PartialEq, Eq, Hash, Debug)]
+    pub struct ConfirmSet;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ConfirmAction {
+        AbandonRun,
+        ResetProfile,
+    }
+
+    impl ConfirmAction {
+        fn question(&self) -> &'static str {
+            match self {
+                ConfirmAction::AbandonRun => "Abandon this run?",
+                ConfirmAction::ResetProfile => "Erase run history, lifetime stats, banked gold and cosmetics?",
+            }
+        }
+    }
+
+    #[derive(Event, Debug)]
+    pub struct ConfirmRequested(pub ConfirmAction);
+
+    #[derive(Event, Debug)]
+    pub struct Confirmed(pub ConfirmAction);
+
+    #[derive(Component)]
+    pub struct ConfirmDialog(ConfirmAction);
+
+    /// The dialog's "Yes" (true) and "No" (false) buttons.
+    #[derive(Component)]
+    struct DialogButton(bool);
+
+    /// Run condition for input that would otherwise act behind an open dialog.
+    pub fn dialog_closed(query: Query<(), With<ConfirmDialog>>) -> bool {
+        query.is_empty()
+    }
+
+    fn open_dialog(
+        mut commands: Commands,
+        mut requests: EventReader<ConfirmRequested>,
+        dialog_query: Query<(), With<ConfirmDialog>>,
+        state: Res<State<GameState>>,
+        mut focus: ResMut<buttons::ButtonFocus>,
+        mut virtual_time: ResMut<Time<Virtual>>,
+    ) {
+        // One question at a time; anything asked while one is open is dropped.
+        let Some(ConfirmRequested(action)) = requests.read().last() else {
+            return;
+        };
+        if !dialog_query.is_empty() {
+            return;
+        }
+        virtual_time.pause();
+        let button = |label: &str| {
+            (
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(120.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(10.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                },
+                TextBundle::from_section(label, TextStyle { font_size: 22.0, color: Color::WHITE, ..default() }),
+            )
+        };
+        let mut no_button = None;
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(200),
+                ..default()
+            },
+            ConfirmDialog(*action),
+            buttons::FocusTrap,
+            DespawnOnExit(*state.get()),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                action.question(),
+                TextStyle { font_size: 32.0, color: Color::WHITE, ..default() },
+            ));
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                for (answer, label) in [(true, "Yes"), (false, "No")] {
+                    let (bundle, text) = button(label);
+                    let entity = parent.spawn((bundle, DialogButton(answer))).with_children(|parent| {
+                        parent.spawn(text);
+                    }).id();
+                    if !answer {
+                        no_button = Some(entity);
+                    }
+                }
+            });
+        });
+        focus.0 = no_button;
+    }
+
+    fn answer_dialog(
+        mut commands: Commands,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+        dialog_query: Query<(Entity, &ConfirmDialog)>,
+        button_query: Query<(&Interaction, &DialogButton), Changed<Interaction>>,
+        mut confirmed: EventWriter<Confirmed>,
+    ) {
+        let Ok((entity, dialog)) = dialog_query.get_single() else {
+            return;
+        };
+        let clicked = button_query
+            .iter()
+            .find(|(interaction, _)| **interaction == Interaction::Pressed)
+            .map(|(_, button)| button.0);
+        let cancelled = keyboard_input.any_just_pressed([KeyCode::KeyN, KeyCode::Escape])
+            || gamepads.iter().any(|gamepad| {
+                gamepad_buttons.just_pressed(GamepadButton::new(gamepad, GamepadButtonType::East))
+            });
+        let answer = if keyboard_input.just_pressed(KeyCode::KeyY) {
+            Some(true)
+        } else if cancelled {
+            Some(false)
+        } else {
+            clicked
+        };
+        let Some(answer) = answer else {
+            return;
+        };
+        if answer {
+            confirmed.send(Confirmed(dialog.0));
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+
+    /// Lets game time run again once the dialog is gone, however it went.
+    fn resume_time(mut removed: RemovedComponents<ConfirmDialog>, mut virtual_time: ResMut<Time<Virtual>>) {
+        if removed.read().count() > 0 {
+            virtual_time.unpause();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn dialog_app() -> App {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .init_state::<GameState>()
+               .init_resource::<ButtonInput<KeyCode>>()
+               .init_resource::<ButtonInput<GamepadButton>>()
+               .init_resource::<Gamepads>()
+               .init_resource::<buttons::ButtonFocus>()
+               .add_plugins(ConfirmPlugin);
+            app
+        }
+
+        fn press(app: &mut App, key: KeyCode) {
+            let mut input = app.world.resource_mut::<ButtonInput<KeyCode>>();
+            input.clear();
+            input.press(key);
+            app.update();
+            app.world.resource_mut::<ButtonInput<KeyCode>>().release(key);
+        }
+
+        fn confirmed(app: &mut App) -> Vec<ConfirmAction> {
+            app.world.resource_mut::<Events<Confirmed>>().drain().map(|confirmed| confirmed.0).collect()
+        }
+
+        #[test]
+        fn test_dialog_pauses_time_and_only_yes_confirms() {
+            let mut app = dialog_app();
+            app.world.send_event(ConfirmRequested(ConfirmAction::AbandonRun));
+            app.update();
+            assert_eq!(app.world.query::<&ConfirmDialog>().iter(&app.world).count(), 1);
+            assert!(app.world.resource::<Time<Virtual>>().is_paused());
+            assert!(app.world.resource::<buttons::ButtonFocus>().0.is_some());
+
+            press(&mut app, KeyCode::Escape);
+            app.update();
+            assert!(confirmed(&mut app).is_empty());
+            assert!(!app.world.resource::<Time<Virtual>>().is_paused());
+
+            app.world.send_event(ConfirmRequested(ConfirmAction::ResetProfile));
+            app.update();
+            press(&mut app, KeyCode::KeyY);
+            app.update();
+            assert_eq!(confirmed(&mut app), vec![ConfirmAction::ResetProfile]);
+            assert_eq!(app.world.query::<&ConfirmDialog>().iter(&app.world).count(), 0);
+        }
+    }
+}
+
+mod toasts {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const MAX_VISIBLE_TOASTS: usize = 4;
+    const TOAST_DURATION: f32 = 3.0;
+    const TOAST_SLIDE: f32 = 60.0;
+
+    /// Short notices stacked in the top-right corner. Any system can send a `ToastEvent`;
+    /// at most `MAX_VISIBLE_TOASTS` show at once and the rest wait their turn. Each one
+    /// dismisses itself after `TOAST_DURATION` of real time, so they keep going while paused.
+    pub struct ToastsPlugin;
+
+    impl Plugin for ToastsPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<ToastEvent>()
+                .init_resource::<ToastQueue>()
+                .add_systems(Startup, setup_toast_stack)
+                .add_systems(Update, (queue_toasts, dismiss_toasts, show_toasts).chain());
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ToastKind {
+        Achievement,
+        Pickup,
+        Saved,
+        Error,
+    }
+
+    impl ToastKind {
+        fn icon(&self) -> &'static str {
+            match self {
+                ToastKind::Achievement => "*",
+                ToastKind::Pickup => "+",
+                ToastKind::Saved => "S",
+                ToastKind::Error => "!",
+            }
+        }
+
+        fn color(&self) -> Color {
+            match self {
+                ToastKind::Achievement => Color::rgb(1.0, 0.8, 0.1),
+                ToastKind::Pickup => Color::rgb(0.3, 0.9, 0.4),
+                ToastKind::Saved => Color::rgb(0.6, 0.6, 0.6),
+                ToastKind::Error => Color::rgb(1.0, 0.3, 0.3),
+            }
+        }
+    }
+
+    #[derive(Event, Clone, Debug)]
+    pub struct ToastEvent {
+        pub kind: ToastKind,
+        pub text: String,
+    }
+
+    impl ToastEvent {
+        pub fn new(kind: ToastKind, text: impl Into<String>) -> Self {
+            Self { kind, text: text.into() }
+        }
+    }
+
+    /// Toasts sent while the stack was full, oldest first.
+    #[derive(Resource, Default, Debug)]
+    struct ToastQueue(VecDeque<ToastEvent>);
+
+    #[derive(Component)]
+    struct ToastStack;
+
+    #[derive(Component)]
+    struct Toast(Timer);
+
+    fn setup_toast_stack(mut commands: Commands) {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(60.0),
+                    right: Val::Px(10.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::FlexEnd,
+                    row_gap: Val::Px(6.0),
+                    ..default()
+                },
+                z_index: ZIndex::Global(300),
+                ..default()
+            },
+            ToastStack,
+        ));
+    }
+
+    fn queue_toasts(mut events: EventReader<ToastEvent>, mut queue: ResMut<ToastQueue>) {
+        queue.0.extend(events.read().cloned());
+    }
+
+    /// Fades out toasts whose time is up and clears away the ones already faded.
+    fn dismiss_toasts(
+        mut commands: Commands,
+        time: Res<Time<Real>>,
+        mut toast_query: Query<(Entity, &mut Toast, &Style)>,
+    ) {
+        for (entity, mut toast, style) in toast_query.iter_mut() {
+            if style.display == Display::None {
+                commands.entity(entity).despawn_recursive();
+            } else if toast.0.tick(time.delta()).just_finished() {
+                commands.entity(entity).insert(ui_anim::Tween::fade_out());
+            }
+        }
+    }
+
+    fn show_toasts(
+        mut commands: Commands,
+        mut queue: ResMut<ToastQueue>,
+        stack_query: Query<Entity, With<ToastStack>>,
+        toast_query: Query<(), With<Toast>>,
+    ) {
+        let Ok(stack) = stack_query.get_single() else {
+            return;
+        };
+        let free = MAX_VISIBLE_TOASTS.saturating_sub(toast_query.iter().count()).min(queue.0.len());
+        for toast in queue.0.drain(..free) {
+            let color = toast.kind.color();
+            let entity = commands.spawn((
+                NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(8.0),
+                        padding: UiRect::axes(Val::Px(10.0), Val::Px(6.0)),
+                        border: UiRect::left(Val::Px(3.0)),
+                        ..default()
+                    },
+                    background_color: Color::rgba(0.05, 0.05, 0.1, 0.85).into(),
+                    border_color: color.into(),
+                    ..default()
+                },
+                Toast(Timer::from_seconds(TOAST_DURATION, TimerMode::Once)),
+                ui_anim::Tween::slide_in(Vec2::new(TOAST_SLIDE, 0.0)),
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    toast.kind.icon(),
+                    TextStyle { font_size: 20.0, color, ..default() },
+                ));
+                parent.spawn(TextBundle::from_section(
+                    toast.text,
+                    TextStyle { font_size: 18.0, color: Color::WHITE, ..default() },
+                ));
+            }).id();
+            commands.entity(stack).add_child(entity);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_toasts_past_the_visible_limit_wait_their_turn() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins).add_plugins(ToastsPlugin);
+            for n in 0..MAX_VISIBLE_TOASTS + 2 {
+                app.world.send_event(ToastEvent::new(ToastKind::Pickup, format!("Toast {}", n)));
+            }
+            app.update();
+            app.update();
+
+            assert_eq!(app.world.query::<&Toast>().iter(&app.world).count(), MAX_VISIBLE_TOASTS);
+            assert_eq!(app.world.resource::<ToastQueue>().0.len(), 2);
+            assert_eq!(app.world.resource::<ToastQueue>().0[0].text, format!("Toast {}", MAX_VISIBLE_TOASTS));
+        }
+    }
+}
+
+mod waves {
+    use super::*;
+    use serde::Deserialize;
+    use std::collections::VecDeque;
+
+    pub struct WavePlugin;
+
+    impl Plugin for WavePlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(WaveDirector::new(WaveScript::load()))
+                .add_event::<ScriptedEventStarted>()
+                .add_systems(Update, (run_wave_director.run_if(run::not_sandbox), hatch_telegraphs).run_if(in_play))
+                .add_systems(ResetRun, reset_wave_director);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+    pub enum ScriptedEvent {
+        MegaWave,
+        /// Closes in from a circle all round the player.
+        Ring,
+        /// Two groups from opposite sides at once.
+        Pincer,
+        Elite,
+        Boss,
+    }
+
+    impl ScriptedEvent {
+        pub fn icon(&self) -> &'static str {
+            match self {
+                ScriptedEvent::MegaWave => "W",
+                ScriptedEvent::Ring => "R",
+                ScriptedEvent::Pincer => "P",
+                ScriptedEvent::Elite => "E",
+                ScriptedEvent::Boss => "B",
+            }
+        }
+
+        pub fn color(&self) -> Color {
+            match self {
+                ScriptedEvent::MegaWave => Color::rgb(0.9, 0.2, 0.2),
+                ScriptedEvent::Ring => Color::rgb(1.0, 0.4, 0.1),
+                ScriptedEvent::Pincer => Color::rgb(0.9, 0.1, 0.5),
+                ScriptedEvent::Elite => Color::rgb(1.0, 0.8, 0.1),
+                ScriptedEvent::Boss => Color::rgb(0.8, 0.1, 0.9),
+            }
+        }
+    }
+
+    #[derive(Component)]
+    pub struct Boss;
+
+    /// Sent when the director fires a scripted event, after its enemies are queued to spawn.
+    #[derive(Event, Debug)]
+    pub struct ScriptedEventStarted {
+        pub event: ScriptedEvent,
+        /// Where its enemies are coming in.
+        #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+        pub position: Vec3,
+    }
+
+    const WAVE_SCRIPT_PATH: &str = "assets/data/waves.ron";
+    const MEGA_WAVE_SIZE: usize = 100;
+    const RING_SIZE: usize = 32;
+    const RING_RADIUS: f32 = 600.0;
+    /// Enemies on each side of a pincer.
+    const PINCER_SIZE: usize = 25;
+    const PINCER_DISTANCE: f32 = 800.0;
+    /// How long a formation's ground markers show before its enemies appear.
+    const TELEGRAPH_DURATION: f32 = 1.0;
+    /// How far back `Condition::RecentKillsBelow` and `RecentKillsAbove` count kills.
+    const RECENT_KILLS_WINDOW: f32 = 60.0;
+
+    /// Something about the run a block can wait for before it fires.
+    #[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+    pub enum Condition {
+        LevelAbove(u32),
+        LevelBelow(u32),
+        /// Fewer kills than this in the last minute.
+        RecentKillsBelow(u32),
+        RecentKillsAbove(u32),
+    }
+
+    impl Condition {
+        fn holds(&self, level: u32, recent_kills: u32) -> bool {
+            match *self {
+                Condition::LevelAbove(n) => level > n,
+                Condition::LevelBelow(n) => level < n,
+                Condition::RecentKillsBelow(n) => recent_kills < n,
+                Condition::RecentKillsAbove(n) => recent_kills > n,
+            }
+        }
+    }
+
+    /// One entry in the wave script: `events` fire together at `at` run seconds, or
+    /// the first moment after that when every `when` condition holds. With `every`
+    /// the block repeats, up to `times` times; a block with conditions counts its
+    /// next repeat from when it last fired. Nothing fires after `until`.
+    #[derive(Clone, Debug, PartialEq, Deserialize)]
+    pub struct WaveBlock {
+        pub at: f32,
+        #[serde(default)]
+        pub every: Option<f32>,
+        #[serde(default)]
+        pub times: Option<u32>,
+        #[serde(default)]
+        pub until: Option<f32>,
+        #[serde(default)]
+        pub when: Vec<Condition>,
+        pub events: Vec<ScriptedEvent>,
+    }
+
+    impl WaveBlock {
+        fn fires_again(&self, fired: u32) -> bool {
+            self.every.is_some() && self.times.is_none_or(|times| fired < times)
+        }
+
+        fn expired(&self, at: f32) -> bool {
+            self.until.is_some_and(|until| at > until)
+        }
+
+        /// Why the director couldn't run this block, if it couldn't. An `every` of zero or
+        /// less would never get past the current time.
+        fn problem(&self) -> Option<&'static str> {
+            if !self.at.is_finite() || self.until.is_some_and(|until| !until.is_finite()) {
+                Some("`at` and `until` must be finite")
+            } else if self.every.is_some_and(|every| !(every > 0.0 && every.is_finite())) {
+                Some("`every` must be a finite number of seconds above zero")
+            } else if self.times == Some(0) {
+                Some("`times` must be at least 1")
+            } else {
+                None
+            }
+        }
+    }
+
+    /// The scripted events a run goes through, loaded from `WAVE_SCRIPT_PATH`. The
+    /// default is the built-in timeline, used when the file is missing.
+    #[derive(Clone, Debug, PartialEq, Deserialize)]
+    #[serde(transparent)]
+    pub struct WaveScript(Vec<WaveBlock>);
+
+    impl WaveScript {
+        fn load() -> Self {
+            Self::checked(persistence::load_ron(WAVE_SCRIPT_PATH))
+        }
+
+        /// `script`, or the built-in timeline if any block in it can't be run.
+        fn checked(script: Self) -> Self {
+            match script.0.iter().find_map(WaveBlock::problem) {
+                Some(problem) => {
+                    warn!("Ignoring {}: {}", WAVE_SCRIPT_PATH, problem);
+                    Self::default()
+                }
+                None => script,
+            }
+        }
+    }
+
+    impl Default for WaveScript {
+        fn default() -> Self {
+            let block = |at, every, events: &[ScriptedEvent]| WaveBlock {
+                at,
+                every,
+                times: None,
+                until: None,
+                when: Vec::new(),
+                events: events.to_vec(),
+            };
+            Self(vec![
+                block(60.0, Some(60.0), &[ScriptedEvent::MegaWave]),
+                block(90.0, Some(120.0), &[ScriptedEvent::Ring]),
+                block(150.0, None, &[ScriptedEvent::Elite]),
+                block(270.0, Some(120.0), &[ScriptedEvent::Pincer]),
+                block(300.0, None, &[ScriptedEvent::Boss]),
+            ])
+        }
+    }
+
+    /// Where the director is in a block: when it's next due and how often it has fired.
+    #[derive(Clone, Copy, Debug)]
+    struct BlockState {
+        next_at: f32,
+        fired: u32,
+        done: bool,
+    }
+
+    /// Interprets the wave script over the course of a run.
+    #[derive(Resource)]
+    pub struct WaveDirector {
+        script: WaveScript,
+        blocks: Vec<BlockState>,
+        /// Run times of kills within `RECENT_KILLS_WINDOW`, oldest first.
+        recent_kills: VecDeque<f32>,
+    }
+
+    impl WaveDirector {
+        fn new(script: WaveScript) -> Self {
+            let blocks = script
+                .0
+                .iter()
+                .map(|block| BlockState { next_at: block.at, fired: 0, done: false })
+                .collect();
+            Self { script, blocks, recent_kills: VecDeque::new() }
+        }
+
+        fn restart(&mut self) {
+            *self = Self::new(self.script.clone());
+        }
+
+        fn record_kill(&mut self, now: f32) {
+            self.recent_kills.push_back(now);
+        }
+
+        /// Every event due by `now`, in script order, moving each block on past it.
+        fn advance(&mut self, now: f32, level: u32) -> Vec<ScriptedEvent> {
+            while self.recent_kills.front().is_some_and(|&at| at < now - RECENT_KILLS_WINDOW) {
+                self.recent_kills.pop_front();
+            }
+            let recent_kills = self.recent_kills.len() as u32;
+
+            let mut due = Vec::new();
+            for (block, state) in self.script.0.iter().zip(self.blocks.iter_mut()) {
+                while !state.done && state.next_at <= now {
+                    if block.expired(state.next_at) || block.expired(now) {
+                        state.done = true;
+                        break;
+                    }
+                    if !block.when.iter().all(|condition| condition.holds(level, recent_kills)) {
+                        break;
+                    }
+                    due.extend(block.events.iter().copied());
+                    state.fired += 1;
+                    match block.every {
+                        Some(every) if block.fires_again(state.fired) => {
+                            let from = if block.when.is_empty() { state.next_at } else { now };
+                            state.next_at = from + every;
+                        }
+                        _ => state.done = true,
+                    }
+                }
+            }
+            due
+        }
+
+        /// Events certain to fire in `(now, to]`, in order, for the HUD timeline.
+        /// Blocks with conditions are left out, since they may not fire on time or at all.
+        pub fn upcoming(&self, now: f32, to: f32) -> Vec<(f32, ScriptedEvent)> {
+            let mut events = Vec::new();
+            for (block, state) in self.script.0.iter().zip(self.blocks.iter()) {
+                if state.done || !block.when.is_empty() {
+                    continue;
+                }
+                let (mut at, mut fired) = (state.next_at, state.fired);
+                while at <= to && !block.expired(at) {
+                    if at > now {
+                        events.extend(block.events.iter().map(|&event| (at, event)));
+                    }
+                    fired += 1;
+                    match block.every {
+                        Some(every) if block.fires_again(fired) => at += every,
+                        _ => break,
+                    }
+                }
+            }
+            events.sort_by(|a, b| a.0.total_cmp(&b.0));
+            events
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn run_wave_director(
+        mut commands: Commands,
+        mut director: ResMut<WaveDirector>,
+        current_run: Res<run::CurrentRun>,
+        player_stats: Res<leveling::PlayerStats>,
+        mut death_events: EventReader<enemy::EnemyDied>,
+        mut run_rng: ResMut<run::RunRng>,
+        settings: Res<settings::Settings>,
+        arena: Res<arena::BossArena>,
+        player_query: Query<&Transform, With<player::Player>>,
+        mut started_events: EventWriter<ScriptedEventStarted>,
+    ) {
+        for _ in death_events.read() {
+            director.record_kill(current_run.elapsed);
+        }
+        let due = director.advance(current_run.elapsed, player_stats.level);
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player_position = player_transform.translation;
+        let safe_radius = settings.gameplay.spawn_safe_radius;
+        let safe_position = |position| enemy::outside_safe_zone(position, player_position, safe_radius);
+
+        for event in due {
+            // The boss gets the arena to itself.
+            let swarm = matches!(event, ScriptedEvent::MegaWave | ScriptedEvent::Ring | ScriptedEvent::Pincer);
+            if swarm && arena.is_active() {
+                continue;
+            }
+            let rng = &mut run_rng.0;
+            let direction = match rng.gen_range(0..4) {
+                0 => Vec3::new(0.0, 1.0, 0.0),  // North
+                1 => Vec3::new(0.0, -1.0, 0.0), // South
+                2 => Vec3::new(1.0, 0.0, 0.0),  // East
+                _ => Vec3::new(-1.0, 0.0, 0.0), // West
+            };
+            let spawn_center = safe_position(player_position + direction * 1200.0);
+
+            match event {
+                ScriptedEvent::MegaWave => {
+                    for _ in 0..MEGA_WAVE_SIZE {
+                        let offset = Vec3::new(
+                            rng.gen_range(-100.0..100.0),
+                            rng.gen_range(-100.0..100.0),
+                            0.0,
+                        );
+                        let position = safe_position(spawn_center + offset);
+                        commands.spawn(enemy::enemy_bundle(position, event.color(), enemy::EnemyKind::Grunt));
+                    }
+                }
+                ScriptedEvent::Ring => {
+                    for i in 0..RING_SIZE {
+                        let angle = i as f32 / RING_SIZE as f32 * std::f32::consts::TAU;
+                        let offset = Vec3::new(angle.cos(), angle.sin(), 0.0) * RING_RADIUS;
+                        commands.spawn(telegraph_bundle(safe_position(player_position + offset), event.color()));
+                    }
+                }
+                ScriptedEvent::Pincer => {
+                    for side in [direction, -direction] {
+                        let center = player_position + side * PINCER_DISTANCE;
+                        for _ in 0..PINCER_SIZE {
+                            let offset = Vec3::new(rng.gen_range(-80.0..80.0), rng.gen_range(-80.0..80.0), 0.0);
+                            commands.spawn(telegraph_bundle(safe_position(center + offset), event.color()));
+                        }
+                    }
+                }
+                ScriptedEvent::Elite => {
+                    commands.spawn((
+                        enemy::champion_bundle(spawn_center, event.color(), 1.8, ENEMY_HEALTH * 25.0, enemy::EnemyKind::Elite),
+                        enemy::AvoidsObstacles,
+                    ));
+                }
+                ScriptedEvent::Boss => {
+                    commands.spawn((
+                        enemy::champion_bundle(spawn_center, event.color(), 3.0, ENEMY_HEALTH * 150.0, enemy::EnemyKind::Boss),
+                        enemy::AvoidsObstacles,
+                        Boss,
+                    ));
+                }
+            }
+            started_events.send(ScriptedEventStarted {
+                event,
+                position: spawn_center,
+            });
+        }
+    }
+
+    /// A faint ground marker where a formation enemy is about to appear.
+    #[derive(Component)]
+    struct SpawnTelegraph {
+        timer: Timer,
+        color: Color,
+    }
+
+    fn telegraph_bundle(position: Vec3, color: Color) -> impl Bundle {
+        (
+            SpriteBundle {
+                sprite: Sprite {
+                    color: color.with_a(0.0),
+                    custom_size: Some(Vec2::splat(ENEMY_SIZE * 1.5)),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Decals.at(position)),
+                ..default()
+            },
+            SpawnTelegraph {
+                timer: Timer::from_seconds(TELEGRAPH_DURATION, TimerMode::Once),
+                color,
+            },
+            DespawnOnExit::RUN,
+        )
+    }
+
+    /// Fades telegraphs in, then swaps each for its enemy.
+    fn hatch_telegraphs(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut SpawnTelegraph, &mut Sprite, &Transform)>,
+    ) {
+        for (entity, mut telegraph, mut sprite, transform) in query.iter_mut() {
+            let progress = telegraph.timer.tick(time.delta()).fraction();
+            sprite.color.set_a(0.35 * progress);
+            if telegraph.timer.finished() {
+                commands.entity(entity).despawn();
+                let position = render_layer::RenderLayer::Enemies.at(transform.translation);
+                commands.spawn(enemy::enemy_bundle(position, telegraph.color, enemy::EnemyKind::Grunt));
+            }
+        }
+    }
+
+    fn reset_wave_director(mut director: ResMut<WaveDirector>) {
+        director.restart();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_shipped_wave_script_matches_the_built_in_one() {
+            let shipped: WaveScript = ron::from_str(include_str!("../assets/data/waves.ron")).unwrap();
+            assert_eq!(shipped, WaveScript::default());
+        }
+
+        #[test]
+        fn test_upcoming_mixes_recurring_and_one_off_events() {
+            let director = WaveDirector::new(WaveScript::default());
+            let events = director.upcoming(100.0, 300.0);
+            assert_eq!(
+                events,
+                vec![
+                    (120.0, ScriptedEvent::MegaWave),
+                    (150.0, ScriptedEvent::Elite),
+                    (180.0, ScriptedEvent::MegaWave),
+                    (210.0, ScriptedEvent::Ring),
+                    (240.0, ScriptedEvent::MegaWave),
+                    (270.0, ScriptedEvent::Pincer),
+                    (300.0, ScriptedEvent::MegaWave),
+                    (300.0, ScriptedEvent::Boss),
+                ]
+            );
+            assert!(director.upcoming(300.0, 300.0).is_empty());
+        }
+
+        #[test]
+        fn test_conditional_blocks_wait_and_repeat_from_when_they_fired() {
+            let script: WaveScript = ron::from_str(
+                "[
+                    (at: 10.0, when: [LevelAbove(4)], events: [Elite]),
+                    (at: 0.0, every: Some(30.0), times: Some(2), when: [RecentKillsBelow(2)], events: [MegaWave]),
+                ]",
+            )
+            .unwrap();
+            let mut director = WaveDirector::new(script);
+            assert!(director.upcoming(0.0, 100.0).is_empty());
+
+            director.record_kill(0.0);
+            director.record_kill(1.0);
+            assert_eq!(director.advance(5.0, 1), vec![]);
+            assert_eq!(director.advance(20.0, 4), vec![]);
+            // The kill at 0 has aged out of the last minute.
+            assert_eq!(director.advance(61.0, 5), vec![ScriptedEvent::Elite, ScriptedEvent::MegaWave]);
+            assert_eq!(director.advance(90.0, 5), vec![]);
+            assert_eq!(director.advance(91.0, 5), vec![ScriptedEvent::MegaWave]);
+            assert_eq!(director.advance(500.0, 5), vec![]);
+        }
+
+        #[test]
+        fn test_scripts_that_would_stall_fall_back_to_the_built_in_one() {
+            for block in [
+                "(at: 10.0, every: Some(0.0), events: [Ring])",
+                "(at: 10.0, every: Some(-5.0), events: [Ring])",
+                "(at: 10.0, every: Some(30.0), times: Some(0), events: [Ring])",
+                "(at: inf, events: [Ring])",
+                "(at: 10.0, until: Some(NaN), events: [Ring])",
+            ] {
+                let script: WaveScript = ron::from_str(&format!("[(at: 60.0, events: [Elite]), {}]", block)).unwrap();
+                assert_eq!(WaveScript::checked(script), WaveScript::default(), "{}", block);
+            }
+
+            let fine: WaveScript = ron::from_str("[(at: 10.0, every: Some(30.0), times: Some(2), events: [Ring])]").unwrap();
+            assert_eq!(WaveScript::checked(fine.clone()), fine);
+        }
+    }
+}
+
+mod game_speed {
+    use super::*;
+
+    #[cfg(debug_assertions)]
+    const MIN_DEBUG_SCALE: f32 = 0.125;
+    #[cfg(debug_assertions)]
+    const MAX_DEBUG_SCALE: f32 = 8.0;
+
+    pub struct GameSpeedPlugin;
+
+    impl Plugin for GameSpeedPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(GameSpeed::default())
+                .add_systems(Update, apply_game_speed)
+                .add_systems(ResetRun, clear_speed_effects);
+
+            #[cfg(debug_assertions)]
+            app.add_systems(Update, debug_speed_controls.before(apply_game_speed));
+        }
+    }
+
+    /// Global time scale applied to `Time<Virtual>`, so every gameplay system
+    /// reading `Res<Time>` slows down together.
+    #[derive(Resource, Debug)]
+    pub struct GameSpeed {
+        /// Manual multiplier, adjusted with `[` / `]` in debug builds.
+        pub debug_scale: f32,
+        effects: Vec<SpeedEffect>,
+    }
+
+    /// A temporary slow-down that lasts for a duration of real (unscaled) time.
+    #[derive(Debug)]
+    struct SpeedEffect {
+        scale: f32,
+        remaining: Timer,
+    }
+
+    impl Default for GameSpeed {
+        fn default() -> Self {
+            Self {
+                debug_scale: 1.0,
+                effects: Vec::new(),
+            }
+        }
+    }
+
+    impl GameSpeed {
+        /// Runs the game at `scale` for `seconds` of real time. Overlapping
+        /// effects don't stack; the slowest one wins.
+        pub fn slow_motion(&mut self, scale: f32, seconds: f32) {
+            self.effects.push(SpeedEffect {
+                scale,
+                remaining: Timer::from_seconds(seconds, TimerMode::Once),
+            });
+        }
+
+        pub fn scale(&self) -> f32 {
+            let effect_scale = self
+                .effects
+                .iter()
+                .map(|effect| effect.scale)
+                .fold(1.0, f32::min);
+            self.debug_scale * effect_scale
+        }
+    }
+
+    fn apply_game_speed(
+        mut game_speed: ResMut<GameSpeed>,
+        real_time: Res<Time<Real>>,
+        mut virtual_time: ResMut<Time<Virtual>>,
+    ) {
+        let delta = real_time.delta();
+        game_speed
+            .effects
+            .retain_mut(|effect| !effect.remaining.tick(delta).finished());
+
+        let scale = game_speed.scale();
+        if virtual_time.relative_speed() != scale {
+            virtual_time.set_relative_speed(scale);
+        }
+    }
+
+    #[cfg(debug_assertions)]
+    fn debug_speed_controls(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut game_speed: ResMut<GameSpeed>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::BracketLeft) {
+            game_speed.debug_scale = (game_speed.debug_scale / 2.0).max(MIN_DEBUG_SCALE);
+        }
+        if keyboard_input.just_pressed(KeyCode::BracketRight) {
+            game_speed.debug_scale = (game_speed.debug_scale * 2.0).min(MAX_DEBUG_SCALE);
+        }
+        if keyboard_input.just_pressed(KeyCode::Backslash) {
+            game_speed.debug_scale = 1.0;
+        }
+    }
+
+    fn clear_speed_effects(mut game_speed: ResMut<GameSpeed>) {
+        game_speed.effects.clear();
+    }
+}
+
+mod last_stand {
+    use super::*;
+
+    const LAST_STAND_HEALTH_FRACTION: f32 = 0.15;
+    const LAST_STAND_DURATION: f32 = 4.0;
+    const LAST_STAND_DAMAGE_MULTIPLIER: f32 = 2.0;
+    const BULLET_TIME_SCALE: f32 = 0.5;
+    const BULLET_TIME_DURATION: f32 = 1.5; // Real-time seconds
+
+    pub struct LastStandPlugin;
+
+    impl Plugin for LastStandPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                (trigger_last_stand, tick_last_stand)
+                    .chain()
+                    .in_set(combat::CombatSet::Death)
+                    .run_if(in_play),
+            );
+        }
+    }
+
+    /// Comeback window when the player drops to low health: bullet time plus a
+    /// short damage buff. Re-arms once health climbs back above the threshold.
+    #[derive(Component, Debug)]
+    pub struct LastStand {
+        armed: bool,
+        buff: Option<Timer>,
+    }
+
+    impl Default for LastStand {
+        fn default() -> Self {
+            Self {
+                armed: true,
+                buff: None,
+            }
+        }
+    }
+
+    impl LastStand {
+        pub fn is_active(&self) -> bool {
+            self.buff.is_some()
+        }
+
+        pub fn damage_multiplier(&self) -> f32 {
+            if self.is_active() {
+                LAST_STAND_DAMAGE_MULTIPLIER
+            } else {
+                1.0
+            }
+        }
+    }
+
+    fn trigger_last_stand(
+        mut query: Query<(&combat::Health, &mut LastStand), With<player::Player>>,
+        mut game_speed: ResMut<game_speed::GameSpeed>,
+    ) {
+        for (health, mut last_stand) in query.iter_mut() {
+            let low_health = health.current < health.max * LAST_STAND_HEALTH_FRACTION;
+            if !low_health {
+                last_stand.armed = true;
+            } else if last_stand.armed && health.current > 0.0 {
+                last_stand.armed = false;
+                last_stand.buff = Some(Timer::from_seconds(LAST_STAND_DURATION, TimerMode::Once));
+                game_speed.slow_motion(BULLET_TIME_SCALE, BULLET_TIME_DURATION);
+            }
+        }
+    }
+
+    fn tick_last_stand(mut query: Query<&mut LastStand>, time: Res<Time>) {
+        for mut last_stand in query.iter_mut() {
+            let expired = last_stand
+                .buff
+                .as_mut()
+                .is_some_and(|buff| buff.tick(time.delta()).finished());
+            if expired {
+                last_stand.buff = None;
+            }
+        }
+    }
+}
+
+mod kill_cam {
+    use super::*;
+
+    /// Real seconds from the killing blow to the game-over screen.
+    const KILL_CAM_DURATION: f32 = 2.0;
+    const KILL_CAM_TIME_SCALE: f32 = 0.2;
+    /// Camera zoom by the end of the kill cam; below 1.0 is closer in.
+    const KILL_CAM_ZOOM: f32 = 0.5;
+    /// The game-over screen's backdrop, so the fade hands over to it without a jump.
+    const FADE_ALPHA: f32 = 0.7;
+
+    /// Plays between the player's death and the game-over screen: the world drops into
+    /// slow motion while the camera closes in on the player as they spin away, and the
+    /// screen fades out over the second half.
+    pub struct KillCamPlugin;
+
+    impl Plugin for KillCamPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Dying), start_kill_cam)
+                .add_systems(
+                    Update,
+                    play_kill_cam
+                        .after(input::InputSet)
+                        .before(combat::CombatSet::Targeting)
+                        .run_if(in_state(GameState::Dying)),
+                )
+                .add_systems(OnExit(GameState::Dying), end_kill_cam)
+                .add_systems(ResetRun, reset_camera);
+        }
+    }
+
+    #[derive(Resource)]
+    struct KillCam {
+        timer: Timer,
+        camera_from: Vec2,
+    }
+
+    #[derive(Component)]
+    struct KillCamFade;
+
+    fn start_kill_cam(
+        mut commands: Commands,
+        mut game_speed: ResMut<game_speed::GameSpeed>,
+        camera_query: Query<&Transform, With<world_view::WorldCamera>>,
+    ) {
+        game_speed.slow_motion(KILL_CAM_TIME_SCALE, KILL_CAM_DURATION);
+        commands.insert_resource(KillCam {
+            timer: Timer::from_seconds(KILL_CAM_DURATION, TimerMode::Once),
+            camera_from: camera_query
+                .get_single()
+                .map_or(Vec2::ZERO, |transform| transform.translation.truncate()),
+        });
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    ..default()
+                },
+                background_color: Color::NONE.into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            KillCamFade,
+            DespawnOnExit(GameState::Dying),
+        ));
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn play_kill_cam(
+        real_time: Res<Time<Real>>,
+        mut kill_cam: ResMut<KillCam>,
+        mut input: ResMut<input::PlayerInput>,
+        mut player_query: Query<(&mut Transform, &mut Sprite), With<player::Player>>,
+        mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), (With<world_view::WorldCamera>, Without<player::Player>)>,
+        mut fade_query: Query<&mut BackgroundColor, With<KillCamFade>>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        let progress = kill_cam.timer.tick(real_time.delta()).fraction();
+        let eased = progress * (2.0 - progress);
+        // Nobody is steering a dead player.
+        input.movement = Vec3::ZERO;
+
+        if let Ok((mut player_transform, mut sprite)) = player_query.get_single_mut() {
+            player_transform.scale = Vec3::splat(1.0 - 0.7 * eased);
+            player_transform.rotation = Quat::from_rotation_z(eased * std::f32::consts::TAU);
+            sprite.color.set_a(1.0 - eased);
+            if let Ok((mut camera_transform, mut projection)) = camera_query.get_single_mut() {
+                let target = player_transform.translation.truncate();
+                camera_transform.translation =
+                    kill_cam.camera_from.lerp(target, eased).extend(camera_transform.translation.z);
+                projection.scale = 1.0 + (KILL_CAM_ZOOM - 1.0) * eased;
+            }
+        }
+        for mut fade in fade_query.iter_mut() {
+            fade.0 = Color::rgba(0.0, 0.0, 0.0, FADE_ALPHA * (progress * 2.0 - 1.0).clamp(0.0, 1.0));
+        }
+
+        if kill_cam.timer.finished() {
+            next_state.set(GameState::GameOver);
+        }
+    }
+
+    fn end_kill_cam(mut commands: Commands) {
+        commands.remove_resource::<KillCam>();
+    }
+
+    /// The camera stays zoomed in on where the player fell behind the game-over screen.
+    fn reset_camera(
+        mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<world_view::WorldCamera>>,
+    ) {
+        for (mut transform, mut projection) in camera_query.iter_mut() {
+            transform.translation = Vec2::ZERO.extend(transform.translation.z);
+            projection.scale = 1.0;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use bevy::time::TimeUpdateStrategy;
+        use std::time::Duration;
+
+        #[test]
+        fn test_kill_cam_hands_over_to_game_over() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(0.25)))
+               .init_state::<GameState>()
+               .init_resource::<input::PlayerInput>()
+               .init_resource::<game_speed::GameSpeed>()
+               .add_plugins(KillCamPlugin);
+            app.world.resource_mut::<NextState<GameState>>().set(GameState::Dying);
+
+            let state = |app: &App| *app.world.resource::<State<GameState>>().get();
+            app.update();
+            assert_eq!(state(&app), GameState::Dying);
+            for _ in 0..(KILL_CAM_DURATION / 0.25) as usize + 2 {
+                app.update();
+            }
+            assert_eq!(state(&app), GameState::GameOver);
+            assert!(!app.world.contains_resource::<KillCam>());
+        }
+    }
+}
+
+mod damage_log {
+    use super::*;
+    use player::{DamageKind, PlayerHurt};
+    use std::collections::VecDeque;
+
+    /// How many hits the log keeps.
+    const LOG_LENGTH: usize = 5;
+    /// Contact damage from the same kind of enemy within this many seconds of the last
+    /// tick counts as one hit, so the log reads as a few hits rather than frame ticks.
+    const CONTACT_MERGE_WINDOW: f32 = 0.5;
+
+    pub struct DamageLogPlugin;
+
+    impl Plugin for DamageLogPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<DamageLog>()
+                // In the same frame as the hits, before the state leaves `Running`.
+                .add_systems(
+                    Update,
+                    record_damage
+                        .in_set(combat::CombatSet::Death)
+                        .run_if(in_state(GameState::Running)),
+                )
+                .add_systems(ResetRun, clear_log);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct DamageEntry {
+        pub source: enemy::EnemyKind,
+        pub kind: DamageKind,
+        pub amount: f32,
+        /// Seconds into the run of the hit's last tick.
+        pub at: f32,
+    }
+
+    impl DamageEntry {
+        /// "Exploder (explosion, 20 dmg)".
+        pub fn describe(&self) -> String {
+            format!("{} ({}, {:.0} dmg)", self.source.label(), self.kind.label(), self.amount)
+        }
+    }
+
+    /// The last few hits the player took this run, oldest first.
+    #[derive(Resource, Default, Debug)]
+    pub struct DamageLog(VecDeque<DamageEntry>);
+
+    impl DamageLog {
+        fn record(&mut self, source: enemy::EnemyKind, kind: DamageKind, amount: f32, at: f32) {
+            if let Some(last) = self.0.back_mut() {
+                if kind == DamageKind::Contact
+                    && last.kind == kind
+                    && last.source == source
+                    && at - last.at <= CONTACT_MERGE_WINDOW
+                {
+                    last.amount += amount;
+                    last.at = at;
+                    return;
+                }
+            }
+            self.0.push_back(DamageEntry { source, kind, amount, at });
+            if self.0.len() > LOG_LENGTH {
+                self.0.pop_front();
+            }
+        }
+
+        /// The hit that ended the run, if the player died.
+        pub fn killing_blow(&self) -> Option<&DamageEntry> {
+            self.0.back()
+        }
+    }
+
+    fn record_damage(
+        mut hurt_events: EventReader<PlayerHurt>,
+        mut log: ResMut<DamageLog>,
+        current_run: Res<run::CurrentRun>,
+    ) {
+        for event in hurt_events.read() {
+            log.record(event.source, event.kind, event.amount, current_run.elapsed);
+        }
+    }
+
+    fn clear_log(mut log: ResMut<DamageLog>) {
+        log.0.clear();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use enemy::EnemyKind;
+
+        #[test]
+        fn test_contact_ticks_merge_into_one_hit() {
+            let mut log = DamageLog::default();
+            for tick in 0..30 {
+                log.record(EnemyKind::Grunt, DamageKind::Contact, 0.5, tick as f32 * 0.1);
+            }
+            log.record(EnemyKind::Spitter, DamageKind::Projectile, 8.0, 3.0);
+            log.record(EnemyKind::Spitter, DamageKind::Projectile, 8.0, 3.1);
+            for tick in 0..10 {
+                log.record(EnemyKind::Exploder, DamageKind::Contact, 3.5, 4.0 + tick as f32 * 0.1);
+            }
+
+            assert_eq!(log.0.len(), 4);
+            assert_eq!(log.0[0].amount, 15.0);
+            assert_eq!(log.killing_blow().unwrap().describe(), "Exploder (contact, 35 dmg)");
+        }
+    }
+}
+
+mod taunt {
+    use super::*;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+    const TAUNT_RADIUS: f32 = 450.0;
+    const TAUNT_COOLDOWN: f32 = 15.0;
+    const TAUNT_DURATION: f32 = 5.0;
+    const TAUNT_DAMAGE_BONUS: f32 = 0.5;
+    /// Taunted enemies come at the player this much faster than usual.
+    pub const TAUNTED_SPEED: f32 = 1.3;
+
+    pub struct TauntPlugin;
+
+    impl Plugin for TauntPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<Taunt>()
+                .add_event::<TauntUsed>()
+                .add_systems(
+                    Update,
+                    ((use_taunt, tick_taunt).in_set(combat::CombatSet::Targeting), expand_pulses).run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_taunt);
+        }
+    }
+
+    /// Pulls every enemy in range onto the player, in exchange for a short damage buff.
+    #[derive(Resource, Debug)]
+    pub struct Taunt {
+        cooldown: Timer,
+        buff: Option<Timer>,
+    }
+
+    impl Default for Taunt {
+        fn default() -> Self {
+            let mut cooldown = Timer::from_seconds(TAUNT_COOLDOWN, TimerMode::Once);
+            // Ready from the start of a run.
+            cooldown.tick(cooldown.duration());
+            Self { cooldown, buff: None }
+        }
+    }
+
+    impl Taunt {
+        pub fn modifier(&self) -> Option<attributes::StatModifier> {
+            self.buff.as_ref().map(|_| attributes::StatModifier {
+                stat: attributes::Stat::Damage,
+                op: attributes::ModifierOp::Percent(TAUNT_DAMAGE_BONUS),
+            })
+        }
+    }
+
+    #[derive(Event, Debug)]
+    pub struct TauntUsed;
+
+    /// Drops whatever the enemy was doing and goes straight for the player until it runs out.
+    #[derive(Component)]
+    pub struct Taunted(Timer);
+
+    #[derive(Component)]
+    struct TauntPulse(Timer);
+
+    #[allow(clippy::too_many_arguments)]
+    fn use_taunt(
+        mut commands: Commands,
+        mut input: ResMut<input::PlayerInput>,
+        mut taunt: ResMut<Taunt>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+        mut used_events: EventWriter<TauntUsed>,
+        mut player_query: Query<(&Transform, &mut energy::Energy), With<player::Player>>,
+        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+    ) {
+        if !taunt.cooldown.finished() {
+            return;
+        }
+        let Ok((player_transform, mut energy)) = player_query.get_single_mut() else {
+            return;
+        };
+        if !energy.can_afford(energy::TAUNT_COST) || !input.consume(input::Action::Taunt) {
+            return;
+        }
+        energy.spend(energy::TAUNT_COST);
+        let origin = player_transform.translation;
+        taunt.cooldown.reset();
+        taunt.buff = Some(Timer::from_seconds(TAUNT_DURATION, TimerMode::Once));
+        stats_changed.send(attributes::StatsChanged);
+        used_events.send(TauntUsed);
+
+        for (entity, transform) in enemy_query.iter() {
+            if transform.translation.distance(origin) < TAUNT_RADIUS {
+                commands
+                    .entity(entity)
+                    .insert(Taunted(Timer::from_seconds(TAUNT_DURATION, TimerMode::Once)));
+            }
+        }
+
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: Mesh2dHandle(meshes.add(Circle::new(TAUNT_RADIUS))),
+                material: materials.add(Color::rgba(1.0, 0.25, 0.2, 0.25)),
+                transform: Transform::from_translation(render_layer::RenderLayer::Vfx.at(origin)).with_scale(Vec3::ZERO),
+                ..default()
+            },
+            TauntPulse(Timer::from_seconds(0.35, TimerMode::Once)),
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    fn tick_taunt(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut taunt: ResMut<Taunt>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+        mut taunted_query: Query<(Entity, &mut Taunted)>,
+    ) {
+        taunt.cooldown.tick(time.delta());
+        if let Some(buff) = taunt.buff.as_mut() {
+            if buff.tick(time.delta()).finished() {
+                taunt.buff = None;
+                stats_changed.send(attributes::StatsChanged);
+            }
+        }
+        for (entity, mut taunted) in taunted_query.iter_mut() {
+            if taunted.0.tick(time.delta()).finished() {
+                commands.entity(entity).remove::<Taunted>();
+            }
+        }
+    }
+
+    fn expand_pulses(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut TauntPulse, &mut Transform)>,
+    ) {
+        for (entity, mut pulse, mut transform) in query.iter_mut() {
+            pulse.0.tick(time.delta());
+            transform.scale = Vec3::splat(pulse.0.fraction());
+            if pulse.0.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn reset_taunt(mut taunt: ResMut<Taunt>) {
+        *taunt = Taunt::default();
+    }
+}
+
+mod energy {
+    use super::*;
+
+    pub const BASE_MAX_ENERGY: f32 = 100.0;
+    /// Per second.
+    pub const BASE_ENERGY_REGEN: f32 = 12.0;
+    const ENERGY_PER_KILL: f32 = 1.0;
+    pub const DASH_COST: f32 = 20.0;
+    pub const TAUNT_COST: f32 = 60.0;
+
+    /// Shared pool that dash, taunt and any later active ability spend from, on top of
+    /// their own cooldowns. Refills over time and a little with every kill.
+    pub struct EnergyPlugin;
+
+    impl Plugin for EnergyPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                recharge_energy.in_set(combat::CombatSet::Drops).run_if(in_play),
+            );
+        }
+    }
+
+    #[derive(Component, Debug)]
+    pub struct Energy {
+        pub current: f32,
+        pub max: f32,
+    }
+
+    impl Default for Energy {
+        fn default() -> Self {
+            Self {
+                current: BASE_MAX_ENERGY,
+                max: BASE_MAX_ENERGY,
+            }
+        }
+    }
+
+    impl Energy {
+        pub fn can_afford(&self, cost: f32) -> bool {
+            self.current >= cost
+        }
+
+        pub fn spend(&mut self, cost: f32) {
+            self.current = (self.current - cost).max(0.0);
+        }
+
+        pub fn gain(&mut self, amount: f32) {
+            self.current = (self.current + amount).min(self.max);
+        }
+
+        pub fn fraction(&self) -> f32 {
+            if self.max > 0.0 {
+                self.current / self.max
+            } else {
+                0.0
+            }
+        }
+    }
+
+    fn recharge_energy(
+        mut player_query: Query<&mut Energy, With<player::Player>>,
+        mut died_events: EventReader<enemy::EnemyDied>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        time: Res<Time>,
+    ) {
+        let kills = died_events.read().count();
+        let Ok(mut energy) = player_query.get_single_mut() else {
+            return;
+        };
+        // A bigger capacitor comes charged by however much it grew.
+        if energy.max != effective_stats.max_energy {
+            let growth = effective_stats.max_energy - energy.max;
+            energy.max = effective_stats.max_energy;
+            energy.current = (energy.current + growth).clamp(0.0, energy.max);
+        }
+        let amount = effective_stats.energy_regen * time.delta_seconds() + kills as f32 * ENERGY_PER_KILL;
+        energy.gain(amount);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_energy_is_spent_and_refilled_within_bounds() {
+            let mut energy = Energy::default();
+            assert!(energy.can_afford(DASH_COST));
+            for _ in 0..5 {
+                energy.spend(DASH_COST);
+            }
+            assert!(!energy.can_afford(DASH_COST));
+            assert_eq!(energy.fraction(), 0.0);
+            energy.gain(BASE_MAX_ENERGY * 2.0);
+            assert_eq!(energy.current, energy.max);
+        }
+    }
+}
+
+mod buffs {
+    use super::*;
+
+    const BUFF_DURATION: f32 = 10.0;
+
+    /// Timed effects on the player, from power-ups dropped by enemies. Each is its own
+    /// entity, like a passive item, and feeds the stat pipeline while it lasts.
+    pub struct BuffsPlugin;
+
+    impl Plugin for BuffsPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<BuffGained>()
+                .add_systems(
+                    Update,
+                    (gain_buffs, tick_buffs)
+                        .chain()
+                        .after(combat::CombatSet::Drops)
+                        .run_if(in_play),
+                );
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum BuffKind {
+        DoubleDamage,
+        Haste,
+        Invulnerable,
+    }
+
+    impl BuffKind {
+        pub const ALL: [BuffKind; 3] = [BuffKind::DoubleDamage, BuffKind::Haste, BuffKind::Invulnerable];
+
+        /// Short text shown on the buff's icon in the HUD tray.
+        pub fn icon(&self) -> &'static str {
+            match self {
+                BuffKind::DoubleDamage => "2x",
+                BuffKind::Haste => "SPD",
+                BuffKind::Invulnerable => "INV",
+            }
+        }
+
+        pub fn color(&self) -> Color {
+            match self {
+                BuffKind::DoubleDamage => Color::rgb(1.8, 0.3, 0.2),
+                BuffKind::Haste => Color::rgb(1.4, 1.6, 0.2),
+                BuffKind::Invulnerable => Color::rgb(1.4, 1.6, 2.0),
+            }
+        }
+
+        /// Invulnerability has no stat; damage sources check for `Invulnerable` instead.
+        pub fn modifier(&self) -> Option<attributes::StatModifier> {
+            match self {
+                BuffKind::DoubleDamage => Some(attributes::StatModifier {
+                    stat: attributes::Stat::Damage,
+                    op: attributes::ModifierOp::Multiply(2.0),
+                }),
+                BuffKind::Haste => Some(attributes::StatModifier {
+                    stat: attributes::Stat::MoveSpeed,
+                    op: attributes::ModifierOp::Percent(0.5),
+                }),
+                BuffKind::Invulnerable => None,
+            }
+        }
+    }
+
+    #[derive(Component, Debug)]
+    pub struct Buff {
+        pub kind: BuffKind,
+        pub remaining: Timer,
+    }
+
+    /// On the player while an invulnerability buff lasts. Everything that hurts the
+    /// player skips players with it.
+    #[derive(Component)]
+    pub struct Invulnerable;
+
+    /// Sent when the player picks up a power-up.
+    #[derive(Event, Debug)]
+    pub struct BuffGained(pub BuffKind);
+
+    /// Starts each gained buff, or restarts its timer if it is already running;
+    /// the same buff never stacks with itself.
+    fn gain_buffs(
+        mut commands: Commands,
+        mut events: EventReader<BuffGained>,
+        mut buff_query: Query<&mut Buff>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+    ) {
+        for BuffGained(kind) in events.read() {
+            match buff_query.iter_mut().find(|buff| buff.kind == *kind) {
+                Some(mut buff) => buff.remaining.reset(),
+                None => {
+                    commands.spawn((
+                        Buff {
+                            kind: *kind,
+                            remaining: Timer::from_seconds(BUFF_DURATION, TimerMode::Once),
+                        },
+                        DespawnOnExit::RUN,
+                    ));
+                    stats_changed.send(attributes::StatsChanged);
+                }
+            }
+        }
+    }
+
+    fn tick_buffs(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut buff_query: Query<(Entity, &mut Buff)>,
+        player_query: Query<(Entity, Has<Invulnerable>), With<player::Player>>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+    ) {
+        let mut invulnerable = false;
+        for (entity, mut buff) in buff_query.iter_mut() {
+            if buff.remaining.tick(time.delta()).finished() {
+                commands.entity(entity).despawn();
+                stats_changed.send(attributes::StatsChanged);
+            } else {
+                invulnerable |= buff.kind == BuffKind::Invulnerable;
+            }
+        }
+        if let Ok((player, has_invulnerable)) = player_query.get_single() {
+            if invulnerable && !has_invulnerable {
+                commands.entity(player).try_insert(Invulnerable);
+            } else if !invulnerable && has_invulnerable {
+                commands.entity(player).remove::<Invulnerable>();
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_picking_up_a_running_buff_refreshes_it() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .add_event::<BuffGained>()
+               .add_event::<attributes::StatsChanged>()
+               .add_systems(Update, gain_buffs);
+
+            app.world.send_event(BuffGained(BuffKind::Haste));
+            app.update();
+            let mut query = app.world.query::<&mut Buff>();
+            query.single_mut(&mut app.world).remaining.tick(std::time::Duration::from_secs(6));
+
+            app.world.send_event(BuffGained(BuffKind::Haste));
+            app.update();
+            let buff = query.single(&app.world);
+            assert_eq!(buff.kind, BuffKind::Haste);
+            assert_eq!(buff.remaining.remaining_secs(), BUFF_DURATION);
+        }
+    }
+}
+
+mod buff_tray {
+    use super::*;
+    use bevy::{
+        asset::load_internal_asset,
+        render::render_resource::{AsBindGroup, ShaderRef},
+    };
+    use std::collections::HashSet;
+
+    const WIPE_SHADER_HANDLE: Handle<Shader> =
+        Handle::weak_from_u128(0x9b21_6e0f_43c8_4d7a_a5e2_17f0_c83d_6a94);
+    const ICON_SIZE: f32 = 36.0;
+
+    /// HUD tray along the top of the screen with an icon for every active `buffs::Buff`,
+    /// each darkened by a clockwise wipe as it runs out. It reads the buff entities
+    /// directly, so a new kind of effect shows up here without any changes.
+    /// Render-only, so the headless simulation leaves it out.
+    pub struct BuffTrayPlugin;
+
+    impl Plugin for BuffTrayPlugin {
+        fn build(&self, app: &mut App) {
+            load_internal_asset!(app, WIPE_SHADER_HANDLE, "buff_wipe.wgsl", Shader::from_wgsl);
+
+            app.add_plugins(UiMaterialPlugin::<WipeMaterial>::default())
+                .add_systems(OnEnter(GameState::Running), spawn_tray)
+                .add_systems(Update, sync_tray.run_if(in_state(GameState::Running)));
+        }
+    }
+
+    /// Shades the part of an icon whose time has elapsed; see `buff_wipe.wgsl`.
+    #[derive(Asset, TypePath, AsBindGroup, Clone, Debug)]
+    struct WipeMaterial {
+        #[uniform(0)]
+        color: Color,
+        /// Fraction of the buff still to run, from 1.0 down to 0.0.
+        #[uniform(0)]
+        remaining: f32,
+    }
+
+    impl UiMaterial for WipeMaterial {
+        fn fragment_shader() -> ShaderRef {
+            WIPE_SHADER_HANDLE.into()
+        }
+    }
+
+    #[derive(Component)]
+    struct BuffTray;
+
+    /// One icon in the tray, for the buff entity it shows.
+    #[derive(Component)]
+    struct TrayIcon {
+        buff: Entity,
+        wipe: Handle<WipeMaterial>,
+        countdown: Entity,
+    }
+
+    fn spawn_tray(mut commands: Commands, query: Query<(), With<BuffTray>>) {
+        if !query.is_empty() {
+            return;
+        }
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    top: Val::Px(10.0),
+                    justify_content: JustifyContent::Center,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                ..default()
+            },
+            BuffTray,
+            settings::SafeArea,
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    /// Adds an icon for every buff that lacks one, winds the rest down and drops
+    /// icons whose buff has expired.
+    fn sync_tray(
+        mut commands: Commands,
+        tray_query: Query<Entity, With<BuffTray>>,
+        buff_query: Query<(Entity, &buffs::Buff)>,
+        icon_query: Query<(Entity, &TrayIcon)>,
+        mut text_query: Query<&mut Text>,
+        mut materials: ResMut<Assets<WipeMaterial>>,
+    ) {
+        let Ok(tray) = tray_query.get_single() else {
+            return;
+        };
+
+        let mut shown = HashSet::new();
+        for (icon_entity, icon) in icon_query.iter() {
+            let Ok((_, buff)) = buff_query.get(icon.buff) else {
+                commands.entity(icon_entity).despawn_recursive();
+                continue;
+            };
+            shown.insert(icon.buff);
+            if let Some(wipe) = materials.get_mut(&icon.wipe) {
+                wipe.remaining = buff.remaining.fraction_remaining();
+            }
+            if let Ok(mut text) = text_query.get_mut(icon.countdown) {
+                text.sections[0].value = format!("{:.0}", buff.remaining.remaining_secs().ceil());
+            }
+        }
+
+        for (buff_entity, buff) in buff_query.iter().filter(|(entity, _)| !shown.contains(entity)) {
+            let wipe = materials.add(WipeMaterial {
+                color: Color::rgba(0.0, 0.0, 0.0, 0.65),
+                remaining: buff.remaining.fraction_remaining(),
+            });
+            let countdown = commands
+                .spawn(TextBundle::from_section("", TextStyle { font_size: 14.0, ..default() }))
+                .id();
+            let square = commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(ICON_SIZE),
+                        height: Val::Px(ICON_SIZE),
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    background_color: buff.kind.color().with_a(0.8).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        buff.kind.icon(),
+                        TextStyle { font_size: 16.0, color: Color::BLACK, ..default() },
+                    ));
+                    parent.spawn(MaterialNodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        material: wipe.clone(),
+                        ..default()
+                    });
+                })
+                .id();
+            let icon = commands
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    TrayIcon { buff: buff_entity, wipe, countdown },
+                ))
+                .push_children(&[square, countdown])
+                .id();
+            commands.entity(tray).add_child(icon);
+        }
+    }
+}
+
+mod weapon_strip {
+    use super::*;
+
+    const SLOT_WIDTH: f32 = 48.0;
+
+    /// HUD strip along the bottom of the screen with a slot for each owned weapon that
+    /// fires on a long cooldown, its bar filling as the next shot gets ready, so players
+    /// can time their movement around it. Render-only, so the headless simulation leaves it out.
+    pub struct WeaponStripPlugin;
+
+    impl Plugin for WeaponStripPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Running), spawn_strip)
+                .add_systems(Update, sync_strip.run_if(in_state(GameState::Running)));
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum StripWeapon {
+        Railgun,
+        Mines,
+        Whip,
+        Turret,
+        BlackHole,
+    }
+
+    impl StripWeapon {
+        const ALL: [StripWeapon; 5] = [
+            StripWeapon::Railgun,
+            StripWeapon::Mines,
+            StripWeapon::Whip,
+            StripWeapon::Turret,
+            StripWeapon::BlackHole,
+        ];
+
+        fn label(&self) -> &'static str {
+            match self {
+                StripWeapon::Railgun => "RAIL",
+                StripWeapon::Mines => "MINE",
+                StripWeapon::Whip => "WHIP",
+                StripWeapon::Turret => "TURR",
+                StripWeapon::BlackHole => "HOLE",
+            }
+        }
+    }
+
+    #[derive(Component)]
+    struct WeaponStrip;
+
+    /// A weapon's slot, hidden until it is owned.
+    #[derive(Component)]
+    struct StripSlot(StripWeapon);
+
+    /// The part of a slot's bar that fills as the weapon charges.
+    #[derive(Component)]
+    struct StripFill(StripWeapon);
+
+    fn spawn_strip(mut commands: Commands, query: Query<(), With<WeaponStrip>>) {
+        if !query.is_empty() {
+            return;
+        }
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Px(10.0),
+                    justify_content: JustifyContent::Center,
+                    column_gap: Val::Px(6.0),
+                    ..default()
+                },
+                ..default()
+            },
+            WeaponStrip,
+            settings::SafeArea,
+            DespawnOnExit::RUN,
+        )).with_children(|parent| {
+            for weapon in StripWeapon::ALL {
+                parent.spawn((
+                    NodeBundle {
+                        style: Style {
+                            display: Display::None,
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    StripSlot(weapon),
+                )).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        weapon.label(),
+                        TextStyle { font_size: 14.0, color: Color::GRAY, ..default() },
+                    ));
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            width: Val::Px(SLOT_WIDTH),
+                            height: Val::Px(6.0),
+                            ..default()
+                        },
+                        background_color: Color::rgba(1.0, 1.0, 1.0, 0.15).into(),
+                        ..default()
+                    }).with_children(|parent| {
+                        parent.spawn((
+                            NodeBundle {
+                                style: Style {
+                                    width: Val::Percent(0.0),
+                                    height: Val::Percent(100.0),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            StripFill(weapon),
+                        ));
+                    });
+                });
+            }
+        });
+    }
+
+    fn sync_strip(
+        railgun: Res<railgun::Railgun>,
+        mine_layer: Res<mines::MineLayer>,
+        whip: Res<whip::Whip>,
+        turret_deployer: Res<turrets::TurretDeployer>,
+        black_hole: Res<vortex::BlackHole>,
+        mut slot_query: Query<(&mut Style, &StripSlot), Without<StripFill>>,
+        mut fill_query: Query<(&mut Style, &mut BackgroundColor, &StripFill)>,
+    ) {
+        let charge = |weapon| match weapon {
+            StripWeapon::Railgun => railgun.charge(),
+            StripWeapon::Mines => mine_layer.charge(),
+            StripWeapon::Whip => whip.charge(),
+            StripWeapon::Turret => turret_deployer.charge(),
+            StripWeapon::BlackHole => black_hole.charge(),
+        };
+        for (mut style, slot) in slot_query.iter_mut() {
+            let display = if charge(slot.0).is_some() { Display::Flex } else { Display::None };
+            if style.display != display {
+                style.display = display;
+            }
+        }
+        for (mut style, mut color, fill) in fill_query.iter_mut() {
+            let charge = charge(fill.0).unwrap_or_default();
+            style.width = Val::Percent(charge * 100.0);
+            // Bright once ready, so a full bar reads at a glance.
+            *color = if charge >= 1.0 { Color::rgb(1.0, 0.9, 0.3) } else { Color::rgb(0.5, 0.6, 0.8) }.into();
+        }
+    }
+}
+
+mod frame_limit {
+    use super::*;
+    use bevy::{
+        utils::Instant,
+        winit::{UpdateMode, WinitSettings},
+    };
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration;
+
+    /// Frames per second while the window is in the background, when throttling is on.
+    const BACKGROUND_FPS: f64 = 10.0;
+
+    /// Caps the frame rate at the one picked in the options by sleeping at the end of
+    /// each frame, and drops to `BACKGROUND_FPS` while the window is unfocused or
+    /// minimized, to save battery on laptops and handhelds. Render-only, so the headless
+    /// simulation still runs flat out.
+    pub struct FrameLimitPlugin;
+
+    impl Plugin for FrameLimitPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Update, throttle_background)
+                .add_systems(Last, limit_frame_rate);
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum FrameCap {
+        Fps30,
+        Fps60,
+        Fps120,
+        #[default]
+        Uncapped,
+    }
+
+    impl FrameCap {
+        pub fn label(&self) -> &'static str {
+            match self {
+                FrameCap::Fps30 => "30 FPS",
+                FrameCap::Fps60 => "60 FPS",
+                FrameCap::Fps120 => "120 FPS",
+                FrameCap::Uncapped => "Uncapped",
+            }
+        }
+
+        pub fn next(&self) -> Self {
+            match self {
+                FrameCap::Fps30 => FrameCap::Fps60,
+                FrameCap::Fps60 => FrameCap::Fps120,
+                FrameCap::Fps120 => FrameCap::Uncapped,
+                FrameCap::Uncapped => FrameCap::Fps30,
+            }
+        }
+
+        fn frame_time(&self) -> Option<Duration> {
+            let fps = match self {
+                FrameCap::Fps30 => 30.0,
+                FrameCap::Fps60 => 60.0,
+                FrameCap::Fps120 => 120.0,
+                FrameCap::Uncapped => return None,
+            };
+            Some(Duration::from_secs_f64(1.0 / fps))
+        }
+    }
+
+    /// When the frame started at `previous` should end. Deadlines follow on from each
+    /// other so the rate holds steady, but a frame that overran starts the count afresh
+    /// rather than letting the next few rush to catch up.
+    fn next_deadline(previous: Option<Instant>, now: Instant, frame_time: Duration) -> Instant {
+        match previous {
+            Some(previous) if previous + frame_time > now => previous + frame_time,
+            _ => now,
+        }
+    }
+
+    fn limit_frame_rate(settings: Res<settings::Settings>, mut deadline: Local<Option<Instant>>) {
+        let Some(frame_time) = settings.graphics.frame_cap.frame_time() else {
+            *deadline = None;
+            return;
+        };
+        let now = Instant::now();
+        let next = next_deadline(*deadline, now, frame_time);
+        if next > now {
+            std::thread::sleep(next - now);
+        }
+        *deadline = Some(next);
+    }
+
+    fn throttle_background(settings: Res<settings::Settings>, mut winit_settings: ResMut<WinitSettings>) {
+        if !settings.is_changed() {
+            return;
+        }
+        winit_settings.unfocused_mode = if settings.graphics.background_throttle {
+            UpdateMode::Reactive { wait: Duration::from_secs_f64(1.0 / BACKGROUND_FPS) }
+        } else {
+            UpdateMode::Continuous
+        };
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_deadlines_hold_the_rate_and_reset_after_a_slow_frame() {
+            let frame_time = FrameCap::Fps60.frame_time().unwrap();
+            let start = Instant::now();
+            assert_eq!(next_deadline(None, start, frame_time), start);
+
+            let quick = start + Duration::from_millis(5);
+            assert_eq!(next_deadline(Some(start), quick, frame_time), start + frame_time);
+
+            let slow = start + Duration::from_millis(40);
+            assert_eq!(next_deadline(Some(start), slow, frame_time), slow);
+            assert_eq!(FrameCap::Uncapped.frame_time(), None);
+        }
+    }
+}
+
+mod world_view {
+    use super::*;
+    use bevy::{
+        render::{
+            camera::RenderTarget,
+            render_resource::{Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages},
+            texture::BevyDefault,
+            view::RenderLayers,
+        },
+        window::PrimaryWindow,
+    };
+
+    /// The game world is drawn by `WorldCamera` into an offscreen texture the size of the
+    /// window, and a second camera puts that texture on screen with the UI over it. So
+    /// fullscreen effects on the world camera (bloom and the post-process pass today)
+    /// never reach the UI, and the world's last frame is there to reuse. Render-only;
+    /// the headless simulation has no cameras at all.
+    pub struct WorldViewPlugin;
+
+    impl Plugin for WorldViewPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Startup, setup_world_view)
+                .add_systems(Update, fit_world_view_to_window);
+        }
+    }
+
+    /// The camera that draws the world. Anything that moves, zooms or listens from the
+    /// game's point of view wants this one rather than the screen camera.
+    #[derive(Component)]
+    pub struct WorldCamera;
+
+    /// The offscreen texture the world is drawn into.
+    #[derive(Resource)]
+    struct WorldView(Handle<Image>);
+
+    fn world_view_image(width: u32, height: u32) -> Image {
+        let size = Extent3d { width, height, depth_or_array_layers: 1 };
+        let mut image = Image {
+            texture_descriptor: TextureDescriptor {
+                label: Some("world_view"),
+                size,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::bevy_default(),
+                mip_level_count: 1,
+                sample_count: 1,
+                usage: TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_DST
+                    | TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            },
+            ..default()
+        };
+        image.resize(size);
+        image
+    }
+
+    fn setup_world_view(
+        mut commands: Commands,
+        mut images: ResMut<Assets<Image>>,
+        window_query: Query<&Window, With<PrimaryWindow>>,
+    ) {
+        let (width, height) = window_query
+            .get_single()
+            .map_or((1, 1), |window| (window.physical_width().max(1), window.physical_height().max(1)));
+        let image = images.add(world_view_image(width, height));
+
+        commands.spawn((
+            Camera2dBundle {
+                camera: Camera { target: RenderTarget::Image(image.clone()), ..default() },
+                ..default()
+            },
+            WorldCamera,
+            post_process::PostProcessSettings::default(),
+        ));
+        // Draws no sprites of its own, only the UI, with the world as its bottom node.
+        commands.spawn((
+            Camera2dBundle { camera: Camera { order: 1, ..default() }, ..default() },
+            RenderLayers::none(),
+        ));
+        commands.spawn(ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            image: UiImage::new(image.clone()),
+            z_index: ZIndex::Global(i32::MIN),
+            ..default()
+        });
+        commands.insert_resource(WorldView(image));
+    }
+
+    /// Keeps the texture at the window's full resolution. A minimized window reports
+    /// zero size, and the texture keeps its last one until it comes back.
+    fn fit_world_view_to_window(
+        world_view: Res<WorldView>,
+        mut images: ResMut<Assets<Image>>,
+        window_query: Query<&Window, (With<PrimaryWindow>, Changed<Window>)>,
+    ) {
+        let Ok(window) = window_query.get_single() else {
+            return;
+        };
+        let (width, height) = (window.physical_width(), window.physical_height());
+        if width == 0 || height == 0 {
+            return;
+        }
+        let Some(image) = images.get(&world_view.0) else {
+            return;
+        };
+        if image.width() != width || image.height() != height {
+            if let Some(image) = images.get_mut(&world_view.0) {
+                image.resize(Extent3d { width, height, depth_or_array_layers: 1 });
+            }
+        }
+    }
+}
+
+mod post_process {
+    use super::*;
+    use bevy::{
+        asset::load_internal_asset,
+        core_pipeline::{
+            bloom::{BloomCompositeMode, BloomPrefilterSettings, BloomSettings},
+            core_2d::graph::{Core2d, Node2d},
+            fullscreen_vertex_shader::fullscreen_shader_vertex_state,
+        },
+        ecs::query::QueryItem,
+        render::{
+            extract_component::{
+                ComponentUniforms, ExtractComponent, ExtractComponentPlugin, UniformComponentPlugin,
+            },
+            render_graph::{
+                NodeRunError, RenderGraphApp, RenderGraphContext, RenderLabel, ViewNode, ViewNodeRunner,
+            },
+            render_resource::{
+                binding_types::{sampler, texture_2d, uniform_buffer},
+                *,
+            },
+            renderer::{RenderContext, RenderDevice},
+            texture::BevyDefault,
+            view::ViewTarget,
+            RenderApp,
+        },
+    };
+
+    const POST_PROCESS_SHADER_HANDLE: Handle<Shader> =
+        Handle::weak_from_u128(0x5e4f_0c1d_2a7b_4e39_9d61_8c3a_f027_b415);
+    const VIGNETTE_PER_DAMAGE: f32 = 4.0; // Vignette strength per fraction of max HP lost
+    const VIGNETTE_DECAY: f32 = 1.5;
+    const CHROMATIC_DECAY: f32 = 4.0;
+    const DESATURATION_SPEED: f32 = 3.0;
+
+    /// Bloom for glowing projectiles/gems plus a fullscreen pass for the damage
+    /// vignette, chromatic flash and last-stand desaturation.
+    pub struct PostProcessPlugin;
+
+    impl Plugin for PostProcessPlugin {
+        fn build(&self, app: &mut App) {
+            load_internal_asset!(
+                app,
+                POST_PROCESS_SHADER_HANDLE,
+                "post_process.wgsl",
+                Shader::from_wgsl
+            );
+
+            app.insert_resource(ScreenEffects::default())
+                .add_plugins((
+                    ExtractComponentPlugin::<PostProcessSettings>::default(),
+                    UniformComponentPlugin::<PostProcessSettings>::default(),
+                ))
+                .add_systems(
+                    Update,
+                    (
+                        apply_bloom_setting,
+                        (damage_vignette, last_stand_desaturation).run_if(in_state(GameState::Running)),
+                        update_post_process_settings,
+                    )
+                        .chain(),
+                )
+                .add_systems(ResetRun, reset_screen_effects);
+
+            let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+                return;
+            };
+            render_app
+                .add_render_graph_node::<ViewNodeRunner<PostProcessNode>>(Core2d, PostProcessLabel)
+                .add_render_graph_edges(
+                    Core2d,
+                    (
+                        Node2d::Tonemapping,
+                        PostProcessLabel,
+                        Node2d::EndMainPassPostProcessing,
+                    ),
+                );
+        }
+
+        fn finish(&self, app: &mut App) {
+            let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+                return;
+            };
+            render_app.init_resource::<PostProcessPipeline>();
+        }
+    }
+
+    /// Effect intensities driven by gameplay, before the graphics toggles apply.
+    #[derive(Resource, Default, Debug)]
+    pub struct ScreenEffects {
+        vignette: f32,
+        chromatic_aberration: f32,
+        desaturation: f32,
+    }
+
+    /// Chromatic flash from a single explosion; several at once add up to the cap.
+    pub const EXPLOSION_FLASH: f32 = 0.3;
+
+    impl ScreenEffects {
+        pub fn chromatic_flash(&mut self, strength: f32) {
+            self.chromatic_aberration = (self.chromatic_aberration + strength).min(1.0);
+        }
+    }
+
+    pub use uniform::PostProcessSettings;
+
+    // `ShaderType`'s generated layout checks trip dead_code on newer compilers,
+    // and the lint can only be silenced at the enclosing module.
+    #[allow(dead_code)]
+    mod uniform {
+        use super::*;
+
+        /// Per-camera uniform read by `post_process.wgsl`.
+        #[derive(Component, Default, Clone, Copy, ExtractComponent, ShaderType)]
+        pub struct PostProcessSettings {
+            pub vignette: f32,
+            pub chromatic_aberration: f32,
+            pub desaturation: f32,
+        }
+    }
+
+    impl PostProcessSettings {
+        fn is_noop(&self) -> bool {
+            self.vignette <= 0.0 && self.chromatic_aberration <= 0.0 && self.desaturation <= 0.0
+        }
+    }
+
+    fn apply_bloom_setting(
+        mut commands: Commands,
+        settings: Res<settings::Settings>,
+        mut camera_query: Query<(Entity, &mut Camera), With<PostProcessSettings>>,
+    ) {
+        if !settings.is_changed() {
+            return;
+        }
+        for (entity, mut camera) in camera_query.iter_mut() {
+            // Bloom needs an HDR target so colours above 1.0 survive to the blur.
+            camera.hdr = settings.graphics.bloom;
+            if settings.graphics.bloom {
+                commands.entity(entity).insert(BloomSettings {
+                    intensity: 0.2,
+                    prefilter_settings: BloomPrefilterSettings {
+                        threshold: 1.0,
+                        threshold_softness: 0.3,
+                    },
+                    composite_mode: BloomCompositeMode::Additive,
+                    ..BloomSettings::NATURAL
+                });
+            } else {
+                commands.entity(entity).remove::<BloomSettings>();
+            }
+        }
+    }
+
+    fn damage_vignette(
+        player_query: Query<&combat::Health, With<player::Player>>,
+        mut screen_effects: ResMut<ScreenEffects>,
+        mut last_health: Local<Option<f32>>,
+    ) {
+        let Ok(health) = player_query.get_single() else {
+            *last_health = None;
+            return;
+        };
+        if let Some(previous) = *last_health {
+            let lost = (previous - health.current).max(0.0);
+            screen_effects.vignette =
+                (screen_effects.vignette + lost / health.max * VIGNETTE_PER_DAMAGE).min(1.0);
+        }
+        *last_health = Some(health.current);
+    }
+
+    fn last_stand_desaturation(
+        player_query: Query<&last_stand::LastStand, With<player::Player>>,
+        mut screen_effects: ResMut<ScreenEffects>,
+        real_time: Res<Time<Real>>,
+    ) {
+        let target = match player_query.get_single() {
+            Ok(last_stand) if last_stand.is_active() => 1.0,
+            _ => 0.0,
+        };
+        let step = DESATURATION_SPEED * real_time.delta_seconds();
+        let current = screen_effects.desaturation;
+        screen_effects.desaturation = if current < target {
+            (current + step).min(target)
+        } else {
+            (current - step).max(target)
+        };
+    }
+
+    // Flashes decay on real time so they still clear up during bullet time.
+    fn update_post_process_settings(
+        mut screen_effects: ResMut<ScreenEffects>,
+        settings: Res<settings::Settings>,
+        real_time: Res<Time<Real>>,
+        mut camera_query: Query<&mut PostProcessSettings>,
+    ) {
+        let delta = real_time.delta_seconds();
+        screen_effects.vignette = (screen_effects.vignette - VIGNETTE_DECAY * delta).max(0.0);
+        screen_effects.chromatic_aberration =
+            (screen_effects.chromatic_aberration - CHROMATIC_DECAY * delta).max(0.0);
+
+        let graphics = &settings.graphics;
+        for mut post_process in camera_query.iter_mut() {
+            post_process.vignette = if graphics.damage_vignette { screen_effects.vignette } else { 0.0 };
+            post_process.chromatic_aberration = if graphics.chromatic_flash {
+                screen_effects.chromatic_aberration
+            } else {
+                0.0
+            };
+            post_process.desaturation = screen_effects.desaturation;
+        }
+    }
+
+    fn reset_screen_effects(mut screen_effects: ResMut<ScreenEffects>) {
+        *screen_effects = ScreenEffects::default();
+    }
+
+    #[derive(Debug, Hash, PartialEq, Eq, Clone, RenderLabel)]
+    struct PostProcessLabel;
+
+    #[derive(Default)]
+    struct PostProcessNode;
+
+    impl ViewNode for PostProcessNode {
+        type ViewQuery = (&'static ViewTarget, &'static PostProcessSettings);
+
+        fn run(
+            &self,
+            _graph: &mut RenderGraphContext,
+            render_context: &mut RenderContext,
+            (view_target, settings): QueryItem<Self::ViewQuery>,
+            world: &World,
+        ) -> Result<(), NodeRunError> {
+            // Skip the extra fullscreen pass entirely when nothing is showing.
+            if settings.is_noop() {
+                return Ok(());
+            }
+
+            let post_process_pipeline = world.resource::<PostProcessPipeline>();
+            let pipeline_id = if view_target.is_hdr() {
+                post_process_pipeline.hdr_pipeline_id
+            } else {
+                post_process_pipeline.pipeline_id
+            };
+            let Some(pipeline) = world.resource::<PipelineCache>().get_render_pipeline(pipeline_id) else {
+                return Ok(());
+            };
+            let settings_uniforms = world.resource::<ComponentUniforms<PostProcessSettings>>();
+            let Some(settings_binding) = settings_uniforms.uniforms().binding() else {
+                return Ok(());
+            };
+
+            let post_process = view_target.post_process_write();
+            let bind_group = render_context.render_device().create_bind_group(
+                "post_process_bind_group",
+                &post_process_pipeline.layout,
+                &BindGroupEntries::sequential((
+                    post_process.source,
+                    &post_process_pipeline.sampler,
+                    settings_binding.clone(),
+                )),
+            );
+
+            let mut render_pass = render_context.begin_tracked_render_pass(RenderPassDescriptor {
+                label: Some("post_process_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.destination,
+                    resolve_target: None,
+                    ops: Operations::default(),
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_render_pipeline(pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+
+            Ok(())
+        }
+    }
+
+    #[derive(Resource)]
+    struct PostProcessPipeline {
+        layout: BindGroupLayout,
+        sampler: Sampler,
+        pipeline_id: CachedRenderPipelineId,
+        hdr_pipeline_id: CachedRenderPipelineId,
+    }
+
+    impl FromWorld for PostProcessPipeline {
+        fn from_world(world: &mut World) -> Self {
+            let render_device = world.resource::<RenderDevice>();
+            let layout = render_device.create_bind_group_layout(
+                "post_process_bind_group_layout",
+                &BindGroupLayoutEntries::sequential(
+                    ShaderStages::FRAGMENT,
+                    (
+                        texture_2d(TextureSampleType::Float { filterable: true }),
+                        sampler(SamplerBindingType::Filtering),
+                        uniform_buffer::<PostProcessSettings>(false),
+                    ),
+                ),
+            );
+            let sampler = render_device.create_sampler(&SamplerDescriptor::default());
+
+            let descriptor = |format: TextureFormat| RenderPipelineDescriptor {
+                label: Some("post_process_pipeline".into()),
+                layout: vec![layout.clone()],
+                vertex: fullscreen_shader_vertex_state(),
+                fragment: Some(FragmentState {
+                    shader: POST_PROCESS_SHADER_HANDLE,
+                    shader_defs: vec![],
+                    entry_point: "fragment".into(),
+                    targets: vec![Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                push_constant_ranges: vec![],
+            };
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let pipeline_id = pipeline_cache.queue_render_pipeline(descriptor(TextureFormat::bevy_default()));
+            let hdr_pipeline_id =
+                pipeline_cache.queue_render_pipeline(descriptor(ViewTarget::TEXTURE_FORMAT_HDR));
+
+            Self {
+                layout,
+                sampler,
+                pipeline_id,
+                hdr_pipeline_id,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+mod music {
+    use super::*;
+    use bevy::audio::Volume;
+
+    /// Enemies on screen at which the swarm alone drives the music to full intensity.
+    const FULL_INTENSITY_ENEMIES: f32 = 400.0;
+    const LOW_HEALTH_FRACTION: f32 = 0.25;
+    /// Volume change per second as stems and the heartbeat fade in and out.
+    const FADE_RATE: f32 = 0.75;
+
+    /// Looping music stems, each fading in above its intensity threshold; they're
+    /// authored to the same tempo and length so they stay in sync.
+    const STEMS: [(&str, f32); 3] = [
+        ("audio/music_base.ogg", 0.0),
+        ("audio/music_drums.ogg", 0.3),
+        ("audio/music_lead.ogg", 0.65),
+    ];
+    const HEARTBEAT: &str = "audio/heartbeat.ogg";
+
+    /// Layered music and the low-health heartbeat. Kept out of `GameplayPlugins`
+    /// because it needs Bevy's audio plugin.
+    pub struct MusicPlugin;
+
+    impl Plugin for MusicPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<MusicIntensity>()
+                .add_systems(Startup, start_music)
+                .add_systems(Update, (compute_music_intensity, fade_stems).chain());
+        }
+    }
+
+    /// How intense the game is right now, from 0 (calm) to 1 (everything's on fire).
+    #[derive(Resource, Default, Debug)]
+    pub struct MusicIntensity {
+        pub level: f32,
+        pub low_health: bool,
+    }
+
+    pub fn intensity(enemy_count: usize, boss_present: bool) -> f32 {
+        let swarm = enemy_count as f32 / FULL_INTENSITY_ENEMIES;
+        let boss = if boss_present { 0.7 } else { 0.0 };
+        swarm.max(boss).clamp(0.0, 1.0)
+    }
+
+    /// A stem is silent at its threshold and fully in 0.2 of intensity above it.
+    pub fn stem_volume(threshold: f32, level: f32) -> f32 {
+        if threshold <= 0.0 {
+            return 1.0;
+        }
+        ((level - threshold) / 0.2).clamp(0.0, 1.0)
+    }
+
+    #[derive(Component)]
+    struct MusicStem {
+        threshold: f32,
+    }
+
+    #[derive(Component)]
+    struct Heartbeat;
+
+    fn start_music(mut commands: Commands, asset_server: Res<AssetServer>) {
+        for (path, threshold) in STEMS {
+            commands.spawn((
+                AudioBundle {
+                    source: asset_server.load(path),
+                    settings: PlaybackSettings::LOOP.with_volume(Volume::new(stem_volume(threshold, 0.0))),
+                },
+                MusicStem { threshold },
+            ));
+        }
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(HEARTBEAT),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.0)),
+            },
+            Heartbeat,
+        ));
+    }
+
+    fn compute_music_intensity(
+        mut music_intensity: ResMut<MusicIntensity>,
+        enemy_query: Query<(), With<enemy::Enemy>>,
+        boss_query: Query<(), With<waves::Boss>>,
+        player_query: Query<&combat::Health, With<player::Player>>,
+        state: Res<State<GameState>>,
+    ) {
+        // Menus and the game-over screen sit at the base layer only.
+        if *state.get() != GameState::Running {
+            *music_intensity = MusicIntensity::default();
+            return;
+        }
+        music_intensity.level = intensity(enemy_query.iter().count(), !boss_query.is_empty());
+        music_intensity.low_health = player_query
+            .get_single()
+            .is_ok_and(|health| health.current > 0.0 && health.current < health.max * LOW_HEALTH_FRACTION);
+    }
+
+    fn fade_stems(
+        music_intensity: Res<MusicIntensity>,
+        stem_query: Query<(&MusicStem, &AudioSink)>,
+        heartbeat_query: Query<&AudioSink, With<Heartbeat>>,
+        time: Res<Time<Real>>,
+    ) {
+        let max_step = FADE_RATE * time.delta_seconds();
+        let fade = |sink: &AudioSink, target: f32| {
+            let volume = sink.volume();
+            sink.set_volume(volume + (target - volume).clamp(-max_step, max_step));
+        };
+        for (stem, sink) in stem_query.iter() {
+            fade(sink, stem_volume(stem.threshold, music_intensity.level));
+        }
+        for sink in heartbeat_query.iter() {
+            fade(sink, if music_intensity.low_health { 1.0 } else { 0.0 });
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_stems_fade_in_above_their_threshold() {
+            assert_eq!(stem_volume(0.0, 0.0), 1.0);
+            assert_eq!(stem_volume(0.3, 0.3), 0.0);
+            assert!((stem_volume(0.3, 0.4) - 0.5).abs() < 1e-5);
+            assert_eq!(stem_volume(0.3, 1.0), 1.0);
+            assert_eq!(intensity(0, true), 0.7);
+            assert_eq!(intensity(10_000, false), 1.0);
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+mod sfx {
+    use super::*;
+    use bevy::audio::{SpatialScale, Volume};
+
+    /// World units per unit of audio distance. A wave arriving at the usual 1200
+    /// units is quiet but clearly off to one side.
+    const SFX_SPATIAL_SCALE: f32 = 1.0 / 300.0;
+    /// Distance between the listener's ears, in world units.
+    const EAR_GAP: f32 = 200.0;
+    /// At most one death sound per this many seconds, or a mega wave dying is just noise.
+    const DEATH_SFX_INTERVAL: f32 = 0.05;
+
+    /// Sound effects, panned and attenuated by where they happen relative to the
+    /// camera. Kept out of `GameplayPlugins` because it needs Bevy's audio plugin.
+    pub struct SfxPlugin;
+
+    impl Plugin for SfxPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Startup, load_sfx)
+                .add_systems(Update, add_listener)
+                .add_systems(
+                    Update,
+                    (play_wave_sfx, play_nova_sfx, play_death_sfx).run_if(in_state(GameState::Running)),
+                );
+        }
+    }
+
+    #[derive(Resource)]
+    struct SfxHandles {
+        wave: Handle<AudioSource>,
+        boss: Handle<AudioSource>,
+        nova: Handle<AudioSource>,
+        death: Handle<AudioSource>,
+    }
+
+    fn load_sfx(mut commands: Commands, asset_server: Res<AssetServer>) {
+        commands.insert_resource(SfxHandles {
+            wave: asset_server.load("audio/wave_incoming.ogg"),
+            boss: asset_server.load("audio/boss_roar.ogg"),
+            nova: asset_server.load("audio/nova.ogg"),
+            death: asset_server.load("audio/enemy_death.ogg"),
+        });
+    }
+
+    fn add_listener(mut commands: Commands, camera_query: Query<Entity, Added<world_view::WorldCamera>>) {
+        for entity in camera_query.iter() {
+            commands.entity(entity).insert(SpatialListener::new(EAR_GAP));
+        }
+    }
+
+    fn play_at(commands: &mut Commands, source: &Handle<AudioSource>, position: Vec3, volume: f32) {
+        commands.spawn((
+            AudioBundle {
+                source: source.clone(),
+                settings: PlaybackSettings::DESPAWN
+                    .with_volume(Volume::new(volume))
+                    .with_spatial(true)
+                    .with_spatial_scale(SpatialScale::new_2d(SFX_SPATIAL_SCALE)),
+            },
+            TransformBundle::from_transform(Transform::from_translation(position)),
+        ));
+    }
+
+    fn play_wave_sfx(
+        mut commands: Commands,
+        mut started_events: EventReader<waves::ScriptedEventStarted>,
+        handles: Res<SfxHandles>,
+    ) {
+        for started in started_events.read() {
+            let source = match started.event {
+                waves::ScriptedEvent::Boss => &handles.boss,
+                waves::ScriptedEvent::MegaWave
+                | waves::ScriptedEvent::Ring
+                | waves::ScriptedEvent::Pincer
+                | waves::ScriptedEvent::Elite => &handles.wave,
+            };
+            play_at(&mut commands, source, started.position, 1.0);
+        }
+    }
+
+    fn play_nova_sfx(
+        mut commands: Commands,
+        mut fired_events: EventReader<triggers::WeaponTriggered>,
+        handles: Res<SfxHandles>,
+    ) {
+        for event in fired_events.read() {
+            play_at(&mut commands, &handles.nova, event.origin, 0.8);
+        }
+    }
+
+    fn play_death_sfx(
+        mut commands: Commands,
+        mut died_events: EventReader<enemy::EnemyDied>,
+        handles: Res<SfxHandles>,
+        time: Res<Time<Real>>,
+        mut last_played: Local<f32>,
+    ) {
+        let now = time.elapsed_seconds();
+        // Only the most recent death in a burst gets a sound.
+        let Some(event) = died_events.read().last() else {
+            return;
+        };
+        if now - *last_played < DEATH_SFX_INTERVAL {
+            return;
+        }
+        *last_played = now;
+        play_at(&mut commands, &handles.death, event.position, 0.4);
+    }
+}
+
+mod triggers {
+    use super::*;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+    pub struct TriggersPlugin;
+
+    impl Plugin for TriggersPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<TriggeredWeapons>()
+                .init_resource::<CorpseBlast>()
+                .add_event::<WeaponTriggered>()
+                .add_systems(
+                    Update,
+                    (
+                        (dispatch_triggers, detonate_corpses, resolve_triggered_effects)
+                            .chain()
+                            .in_set(combat::CombatSet::Fire),
+                        fade_novas,
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_triggered_weapons);
+        }
+    }
+
+    /// What makes a triggered weapon go off.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum Trigger {
+        /// Fires every `cooldown` seconds on its own.
+        Timer,
+        Kill,
+        Dash,
+        Hurt,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum TriggerEffect {
+        /// Damages every enemy within `radius` of the player.
+        Nova { radius: f32, damage: f32, color: Color },
+    }
+
+    /// A weapon that activates on a game event rather than the fire-rate timer.
+    /// `cooldown` throttles re-triggering; for `Trigger::Timer` it is the interval.
+    #[derive(Clone, Copy, Debug)]
+    pub struct TriggeredWeapon {
+        pub trigger: Trigger,
+        pub effect: TriggerEffect,
+        pub cooldown: f32,
+        ready_in: f32,
+    }
+
+    impl TriggeredWeapon {
+        pub const fn new(trigger: Trigger, effect: TriggerEffect, cooldown: f32) -> Self {
+            Self {
+                trigger,
+                effect,
+                cooldown,
+                ready_in: 0.0,
+            }
+        }
+    }
+
+    pub const DASH_BLAST: TriggeredWeapon = TriggeredWeapon::new(
+        Trigger::Dash,
+        TriggerEffect::Nova {
+            radius: 150.0,
+            damage: 20.0,
+            color: Color::rgba(2.0, 0.8, 0.2, 0.5),
+        },
+        0.0,
+    );
+
+    pub const RETALIATION_NOVA: TriggeredWeapon = TriggeredWeapon::new(
+        Trigger::Hurt,
+        TriggerEffect::Nova {
+            radius: 220.0,
+            damage: 30.0,
+            color: Color::rgba(1.5, 0.2, 0.4, 0.5),
+        },
+        3.0,
+    );
+
+    pub const PULSE_NOVA: TriggeredWeapon = TriggeredWeapon::new(
+        Trigger::Timer,
+        TriggerEffect::Nova {
+            radius: 180.0,
+            damage: 15.0,
+            color: Color::rgba(0.3, 1.2, 2.0, 0.4),
+        },
+        4.0,
+    );
+
+    pub const REAPER_NOVA: TriggeredWeapon = TriggeredWeapon::new(
+        Trigger::Kill,
+        TriggerEffect::Nova {
+            radius: 160.0,
+            damage: 20.0,
+            color: Color::rgba(1.2, 0.3, 1.8, 0.45),
+        },
+        2.0,
+    );
+
+    /// Each level of the upgrade adds this much to the chance a corpse blows up...
+    const CORPSE_BLAST_CHANCE: f64 = 0.15;
+    /// ...up to this many levels.
+    pub const MAX_CORPSE_BLAST_LEVEL: u32 = 3;
+
+    const CORPSE_BLAST: TriggerEffect = TriggerEffect::Nova {
+        radius: 90.0,
+        damage: 15.0,
+        color: Color::rgba(1.8, 1.0, 0.3, 0.4),
+    };
+
+    /// Volatile Corpses: slain enemies sometimes go off as a small nova where they fell.
+    /// Enemies the blast kills can go off in turn.
+    #[derive(Resource, Default, Debug)]
+    pub struct CorpseBlast {
+        pub level: u32,
+    }
+
+    impl CorpseBlast {
+        fn chance(&self) -> f64 {
+            CORPSE_BLAST_CHANCE * self.level.min(MAX_CORPSE_BLAST_LEVEL) as f64
+        }
+    }
+
+    #[derive(Resource, Default, Debug)]
+    pub struct TriggeredWeapons {
+        pub weapons: Vec<TriggeredWeapon>,
+    }
+
+    impl TriggeredWeapons {
+        pub fn add(&mut self, weapon: TriggeredWeapon) {
+            self.weapons.push(weapon);
+        }
+    }
+
+    #[derive(Event, Debug)]
+    pub struct WeaponTriggered {
+        pub effect: TriggerEffect,
+        pub origin: Vec3,
+    }
+
+    #[derive(Component)]
+    struct NovaFx(Timer);
+
+    fn dispatch_triggers(
+        time: Res<Time>,
+        mut triggered_weapons: ResMut<TriggeredWeapons>,
+        mut kill_events: EventReader<enemy::EnemyDied>,
+        mut dash_events: EventReader<player::PlayerDashed>,
+        mut hurt_events: EventReader<player::PlayerHurt>,
+        mut fired_events: EventWriter<WeaponTriggered>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        let killed = kill_events.read().count() > 0;
+        let dashed_from = dash_events.read().last().map(|event| event.position);
+        let hurt = hurt_events.read().count() > 0;
+
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player_position = player_transform.translation;
+
+        for weapon in triggered_weapons.weapons.iter_mut() {
+            weapon.ready_in = (weapon.ready_in - time.delta_seconds()).max(0.0);
+            let origin = match weapon.trigger {
+                Trigger::Timer => Some(player_position),
+                Trigger::Kill => killed.then_some(player_position),
+                Trigger::Dash => dashed_from,
+                Trigger::Hurt => hurt.then_some(player_position),
+            };
+            if let Some(origin) = origin {
+                if weapon.ready_in <= 0.0 {
+                    weapon.ready_in = weapon.cooldown;
+                    fired_events.send(WeaponTriggered {
+                        effect: weapon.effect,
+                        origin,
+                    });
+                }
+            }
+        }
+    }
+
+    fn detonate_corpses(
+        mut kill_events: EventReader<enemy::EnemyDied>,
+        mut fired_events: EventWriter<WeaponTriggered>,
+        corpse_blast: Res<CorpseBlast>,
+        mut run_rng: ResMut<run::RunRng>,
+    ) {
+        if corpse_blast.level == 0 {
+            kill_events.clear();
+            return;
+        }
+        let rng = &mut run_rng.0;
+        for event in kill_events.read() {
+            if rng.gen_bool(corpse_blast.chance()) {
+                fired_events.send(WeaponTriggered {
+                    effect: CORPSE_BLAST,
+                    origin: event.position,
+                });
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_triggered_effects(
+        mut commands: Commands,
+        mut fired_events: EventReader<WeaponTriggered>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        mut screen_effects: ResMut<post_process::ScreenEffects>,
+    ) {
+        for event in fired_events.read() {
+            match event.effect {
+                TriggerEffect::Nova { radius, damage, color } => {
+                    screen_effects.chromatic_flash(post_process::EXPLOSION_FLASH);
+                    let radius = radius * effective_stats.range;
+                    for (entity, transform) in enemy_query.iter() {
+                        if transform.translation.distance(event.origin) < radius {
+                            damage_events.send(enemy::EnemyDamaged {
+                                entity,
+                                amount: damage,
+                                shred: 0,
+                                split: None,
+                            });
+                        }
+                    }
+
+                    commands.spawn((
+                        MaterialMesh2dBundle {
+                            mesh: Mesh2dHandle(meshes.add(Circle::new(radius))),
+                            material: materials.add(color),
+                            transform: Transform::from_translation(render_layer::RenderLayer::Vfx.at(event.origin)),
+                            ..default()
+                        },
+                        NovaFx(Timer::from_seconds(0.25, TimerMode::Once)),
+                        DespawnOnExit::RUN,
+                    ));
+                }
+            }
+        }
+    }
+
+    fn fade_novas(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut NovaFx, &mut Transform)>,
+    ) {
+        for (entity, mut fx, mut transform) in query.iter_mut() {
+            fx.0.tick(time.delta());
+            transform.scale = Vec3::splat(0.5 + 0.5 * fx.0.fraction());
+            if fx.0.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn reset_triggered_weapons(mut triggered_weapons: ResMut<TriggeredWeapons>, mut corpse_blast: ResMut<CorpseBlast>) {
+        triggered_weapons.weapons.clear();
+        *corpse_blast = CorpseBlast::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::time::Duration;
+
+        const PLAYER_POSITION: Vec3 = Vec3::new(10.0, 20.0, 0.0);
+
+        /// An app running just `dispatch_triggers` for one weapon, with the player at `PLAYER_POSITION`.
+        fn trigger_app(weapon: TriggeredWeapon) -> App {
+            let mut app = App::new();
+            app.init_resource::<Time>()
+               .add_event::<enemy::EnemyDied>()
+               .add_event::<player::PlayerDashed>()
+               .add_event::<player::PlayerHurt>()
+               .add_event::<WeaponTriggered>()
+               .insert_resource(TriggeredWeapons { weapons: vec![weapon] })
+               .add_systems(Update, dispatch_triggers);
+            app.world.spawn((player::Player, Transform::from_translation(PLAYER_POSITION)));
+            app
+        }
+
+        /// Advances a second and runs a frame, returning where anything went off.
+        fn step(app: &mut App) -> Vec<Vec3> {
+            app.world.resource_mut::<Time>().advance_by(Duration::from_secs(1));
+            app.update();
+            app.world
+                .resource_mut::<Events<WeaponTriggered>>()
+                .drain()
+                .map(|fired| fired.origin)
+                .collect()
+        }
+
+        #[test]
+        fn test_timer_trigger_fires_every_cooldown_on_its_own() {
+            let mut app = trigger_app(PULSE_NOVA);
+            let fired = (0..9).map(|_| !step(&mut app).is_empty()).collect::<Vec<_>>();
+            assert_eq!(fired, [true, false, false, false, true, false, false, false, true]);
+        }
+
+        #[test]
+        fn test_kill_and_hurt_triggers_fire_on_their_event_within_the_cooldown() {
+            let kill = |app: &mut App| {
+                app.world.send_event(enemy::EnemyDied {
+                    entity: Entity::PLACEHOLDER,
+                    position: Vec3::ZERO,
+                    kind: enemy::EnemyKind::Grunt,
+                    split: None,
+                });
+            };
+            let hurt = |app: &mut App| {
+                app.world.send_event(player::PlayerHurt {
+                    amount: 1.0,
+                    source: enemy::EnemyKind::Grunt,
+                    kind: player::DamageKind::Contact,
+                });
+            };
+            for (weapon, event) in [(REAPER_NOVA, &kill as &dyn Fn(&mut App)), (RETALIATION_NOVA, &hurt)] {
+                let mut app = trigger_app(weapon);
+                assert_eq!(step(&mut app), vec![], "{:?} went off without its event", weapon.trigger);
+
+                // One event a second: the first goes off, the rest wait out the cooldown.
+                let fired = (0..=weapon.cooldown as usize)
+                    .map(|_| {
+                        event(&mut app);
+                        step(&mut app)
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(fired.first(), Some(&vec![PLAYER_POSITION]), "{:?}", weapon.trigger);
+                assert!(fired[1..fired.len() - 1].iter().all(Vec::is_empty), "{:?}", weapon.trigger);
+                assert_eq!(fired.last(), Some(&vec![PLAYER_POSITION]), "{:?}", weapon.trigger);
+            }
+        }
+
+        #[test]
+        fn test_dash_trigger_fires_where_the_dash_started() {
+            let mut app = trigger_app(DASH_BLAST);
+            assert_eq!(step(&mut app), vec![]);
+            for x in [100.0, 200.0] {
+                app.world.send_event(player::PlayerDashed { position: Vec3::X * x });
+                assert_eq!(step(&mut app), vec![Vec3::X * x]);
+            }
+        }
+    }
+}
+
+mod passives {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    pub struct PassivesPlugin;
+
+    impl Plugin for PassivesPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Update, announce_new_passives);
+        }
+    }
+
+    /// A held passive item. Each pick is its own entity, so duplicates stack.
+    #[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum PassiveItem {
+        HollowHeart,
+        Wings,
+        Spinach,
+        EmptyTome,
+    }
+
+    impl PassiveItem {
+        pub fn label(&self) -> &'static str {
+            match self {
+                PassiveItem::HollowHeart => "Hollow Heart: +20% Max HP",
+                PassiveItem::Wings => "Wings: +10% Speed",
+                PassiveItem::Spinach => "Spinach: +10% Damage",
+                PassiveItem::EmptyTome => "Empty Tome: -8% Cooldown",
+            }
+        }
+
+        pub fn modifier(&self) -> attributes::StatModifier {
+            use attributes::{ModifierOp, Stat, StatModifier};
+            match self {
+                PassiveItem::HollowHeart => StatModifier {
+                    stat: Stat::MaxHealth,
+                    op: ModifierOp::Percent(0.2),
+                },
+                PassiveItem::Wings => StatModifier {
+                    stat: Stat::MoveSpeed,
+                    op: ModifierOp::Percent(0.1),
+                },
+                PassiveItem::Spinach => StatModifier {
+                    stat: Stat::Damage,
+                    op: ModifierOp::Percent(0.1),
+                },
+                PassiveItem::EmptyTome => StatModifier {
+                    stat: Stat::FireCooldown,
+                    op: ModifierOp::Multiply(0.92),
+                },
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum Evolution {
+        StormCaller,
+        RazorHalo,
+    }
+
+    impl Evolution {
+        pub fn label(&self) -> &'static str {
+            match self {
+                Evolution::StormCaller => "EVOLVE: Storm Caller",
+                Evolution::RazorHalo => "EVOLVE: Razor Halo",
+            }
+        }
+    }
+
+    /// A weapon picked `weapon_picks` times plus the passive unlocks the evolution.
+    pub struct EvolutionRecipe {
+        pub weapon: ui::Upgrade,
+        pub weapon_picks: usize,
+        pub passive: PassiveItem,
+        pub evolution: Evolution,
+    }
+
+    pub const RECIPES: [EvolutionRecipe; 2] = [
+        EvolutionRecipe {
+            weapon: ui::Upgrade::ChainLightning,
+            weapon_picks: 3,
+            passive: PassiveItem::EmptyTome,
+            evolution: Evolution::StormCaller,
+        },
+        EvolutionRecipe {
+            weapon: ui::Upgrade::BladeCount,
+            weapon_picks: 3,
+            passive: PassiveItem::Wings,
+            evolution: Evolution::RazorHalo,
+        },
+    ];
+
+    /// Evolutions whose recipe is satisfied by `build` and that haven't been taken yet.
+    pub fn available_evolutions(build: &[ui::Upgrade]) -> Vec<Evolution> {
+        let picks = |upgrade: ui::Upgrade| build.iter().filter(|&&picked| picked == upgrade).count();
+        RECIPES
+            .iter()
+            .filter(|recipe| {
+                picks(recipe.weapon) >= recipe.weapon_picks
+                    && picks(ui::Upgrade::Passive(recipe.passive)) > 0
+                    && picks(ui::Upgrade::Evolve(recipe.evolution)) == 0
+            })
+            .map(|recipe| recipe.evolution)
+            .collect()
+    }
+
+    fn announce_new_passives(
+        query: Query<(), Added<PassiveItem>>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+    ) {
+        if !query.is_empty() {
+            stats_changed.send(attributes::StatsChanged);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_evolution_needs_weapon_picks_and_passive() {
+            let mut build = vec![ui::Upgrade::BladeCount; 3];
+            assert!(available_evolutions(&build).is_empty());
+
+            build.push(ui::Upgrade::Passive(PassiveItem::Wings));
+            assert_eq!(available_evolutions(&build), vec![Evolution::RazorHalo]);
+
+            build.push(ui::Upgrade::Evolve(Evolution::RazorHalo));
+            assert!(available_evolutions(&build).is_empty());
+        }
+    }
+}
+
+mod gems {
+    use super::*;
+    use combat::Weapon;
+    use std::collections::HashMap;
+
+    /// Most gems one weapon can hold.
+    pub const SOCKETS_PER_WEAPON: usize = 2;
+    const SHARDS_PER_SPLIT_GEM: usize = 2;
+    /// Split shards fan out up to this many radians either side of the killing shot.
+    const SPLIT_SPREAD: f32 = 0.7;
+
+    pub struct GemsPlugin;
+
+    impl Plugin for GemsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<GemPouch>()
+                .add_systems(
+                    Update,
+                    (handle_socket_buttons, refresh_socket_panel)
+                        .chain()
+                        .run_if(in_state(GameState::Paused)),
+                )
+                .add_systems(ResetRun, reset_gems);
+        }
+    }
+
+    /// A drop that changes how one weapon's projectiles behave once socketed into it.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ModifierGem {
+        /// Passes through one more enemy before stopping.
+        Pierce,
+        /// A killing shot bursts into shards that carry on past the corpse.
+        SplitOnKill,
+    }
+
+    impl ModifierGem {
+        pub const ALL: [ModifierGem; 2] = [ModifierGem::Pierce, ModifierGem::SplitOnKill];
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                ModifierGem::Pierce => "+1 Pierce",
+                ModifierGem::SplitOnKill => "Split on Kill",
+            }
+        }
+
+        pub fn color(&self) -> Color {
+            match self {
+                ModifierGem::Pierce => Color::rgb(0.3, 1.6, 1.8),
+                ModifierGem::SplitOnKill => Color::rgb(1.8, 0.9, 0.3),
+            }
+        }
+    }
+
+    /// Gems picked up this run: loose ones waiting for a socket, and what each weapon holds.
+    #[derive(Resource, Default, Debug)]
+    pub struct GemPouch {
+        pub loose: Vec<ModifierGem>,
+        sockets: HashMap<Weapon, Vec<ModifierGem>>,
+    }
+
+    impl GemPouch {
+        pub fn socketed(&self, weapon: Weapon) -> &[ModifierGem] {
+            self.sockets.get(&weapon).map_or(&[], Vec::as_slice)
+        }
+
+        pub fn count(&self, weapon: Weapon, gem: ModifierGem) -> usize {
+            self.socketed(weapon).iter().filter(|&&socketed| socketed == gem).count()
+        }
+
+        /// Moves loose gem `index` into `weapon`. Does nothing if there is no such gem or
+        /// the weapon's sockets are full.
+        pub fn socket(&mut self, index: usize, weapon: Weapon) -> bool {
+            if index >= self.loose.len() || self.socketed(weapon).len() >= SOCKETS_PER_WEAPON {
+                return false;
+            }
+            let gem = self.loose.remove(index);
+            self.sockets.entry(weapon).or_default().push(gem);
+            true
+        }
+    }
+
+    /// Enemies a projectile has already hit, so a piercing shot never hits one twice.
+    #[derive(Component, Debug)]
+    pub struct Pierced(pub Vec<Entity>);
+
+    /// A shard from a split; it doesn't split again.
+    #[derive(Component)]
+    pub struct SplitShard;
+
+    /// A projectile hit from a weapon with Split on Kill gems, carried through damage so
+    /// the shards only burst out if the hit really kills.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct SplitShot {
+        pub weapon: Weapon,
+        pub direction: Vec3,
+        pub gems: usize,
+    }
+
+    /// Headings for the shards of a killing shot travelling along `direction`, spread
+    /// evenly across the fan.
+    pub fn split_directions(direction: Vec3, gems: usize) -> Vec<Vec3> {
+        let shards = gems * SHARDS_PER_SPLIT_GEM;
+        (0..shards)
+            .map(|i| {
+                let angle = -SPLIT_SPREAD + 2.0 * SPLIT_SPREAD * i as f32 / (shards - 1).max(1) as f32;
+                Quat::from_rotation_z(angle).mul_vec3(direction)
+            })
+            .collect()
+    }
+
+    /// Weapons the build fires projectiles from, so has sockets for.
+    fn socketable(build: &[ui::Upgrade]) -> Vec<Weapon> {
+        let mut weapons = vec![Weapon::Gun];
+        for (upgrade, weapon) in [
+            (ui::Upgrade::Shotgun, Weapon::Shotgun),
+            (ui::Upgrade::HomingMissile, Weapon::Homing),
+            (ui::Upgrade::Turret, Weapon::Turret),
+        ] {
+            if build.contains(&upgrade) {
+                weapons.push(weapon);
+            }
+        }
+        weapons
+    }
+
+    #[derive(Component)]
+    struct SocketPanel;
+
+    /// Sockets loose gem `gem` into `weapon` when pressed.
+    #[derive(Component)]
+    struct SocketButton {
+        gem: usize,
+        weapon: Weapon,
+    }
+
+    /// The socket panel sits under the level-up cards and is rebuilt whenever a gem moves.
+    fn refresh_socket_panel(
+        mut commands: Commands,
+        pouch: Res<GemPouch>,
+        current_run: Res<run::CurrentRun>,
+        panel_query: Query<Entity, With<SocketPanel>>,
+    ) {
+        if !panel_query.is_empty() && !pouch.is_changed() {
+            return;
+        }
+        for entity in panel_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if pouch.loose.is_empty() && pouch.sockets.values().all(Vec::is_empty) {
+            return;
+        }
+        let weapons = socketable(&current_run.build);
+        let text = |value: String, color: Color| {
+            TextBundle::from_section(value, TextStyle { font_size: 18.0, color, ..default() })
+        };
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(20.0),
+                    width: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            SocketPanel,
+            DespawnOnExit(GameState::Paused),
+        )).with_children(|parent| {
+            for &weapon in &weapons {
+                let socketed = pouch.socketed(weapon);
+                let gems = socketed.iter().map(ModifierGem::label).collect::<Vec<_>>().join(", ");
+                parent.spawn(text(
+                    format!("{} [{}/{}] {}", weapon.label(), socketed.len(), SOCKETS_PER_WEAPON, gems),
+                    Color::GRAY,
+                ));
+            }
+            for (index, gem) in pouch.loose.iter().enumerate() {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::top(Val::Px(6.0)),
+                        ..default()
+                    },
+                    ..default()
+                }).with_children(|parent| {
+                    parent.spawn(text(format!("{} ->", gem.label()), gem.color()));
+                    for &weapon in weapons.iter().filter(|&&weapon| pouch.socketed(weapon).len() < SOCKETS_PER_WEAPON) {
+                        parent.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    margin: UiRect::left(Val::Px(8.0)),
+                                    padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                    ..default()
+                                },
+                                background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                                ..default()
+                            },
+                            SocketButton { gem: index, weapon },
+                        )).with_children(|parent| {
+                            parent.spawn(text(weapon.label().to_string(), Color::WHITE));
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    fn handle_socket_buttons(
+        interaction_query: Query<(&Interaction, &SocketButton), Changed<Interaction>>,
+        mut pouch: ResMut<GemPouch>,
+    ) {
+        for (interaction, button) in interaction_query.iter() {
+            if *interaction == Interaction::Pressed {
+                pouch.socket(button.gem, button.weapon);
+            }
+        }
+    }
+
+    fn reset_gems(mut pouch: ResMut<GemPouch>) {
+        *pouch = GemPouch::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_sockets_fill_up_per_weapon() {
+            let mut pouch = GemPouch {
+                loose: vec![ModifierGem::Pierce; SOCKETS_PER_WEAPON + 1],
+                ..default()
+            };
+            for _ in 0..SOCKETS_PER_WEAPON {
+                assert!(pouch.socket(0, Weapon::Gun));
+            }
+            assert!(!pouch.socket(0, Weapon::Gun));
+            assert!(pouch.socket(0, Weapon::Shotgun));
+            assert!(!pouch.socket(0, Weapon::Shotgun));
+            assert_eq!(pouch.count(Weapon::Gun, ModifierGem::Pierce), SOCKETS_PER_WEAPON);
+            assert_eq!(pouch.count(Weapon::Gun, ModifierGem::SplitOnKill), 0);
+        }
+    }
+}
+
+mod quests {
+    use super::*;
+    use rand::Rng;
+
+    /// Seconds between one quest ending, done or not, and the next being offered.
+    const QUEST_GAP: f32 = 20.0;
+
+    /// Small timed objectives offered one at a time during a run, shown under the clock.
+    /// Finishing one in time pays out gold or a free upgrade; running out of time just
+    /// lets the next one come round.
+    pub struct QuestsPlugin;
+
+    impl Plugin for QuestsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<Quests>()
+                .add_event::<QuestCompleted>()
+                .add_systems(OnEnter(GameState::Running), spawn_quest_text)
+                .add_systems(
+                    Update,
+                    (offer_quest, track_quest.after(combat::CombatSet::Drops), pay_out_quests, update_quest_text)
+                        .chain()
+                        .run_if(in_state(GameState::Running)),
+                )
+                .add_systems(ResetRun, reset_quests);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum QuestGoal {
+        Kills(u32),
+        KindKills(enemy::EnemyKind, u32),
+        Gems(u32),
+        Dashes(u32),
+        /// Seconds in a row without taking a hit.
+        Untouched(f32),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum QuestReward {
+        Gold(u32),
+        /// Fills the XP bar, like a chest.
+        FreeUpgrade,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct QuestDef {
+        goal: QuestGoal,
+        time_limit: f32,
+        reward: QuestReward,
+    }
+
+    const QUESTS: [QuestDef; 5] = [
+        QuestDef { goal: QuestGoal::Kills(50), time_limit: 60.0, reward: QuestReward::Gold(50) },
+        QuestDef {
+            goal: QuestGoal::KindKills(enemy::EnemyKind::Exploder, 10),
+            time_limit: 60.0,
+            reward: QuestReward::Gold(40),
+        },
+        QuestDef { goal: QuestGoal::Gems(30), time_limit: 30.0, reward: QuestReward::FreeUpgrade },
+        QuestDef { goal: QuestGoal::Dashes(8), time_limit: 45.0, reward: QuestReward::Gold(30) },
+        QuestDef { goal: QuestGoal::Untouched(20.0), time_limit: 45.0, reward: QuestReward::FreeUpgrade },
+    ];
+
+    impl QuestDef {
+        fn target(&self) -> f32 {
+            match self.goal {
+                QuestGoal::Kills(count)
+                | QuestGoal::KindKills(_, count)
+                | QuestGoal::Gems(count)
+                | QuestGoal::Dashes(count) => count as f32,
+                QuestGoal::Untouched(seconds) => seconds,
+            }
+        }
+
+        fn description(&self) -> String {
+            match self.goal {
+                QuestGoal::Kills(count) => format!("Kill {} enemies", count),
+                QuestGoal::KindKills(kind, count) => format!("Kill {} {}s", count, kind.label()),
+                QuestGoal::Gems(count) => format!("Collect {} XP gems", count),
+                QuestGoal::Dashes(count) => format!("Dash {} times", count),
+                QuestGoal::Untouched(seconds) => format!("Take no damage for {:.0}s", seconds),
+            }
+        }
+
+        fn reward_label(&self) -> String {
+            match self.reward {
+                QuestReward::Gold(amount) => format!("+{} gold", amount),
+                QuestReward::FreeUpgrade => "free upgrade".to_string(),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    struct Quest {
+        def: QuestDef,
+        progress: f32,
+        time_left: f32,
+    }
+
+    impl Quest {
+        fn new(def: QuestDef) -> Self {
+            Self { def, progress: 0.0, time_left: def.time_limit }
+        }
+
+        /// Counts `amount` towards the goal, returning whether it is now met.
+        fn advance(&mut self, amount: f32) -> bool {
+            self.progress = (self.progress + amount).min(self.def.target());
+            self.progress >= self.def.target()
+        }
+
+        /// A hit breaks an untouched streak; other goals don't care.
+        fn hurt(&mut self) {
+            if matches!(self.def.goal, QuestGoal::Untouched(_)) {
+                self.progress = 0.0;
+            }
+        }
+    }
+
+    /// The quest in progress, if any, and the wait before the next one.
+    #[derive(Resource, Debug)]
+    struct Quests {
+        active: Option<Quest>,
+        last: Option<usize>,
+        gap: Timer,
+    }
+
+    impl Default for Quests {
+        fn default() -> Self {
+            Self { active: None, last: None, gap: Timer::from_seconds(QUEST_GAP, TimerMode::Once) }
+        }
+    }
+
+    #[derive(Component)]
+    struct QuestText;
+
+    fn spawn_quest_text(mut commands: Commands, query: Query<(), With<QuestText>>) {
+        if !query.is_empty() {
+            return;
+        }
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    top: Val::Px(50.0),
+                    justify_content: JustifyContent::FlexEnd,
+                    ..default()
+                },
+                ..default()
+            },
+            settings::SafeArea,
+            DespawnOnExit::RUN,
+        )).with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", TextStyle { font_size: 18.0, color: Color::rgb(1.0, 0.85, 0.4), ..default() })
+                    .with_style(Style { margin: UiRect::horizontal(Val::Px(10.0)), ..default() })
+                    .with_text_justify(JustifyText::Right),
+                QuestText,
+            ));
+        });
+    }
+
+    /// Once the gap has passed, offers a quest other than the one just played.
+    fn offer_quest(mut quests: ResMut<Quests>, mut run_rng: ResMut<run::RunRng>, time: Res<Time>) {
+        if quests.active.is_some() || !quests.gap.tick(time.delta()).finished() {
+            return;
+        }
+        let choices = (0..QUESTS.len()).filter(|&index| Some(index) != quests.last).collect::<Vec<_>>();
+        let index = choices[run_rng.0.gen_range(0..choices.len())];
+        quests.active = Some(Quest::new(QUESTS[index]));
+        quests.last = Some(index);
+    }
+
+    /// Sent when the active quest's goal is reached in time.
+    #[derive(Event, Debug)]
+    struct QuestCompleted(QuestDef);
+
+    fn track_quest(
+        mut quests: ResMut<Quests>,
+        time: Res<Time>,
+        mut died_events: EventReader<enemy::EnemyDied>,
+        mut gem_events: EventReader<leveling::XpGemCollected>,
+        mut dash_events: EventReader<player::PlayerDashed>,
+        mut hurt_events: EventReader<player::PlayerHurt>,
+        mut completed_events: EventWriter<QuestCompleted>,
+    ) {
+        let kills = died_events.read().map(|event| event.kind).collect::<Vec<_>>();
+        let gems = gem_events.read().count();
+        let dashes = dash_events.read().count();
+        let hurt = hurt_events.read().count() > 0;
+        let Some(quest) = quests.active.as_mut() else {
+            return;
+        };
+
+        if hurt {
+            quest.hurt();
+        }
+        let amount = match quest.def.goal {
+            QuestGoal::Kills(_) => kills.len() as f32,
+            QuestGoal::KindKills(kind, _) => kills.iter().filter(|&&killed| killed == kind).count() as f32,
+            QuestGoal::Gems(_) => gems as f32,
+            QuestGoal::Dashes(_) => dashes as f32,
+            QuestGoal::Untouched(_) => time.delta_seconds(),
+        };
+        let done = quest.advance(amount);
+        quest.time_left -= time.delta_seconds();
+        if !done && quest.time_left > 0.0 {
+            return;
+        }
+
+        if done {
+            completed_events.send(QuestCompleted(quest.def));
+        }
+        quests.active = None;
+        quests.gap.reset();
+    }
+
+    fn pay_out_quests(
+        mut completed_events: EventReader<QuestCompleted>,
+        mut purse: ResMut<drops::Purse>,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        for QuestCompleted(def) in completed_events.read() {
+            match def.reward {
+                QuestReward::Gold(amount) => purse.gold += amount,
+                QuestReward::FreeUpgrade => player_stats.xp = player_stats.xp.max(player_stats.xp_to_next_level),
+            }
+            toasts.send(toasts::ToastEvent::new(
+                toasts::ToastKind::Achievement,
+                format!("Quest complete: {} ({})", def.description(), def.reward_label()),
+            ));
+        }
+    }
+
+    fn update_quest_text(quests: Res<Quests>, mut text_query: Query<&mut Text, With<QuestText>>) {
+        let text = match quests.active {
+            Some(quest) => format!(
+                "{} ({:.0}/{:.0})\n{:.0}s left, {}",
+                quest.def.description(),
+                quest.progress.floor(),
+                quest.def.target(),
+                quest.time_left.max(0.0).ceil(),
+                quest.def.reward_label(),
+            ),
+            None => String::new(),
+        };
+        for mut quest_text in text_query.iter_mut() {
+            if quest_text.sections[0].value != text {
+                quest_text.sections[0].value.clone_from(&text);
+            }
+        }
+    }
+
+    fn reset_quests(mut quests: ResMut<Quests>) {
+        *quests = Quests::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_quests_complete_at_their_target_and_hits_break_streaks() {
+            let mut kills = Quest::new(QUESTS[0]);
+            assert!(!kills.advance(49.0));
+            kills.hurt();
+            assert!(kills.advance(3.0));
+            assert_eq!(kills.progress, 50.0);
+
+            let mut untouched = Quest::new(QUESTS[4]);
+            assert!(!untouched.advance(15.0));
+            untouched.hurt();
+            assert_eq!(untouched.progress, 0.0);
+            assert!(untouched.advance(20.0));
+        }
+    }
+}
+
+mod synergies {
+    use super::*;
+
+    /// Distinct upgrades from one group needed before its bonus kicks in.
+    pub const SYNERGY_THRESHOLD: usize = 3;
+
+    pub struct SynergiesPlugin;
+
+    impl Plugin for SynergiesPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<ActiveSynergies>()
+                .add_systems(Update, track_synergies)
+                .add_systems(ResetRun, reset_synergies);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Synergy {
+        Lightning,
+        Blades,
+        Barrage,
+        Guardian,
+    }
+
+    impl Synergy {
+        pub const ALL: [Synergy; 4] = [
+            Synergy::Lightning,
+            Synergy::Blades,
+            Synergy::Barrage,
+            Synergy::Guardian,
+        ];
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                Synergy::Lightning => "Lightning",
+                Synergy::Blades => "Blades",
+                Synergy::Barrage => "Barrage",
+                Synergy::Guardian => "Guardian",
+            }
+        }
+
+        pub fn bonus_label(&self) -> &'static str {
+            match self {
+                Synergy::Lightning => "+15% Damage",
+                Synergy::Blades => "+10% Speed",
+                Synergy::Barrage => "-10% Cooldown",
+                Synergy::Guardian => "+20% Max HP",
+            }
+        }
+
+        pub fn color(&self) -> Color {
+            match self {
+                Synergy::Lightning => Color::rgb(0.3, 0.6, 1.0),
+                Synergy::Blades => Color::rgb(0.8, 0.8, 0.85),
+                Synergy::Barrage => Color::rgb(1.0, 0.6, 0.2),
+                Synergy::Guardian => Color::rgb(0.3, 0.85, 0.4),
+            }
+        }
+
+        pub fn modifier(&self) -> attributes::StatModifier {
+            use attributes::{ModifierOp, Stat, StatModifier};
+            match self {
+                Synergy::Lightning => StatModifier {
+                    stat: Stat::Damage,
+                    op: ModifierOp::Percent(0.15),
+                },
+                Synergy::Blades => StatModifier {
+                    stat: Stat::MoveSpeed,
+                    op: ModifierOp::Percent(0.1),
+                },
+                Synergy::Barrage => StatModifier {
+                    stat: Stat::FireCooldown,
+                    op: ModifierOp::Multiply(0.9),
+                },
+                Synergy::Guardian => StatModifier {
+                    stat: Stat::MaxHealth,
+                    op: ModifierOp::Percent(0.2),
+                },
+            }
+        }
+    }
+
+    /// Synergy groups an upgrade counts toward. Passives and energy upgrades belong to none.
+    pub fn tags(upgrade: ui::Upgrade) -> &'static [Synergy] {
+        use ui::Upgrade;
+        match upgrade {
+            Upgrade::ChainLightning
+            | Upgrade::Railgun
+            | Upgrade::RailgunDamage
+            | Upgrade::RailgunFireRate
+            | Upgrade::Evolve(passives::Evolution::StormCaller) => &[Synergy::Lightning],
+            Upgrade::BladeCount
+            | Upgrade::Whip
+            | Upgrade::WhipWidth
+            | Upgrade::WhipReach
+            | Upgrade::Evolve(passives::Evolution::RazorHalo) => &[Synergy::Blades],
+            Upgrade::Multishot
+            | Upgrade::AttackSpeed
+            | Upgrade::Shotgun
+            | Upgrade::HomingMissile
+            | Upgrade::Turret
+            | Upgrade::Overkill => &[Synergy::Barrage],
+            Upgrade::DashBlast
+            | Upgrade::RetaliationNova
+            | Upgrade::MineLayer
+            | Upgrade::BlackHole
+            | Upgrade::ReflectiveShield
+            | Upgrade::CorpseBlast
+            | Upgrade::PulseNova
+            | Upgrade::ReaperNova => &[Synergy::Guardian],
+            Upgrade::Passive(_) | Upgrade::MaxEnergy | Upgrade::EnergyRegen | Upgrade::Range => &[],
+        }
+    }
+
+    /// Distinct upgrades in `build` tagged with `synergy`; repeat picks count once.
+    pub fn owned(build: &[ui::Upgrade], synergy: Synergy) -> usize {
+        let mut seen: Vec<ui::Upgrade> = Vec::new();
+        for &upgrade in build {
+            if tags(upgrade).contains(&synergy) && !seen.contains(&upgrade) {
+                seen.push(upgrade);
+            }
+        }
+        seen.len()
+    }
+
+    pub fn active(build: &[ui::Upgrade]) -> Vec<Synergy> {
+        Synergy::ALL
+            .into_iter()
+            .filter(|&synergy| owned(build, synergy) >= SYNERGY_THRESHOLD)
+            .collect()
+    }
+
+    /// One line per group the build has started on, e.g. "Blades 2/3".
+    pub fn summary(build: &[ui::Upgrade]) -> String {
+        Synergy::ALL
+            .into_iter()
+            .filter_map(|synergy| {
+                let count = owned(build, synergy);
+                if count == 0 {
+                    None
+                } else if count >= SYNERGY_THRESHOLD {
+                    Some(format!("{} {}/{}: {}", synergy.label(), count, SYNERGY_THRESHOLD, synergy.bonus_label()))
+                } else {
+                    Some(format!("{} {}/{}", synergy.label(), count, SYNERGY_THRESHOLD))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Synergies whose bonus is currently applied to the player's stats.
+    #[derive(Resource, Default, Debug)]
+    pub struct ActiveSynergies(pub Vec<Synergy>);
+
+    fn track_synergies(
+        current_run: Res<run::CurrentRun>,
+        mut active_synergies: ResMut<ActiveSynergies>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        let now_active = active(&current_run.build);
+        if now_active == active_synergies.0 {
+            return;
+        }
+        for synergy in now_active.iter().filter(|synergy| !active_synergies.0.contains(synergy)) {
+            info!("Synergy unlocked: {} ({})", synergy.label(), synergy.bonus_label());
+            toasts.send(toasts::ToastEvent::new(
+                toasts::ToastKind::Achievement,
+                format!("Synergy unlocked: {} ({})", synergy.label(), synergy.bonus_label()),
+            ));
+        }
+        active_synergies.0 = now_active;
+        stats_changed.send(attributes::StatsChanged);
+    }
+
+    fn reset_synergies(mut active_synergies: ResMut<ActiveSynergies>) {
+        active_synergies.0.clear();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_synergy_needs_three_distinct_upgrades() {
+            let mut build = vec![ui::Upgrade::ChainLightning; 3];
+            build.push(ui::Upgrade::Passive(passives::PassiveItem::Spinach));
+            assert!(active(&build).is_empty());
+
+            build.push(ui::Upgrade::Railgun);
+            build.push(ui::Upgrade::RailgunDamage);
+            assert_eq!(active(&build), vec![Synergy::Lightning]);
+            assert_eq!(summary(&build), "Lightning 3/3: +15% Damage");
+        }
+    }
+}
+
+mod attributes {
+    use super::*;
+    use crate::combat::Health;
+
+    pub struct AttributesPlugin;
+
+    impl Plugin for AttributesPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<StatsChanged>()
+                .init_resource::<StatModifiers>()
+                .init_resource::<EffectiveStats>()
+                .add_systems(Update, recalculate_stats)
+                .add_systems(ResetRun, reset_stats);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Stat {
+        MaxHealth,
+        MoveSpeed,
+        Damage,
+        FireCooldown,
+        Spread,
+        PickupRadius,
+        MaxEnergy,
+        EnergyRegen,
+        /// Multiplies projectile lifetimes, and the radius of area weapons.
+        Range,
+    }
+
+    /// Range stops growing at double. The Range upgrade isn't offered once it's there.
+    pub const MAX_RANGE: f32 = 2.0;
+
+    impl Stat {
+        fn base(&self) -> f32 {
+            match self {
+                Stat::MaxHealth => PLAYER_MAX_HEALTH,
+                Stat::MoveSpeed => PLAYER_SPEED,
+                Stat::Damage => BASE_WEAPON_DAMAGE,
+                Stat::FireCooldown => BASE_FIRE_COOLDOWN,
+                Stat::Spread => BASE_PROJECTILE_SPREAD,
+                Stat::PickupRadius => XP_PICKUP_RADIUS,
+                Stat::MaxEnergy => energy::BASE_MAX_ENERGY,
+                Stat::EnergyRegen => energy::BASE_ENERGY_REGEN,
+                Stat::Range => 1.0,
+            }
+        }
+
+        /// (min, max) the resolved value is clamped to.
+        fn cap(&self) -> (f32, f32) {
+            match self {
+                Stat::MaxHealth => (1.0, f32::MAX),
+                Stat::MoveSpeed => (PLAYER_SPEED * 0.5, PLAYER_SPEED * 2.5),
+                Stat::Damage => (0.0, f32::MAX),
+                Stat::FireCooldown => (MIN_FIRE_COOLDOWN, f32::MAX),
+                Stat::Spread => (0.02, 0.6),
+                Stat::PickupRadius => (0.0, XP_PICKUP_RADIUS * 5.0),
+                Stat::MaxEnergy => (1.0, f32::MAX),
+                Stat::EnergyRegen => (0.0, f32::MAX),
+                Stat::Range => (0.5, MAX_RANGE),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum ModifierOp {
+        /// Summed with other percents, then applied once: +0.2 and +0.1 give x1.3.
+        Percent(f32),
+        /// Compounds with every other multiplier.
+        Multiply(f32),
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct StatModifier {
+        pub stat: Stat,
+        pub op: ModifierOp,
+    }
+
+    /// Modifiers granted by upgrades during the current run. Passive items
+    /// contribute theirs through their entities instead.
+    #[derive(Resource, Default, Debug)]
+    pub struct StatModifiers(pub Vec<StatModifier>);
+
+    /// Send after anything that adds or removes a modifier.
+    #[derive(Event, Debug)]
+    pub struct StatsChanged;
+
+    /// Resolved player stats: `base * (1 + percent) * multiply`, clamped to the stat's cap.
+    #[derive(Resource, Debug, Clone, Copy, PartialEq)]
+    pub struct EffectiveStats {
+        pub max_health: f32,
+        pub move_speed: f32,
+        pub damage: f32,
+        pub fire_cooldown: f32,
+        pub spread: f32,
+        pub pickup_radius: f32,
+        pub max_energy: f32,
+        /// Energy per second.
+        pub energy_regen: f32,
+        pub range: f32,
+    }
+
+    impl Default for EffectiveStats {
+        fn default() -> Self {
+            Self::resolve(&[])
+        }
+    }
+
+    impl EffectiveStats {
+        pub fn resolve(modifiers: &[StatModifier]) -> Self {
+            let value = |stat: Stat| resolve_stat(stat, modifiers);
+            Self {
+                max_health: value(Stat::MaxHealth),
+                move_speed: value(Stat::MoveSpeed),
+                damage: value(Stat::Damage),
+                fire_cooldown: value(Stat::FireCooldown),
+                spread: value(Stat::Spread),
+                pickup_radius: value(Stat::PickupRadius),
+                max_energy: value(Stat::MaxEnergy),
+                energy_regen: value(Stat::EnergyRegen),
+                range: value(Stat::Range),
+            }
+        }
+    }
+
+    fn resolve_stat(stat: Stat, modifiers: &[StatModifier]) -> f32 {
+        let mut percent = 0.0;
+        let mut multiplier = 1.0;
+        for modifier in modifiers.iter().filter(|modifier| modifier.stat == stat) {
+            match modifier.op {
+                ModifierOp::Percent(amount) => percent += amount,
+                ModifierOp::Multiply(factor) => multiplier *= factor,
+            }
+        }
+        let (min, max) = stat.cap();
+        (stat.base() * (1.0 + percent) * multiplier).clamp(min, max)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn recalculate_stats(
+        mut events: EventReader<StatsChanged>,
+        stat_modifiers: Res<StatModifiers>,
+        passive_query: Query<&passives::PassiveItem>,
+        buff_query: Query<&buffs::Buff>,
+        active_synergies: Res<synergies::ActiveSynergies>,
+        taunt: Res<taunt::Taunt>,
+        current_run: Res<run::CurrentRun>,
+        mut effective_stats: ResMut<EffectiveStats>,
+        mut player_query: Query<&mut Health, With<player::Player>>,
+    ) {
+        if events.read().count() == 0 {
+            return;
+        }
+
+        let mut modifiers = stat_modifiers.0.clone();
+        modifiers.extend(passive_query.iter().map(|item| item.modifier()));
+        modifiers.extend(buff_query.iter().filter_map(|buff| buff.kind.modifier()));
+        modifiers.extend(active_synergies.0.iter().map(|synergy| synergy.modifier()));
+        modifiers.extend(taunt.modifier());
+        modifiers.extend(heat::stat_modifiers(&current_run.heat));
+        let resolved = EffectiveStats::resolve(&modifiers);
+
+        // Grow (or shrink) current health along with the max so a Hollow Heart heals.
+        let max_health_delta = resolved.max_health - effective_stats.max_health;
+        if let Ok(mut health) = player_query.get_single_mut() {
+            health.max += max_health_delta;
+            health.current += max_health_delta;
+        }
+
+        *effective_stats = resolved;
+    }
+
+    fn reset_stats(
+        mut stat_modifiers: ResMut<StatModifiers>,
+        mut effective_stats: ResMut<EffectiveStats>,
+    ) {
+        stat_modifiers.0.clear();
+        *effective_stats = EffectiveStats::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_percents_sum_and_multipliers_compound() {
+            let modifiers = [
+                StatModifier { stat: Stat::Damage, op: ModifierOp::Percent(0.2) },
+                StatModifier { stat: Stat::Damage, op: ModifierOp::Percent(0.3) },
+                StatModifier { stat: Stat::Damage, op: ModifierOp::Multiply(2.0) },
+                StatModifier { stat: Stat::MoveSpeed, op: ModifierOp::Multiply(10.0) },
+            ];
+            let stats = EffectiveStats::resolve(&modifiers);
+            assert!((stats.damage - BASE_WEAPON_DAMAGE * 1.5 * 2.0).abs() < 1e-4);
+            assert_eq!(stats.move_speed, PLAYER_SPEED * 2.5);
+        }
+
+        #[test]
+        fn test_attack_speed_stacking_respects_cooldown_floor() {
+            let modifiers = vec![
+                StatModifier { stat: Stat::FireCooldown, op: ModifierOp::Multiply(0.9) };
+                100
+            ];
+            assert_eq!(EffectiveStats::resolve(&modifiers).fire_cooldown, MIN_FIRE_COOLDOWN);
+        }
+
+        #[test]
+        fn test_range_stops_being_offered_at_its_cap() {
+            let mut build = Vec::new();
+            while ui::Upgrade::Range.is_offered(&build) {
+                build.push(ui::Upgrade::Range);
+            }
+            let range = StatModifier { stat: Stat::Range, op: ModifierOp::Percent(0.2) };
+            assert_eq!(EffectiveStats::resolve(&vec![range; build.len()]).range, MAX_RANGE);
+            assert_eq!(EffectiveStats::resolve(&vec![range; build.len() - 1]).range, MAX_RANGE - 0.2);
+            assert_eq!(EffectiveStats::resolve(&vec![range; 50]).range, MAX_RANGE);
+        }
+    }
+}
+
+mod build_code {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use std::fmt;
+
+    pub struct BuildCodePlugin;
+
+    impl Plugin for BuildCodePlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<PlannedBuild>()
+                .add_systems(OnExit(GameState::GameOver), clear_planned_build);
+        }
+    }
+
+    const FORMAT_VERSION: u8 = 1;
+
+    /// Everything needed to replay a run: its seed and the order upgrades were picked in.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct BuildCode {
+        pub seed: u64,
+        pub upgrades: Vec<ui::Upgrade>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub enum BuildCodeError {
+        NotBase64,
+        Truncated,
+        UnsupportedVersion(u8),
+        ChecksumMismatch,
+        UnknownUpgrade(u8),
+    }
+
+    impl fmt::Display for BuildCodeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                BuildCodeError::NotBase64 => write!(f, "That doesn't look like a build code"),
+                BuildCodeError::Truncated => write!(f, "Build code is incomplete"),
+                BuildCodeError::UnsupportedVersion(version) => {
+                    write!(f, "Build code version {} is not supported", version)
+                }
+                BuildCodeError::ChecksumMismatch => write!(f, "Build code is corrupted (checksum mismatch)"),
+                BuildCodeError::UnknownUpgrade(id) => write!(f, "Build code contains unknown upgrade #{}", id),
+            }
+        }
+    }
+
+    /// Stable numbering for upgrades inside a code. Only ever append to this.
+    const CATALOG: [ui::Upgrade; 31] = [
+        ui::Upgrade::Multishot,
+        ui::Upgrade::ChainLightning,
+        ui::Upgrade::BladeCount,
+        ui::Upgrade::AttackSpeed,
+        ui::Upgrade::Shotgun,
+        ui::Upgrade::HomingMissile,
+        ui::Upgrade::DashBlast,
+        ui::Upgrade::RetaliationNova,
+        ui::Upgrade::Passive(passives::PassiveItem::HollowHeart),
+        ui::Upgrade::Passive(passives::PassiveItem::Wings),
+        ui::Upgrade::Passive(passives::PassiveItem::Spinach),
+        ui::Upgrade::Passive(passives::PassiveItem::EmptyTome),
+        ui::Upgrade::Evolve(passives::Evolution::StormCaller),
+        ui::Upgrade::Evolve(passives::Evolution::RazorHalo),
+        ui::Upgrade::Railgun,
+        ui::Upgrade::RailgunDamage,
+        ui::Upgrade::RailgunFireRate,
+        ui::Upgrade::MineLayer,
+        ui::Upgrade::Whip,
+        ui::Upgrade::WhipWidth,
+        ui::Upgrade::WhipReach,
+        ui::Upgrade::Turret,
+        ui::Upgrade::BlackHole,
+        ui::Upgrade::ReflectiveShield,
+        ui::Upgrade::MaxEnergy,
+        ui::Upgrade::EnergyRegen,
+        ui::Upgrade::Range,
+        ui::Upgrade::Overkill,
+        ui::Upgrade::CorpseBlast,
+        ui::Upgrade::PulseNova,
+        ui::Upgrade::ReaperNova,
+    ];
+
+    fn checksum(bytes: &[u8]) -> u8 {
+        bytes.iter().fold(0u8, |sum, byte| sum.wrapping_mul(31).wrapping_add(*byte))
+    }
+
+    impl BuildCode {
+        pub fn from_run(run: &run::CurrentRun) -> Self {
+            Self {
+                seed: run.seed,
+                upgrades: run.build.clone(),
+            }
+        }
+
+        /// Layout: version, seed (u64 LE), pick count, one catalog id per pick, checksum.
+        pub fn encode(&self) -> String {
+            let mut bytes = vec![FORMAT_VERSION];
+            bytes.extend_from_slice(&self.seed.to_le_bytes());
+            let upgrades = &self.upgrades[..self.upgrades.len().min(u8::MAX as usize)];
+            bytes.push(upgrades.len() as u8);
+            for upgrade in upgrades {
+                let id = CATALOG.iter().position(|known| known == upgrade).unwrap_or_default();
+                bytes.push(id as u8);
+            }
+            bytes.push(checksum(&bytes));
+            URL_SAFE_NO_PAD.encode(bytes)
+        }
+
+        pub fn decode(code: &str) -> Result<Self, BuildCodeError> {
+            let bytes = URL_SAFE_NO_PAD
+                .decode(code.trim())
+                .map_err(|_| BuildCodeError::NotBase64)?;
+            let (&version, rest) = bytes.split_first().ok_or(BuildCodeError::Truncated)?;
+            if version != FORMAT_VERSION {
+                return Err(BuildCodeError::UnsupportedVersion(version));
+            }
+            if rest.len() < 10 {
+                return Err(BuildCodeError::Truncated);
+            }
+            let seed = u64::from_le_bytes(rest[..8].try_into().unwrap());
+            let count = rest[8] as usize;
+            if rest.len() != 8 + 1 + count + 1 {
+                return Err(BuildCodeError::Truncated);
+            }
+            let (body, &[expected]) = bytes.split_at(bytes.len() - 1) else {
+                unreachable!("length checked above");
+            };
+            if checksum(body) != expected {
+                return Err(BuildCodeError::ChecksumMismatch);
+            }
+
+            let upgrades = rest[9..9 + count]
+                .iter()
+                .map(|&id| CATALOG.get(id as usize).copied().ok_or(BuildCodeError::UnknownUpgrade(id)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Self { seed, upgrades })
+        }
+    }
+
+    /// Upgrade order from a pasted build code, offered first at each level-up.
+    #[derive(Resource, Default)]
+    pub struct PlannedBuild(pub Vec<ui::Upgrade>);
+
+    impl PlannedBuild {
+        pub fn next_pick(&self, picks_so_far: usize) -> Option<ui::Upgrade> {
+            self.0.get(picks_so_far).copied()
+        }
+    }
+
+    fn clear_planned_build(mut planned_build: ResMut<PlannedBuild>) {
+        planned_build.0.clear();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_build_code_round_trip_and_validation() {
+            let code = BuildCode {
+                seed: 0xDEAD_BEEF_1234,
+                upgrades: vec![
+                    ui::Upgrade::Shotgun,
+                    ui::Upgrade::Passive(passives::PassiveItem::Wings),
+                    ui::Upgrade::Evolve(passives::Evolution::RazorHalo),
+                ],
+            };
+            let encoded = code.encode();
+            assert_eq!(BuildCode::decode(&encoded), Ok(code));
+
+            let mut tampered = encoded.into_bytes();
+            tampered[3] = if tampered[3] == b'A' { b'B' } else { b'A' };
+            let tampered = String::from_utf8(tampered).unwrap();
+            assert_eq!(BuildCode::decode(&tampered), Err(BuildCodeError::ChecksumMismatch));
+            assert_eq!(BuildCode::decode("not a code!"), Err(BuildCodeError::NotBase64));
+        }
+
+        #[test]
+        fn test_catalog_covers_every_upgrade() {
+            for upgrade in ui::Upgrade::ALL {
+                assert!(CATALOG.contains(&upgrade), "{:?} missing from the build code catalog", upgrade);
+            }
+            for recipe in passives::RECIPES {
+                assert!(CATALOG.contains(&ui::Upgrade::Evolve(recipe.evolution)));
+            }
+        }
+    }
+}
+
+mod collision {
+    use super::*;
+    use bevy::utils::HashMap;
+
+    const GRID_CELL_SIZE: f32 = 64.0;
+
+    /// Entities bucketed into square cells by position, so a hit test only looks at the
+    /// handful of cells around it instead of every entity in the world. Cells keep their
+    /// allocations between rebuilds.
+    pub struct SpatialGrid {
+        cell_size: f32,
+        cells: HashMap<IVec2, Vec<(Entity, Vec3, f32)>>,
+    }
+
+    impl Default for SpatialGrid {
+        fn default() -> Self {
+            Self {
+                cell_size: GRID_CELL_SIZE,
+                cells: HashMap::default(),
+            }
+        }
+    }
+
+    impl SpatialGrid {
+        fn cell(&self, position: Vec2) -> IVec2 {
+            (position / self.cell_size).floor().as_ivec2()
+        }
+
+        /// Changes the cell size, dropping every cell since their keys no longer line up.
+        pub fn set_cell_size(&mut self, cell_size: f32) {
+            if self.cell_size != cell_size {
+                self.cell_size = cell_size;
+                self.cells.clear();
+            }
+        }
+
+        pub fn clear(&mut self) {
+            for cell in self.cells.values_mut() {
+                cell.clear();
+            }
+        }
+
+        pub fn insert(&mut self, entity: Entity, position: Vec3, radius: f32) {
+            let cell = self.cell(position.truncate());
+            self.cells.entry(cell).or_default().push((entity, position, radius));
+        }
+
+        /// Everything in the cells overlapping the square around `center`, as
+        /// `(entity, position, radius)`. Callers do their own exact test.
+        pub fn near(&self, center: Vec3, range: f32) -> impl Iterator<Item = &(Entity, Vec3, f32)> {
+            let min = self.cell(center.truncate() - Vec2::splat(range));
+            let max = self.cell(center.truncate() + Vec2::splat(range));
+            (min.y..=max.y)
+                .flat_map(move |y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .flatten()
+        }
+    }
+
+    /// How many cells apart two points are at the default cell size, counting diagonal
+    /// steps as one.
+    pub fn cell_distance(a: Vec3, b: Vec3) -> u32 {
+        let cell = |position: Vec3| (position.truncate() / GRID_CELL_SIZE).floor().as_ivec2();
+        (cell(a) - cell(b)).abs().max_element() as u32
+    }
+
+    /// Every live enemy, rebuilt after movement each frame for the collision stage.
+    #[derive(Resource, Default)]
+    pub struct EnemyGrid(pub SpatialGrid);
+
+    #[allow(clippy::type_complexity)]
+    pub fn build_enemy_grid(
+        mut grid: ResMut<EnemyGrid>,
+        budget: Res<budget::FrameBudget>,
+        enemy_query: Query<(Entity, &Transform), (With<enemy::Enemy>, Without<combat::MarkedForDeath>)>,
+    ) {
+        grid.0.set_cell_size(budget.fidelity.grid_cell_size());
+        grid.0.clear();
+        for (entity, transform) in enemy_query.iter() {
+            grid.0.insert(entity, transform.translation, enemy::radius(transform));
+        }
+    }
+
+    /// Where an entity was before this frame's movement, so hit tests can sweep
+    /// the whole path instead of sampling only the end point.
+    #[derive(Component, Default)]
+    pub struct PreviousPosition(pub Option<Vec3>);
+
+    impl PreviousPosition {
+        pub fn segment_to(&self, current: Vec3) -> (Vec3, Vec3) {
+            (self.0.unwrap_or(current), current)
+        }
+    }
+
+    /// Closest point to `point` on the segment `start..end`, ignoring z.
+    pub fn closest_point_on_segment(start: Vec3, end: Vec3, point: Vec3) -> Vec2 {
+        let (start, end, point) = (start.truncate(), end.truncate(), point.truncate());
+        let segment = end - start;
+        let length_squared = segment.length_squared();
+        if length_squared <= f32::EPSILON {
+            return start;
+        }
+        let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+        start + segment * t
+    }
+
+    pub fn segment_hits_circle(start: Vec3, end: Vec3, center: Vec3, radius: f32) -> bool {
+        closest_point_on_segment(start, end, center).distance_squared(center.truncate()) < radius * radius
+    }
+
+    /// Fraction along `start..end` (0.0..=1.0) where the segment first touches the circle,
+    /// or `None` if it never does. Starting inside the circle counts as touching at 0.0.
+    pub fn segment_circle_entry(start: Vec3, end: Vec3, center: Vec3, radius: f32) -> Option<f32> {
+        let (start, end, center) = (start.truncate(), end.truncate(), center.truncate());
+        let to_start = start - center;
+        let c = to_start.length_squared() - radius * radius;
+        if c < 0.0 {
+            return Some(0.0);
+        }
+        let segment = end - start;
+        let a = segment.length_squared();
+        if a <= f32::EPSILON {
+            return None;
+        }
+        let b = to_start.dot(segment);
+        let discriminant = b * b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t = (-b - discriminant.sqrt()) / a;
+        (0.0..=1.0).contains(&t).then_some(t)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_spatial_grid_finds_only_nearby_entities() {
+            let mut grid = SpatialGrid::default();
+            let near = Entity::from_raw(1);
+            let far = Entity::from_raw(2);
+            grid.insert(near, Vec3::new(-70.0, 10.0, 0.0), 10.0);
+            grid.insert(far, Vec3::new(500.0, 500.0, 0.0), 10.0);
+
+            let found = grid.near(Vec3::ZERO, 100.0).map(|&(entity, ..)| entity).collect::<Vec<_>>();
+            assert_eq!(found, vec![near]);
+
+            grid.clear();
+            assert_eq!(grid.near(Vec3::ZERO, 100.0).count(), 0);
+        }
+
+        #[test]
+        fn test_segment_catches_what_the_end_points_skip() {
+            // One frame of an 800 u/s projectile at 20 FPS, straight through a 20-unit enemy.
+            let start = Vec3::new(-20.0, 0.0, 0.0);
+            let end = Vec3::new(20.0, 0.0, 0.0);
+            let enemy = Vec3::new(0.0, 5.0, 0.0);
+            assert!(start.distance(enemy) > 10.0 && end.distance(enemy) > 10.0);
+            assert!(segment_hits_circle(start, end, enemy, 10.0));
+            assert!(!segment_hits_circle(start, end, Vec3::new(0.0, 15.0, 0.0), 10.0));
+            assert!(!segment_hits_circle(start, end, Vec3::new(40.0, 0.0, 0.0), 10.0));
+            assert!(segment_hits_circle(end, end, end, 1.0));
+        }
+
+        #[test]
+        fn test_segment_entry_orders_hits_along_the_path() {
+            // A sniper round covering 150 units in one frame, passing two enemies.
+            let start = Vec3::ZERO;
+            let end = Vec3::new(150.0, 0.0, 0.0);
+            let near = segment_circle_entry(start, end, Vec3::new(40.0, 0.0, 0.0), 10.0).unwrap();
+            let far = segment_circle_entry(start, end, Vec3::new(120.0, 3.0, 0.0), 10.0).unwrap();
+            assert!((near - 0.2).abs() < 1e-4);
+            assert!(near < far);
+            assert_eq!(segment_circle_entry(start, end, Vec3::new(75.0, 30.0, 0.0), 10.0), None);
+            assert_eq!(segment_circle_entry(start, end, Vec3::new(200.0, 0.0, 0.0), 10.0), None);
+            assert_eq!(segment_circle_entry(start, end, Vec3::new(5.0, 0.0, 0.0), 10.0), Some(0.0));
+        }
+    }
+}
+
+mod budget {
+    use super::*;
+    use bevy::utils::Instant;
+    use std::time::Duration;
+
+    /// How long the collision stage and boid steering may take together each frame.
+    const FRAME_BUDGET_MS: f32 = 4.0;
+    /// Fidelity only comes back once the smoothed cost is this far under budget, so
+    /// it doesn't flip straight back to the level it just left.
+    const RECOVERY_FRACTION: f32 = 0.5;
+    /// Consecutive frames over budget before fidelity drops a level.
+    const OVER_BUDGET_FRAMES: u32 = 30;
+    /// Consecutive frames comfortably under budget before fidelity comes back a level.
+    const UNDER_BUDGET_FRAMES: u32 = 300;
+    /// Weight of the newest frame in the smoothed cost. Boids skip frames when degraded,
+    /// so single frames swing too much to judge by.
+    const SMOOTHING: f32 = 0.1;
+
+    /// Watchdog for the systems that scale with the size of the swarm. When they run
+    /// over budget for a while it trades away fidelity (coarser collision cells, boids
+    /// every few frames, fewer ambient particles) instead of letting the frame rate sink.
+    pub struct BudgetPlugin;
+
+    impl Plugin for BudgetPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<FrameBudget>()
+                .add_systems(
+                    Update,
+                    (
+                        start_collision_timer
+                            .after(combat::CombatSet::Move)
+                            .before(collision::build_enemy_grid),
+                        (stop_collision_timer, govern_fidelity)
+                            .chain()
+                            .after(combat::CombatSet::Collide)
+                            .before(combat::CombatSet::ApplyDamage),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_budget);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+    pub enum Fidelity {
+        #[default]
+        Full,
+        Reduced,
+        Minimal,
+    }
+
+    impl Fidelity {
+        /// Shown on the HUD while fidelity is degraded.
+        pub fn label(&self) -> Option<&'static str> {
+            match self {
+                Fidelity::Full => None,
+                Fidelity::Reduced => Some("Reduced"),
+                Fidelity::Minimal => Some("Minimal"),
+            }
+        }
+
+        /// Larger cells mean fewer lookups per hit test, at the cost of more exact tests.
+        pub fn grid_cell_size(&self) -> f32 {
+            match self {
+                Fidelity::Full => 64.0,
+                Fidelity::Reduced => 128.0,
+                Fidelity::Minimal => 192.0,
+            }
+        }
+
+        /// Boid steering runs once every this many frames.
+        pub fn boid_interval(&self) -> u32 {
+            match self {
+                Fidelity::Full => 1,
+                Fidelity::Reduced => 2,
+                Fidelity::Minimal => 3,
+            }
+        }
+
+        /// Fraction of the ambient particle setting that is actually spawned.
+        pub fn particle_scale(&self) -> f32 {
+            match self {
+                Fidelity::Full => 1.0,
+                Fidelity::Reduced => 0.5,
+                Fidelity::Minimal => 0.25,
+            }
+        }
+
+        fn lower(self) -> Self {
+            match self {
+                Fidelity::Full => Fidelity::Reduced,
+                _ => Fidelity::Minimal,
+            }
+        }
+
+        fn raise(self) -> Self {
+            match self {
+                Fidelity::Minimal => Fidelity::Reduced,
+                _ => Fidelity::Full,
+            }
+        }
+    }
+
+    #[derive(Resource, Debug)]
+    pub struct FrameBudget {
+        pub fidelity: Fidelity,
+        /// Time boid steering took on its last run, written by the system itself.
+        pub boids: Duration,
+        /// Off for the balance simulation, whose results mustn't depend on the machine.
+        governed: bool,
+        collision_started: Option<Instant>,
+        collision: Duration,
+        smoothed_ms: f32,
+        over_frames: u32,
+        under_frames: u32,
+    }
+
+    impl Default for FrameBudget {
+        fn default() -> Self {
+            Self {
+                fidelity: Fidelity::Full,
+                boids: Duration::ZERO,
+                governed: true,
+                collision_started: None,
+                collision: Duration::ZERO,
+                smoothed_ms: 0.0,
+                over_frames: 0,
+                under_frames: 0,
+            }
+        }
+    }
+
+    impl FrameBudget {
+        /// Stays at full fidelity however long frames take.
+        pub fn fixed() -> Self {
+            Self {
+                governed: false,
+                ..default()
+            }
+        }
+
+        /// Folds one frame's cost into the smoothed cost and moves fidelity a level
+        /// if it has been over or well under budget for long enough.
+        fn record(&mut self, spent_ms: f32) {
+            self.smoothed_ms += (spent_ms - self.smoothed_ms) * SMOOTHING;
+            if self.smoothed_ms > FRAME_BUDGET_MS {
+                self.over_frames += 1;
+                self.under_frames = 0;
+            } else if self.smoothed_ms < FRAME_BUDGET_MS * RECOVERY_FRACTION {
+                self.under_frames += 1;
+                self.over_frames = 0;
+            } else {
+                self.over_frames = 0;
+                self.under_frames = 0;
+            }
+
+            if self.over_frames >= OVER_BUDGET_FRAMES {
+                self.fidelity = self.fidelity.lower();
+                self.over_frames = 0;
+            } else if self.under_frames >= UNDER_BUDGET_FRAMES {
+                self.fidelity = self.fidelity.raise();
+                self.under_frames = 0;
+            }
+        }
+    }
+
+    fn start_collision_timer(mut budget: ResMut<FrameBudget>) {
+        budget.collision_started = Some(Instant::now());
+    }
+
+    fn stop_collision_timer(mut budget: ResMut<FrameBudget>) {
+        if let Some(started) = budget.collision_started.take() {
+            budget.collision = started.elapsed();
+        }
+    }
+
+    fn govern_fidelity(mut budget: ResMut<FrameBudget>) {
+        if !budget.governed {
+            return;
+        }
+        let spent = budget.collision + std::mem::take(&mut budget.boids);
+        let previous = budget.fidelity;
+        budget.record(spent.as_secs_f32() * 1000.0);
+        if budget.fidelity != previous {
+            info!("Frame budget: fidelity {:?} -> {:?}", previous, budget.fidelity);
+        }
+    }
+
+    fn reset_budget(mut budget: ResMut<FrameBudget>) {
+        let governed = budget.governed;
+        *budget = FrameBudget { governed, ..default() };
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_fidelity_drops_under_load_and_recovers_slowly() {
+            let mut budget = FrameBudget::default();
+            for _ in 0..20 {
+                budget.record(FRAME_BUDGET_MS * 3.0);
+            }
+            assert_eq!(budget.fidelity, Fidelity::Full, "a short spike shouldn't degrade");
+            for _ in 0..OVER_BUDGET_FRAMES * 2 {
+                budget.record(FRAME_BUDGET_MS * 3.0);
+            }
+            assert_eq!(budget.fidelity, Fidelity::Minimal);
+
+            // Alternating frames (boids skipped every other one) are judged on their average.
+            for i in 0..UNDER_BUDGET_FRAMES {
+                budget.record(if i % 2 == 0 { FRAME_BUDGET_MS * 1.2 } else { 0.0 });
+            }
+            assert_eq!(budget.fidelity, Fidelity::Minimal, "average is not yet well under budget");
+            for _ in 0..UNDER_BUDGET_FRAMES * 2 + 60 {
+                budget.record(0.5);
+            }
+            assert_eq!(budget.fidelity, Fidelity::Full);
+        }
+    }
+}
+
+mod hibernation {
+    use super::*;
+    use bevy::time::common_conditions::on_timer;
+    use std::time::Duration;
+
+    /// Entities further than this from the player go to sleep.
+    const HIBERNATE_DISTANCE: f32 = 1600.0;
+    /// ...and wake again once the player is back within this, a little closer so
+    /// something on the boundary doesn't flicker between the two.
+    const WAKE_DISTANCE: f32 = 1400.0;
+    const SCAN_INTERVAL: f32 = 0.5;
+
+    /// Freezes long-lived world entities the player has left far behind, so the
+    /// systems that tick them only see the ones nearby however long the run goes.
+    pub struct HibernationPlugin;
+
+    impl Plugin for HibernationPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                update_hibernation
+                    .run_if(in_play)
+                    .run_if(on_timer(Duration::from_secs_f32(SCAN_INTERVAL))),
+            );
+        }
+    }
+
+    /// May be put to sleep when far from the player: gems, corpse decals, mines and vortices.
+    #[derive(Component)]
+    pub struct Hibernates;
+
+    /// Asleep. The systems that tick a hibernating kind skip entities with this, so
+    /// their timers and effects pick up where they left off once woken.
+    #[derive(Component)]
+    pub struct Hibernating;
+
+    /// Whether an entity `distance` from the player should be asleep, given whether it is now.
+    pub fn should_hibernate(distance: f32, hibernating: bool) -> bool {
+        if hibernating {
+            distance > WAKE_DISTANCE
+        } else {
+            distance > HIBERNATE_DISTANCE
+        }
+    }
+
+    fn update_hibernation(
+        mut commands: Commands,
+        player_query: Query<&Transform, With<player::Player>>,
+        query: Query<(Entity, &GlobalTransform, Has<Hibernating>), With<Hibernates>>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player = player_transform.translation.truncate();
+        for (entity, transform, hibernating) in query.iter() {
+            let distance = transform.translation().truncate().distance(player);
+            match (hibernating, should_hibernate(distance, hibernating)) {
+                (false, true) => {
+                    commands.entity(entity).try_insert(Hibernating);
+                }
+                (true, false) => {
+                    commands.entity(entity).remove::<Hibernating>();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_hibernation_has_a_dead_zone() {
+            assert!(!should_hibernate(HIBERNATE_DISTANCE - 1.0, false));
+            assert!(should_hibernate(HIBERNATE_DISTANCE + 1.0, false));
+            // Between the two distances, things stay as they are.
+            let between = (HIBERNATE_DISTANCE + WAKE_DISTANCE) / 2.0;
+            assert!(should_hibernate(between, true));
+            assert!(!should_hibernate(between, false));
+            assert!(!should_hibernate(WAKE_DISTANCE - 1.0, true));
+        }
+    }
+}
+
+mod railgun {
+    use super::*;
+
+    pub struct RailgunPlugin;
+
+    impl Plugin for RailgunPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<Railgun>()
+                .add_systems(
+                    Update,
+                    (fire_railgun.in_set(combat::CombatSet::Fire), fade_beams).run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_railgun);
+        }
+    }
+
+    const RAILGUN_BASE_DAMAGE: f32 = 40.0;
+    /// Armor shred stacks a beam leaves on everything it passes through.
+    const RAILGUN_SHRED: u32 = 2;
+    const RAILGUN_BASE_COOLDOWN: f32 = 3.0;
+    const RAILGUN_MIN_COOLDOWN: f32 = 1.0;
+    const RAILGUN_RANGE: f32 = 2000.0;
+    const BEAM_WIDTH: f32 = 8.0;
+    const BEAM_FADE: f32 = 0.3;
+
+    #[derive(Resource, Debug)]
+    pub struct Railgun {
+        pub unlocked: bool,
+        pub damage: f32,
+        timer: Timer,
+    }
+
+    impl Default for Railgun {
+        fn default() -> Self {
+            Self {
+                unlocked: false,
+                damage: RAILGUN_BASE_DAMAGE,
+                timer: Timer::from_seconds(RAILGUN_BASE_COOLDOWN, TimerMode::Repeating),
+            }
+        }
+    }
+
+    impl Railgun {
+        /// How far the next shot has charged, from 0.0 to 1.0, or `None` before it's picked.
+        pub fn charge(&self) -> Option<f32> {
+            self.unlocked.then(|| self.timer.fraction())
+        }
+
+        pub fn faster_charge(&mut self) {
+            let cooldown = (self.timer.duration().as_secs_f32() * 0.85).max(RAILGUN_MIN_COOLDOWN);
+            self.timer.set_duration(std::time::Duration::from_secs_f32(cooldown));
+        }
+    }
+
+    #[derive(Component)]
+    struct RailBeam(Timer);
+
+    #[allow(clippy::too_many_arguments)]
+    fn fire_railgun(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut railgun: ResMut<Railgun>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        player_query: Query<&Transform, With<player::Player>>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
+        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+    ) {
+        if !railgun.unlocked || !railgun.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let origin = player_transform.translation;
+        let Some(target) = enemy_query
+            .iter()
+            .map(|(_, transform)| transform.translation)
+            .min_by(|a, b| a.distance_squared(origin).total_cmp(&b.distance_squared(origin)))
+        else {
+            return;
+        };
+
+        let direction = (target - origin).truncate().normalize_or_zero().extend(0.0);
+        let end = origin + direction * RAILGUN_RANGE;
+        // Weapon damage modifiers scale the railgun the same way they scale the main gun.
+        let damage = railgun.damage * effective_stats.damage / BASE_WEAPON_DAMAGE
+            * combat::damage_multiplier(&last_stand_query);
+
+        for (entity, transform) in enemy_query.iter() {
+            if collision::segment_hits_circle(
+                origin,
+                end,
+                transform.translation,
+                enemy::radius(transform) + BEAM_WIDTH / 2.0,
+            ) {
+                damage_events.send(enemy::EnemyDamaged {
+                    entity,
+                    amount: damage,
+                    shred: RAILGUN_SHRED,
+                    split: None,
+                });
+            }
+        }
+
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(2.5, 2.5, 3.5),
+                    custom_size: Some(Vec2::new(RAILGUN_RANGE, BEAM_WIDTH)),
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Vfx.at((origin + end) / 2.0))
+                    .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                ..default()
+            },
+            RailBeam(Timer::from_seconds(BEAM_FADE, TimerMode::Once)),
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    fn fade_beams(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut query: Query<(Entity, &mut RailBeam, &mut Sprite)>,
+    ) {
+        for (entity, mut beam, mut sprite) in query.iter_mut() {
+            beam.0.tick(time.delta());
+            sprite.color.set_a(beam.0.fraction_remaining());
+            if let Some(size) = sprite.custom_size.as_mut() {
+                size.y = BEAM_WIDTH * beam.0.fraction_remaining();
+            }
+            if beam.0.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn reset_railgun(mut railgun: ResMut<Railgun>) {
+        *railgun = Railgun::default();
+    }
+}
+
+mod mines {
+    use super::*;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+    pub struct MinesPlugin;
+
+    impl Plugin for MinesPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<MineLayer>()
+                .init_resource::<ExplosionPool>()
+                .add_systems(
+                    Update,
+                    (
+                        drop_mines.in_set(combat::CombatSet::Fire),
+                        arm_and_trigger_mines.in_set(combat::CombatSet::Collide),
+                        animate_explosions.after(arm_and_trigger_mines),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_mines);
+        }
+    }
+
+    const MINE_SIZE: f32 = 14.0;
+    const MINE_BASE_INTERVAL: f32 = 2.0;
+    const MINE_BASE_DAMAGE: f32 = 25.0;
+    const MINE_ARMING_TIME: f32 = 0.6;
+    const MINE_LIFETIME: f32 = 12.0;
+    const MINE_TRIGGER_RADIUS: f32 = 50.0;
+    const MINE_BLAST_RADIUS: f32 = 120.0;
+    const EXPLOSION_DURATION: f32 = 0.3;
+
+    /// Mine layer weapon level; 0 means the player doesn't have it.
+    #[derive(Resource, Debug)]
+    pub struct MineLayer {
+        pub level: u32,
+        timer: Timer,
+    }
+
+    impl Default for MineLayer {
+        fn default() -> Self {
+            Self {
+                level: 0,
+                timer: Timer::from_seconds(MINE_BASE_INTERVAL, TimerMode::Repeating),
+            }
+        }
+    }
+
+    impl MineLayer {
+        /// How far along the next mine is, from 0.0 to 1.0, or `None` before it's picked.
+        pub fn charge(&self) -> Option<f32> {
+            (self.level > 0).then(|| self.timer.fraction())
+        }
+
+        fn interval(&self) -> f32 {
+            MINE_BASE_INTERVAL / (1.0 + 0.25 * self.level.saturating_sub(1) as f32)
+        }
+
+        fn damage(&self) -> f32 {
+            MINE_BASE_DAMAGE * (1.0 + 0.2 * self.level.saturating_sub(1) as f32)
+        }
+    }
+
+    #[derive(Component)]
+    struct Mine {
+        arming: Timer,
+        lifetime: Timer,
+    }
+
+    /// Explosion visuals are reused rather than spawned per blast, since a
+    /// chain of mines going off in a crowd can mean dozens in a few frames.
+    #[derive(Resource, Default)]
+    struct ExplosionPool {
+        free: Vec<Entity>,
+    }
+
+    /// Drawn with a `MINE_BLAST_RADIUS` mesh, scaled up to `radius`.
+    #[derive(Component)]
+    struct Explosion {
+        timer: Timer,
+        material: Handle<ColorMaterial>,
+        radius: f32,
+    }
+
+    fn drop_mines(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut mine_layer: ResMut<MineLayer>,
+        player_query: Query<(&Transform, &collision::PreviousPosition), With<player::Player>>,
+    ) {
+        if mine_layer.level == 0 {
+            return;
+        }
+        let interval = std::time::Duration::from_secs_f32(mine_layer.interval());
+        mine_layer.timer.set_duration(interval);
+        if !mine_layer.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+        let Ok((player_transform, previous)) = player_query.get_single() else {
+            return;
+        };
+
+        // Drop just behind the player so kiting drags the swarm over them.
+        let (from, to) = previous.segment_to(player_transform.translation);
+        let behind = (from - to).truncate().normalize_or_zero().extend(0.0);
+        let position = (player_transform.translation + behind * PLAYER_SIZE).truncate().extend(render_layer::RenderLayer::Decals.z() + 0.1);
+
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.4, 0.3, 0.1),
+                    custom_size: Some(Vec2::splat(MINE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position)
+                    .with_rotation(Quat::from_rotation_z(std::f32::consts::FRAC_PI_4)),
+                ..default()
+            },
+            Mine {
+                arming: Timer::from_seconds(MINE_ARMING_TIME, TimerMode::Once),
+                lifetime: Timer::from_seconds(MINE_LIFETIME, TimerMode::Once),
+            },
+            hibernation::Hibernates,
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    fn arm_and_trigger_mines(
+        mut commands: Commands,
+        time: Res<Time>,
+        mine_layer: Res<MineLayer>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
+        mut mine_query: Query<(Entity, &mut Mine, &mut Sprite, &Transform), Without<hibernation::Hibernating>>,
+        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+        mut pool: ResMut<ExplosionPool>,
+        mut explosion_query: Query<
+            (&mut Explosion, &mut Transform, &mut Visibility),
+            (Without<Mine>, Without<enemy::Enemy>),
+        >,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut screen_effects: ResMut<post_process::ScreenEffects>,
+    ) {
+        let damage = mine_layer.damage() * effective_stats.damage / BASE_WEAPON_DAMAGE
+            * combat::damage_multiplier(&last_stand_query);
+        let blast_radius = MINE_BLAST_RADIUS * effective_stats.range;
+
+        for (entity, mut mine, mut sprite, transform) in mine_query.iter_mut() {
+            if mine.lifetime.tick(time.delta()).finished() {
+                commands.entity(entity).despawn();
+                continue;
+            }
+            if !mine.arming.tick(time.delta()).finished() {
+                continue;
+            }
+            if mine.arming.just_finished() {
+                sprite.color = Color::rgb(2.0, 0.6, 0.1);
+            }
+
+            let position = transform.translation;
+            let triggered = enemy_query.iter().any(|(_, enemy_transform)| {
+                enemy_transform.translation.truncate().distance(position.truncate())
+                    < MINE_TRIGGER_RADIUS + enemy::radius(enemy_transform)
+            });
+            if !triggered {
+                continue;
+            }
+
+            for (enemy_entity, enemy_transform) in enemy_query.iter() {
+                if enemy_transform.translation.truncate().distance(position.truncate()) < blast_radius {
+                    damage_events.send(enemy::EnemyDamaged {
+                        entity: enemy_entity,
+                        amount: damage,
+                        shred: 0,
+                        split: None,
+                    });
+                }
+            }
+            commands.entity(entity).despawn();
+            screen_effects.chromatic_flash(post_process::EXPLOSION_FLASH);
+
+            let blast_position = render_layer::RenderLayer::Vfx.at(position);
+            match pool.free.pop() {
+                Some(explosion_entity) => {
+                    if let Ok((mut explosion, mut explosion_transform, mut visibility)) =
+                        explosion_query.get_mut(explosion_entity)
+                    {
+                        explosion.timer.reset();
+                        explosion.radius = blast_radius;
+                        explosion_transform.translation = blast_position;
+                        *visibility = Visibility::Visible;
+                    }
+                }
+                None => {
+                    let material = materials.add(Color::rgba(2.0, 0.8, 0.2, 0.6));
+                    commands.spawn((
+                        MaterialMesh2dBundle {
+                            mesh: Mesh2dHandle(meshes.add(Circle::new(MINE_BLAST_RADIUS))),
+                            material: material.clone(),
+                            transform: Transform::from_translation(blast_position),
+                            ..default()
+                        },
+                        Explosion {
+                            timer: Timer::from_seconds(EXPLOSION_DURATION, TimerMode::Once),
+                            material,
+                            radius: blast_radius,
+                        },
+                        DespawnOnExit::RUN,
+                    ));
+                }
+            }
+        }
+    }
+
+    fn animate_explosions(
+        time: Res<Time>,
+        mut pool: ResMut<ExplosionPool>,
+        mut query: Query<(Entity, &mut Explosion, &mut Transform, &mut Visibility)>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+    ) {
+        for (entity, mut explosion, mut transform, mut visibility) in query.iter_mut() {
+            if *visibility == Visibility::Hidden {
+                continue;
+            }
+            explosion.timer.tick(time.delta());
+            let progress = explosion.timer.fraction();
+            transform.scale = Vec3::splat((0.3 + 0.7 * progress) * explosion.radius / MINE_BLAST_RADIUS);
+            if let Some(material) = materials.get_mut(&explosion.material) {
+                material.color.set_a(0.6 * (1.0 - progress));
+            }
+            if explosion.timer.finished() {
+                *visibility = Visibility::Hidden;
+                pool.free.push(entity);
+            }
+        }
+    }
+
+    fn reset_mines(mut mine_layer: ResMut<MineLayer>, mut pool: ResMut<ExplosionPool>) {
+        *mine_layer = MineLayer::default();
+        pool.free.clear();
+    }
+}
+
+mod whip {
+    use super::*;
+
+    pub struct WhipPlugin;
+
+    impl Plugin for WhipPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<Whip>()
+                .add_systems(
+                    Update,
+                    (crack_whip.in_set(combat::CombatSet::Fire), animate_swings.after(crack_whip)).run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_whip);
+        }
+    }
+
+    const WHIP_COOLDOWN: f32 = 1.2;
+    const WHIP_DAMAGE: f32 = 15.0;
+    const WHIP_SHRED: u32 = 1;
+    const WHIP_BASE_ARC: f32 = std::f32::consts::FRAC_PI_2;
+    const WHIP_MAX_ARC: f32 = std::f32::consts::PI * 1.5;
+    const WHIP_BASE_REACH: f32 = 160.0;
+    const SWING_DURATION: f32 = 0.15;
+
+    #[derive(Resource, Debug)]
+    pub struct Whip {
+        pub unlocked: bool,
+        /// Total angle of the swing, centred on the facing direction.
+        pub arc: f32,
+        pub reach: f32,
+        timer: Timer,
+    }
+
+    impl Default for Whip {
+        fn default() -> Self {
+            Self {
+                unlocked: false,
+                arc: WHIP_BASE_ARC,
+                reach: WHIP_BASE_REACH,
+                timer: Timer::from_seconds(WHIP_COOLDOWN, TimerMode::Repeating),
+            }
+        }
+    }
+
+    impl Whip {
+        /// How far along the next swing is, from 0.0 to 1.0, or `None` before it's picked.
+        pub fn charge(&self) -> Option<f32> {
+            self.unlocked.then(|| self.timer.fraction())
+        }
+
+        pub fn widen(&mut self) {
+            self.arc = (self.arc * 1.3).min(WHIP_MAX_ARC);
+        }
+    }
+
+    /// True if a circle at `point` overlaps the arc of `arc` radians and `reach`
+    /// length swept around `facing` from `origin`.
+    fn in_arc(origin: Vec2, facing: Vec2, arc: f32, reach: f32, point: Vec2, radius: f32) -> bool {
+        let offset = point - origin;
+        let distance = offset.length();
+        if distance > reach + radius {
+            return false;
+        }
+        if distance <= radius {
+            return true;
+        }
+        // Widen the half-angle by the target's angular size so edge grazes still connect.
+        let half_arc = arc / 2.0 + (radius / distance).asin();
+        facing.angle_between(offset).abs() <= half_arc
+    }
+
+    #[derive(Component)]
+    struct WhipSwing {
+        facing_angle: f32,
+        arc: f32,
+        reach: f32,
+        timer: Timer,
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn crack_whip(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut whip: ResMut<Whip>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
+        player_query: Query<(&Transform, &player::Facing), With<player::Player>>,
+        enemy_query: Query<(Entity, &Transform), With<enemy::Enemy>>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+    ) {
+        if !whip.unlocked || !whip.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+        let Ok((player_transform, facing)) = player_query.get_single() else {
+            return;
+        };
+        let origin = player_transform.translation.truncate();
+        let facing = facing.0.truncate();
+        let damage = WHIP_DAMAGE * effective_stats.damage / BASE_WEAPON_DAMAGE
+            * combat::damage_multiplier(&last_stand_query);
+
+        for (entity, transform) in enemy_query.iter() {
+            let position = transform.translation.truncate();
+            if in_arc(origin, facing, whip.arc, whip.reach, position, enemy::radius(transform)) {
+                damage_events.send(enemy::EnemyDamaged {
+                    entity,
+                    amount: damage,
+                    shred: WHIP_SHRED,
+                    split: None,
+                });
+            }
+        }
+
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1.8, 1.4, 0.9),
+                    custom_size: Some(Vec2::new(whip.reach, 6.0)),
+                    anchor: bevy::sprite::Anchor::CenterLeft,
+                    ..default()
+                },
+                transform: Transform::from_translation(render_layer::RenderLayer::Vfx.at(player_transform.translation)),
+                ..default()
+            },
+            WhipSwing {
+                facing_angle: facing.y.atan2(facing.x),
+                arc: whip.arc,
+                reach: whip.reach,
+                timer: Timer::from_seconds(SWING_DURATION, TimerMode::Once),
+            },
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    /// Sweeps the whip sprite from one edge of the arc to the other, following the player.
+    fn animate_swings(
+        mut commands: Commands,
+        time: Res<Time>,
+        player_query: Query<&Transform, (With<player::Player>, Without<WhipSwing>)>,
+        mut swing_query: Query<(Entity, &mut WhipSwing, &mut Transform, &mut Sprite)>,
+    ) {
+        let player_position = player_query.get_single().map(|transform| transform.translation).ok();
+        for (entity, mut swing, mut transform, mut sprite) in swing_query.iter_mut() {
+            swing.timer.tick(time.delta());
+            let progress = swing.timer.fraction();
+            let angle = swing.facing_angle - swing.arc / 2.0 + swing.arc * progress;
+            if let Some(position) = player_position {
+                transform.translation = render_layer::RenderLayer::Vfx.at(position);
+            }
+            transform.rotation = Quat::from_rotation_z(angle);
+            sprite.custom_size = Some(Vec2::new(swing.reach, 6.0));
+            sprite.color.set_a(1.0 - progress * 0.5);
+            if swing.timer.finished() {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+
+    fn reset_whip(mut whip: ResMut<Whip>) {
+        *whip = Whip::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_in_arc_respects_width_and_reach() {
+            let origin = Vec2::ZERO;
+            let facing = Vec2::X;
+            let arc = std::f32::consts::FRAC_PI_2;
+            assert!(in_arc(origin, facing, arc, 100.0, Vec2::new(80.0, 0.0), 10.0));
+            assert!(in_arc(origin, facing, arc, 100.0, Vec2::new(50.0, 40.0), 10.0));
+            assert!(!in_arc(origin, facing, arc, 100.0, Vec2::new(-50.0, 0.0), 10.0));
+            assert!(!in_arc(origin, facing, arc, 100.0, Vec2::new(0.0, 60.0), 10.0));
+            assert!(!in_arc(origin, facing, arc, 100.0, Vec2::new(130.0, 0.0), 10.0));
+        }
+    }
+}
+
+mod turrets {
+    use super::*;
+
+    pub struct TurretsPlugin;
+
+    impl Plugin for TurretsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<TurretDeployer>()
+                .add_systems(
+                    Update,
+                    (deploy_turrets, fire_turrets).chain().in_set(combat::CombatSet::Fire).run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_turrets);
+        }
+    }
+
+    const TURRET_DEPLOY_INTERVAL: f32 = 20.0;
+    const TURRET_BASE_LIFETIME: f32 = 10.0;
+    const TURRET_FIRE_INTERVAL: f32 = 0.4;
+    const TURRET_RANGE: f32 = 500.0;
+    const TURRET_SIZE: f32 = 24.0;
+
+    /// Turret weapon level; 0 means the player doesn't have it. Each level keeps turrets up longer.
+    #[derive(Resource, Debug)]
+    pub struct TurretDeployer {
+        pub level: u32,
+        timer: Timer,
+    }
+
+    impl Default for TurretDeployer {
+        fn default() -> Self {
+            let mut timer = Timer::from_seconds(TURRET_DEPLOY_INTERVAL, TimerMode::Repeating);
+            // The first turret goes down as soon as the weapon is picked.
+            timer.tick(timer.duration());
+            Self { level: 0, timer }
+        }
+    }
+
+    impl TurretDeployer {
+        /// How far along the next turret is, from 0.0 to 1.0, or `None` before it's picked.
+        pub fn charge(&self) -> Option<f32> {
+            (self.level > 0).then(|| self.timer.fraction())
+        }
+    }
+
+    /// A stationary owner for a gun: it fires the same bullets the player does, from where it stands.
+    #[derive(Component)]
+    struct Turret {
+        fire: Timer,
+        lifetime: Timer,
+    }
+
+    fn deploy_turrets(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut deployer: ResMut<TurretDeployer>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        if deployer.level == 0 {
+            return;
+        }
+        let ready = deployer.timer.finished() || deployer.timer.tick(time.delta()).just_finished();
+        if !ready {
+            return;
+        }
+        deployer.timer.reset();
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+
+        let lifetime = TURRET_BASE_LIFETIME + 3.0 * (deployer.level - 1) as f32;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(0.3, 0.9, 0.6),
+                    custom_size: Some(Vec2::splat(TURRET_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(player_transform.translation.truncate().extend(render_layer::RenderLayer::Player.z() - 0.4)),
+                ..default()
+            },
+            Turret {
+                fire: Timer::from_seconds(TURRET_FIRE_INTERVAL, TimerMode::Repeating),
+                lifetime: Timer::from_seconds(lifetime, TimerMode::Once),
+            },
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    fn fire_turrets(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut turret_query: Query<(Entity, &mut Turret, &Transform, &mut Sprite)>,
+        enemy_query: Query<&Transform, (With<enemy::Enemy>, Without<Turret>)>,
+    ) {
+        for (entity, mut turret, transform, mut sprite) in turret_query.iter_mut() {
+            if turret.lifetime.tick(time.delta()).finished() {
+                commands.entity(entity).despawn();
+                continue;
+            }
+            // Fade over the last second so it's clear the turret is about to go.
+            sprite.color.set_a(turret.lifetime.remaining_secs().min(1.0));
+
+            if !turret.fire.tick(time.delta()).just_finished() {
+                continue;
+            }
+            let origin = transform.translation;
+            let target = enemy_query
+                .iter()
+                .map(|enemy_transform| enemy_transform.translation)
+                .filter(|position| position.distance(origin) < TURRET_RANGE)
+                .min_by(|a, b| a.distance_squared(origin).total_cmp(&b.distance_squared(origin)));
+            if let Some(target) = target {
+                let direction = (target - origin).truncate().normalize_or_zero().extend(0.0);
+                commands.spawn(combat::bullet_bundle(origin, direction, combat::Weapon::Turret));
+            }
+        }
+    }
+
+    fn reset_turrets(mut deployer: ResMut<TurretDeployer>) {
+        *deployer = TurretDeployer::default();
+    }
+}
+
+mod vortex {
+    use super::*;
+
+    pub struct VortexPlugin;
+
+    impl Plugin for VortexPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<BlackHole>()
+                .add_systems(
+                    Update,
+                    (
+                        spawn_vortices.in_set(combat::CombatSet::Fire),
+                        tick_vortices.in_set(combat::CombatSet::Collide),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_vortices);
+        }
+    }
+
+    const VORTEX_INTERVAL: f32 = 8.0;
+    const VORTEX_LIFETIME: f32 = 3.0;
+    const VORTEX_TICK: f32 = 0.25;
+    const VORTEX_TICK_DAMAGE: f32 = 4.0;
+    const VORTEX_BASE_RADIUS: f32 = 180.0;
+    const VORTEX_PULL: f32 = 350.0; // Stronger than ENEMY_SPEED at the core, so enemies get held
+    const VORTEX_SPIN: f32 = 6.0;
+    const VORTEX_CAST_RANGE: f32 = 600.0;
+
+    /// Black hole weapon level; 0 means the player doesn't have it. Each level widens the pull.
+    #[derive(Resource, Debug)]
+    pub struct BlackHole {
+        pub level: u32,
+        timer: Timer,
+    }
+
+    impl Default for BlackHole {
+        fn default() -> Self {
+            Self {
+                level: 0,
+                timer: Timer::from_seconds(VORTEX_INTERVAL, TimerMode::Repeating),
+            }
+        }
+    }
+
+    impl BlackHole {
+        /// How far along the next vortex is, from 0.0 to 1.0, or `None` before it's owned.
+        pub fn charge(&self) -> Option<f32> {
+            (self.level > 0).then(|| self.timer.fraction())
+        }
+
+        fn radius(&self) -> f32 {
+            VORTEX_BASE_RADIUS * (1.0 + 0.15 * self.level.saturating_sub(1) as f32)
+        }
+    }
+
+    #[derive(Component)]
+    struct Vortex {
+        radius: f32,
+        tick: Timer,
+        lifetime: Timer,
+    }
+
+    fn spawn_vortices(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut black_hole: ResMut<BlackHole>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        player_query: Query<&Transform, With<player::Player>>,
+        enemy_query: Query<&Transform, With<enemy::Enemy>>,
+    ) {
+        if black_hole.level == 0 || !black_hole.timer.tick(time.delta()).just_finished() {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let origin = player_transform.translation;
+        // Open on the nearest enemy in range, so it lands in the thick of the approaching swarm.
+        let Some(center) = enemy_query
+            .iter()
+            .map(|transform| transform.translation)
+            .filter(|position| position.distance(origin) < VORTEX_CAST_RANGE)
+            .min_by(|a, b| a.distance_squared(origin).total_cmp(&b.distance_squared(origin)))
+        else {
+            return;
+        };
+
+        let radius = black_hole.radius() * effective_stats.range;
+        commands
+            .spawn((
+                SpatialBundle::from_transform(Transform::from_translation(center.truncate().extend(render_layer::RenderLayer::Decals.z() + 0.3))),
+                Vortex {
+                    radius,
+                    tick: Timer::from_seconds(VORTEX_TICK, TimerMode::Repeating),
+                    lifetime: Timer::from_seconds(VORTEX_LIFETIME, TimerMode::Once),
+                },
+                enemy::GravityWell {
+                    radius,
+                    strength: VORTEX_PULL,
+                },
+                hibernation::Hibernates,
+                DespawnOnExit::RUN,
+            ))
+            .with_children(|parent| {
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.05, 0.0, 0.1),
+                        custom_size: Some(Vec2::splat(28.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.2),
+                    ..default()
+                });
+                // Swirl arms; spun by rotating the parent.
+                for arm in 0..6 {
+                    let angle = arm as f32 * std::f32::consts::TAU / 6.0;
+                    let distance = radius * (0.3 + 0.1 * (arm % 3) as f32);
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgba(0.8, 0.3, 1.6, 0.5),
+                            custom_size: Some(Vec2::new(radius * 0.5, 4.0)),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(angle.cos() * distance, angle.sin() * distance, 0.1)
+                            .with_rotation(Quat::from_rotation_z(angle + std::f32::consts::FRAC_PI_2 * 0.7)),
+                        ..default()
+                    });
+                }
+            });
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn tick_vortices(
+        mut commands: Commands,
+        time: Res<Time>,
+        effective_stats: Res<attributes::EffectiveStats>,
+        last_stand_query: Query<&last_stand::LastStand, With<player::Player>>,
+        mut vortex_query: Query<(Entity, &mut Vortex, &mut Transform), Without<hibernation::Hibernating>>,
+        enemy_query: Query<(Entity, &Transform), (With<enemy::Enemy>, Without<Vortex>)>,
+        mut damage_events: EventWriter<enemy::EnemyDamaged>,
+    ) {
+        let damage = VORTEX_TICK_DAMAGE * effective_stats.damage / BASE_WEAPON_DAMAGE
+            * combat::damage_multiplier(&last_stand_query);
+        for (entity, mut vortex, mut transform) in vortex_query.iter_mut() {
+            transform.rotate_z(VORTEX_SPIN * time.delta_seconds());
+            // Collapse over the last half second.
+            transform.scale = Vec3::splat((vortex.lifetime.remaining_secs() * 2.0).min(1.0));
+
+            if vortex.tick.tick(time.delta()).just_finished() {
+                let center = transform.translation.truncate();
+                for (enemy_entity, enemy_transform) in enemy_query.iter() {
+                    if enemy_transform.translation.truncate().distance(center) < vortex.radius {
+                        damage_events.send(enemy::EnemyDamaged {
+                            entity: enemy_entity,
+                            amount: damage,
+                            shred: 0,
+                            split: None,
+                        });
+                    }
+                }
+            }
+            if vortex.lifetime.tick(time.delta()).finished() {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    fn reset_vortices(mut black_hole: ResMut<BlackHole>) {
+        *black_hole = BlackHole::default();
+    }
+}
+
+mod shield {
+    use super::*;
+
+    pub struct ShieldPlugin;
+
+    impl Plugin for ShieldPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<ReflectiveShield>()
+                .add_systems(
+                    Update,
+                    (
+                        sync_shield_segments.in_set(combat::CombatSet::Fire),
+                        orbit_shield_segments.in_set(combat::CombatSet::Move),
+                        reflect_enemy_projectiles.in_set(combat::CombatSet::Collide),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_shields);
+        }
+    }
+
+    const SHIELD_ORBIT_RADIUS: f32 = 70.0;
+    const SHIELD_ORBIT_SPEED: f32 = 2.5;
+    const SHIELD_LENGTH: f32 = 36.0;
+    const SHIELD_THICKNESS: f32 = 6.0;
+
+    /// Reflective shield level; each level adds one more segment to the orbit.
+    #[derive(Resource, Debug, Default)]
+    pub struct ReflectiveShield {
+        pub level: u32,
+        angle: f32,
+    }
+
+    #[derive(Component)]
+    struct ShieldSegment {
+        offset: f32,
+    }
+
+    fn sync_shield_segments(
+        mut commands: Commands,
+        shield: Res<ReflectiveShield>,
+        segment_query: Query<Entity, With<ShieldSegment>>,
+    ) {
+        if segment_query.iter().count() == shield.level as usize {
+            return;
+        }
+        // Respawn the whole ring so the segments stay evenly spaced.
+        for entity in segment_query.iter() {
+            commands.entity(entity).despawn();
+        }
+        for i in 0..shield.level {
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.6, 1.2, 2.0),
+                        custom_size: Some(Vec2::new(SHIELD_THICKNESS, SHIELD_LENGTH)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ShieldSegment {
+                    offset: i as f32 * std::f32::consts::TAU / shield.level as f32,
+                },
+                DespawnOnExit::RUN,
+            ));
+        }
+    }
+
+    fn orbit_shield_segments(
+        time: Res<Time>,
+        mut shield: ResMut<ReflectiveShield>,
+        player_query: Query<&Transform, (With<player::Player>, Without<ShieldSegment>)>,
+        mut segment_query: Query<(&mut Transform, &ShieldSegment)>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        shield.angle = (shield.angle + SHIELD_ORBIT_SPEED * time.delta_seconds()) % std::f32::consts::TAU;
+        for (mut transform, segment) in segment_query.iter_mut() {
+            let angle = shield.angle + segment.offset;
+            transform.translation = render_layer::RenderLayer::Projectiles.at(player_transform.translation)
+                + Vec3::new(angle.cos(), angle.sin(), 0.0) * SHIELD_ORBIT_RADIUS;
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+    }
+
+    /// Enemy shots that cross a segment change sides and head back to whoever fired them.
+    #[allow(clippy::type_complexity)]
+    fn reflect_enemy_projectiles(
+        segment_query: Query<&Transform, With<ShieldSegment>>,
+        mut projectile_query: Query<
+            (
+                &Transform,
+                &collision::PreviousPosition,
+                &mut combat::Projectile,
+                &mut combat::Team,
+                &mut Sprite,
+            ),
+            (Without<ShieldSegment>, Without<combat::MarkedForDeath>),
+        >,
+        shooter_query: Query<&Transform, (With<enemy::Enemy>, Without<combat::Projectile>)>,
+    ) {
+        for (transform, previous, mut projectile, mut team, mut sprite) in projectile_query.iter_mut() {
+            let combat::Team::Enemy { shooter, .. } = *team else {
+                continue;
+            };
+            let (start, end) = previous.segment_to(transform.translation);
+            let blocked = segment_query.iter().any(|segment| {
+                collision::segment_hits_circle(
+                    start,
+                    end,
+                    segment.translation,
+                    SHIELD_LENGTH / 2.0 + projectile.radius(),
+                )
+            });
+            if blocked {
+                let target = shooter_query.get(shooter).ok().map(|shooter| shooter.translation);
+                projectile.reflect(transform.translation, target);
+                *team = combat::Team::Player;
+                sprite.color = Color::rgb(0.6, 1.2, 2.0);
+            }
+        }
+    }
+
+    fn reset_shields(mut shield: ResMut<ReflectiveShield>) {
+        *shield = ReflectiveShield::default();
+    }
+}
+
+mod decals {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const MAX_DECALS: usize = 400;
+    const DECAL_LIFETIME: f32 = 8.0;
+    const DECAL_ALPHA: f32 = 0.5;
+
+    /// Splats left where enemies die, faded out over a few seconds. The count is
+    /// capped: once the pool is full, the oldest splat is moved to the new kill.
+    pub struct DecalsPlugin;
+
+    impl Plugin for DecalsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<DecalPool>()
+                .add_systems(
+                    Update,
+                    (spawn_decals.in_set(combat::CombatSet::Drops), fade_decals)
+                        .chain()
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, clear_decals);
+        }
+    }
+
+    #[derive(Component)]
+    struct Decal(Timer);
+
+    /// Live decals, oldest first; the front one is recycled when the pool is full.
+    /// Hibernating decals stop fading, so expired ones can be anywhere in the queue.
+    #[derive(Resource, Default)]
+    struct DecalPool(VecDeque<Entity>);
+
+    fn spawn_decals(
+        mut commands: Commands,
+        mut events: EventReader<enemy::EnemyDied>,
+        mut pool: ResMut<DecalPool>,
+        mut decal_query: Query<(&mut Decal, &mut Transform)>,
+        settings: Res<settings::Settings>,
+    ) {
+        if !settings.graphics.corpse_decals {
+            events.clear();
+            return;
+        }
+        let mut rng = rand::thread_rng();
+        for event in events.read() {
+            let transform = Transform::from_translation(render_layer::RenderLayer::Decals.at(event.position))
+                .with_rotation(Quat::from_rotation_z(rng.gen_range(0.0..std::f32::consts::TAU)))
+                .with_scale(Vec3::splat(rng.gen_range(0.8..1.4)));
+
+            if pool.0.len() >= MAX_DECALS {
+                if let Some(oldest) = pool.0.pop_front() {
+                    if let Ok((mut decal, mut decal_transform)) = decal_query.get_mut(oldest) {
+                        decal.0.reset();
+                        *decal_transform = transform;
+                        pool.0.push_back(oldest);
+                        continue;
+                    }
+                }
+            }
+            let entity = commands
+                .spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgba(0.45, 0.05, 0.1, DECAL_ALPHA),
+                            custom_size: Some(Vec2::splat(ENEMY_SIZE * 1.3)),
+                            ..default()
+                        },
+                        transform,
+                        ..default()
+                    },
+                    Decal(Timer::from_seconds(DECAL_LIFETIME, TimerMode::Once)),
+                    hibernation::Hibernates,
+                    DespawnOnExit::RUN,
+                ))
+                .id();
+            pool.0.push_back(entity);
+        }
+    }
+
+    fn fade_decals(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut pool: ResMut<DecalPool>,
+        mut decal_query: Query<(&mut Decal, &mut Sprite, Has<hibernation::Hibernating>)>,
+    ) {
+        for (mut decal, mut sprite, hibernating) in decal_query.iter_mut() {
+            if !hibernating {
+                decal.0.tick(time.delta());
+                sprite.color.set_a(DECAL_ALPHA * (1.0 - decal.0.fraction()));
+            }
+        }
+        pool.0.retain(|&entity| {
+            let live = decal_query.get(entity).is_ok_and(|(decal, ..)| !decal.0.finished());
+            if !live {
+                commands.entity(entity).despawn();
+            }
+            live
+        });
+    }
+
+    fn clear_decals(mut pool: ResMut<DecalPool>) {
+        pool.0.clear();
+    }
+}
+
+mod ambience {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    /// The area particles wrap around in, centred on the camera; a little larger
+    /// than the window so they don't pop in at the edges.
+    const LAYER_SIZE: Vec2 = Vec2::new(1400.0, 840.0);
+
+    /// Drifting dust, rain or embers drawn behind everything else. The kind is
+    /// picked from the run's seed until there are biomes to tie it to.
+    pub struct AmbiencePlugin;
+
+    impl Plugin for AmbiencePlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<AmbientLayer>()
+                .add_systems(Update, (sync_particles, move_particles.run_if(in_play)).chain());
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ParticleKind {
+        Dust,
+        Rain,
+        Embers,
+    }
+
+    impl ParticleKind {
+        const ALL: [ParticleKind; 3] = [ParticleKind::Dust, ParticleKind::Rain, ParticleKind::Embers];
+
+        pub fn for_seed(seed: u64) -> Self {
+            Self::ALL[(seed % Self::ALL.len() as u64) as usize]
+        }
+
+        fn color(&self) -> Color {
+            match self {
+                ParticleKind::Dust => Color::rgba(0.8, 0.75, 0.65, 0.25),
+                ParticleKind::Rain => Color::rgba(0.6, 0.7, 1.0, 0.3),
+                ParticleKind::Embers => Color::rgba(1.0, 0.5, 0.1, 0.6),
+            }
+        }
+
+        fn size(&self, rng: &mut impl Rng) -> Vec2 {
+            match self {
+                ParticleKind::Dust => Vec2::splat(rng.gen_range(2.0..4.0)),
+                ParticleKind::Rain => Vec2::new(1.5, rng.gen_range(10.0..18.0)),
+                ParticleKind::Embers => Vec2::splat(rng.gen_range(2.0..3.5)),
+            }
+        }
+
+        fn velocity(&self, rng: &mut impl Rng) -> Vec2 {
+            match self {
+                ParticleKind::Dust => Vec2::new(rng.gen_range(-15.0..15.0), rng.gen_range(-10.0..10.0)),
+                ParticleKind::Rain => Vec2::new(-120.0, -rng.gen_range(600.0..800.0)),
+                ParticleKind::Embers => Vec2::new(rng.gen_range(-20.0..20.0), rng.gen_range(30.0..70.0)),
+            }
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum ParticleDensity {
+        Off,
+        Low,
+        #[default]
+        Medium,
+        High,
+    }
+
+    impl ParticleDensity {
+        pub fn label(&self) -> &'static str {
+            match self {
+                ParticleDensity::Off => "Off",
+                ParticleDensity::Low => "Low",
+                ParticleDensity::Medium => "Medium",
+                ParticleDensity::High => "High",
+            }
+        }
+
+        pub fn next(&self) -> Self {
+            match self {
+                ParticleDensity::Off => ParticleDensity::Low,
+                ParticleDensity::Low => ParticleDensity::Medium,
+                ParticleDensity::Medium => ParticleDensity::High,
+                ParticleDensity::High => ParticleDensity::Off,
+            }
+        }
+
+        fn count(&self) -> usize {
+            match self {
+                ParticleDensity::Off => 0,
+                ParticleDensity::Low => 60,
+                ParticleDensity::Medium => 150,
+                ParticleDensity::High => 300,
+            }
+        }
+    }
+
+    /// Which kind of particle is currently spawned, so a new run's kind replaces it.
+    #[derive(Resource, Default)]
+    struct AmbientLayer {
+        kind: Option<ParticleKind>,
+    }
+
+    #[derive(Component)]
+    struct AmbientParticle {
+        /// Position within the layer, before parallax and wrapping.
+        offset: Vec2,
+        velocity: Vec2,
+        /// How much of the camera's movement the particle follows: 0 sticks to the
+        /// world, 1 sticks to the screen. Varied per particle to fake depth.
+        parallax: f32,
+    }
+
+    /// Wraps `point` into the layer rectangle centred on the origin.
+    pub fn wrap(point: Vec2) -> Vec2 {
+        let half = LAYER_SIZE / 2.0;
+        (point + half).rem_euclid(LAYER_SIZE) - half
+    }
+
+    fn sync_particles(
+        mut commands: Commands,
+        mut layer: ResMut<AmbientLayer>,
+        current_run: Res<run::CurrentRun>,
+        settings: Res<settings::Settings>,
+        budget: Res<budget::FrameBudget>,
+        particle_query: Query<Entity, With<AmbientParticle>>,
+    ) {
+        let kind = ParticleKind::for_seed(current_run.seed);
+        let target = (settings.graphics.ambient_particles.count() as f32 * budget.fidelity.particle_scale()) as usize;
+        let mut existing = particle_query.iter().count();
+        if layer.kind != Some(kind) {
+            for entity in particle_query.iter() {
+                commands.entity(entity).despawn();
+            }
+            layer.kind = Some(kind);
+            existing = 0;
+        }
+        if existing > target {
+            for entity in particle_query.iter().take(existing - target) {
+                commands.entity(entity).despawn();
+            }
+            return;
+        }
+
+        let mut rng = rand::thread_rng();
+        for _ in existing..target {
+            let offset = Vec2::new(
+                rng.gen_range(-LAYER_SIZE.x / 2.0..LAYER_SIZE.x / 2.0),
+                rng.gen_range(-LAYER_SIZE.y / 2.0..LAYER_SIZE.y / 2.0),
+            );
+            let velocity = kind.velocity(&mut rng);
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: kind.color(),
+                        custom_size: Some(kind.size(&mut rng)),
+                        ..default()
+                    },
+                    // Streaks line up with the direction they fall in.
+                    transform: Transform::from_translation(offset.extend(render_layer::RenderLayer::Background.z()))
+                        .with_rotation(Quat::from_rotation_z(velocity.to_angle() - std::f32::consts::FRAC_PI_2)),
+                    ..default()
+                },
+                AmbientParticle {
+                    offset,
+                    velocity,
+                    parallax: rng.gen_range(0.3..0.8),
+                },
+            ));
+        }
+    }
+
+    fn move_particles(
+        time: Res<Time>,
+        camera_query: Query<&Transform, (With<world_view::WorldCamera>, Without<AmbientParticle>)>,
+        mut particle_query: Query<(&mut AmbientParticle, &mut Transform, &mut Sprite)>,
+        layer: Res<AmbientLayer>,
+    ) {
+        let camera = camera_query
+            .get_single()
+            .map_or(Vec2::ZERO, |transform| transform.translation.truncate());
+        let flicker = layer.kind == Some(ParticleKind::Embers);
+        let elapsed = time.elapsed_seconds();
+        for (mut particle, mut transform, mut sprite) in particle_query.iter_mut() {
+            let step = particle.velocity * time.delta_seconds();
+            particle.offset = wrap(particle.offset + step);
+            // Farther particles (higher parallax) trail the camera less, so they seem to sit
+            // deeper behind the playfield as it scrolls past.
+            let on_screen = wrap(particle.offset - camera * (1.0 - particle.parallax));
+            transform.translation = (camera + on_screen).extend(transform.translation.z);
+            if flicker {
+                let phase = particle.offset.x * 0.05 + elapsed * 8.0;
+                sprite.color.set_a(0.4 + 0.3 * phase.sin());
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_wrap_keeps_points_inside_the_layer() {
+            assert_eq!(wrap(Vec2::new(10.0, -20.0)), Vec2::new(10.0, -20.0));
+            assert_eq!(wrap(Vec2::new(LAYER_SIZE.x / 2.0 + 5.0, 0.0)), Vec2::new(-LAYER_SIZE.x / 2.0 + 5.0, 0.0));
+            assert_eq!(wrap(Vec2::new(0.0, -LAYER_SIZE.y)), Vec2::ZERO);
+        }
+    }
+}
+
+mod obstacles {
+    use super::*;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+    use bevy::transform::TransformSystem;
+
+    pub struct ObstaclesPlugin;
+
+    impl Plugin for ObstaclesPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Update, spawn_obstacles.run_if(in_play))
+                // After everything has moved for the frame, but before it's drawn.
+                .add_systems(
+                    PostUpdate,
+                    push_out_of_obstacles
+                        .before(TransformSystem::TransformPropagate)
+                        .run_if(in_play),
+                );
+        }
+    }
+
+    const OBSTACLE_COUNT: usize = 40;
+    const OBSTACLE_FIELD_RADIUS: f32 = 3000.0;
+    const OBSTACLE_CLEAR_RADIUS: f32 = 300.0;
+    const AVOIDANCE_LOOKAHEAD: f32 = 200.0;
+
+    /// A static circular collider that nothing can walk through.
+    #[derive(Component)]
+    pub struct Obstacle {
+        pub radius: f32,
+    }
+
+    fn spawn_obstacles(
+        mut commands: Commands,
+        mut run_rng: ResMut<run::RunRng>,
+        obstacle_query: Query<(), With<Obstacle>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+    ) {
+        if !obstacle_query.is_empty() {
+            return;
+        }
+        let rng = &mut run_rng.0;
+        let material = materials.add(Color::rgb(0.25, 0.25, 0.3));
+        for _ in 0..OBSTACLE_COUNT {
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let distance = rng.gen_range(OBSTACLE_CLEAR_RADIUS..OBSTACLE_FIELD_RADIUS);
+            let radius = rng.gen_range(30.0..90.0);
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(meshes.add(Circle::new(radius))),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(angle.cos() * distance, angle.sin() * distance, render_layer::RenderLayer::Background.z() + 0.3),
+                    ..default()
+                },
+                Obstacle { radius },
+                DespawnOnExit::RUN,
+            ));
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn push_out_of_obstacles(
+        obstacle_query: Query<(&Transform, &Obstacle)>,
+        mut mover_query: Query<
+            (&mut Transform, Option<&player::Player>),
+            (Or<(With<player::Player>, With<enemy::Enemy>)>, Without<Obstacle>),
+        >,
+    ) {
+        for (mut transform, player) in mover_query.iter_mut() {
+            let own_radius = if player.is_some() {
+                PLAYER_SIZE / 2.0
+            } else {
+                enemy::radius(&transform)
+            };
+            for (obstacle_transform, obstacle) in obstacle_query.iter() {
+                let offset = (transform.translation - obstacle_transform.translation).truncate();
+                let min_distance = obstacle.radius + own_radius;
+                if offset.length_squared() < min_distance * min_distance {
+                    let out = offset.try_normalize().unwrap_or(Vec2::X) * min_distance;
+                    transform.translation = (obstacle_transform.translation.truncate() + out)
+                        .extend(transform.translation.z);
+                }
+            }
+        }
+    }
+
+    /// Bends `direction` around any obstacle in the mover's path, so it slides past
+    /// the rock instead of pressing into it. Obstacles behind or off to the side are ignored.
+    pub fn avoidance(position: Vec3, direction: Vec3, radius: f32, obstacles: &[(Vec3, f32)]) -> Vec3 {
+        let mut steer = Vec2::ZERO;
+        let heading = direction.truncate();
+        for (center, obstacle_radius) in obstacles {
+            let to_center = (*center - position).truncate();
+            let along = to_center.dot(heading);
+            let clearance = obstacle_radius + radius;
+            if along <= 0.0 || along > AVOIDANCE_LOOKAHEAD + clearance {
+                continue;
+            }
+            let lateral = to_center - heading * along;
+            if lateral.length() >= clearance {
+                continue;
+            }
+            // Dead centre: pick a side rather than stalling.
+            let away = (-lateral).try_normalize().unwrap_or(heading.perp());
+            steer += away * (1.0 - along / (AVOIDANCE_LOOKAHEAD + clearance));
+        }
+        (heading + steer * 2.0).normalize_or_zero().extend(0.0)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_avoidance_only_steers_around_obstacles_ahead() {
+            let ahead = [(Vec3::new(100.0, 0.0, 0.0), 40.0)];
+            let steered = avoidance(Vec3::ZERO, Vec3::X, 10.0, &ahead);
+            assert!(steered.x > 0.0 && steered.y.abs() > 0.1, "{:?}", steered);
+
+            let behind = [(Vec3::new(-100.0, 0.0, 0.0), 40.0)];
+            assert_eq!(avoidance(Vec3::ZERO, Vec3::X, 10.0, &behind), Vec3::X);
+        }
+    }
+}
+
+mod arena {
+    use super::*;
+    use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+    use bevy::transform::TransformSystem;
+
+    /// Radius of the fence raised around the player when a boss arrives.
+    const ARENA_RADIUS: f32 = 800.0;
+    const ARENA_BORDER_WIDTH: f32 = 8.0;
+
+    pub struct ArenaPlugin;
+
+    impl Plugin for ArenaPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<BossArena>()
+                .add_systems(Update, (raise_arena, lower_arena).chain().run_if(in_play))
+                .add_systems(
+                    PostUpdate,
+                    keep_player_in_arena
+                        .before(TransformSystem::TransformPropagate)
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_arena);
+        }
+    }
+
+    /// Centre of the boss fight's fence while one is up. Ordinary spawns and mega
+    /// waves hold off until the boss dies and the fence comes down.
+    #[derive(Resource, Default, Debug)]
+    pub struct BossArena {
+        center: Option<Vec2>,
+    }
+
+    impl BossArena {
+        pub fn is_active(&self) -> bool {
+            self.center.is_some()
+        }
+    }
+
+    #[derive(Component)]
+    struct ArenaFence;
+
+    /// Pulls `position` back inside the fence, leaving `margin` between it and the edge.
+    pub fn confine(position: Vec2, center: Vec2, margin: f32) -> Vec2 {
+        let reach = (ARENA_RADIUS - margin).max(0.0);
+        let offset = position - center;
+        if offset.length() <= reach {
+            return position;
+        }
+        center + offset.normalize() * reach
+    }
+
+    fn raise_arena(
+        mut commands: Commands,
+        mut started_events: EventReader<waves::ScriptedEventStarted>,
+        mut arena: ResMut<BossArena>,
+        settings: Res<settings::Settings>,
+        player_query: Query<&Transform, With<player::Player>>,
+        mut meshes: ResMut<Assets<Mesh>>,
+        mut materials: ResMut<Assets<ColorMaterial>>,
+    ) {
+        let boss_arrived = started_events
+            .read()
+            .any(|started| started.event == waves::ScriptedEvent::Boss);
+        if !boss_arrived || !settings.gameplay.boss_arena || arena.is_active() {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let center = player_transform.translation.truncate();
+        arena.center = Some(center);
+
+        // A bright disc under a slightly smaller dark one reads as a ring around the floor.
+        for (radius, color, z) in [
+            (ARENA_RADIUS + ARENA_BORDER_WIDTH, Color::rgb(0.8, 0.1, 0.9), render_layer::RenderLayer::Background.z() + 0.1),
+            (ARENA_RADIUS, Color::rgb(0.08, 0.04, 0.12), render_layer::RenderLayer::Background.z() + 0.2),
+        ] {
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: Mesh2dHandle(meshes.add(Circle::new(radius))),
+                    material: materials.add(color),
+                    transform: Transform::from_translation(center.extend(z)),
+                    ..default()
+                },
+                ArenaFence,
+                DespawnOnExit::RUN,
+            ));
+        }
+    }
+
+    fn lower_arena(
+        mut commands: Commands,
+        mut died_events: EventReader<enemy::EnemyDied>,
+        mut arena: ResMut<BossArena>,
+        fence_query: Query<Entity, With<ArenaFence>>,
+    ) {
+        if !died_events.read().any(|died| died.kind == enemy::EnemyKind::Boss) {
+            return;
+        }
+        arena.center = None;
+        for entity in fence_query.iter() {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    fn keep_player_in_arena(
+        arena: Res<BossArena>,
+        mut player_query: Query<&mut Transform, With<player::Player>>,
+    ) {
+        let Some(center) = arena.center else {
+            return;
+        };
+        let Ok(mut transform) = player_query.get_single_mut() else {
+            return;
+        };
+        let confined = confine(transform.translation.truncate(), center, PLAYER_SIZE / 2.0);
+        transform.translation = confined.extend(transform.translation.z);
+    }
+
+    fn reset_arena(mut arena: ResMut<BossArena>) {
+        *arena = BossArena::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_confine_only_moves_positions_past_the_fence() {
+            let center = Vec2::new(100.0, 0.0);
+            let inside = Vec2::new(300.0, 200.0);
+            assert_eq!(confine(inside, center, 15.0), inside);
+
+            let outside = confine(Vec2::new(2000.0, 0.0), center, 15.0);
+            assert!(outside.distance(Vec2::new(100.0 + ARENA_RADIUS - 15.0, 0.0)) < 1e-3, "{:?}", outside);
+        }
+    }
+}
+
+mod altars {
+    use super::*;
+    use bevy::sprite::Anchor;
+    use rand::seq::SliceRandom;
+
+    /// Seconds between altars rising. Only one stands at a time.
+    const ALTAR_INTERVAL: f32 = 75.0;
+    const ALTAR_MIN_DISTANCE: f32 = 400.0;
+    const ALTAR_MAX_DISTANCE: f32 = 800.0;
+    /// Seconds an altar waits for an offering before it crumbles.
+    const ALTAR_LIFETIME: f32 = 45.0;
+    const ALTAR_SIZE: f32 = 40.0;
+    /// How close the player has to stand to channel.
+    const ALTAR_REACH: f32 = 70.0;
+    const CHANNEL_DURATION: f32 = 3.0;
+    const CHANNEL_BAR_WIDTH: f32 = 64.0;
+    /// An offering worth at least this much of the current level fuses into an upgrade.
+    const UPGRADE_OFFERING: f32 = 0.5;
+
+    /// Fusion altars: every so often one rises near the player. Standing on it for
+    /// `CHANNEL_DURATION` seconds offers up all the XP carried toward the next level,
+    /// fused into a random upgrade, or a buff if the offering was small.
+    pub struct AltarsPlugin;
+
+    impl Plugin for AltarsPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(AltarSpawnTimer(Timer::from_seconds(ALTAR_INTERVAL, TimerMode::Repeating)))
+                .add_systems(
+                    Update,
+                    (
+                        spawn_altars.run_if(run::not_sandbox),
+                        (channel_altars, update_channel_bars).chain().after(combat::CombatSet::Move),
+                    )
+                        .run_if(in_play),
+                )
+                .add_systems(ResetRun, reset_altars);
+        }
+    }
+
+    #[derive(Resource)]
+    struct AltarSpawnTimer(Timer);
+
+    #[derive(Component)]
+    pub struct Altar {
+        channel: Timer,
+        lifetime: Timer,
+    }
+
+    /// The fill of an altar's channel bar, a child of the altar.
+    #[derive(Component)]
+    struct ChannelBar;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Fusion {
+        Upgrade(ui::Upgrade),
+        Buff(buffs::BuffKind),
+    }
+
+    /// Takes all of the player's carried XP. Half a level's worth or more fuses into an
+    /// upgrade the build could be offered; less, or a build with nothing left to offer,
+    /// into a buff. Nothing comes of an empty offering.
+    fn fuse(
+        player_stats: &mut leveling::PlayerStats,
+        build: &[ui::Upgrade],
+        heat: &[heat::HeatModifier],
+        rng: &mut impl Rng,
+    ) -> Option<Fusion> {
+        let xp = std::mem::take(&mut player_stats.xp);
+        if xp == 0 {
+            return None;
+        }
+        if xp as f32 >= player_stats.xp_to_next_level as f32 * UPGRADE_OFFERING {
+            let pool = ui::Upgrade::ALL
+                .iter()
+                .copied()
+                .filter(|upgrade| upgrade.is_offered(build) && heat::allows(heat, *upgrade))
+                .collect::<Vec<_>>();
+            if let Some(&upgrade) = pool.choose(rng) {
+                return Some(Fusion::Upgrade(upgrade));
+            }
+        }
+        buffs::BuffKind::ALL.choose(rng).copied().map(Fusion::Buff)
+    }
+
+    fn spawn_altars(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut timer: ResMut<AltarSpawnTimer>,
+        altar_query: Query<(), With<Altar>>,
+        player_query: Query<&Transform, With<player::Player>>,
+        arena: Res<arena::BossArena>,
+        mut run_rng: ResMut<run::RunRng>,
+    ) {
+        if !timer.0.tick(time.delta()).just_finished() || !altar_query.is_empty() || arena.is_active() {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let rng = &mut run_rng.0;
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = rng.gen_range(ALTAR_MIN_DISTANCE..ALTAR_MAX_DISTANCE);
+        let position = player_transform.translation + Vec3::new(angle.cos(), angle.sin(), 0.0) * distance;
+        commands
+            .spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.7, 0.3, 1.4),
+                        custom_size: Some(Vec2::splat(ALTAR_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(
+                        render_layer::RenderLayer::Decals.at(position) + Vec3::Z * 0.1,
+                    ),
+                    ..default()
+                },
+                Altar {
+                    channel: Timer::from_seconds(CHANNEL_DURATION, TimerMode::Once),
+                    lifetime: Timer::from_seconds(ALTAR_LIFETIME, TimerMode::Once),
+                },
+                DespawnOnExit::RUN,
+            ))
+            .with_children(|parent| {
+                let bar_position = Vec3::new(-CHANNEL_BAR_WIDTH / 2.0, ALTAR_SIZE, 0.1);
+                parent.spawn(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgba(1.0, 1.0, 1.0, 0.15),
+                        custom_size: Some(Vec2::new(CHANNEL_BAR_WIDTH, 6.0)),
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(bar_position),
+                    ..default()
+                });
+                parent.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::rgb(1.0, 0.6, 2.0),
+                            custom_size: Some(Vec2::new(0.0, 6.0)),
+                            anchor: Anchor::CenterLeft,
+                            ..default()
+                        },
+                        transform: Transform::from_translation(bar_position + Vec3::Z * 0.1),
+                        ..default()
+                    },
+                    ChannelBar,
+                ));
+            });
+    }
+
+    /// Channels while the player stands on an altar with XP to offer, starting over if
+    /// they step off. Altars only age while nobody is channeling them.
+    #[allow(clippy::too_many_arguments)]
+    fn channel_altars(
+        mut commands: Commands,
+        time: Res<Time>,
+        mut altar_query: Query<(Entity, &Transform, &mut Altar)>,
+        player_query: Query<&Transform, With<player::Player>>,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+        current_run: Res<run::CurrentRun>,
+        mut run_rng: ResMut<run::RunRng>,
+        mut upgrade_events: EventWriter<ui::UpgradeChosen>,
+        mut buff_events: EventWriter<buffs::BuffGained>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        for (entity, transform, mut altar) in altar_query.iter_mut() {
+            let distance = transform.translation.truncate().distance(player_transform.translation.truncate());
+            if distance > ALTAR_REACH || player_stats.xp == 0 {
+                altar.channel.reset();
+                if altar.lifetime.tick(time.delta()).finished() {
+                    commands.entity(entity).despawn_recursive();
+                }
+                continue;
+            }
+            if !altar.channel.tick(time.delta()).finished() {
+                continue;
+            }
+            commands.entity(entity).despawn_recursive();
+            match fuse(&mut player_stats, &current_run.build, &current_run.heat, &mut run_rng.0) {
+                Some(Fusion::Upgrade(upgrade)) => {
+                    upgrade_events.send(ui::UpgradeChosen(upgrade));
+                    toasts.send(toasts::ToastEvent::new(
+                        toasts::ToastKind::Pickup,
+                        format!("The altar fused your XP into {}", upgrade.label()),
+                    ));
+                }
+                Some(Fusion::Buff(kind)) => {
+                    buff_events.send(buffs::BuffGained(kind));
+                    toasts.send(toasts::ToastEvent::new(
+                        toasts::ToastKind::Pickup,
+                        format!("The altar fused your XP into a {} buff", kind.icon()),
+                    ));
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn update_channel_bars(
+        altar_query: Query<&Altar>,
+        mut bar_query: Query<(&Parent, &mut Sprite), With<ChannelBar>>,
+    ) {
+        for (parent, mut sprite) in bar_query.iter_mut() {
+            if let Ok(altar) = altar_query.get(parent.get()) {
+                sprite.custom_size = Some(Vec2::new(altar.channel.fraction() * CHANNEL_BAR_WIDTH, 6.0));
+            }
+        }
+    }
+
+    fn reset_altars(mut timer: ResMut<AltarSpawnTimer>) {
+        timer.0.reset();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use rand::{rngs::StdRng, SeedableRng};
+
+        #[test]
+        fn test_fusing_spends_all_xp_on_an_upgrade_or_a_buff() {
+            let mut rng = StdRng::seed_from_u64(3);
+            let mut stats = leveling::PlayerStats::new(leveling::XpCurve::Linear { base: 100, step: 0 });
+            let build = [ui::Upgrade::Railgun];
+            assert_eq!(fuse(&mut stats, &build, &[], &mut rng), None);
+
+            stats.xp = 60;
+            let Some(Fusion::Upgrade(upgrade)) = fuse(&mut stats, &build, &[], &mut rng) else {
+                panic!("a big offering should fuse an upgrade");
+            };
+            assert!(upgrade.is_offered(&build));
+            assert_eq!(stats.xp, 0);
+
+            stats.xp = 20;
+            assert!(matches!(fuse(&mut stats, &build, &[], &mut rng), Some(Fusion::Buff(_))));
+            assert_eq!(stats.xp, 0);
+        }
+    }
+}
+
+mod flow_field {
+    use super::*;
+    use std::collections::VecDeque;
+
+    pub struct FlowFieldPlugin;
+
+    impl Plugin for FlowFieldPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<FlowField>()
+                .insert_resource(FlowFieldTimer(Timer::from_seconds(
+                    FLOW_FIELD_REFRESH,
+                    TimerMode::Repeating,
+                )))
+                .add_systems(Update, rebuild_flow_field.in_set(combat::CombatSet::Targeting).run_if(in_play))
+                .add_systems(ResetRun, reset_flow_field);
+        }
+    }
+
+    const FLOW_FIELD_REFRESH: f32 = 0.25;
+    const CELL_SIZE: f32 = 64.0;
+    const GRID_SIZE: usize = 64;
+    const UNREACHED: u32 = u32::MAX;
+
+    #[derive(Resource)]
+    struct FlowFieldTimer(Timer);
+
+    /// A coarse grid centred on the player where every cell points one step closer to them,
+    /// routing around obstacles. Enemies sample it instead of aiming straight at the player.
+    #[derive(Resource, Default)]
+    pub struct FlowField {
+        origin: Vec2,
+        distances: Vec<u32>,
+        directions: Vec<Vec2>,
+    }
+
+    impl FlowField {
+        pub fn build(target: Vec2, obstacles: &[(Vec3, f32)]) -> Self {
+            // Snap to whole cells so the grid doesn't shimmer as the player moves.
+            let half = GRID_SIZE as f32 * CELL_SIZE / 2.0;
+            let origin = ((target - Vec2::splat(half)) / CELL_SIZE).floor() * CELL_SIZE;
+            let cell_center = |x: usize, y: usize| origin + (Vec2::new(x as f32, y as f32) + 0.5) * CELL_SIZE;
+
+            let blocked = (0..GRID_SIZE * GRID_SIZE)
+                .map(|i| {
+                    let center = cell_center(i % GRID_SIZE, i / GRID_SIZE);
+                    obstacles.iter().any(|(position, radius)| {
+                        position.truncate().distance(center) < radius + ENEMY_SIZE / 2.0
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut distances = vec![UNREACHED; GRID_SIZE * GRID_SIZE];
+            let mut queue = VecDeque::new();
+            let start = ((target - origin) / CELL_SIZE).as_ivec2();
+            let start = start.x as usize + start.y as usize * GRID_SIZE;
+            distances[start] = 0;
+            queue.push_back(start);
+            while let Some(cell) = queue.pop_front() {
+                let (x, y) = ((cell % GRID_SIZE) as i32, (cell / GRID_SIZE) as i32);
+                for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                    if let Some(next) = Self::index(x + dx, y + dy) {
+                        if !blocked[next] && distances[next] == UNREACHED {
+                            distances[next] = distances[cell] + 1;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+            }
+
+            let directions = (0..GRID_SIZE * GRID_SIZE)
+                .map(|cell| {
+                    let (x, y) = ((cell % GRID_SIZE) as i32, (cell / GRID_SIZE) as i32);
+                    let mut best = (distances[cell], Vec2::ZERO);
+                    for dy in -1..=1 {
+                        for dx in -1..=1 {
+                            let Some(next) = Self::index(x + dx, y + dy) else {
+                                continue;
+                            };
+                            // No cutting diagonally past the corner of a blocked cell.
+                            let corner_clear = dx == 0
+                                || dy == 0
+                                || Self::index(x + dx, y).is_some_and(|side| !blocked[side])
+                                    && Self::index(x, y + dy).is_some_and(|side| !blocked[side]);
+                            if corner_clear && distances[next] < best.0 {
+                                best = (distances[next], Vec2::new(dx as f32, dy as f32).normalize());
+                            }
+                        }
+                    }
+                    best.1
+                })
+                .collect();
+
+            Self {
+                origin,
+                distances,
+                directions,
+            }
+        }
+
+        fn index(x: i32, y: i32) -> Option<usize> {
+            let size = GRID_SIZE as i32;
+            if (0..size).contains(&x) && (0..size).contains(&y) {
+                Some((x + y * size) as usize)
+            } else {
+                None
+            }
+        }
+
+        /// Direction to head from `position`. `None` outside the grid, in unreachable cells,
+        /// and right next to the player, where aiming straight at them is more precise.
+        pub fn sample(&self, position: Vec3) -> Option<Vec3> {
+            if self.directions.is_empty() {
+                return None;
+            }
+            let cell = ((position.truncate() - self.origin) / CELL_SIZE).floor().as_ivec2();
+            let index = Self::index(cell.x, cell.y)?;
+            if self.distances[index] <= 1 || self.distances[index] == UNREACHED {
+                return None;
+            }
+            Some(self.directions[index].extend(0.0))
+        }
+    }
+
+    fn rebuild_flow_field(
+        time: Res<Time>,
+        mut timer: ResMut<FlowFieldTimer>,
+        mut flow_field: ResMut<FlowField>,
+        player_query: Query<&Transform, With<player::Player>>,
+        obstacle_query: Query<(&Transform, &obstacles::Obstacle)>,
+    ) {
+        if !timer.0.tick(time.delta()).just_finished() {
+            return;
+        }
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let obstacles = obstacle_query
+            .iter()
+            .map(|(transform, obstacle)| (transform.translation, obstacle.radius))
+            .collect::<Vec<_>>();
+        *flow_field = FlowField::build(player_transform.translation.truncate(), &obstacles);
+    }
+
+    fn reset_flow_field(mut flow_field: ResMut<FlowField>, mut timer: ResMut<FlowFieldTimer>) {
+        *flow_field = FlowField::default();
+        timer.0.reset();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_flow_field_routes_around_obstacles() {
+            let open = FlowField::build(Vec2::ZERO, &[]);
+            let direction = open.sample(Vec3::new(-500.0, 0.0, 0.0)).unwrap();
+            assert!(direction.x > 0.9, "{:?}", direction);
+
+            // A wall of rock directly between the enemy and the player sends it round the side.
+            let wall = (-3..=3)
+                .map(|i| (Vec3::new(-300.0, i as f32 * 60.0, 0.0), 40.0))
+                .collect::<Vec<_>>();
+            let blocked = FlowField::build(Vec2::ZERO, &wall);
+            let direction = blocked.sample(Vec3::new(-340.0, 0.0, 0.0)).unwrap();
+            assert!(direction.y.abs() > 0.5, "{:?}", direction);
+            assert_eq!(blocked.sample(Vec3::new(10.0, 10.0, 0.0)), None);
+        }
+    }
+}
+
+mod input {
+    use super::*;
+
+    pub struct PlayerInputPlugin;
+
+    impl Plugin for PlayerInputPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<PlayerInput>()
+                .add_systems(Update, age_buffered_presses.before(InputSet).run_if(in_play))
+                .add_systems(Update, read_devices.in_set(InputSet))
+                .add_systems(ResetRun, clear_player_input);
+        }
+    }
+
+    /// Systems that fill in `PlayerInput`; anything reading it runs after this set.
+    #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+    pub struct InputSet;
+
+    /// A button-press ability, bound to keys and gamepad buttons.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Action {
+        Dash,
+        Taunt,
+    }
+
+    impl Action {
+        const ALL: [Action; 2] = [Action::Dash, Action::Taunt];
+
+        fn keys(&self) -> &'static [KeyCode] {
+            match self {
+                Action::Dash => &[KeyCode::ShiftLeft, KeyCode::ShiftRight],
+                Action::Taunt => &[KeyCode::KeyQ],
+            }
+        }
+
+        fn gamepad_buttons(&self) -> &'static [GamepadButtonType] {
+            match self {
+                Action::Dash => &[GamepadButtonType::South, GamepadButtonType::RightTrigger],
+                Action::Taunt => &[GamepadButtonType::North],
+            }
+        }
+    }
+
+    /// What the player wants to do, written by whichever provider is in control: the
+    /// keyboard and gamepads, or a bot. Movement is replaced every frame. Action presses
+    /// stay buffered until a gameplay system consumes them or they get too old, so a dash
+    /// pressed a moment before its cooldown ends, or during the level-up menu, still fires.
+    #[derive(Resource, Default, Debug)]
+    pub struct PlayerInput {
+        /// Up to unit length; analog sticks can ask for less than full speed.
+        pub movement: Vec3,
+        /// Seconds of play since each action's unconsumed press, by `Action` index.
+        buffered: [Option<f32>; Action::ALL.len()],
+    }
+
+    impl PlayerInput {
+        pub fn press(&mut self, action: Action) {
+            self.buffered[action as usize] = Some(0.0);
+        }
+
+        /// Takes the pending press for `action`, if there is one. Call this only once the
+        /// action is actually going to happen, so a press made too early stays buffered.
+        pub fn consume(&mut self, action: Action) -> bool {
+            self.buffered[action as usize].take().is_some()
+        }
+
+        /// Drops presses older than `window` seconds.
+        fn age(&mut self, seconds: f32, window: f32) {
+            for press in self.buffered.iter_mut() {
+                *press = press.map(|age| age + seconds).filter(|&age| age <= window);
+            }
+        }
+    }
+
+    /// Zeroes stick input inside `dead_zone` and rescales the rest to start from zero at its
+    /// edge, so there is no jump in speed when the stick leaves the dead zone.
+    pub fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+        let magnitude = stick.length().min(1.0);
+        if magnitude <= dead_zone {
+            return Vec2::ZERO;
+        }
+        stick.normalize() * (magnitude - dead_zone) / (1.0 - dead_zone)
+    }
+
+    /// Presses only age while the game is being played, and with the game's own clock:
+    /// they hold through the level-up pause and last longer in slow motion.
+    fn age_buffered_presses(
+        mut input: ResMut<PlayerInput>,
+        settings: Res<settings::Settings>,
+        time: Res<Time>,
+    ) {
+        input.age(time.delta_seconds(), settings.controls.buffer_window);
+    }
+
+    fn read_devices(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        gamepads: Res<Gamepads>,
+        gamepad_buttons: Res<ButtonInput<GamepadButton>>,
+        gamepad_axes: Res<Axis<GamepadAxis>>,
+        settings: Res<settings::Settings>,
+        mut input: ResMut<PlayerInput>,
+        bot_query: Query<(), With<bot::Bot>>,
+    ) {
+        if !bot_query.is_empty() {
+            return;
+        }
+        let mut movement = Vec3::ZERO;
+        if keyboard_input.pressed(KeyCode::KeyA) || keyboard_input.pressed(KeyCode::ArrowLeft) {
+            movement.x -= 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::KeyD) || keyboard_input.pressed(KeyCode::ArrowRight) {
+            movement.x += 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::KeyW) || keyboard_input.pressed(KeyCode::ArrowUp) {
+            movement.y += 1.0;
+        }
+        if keyboard_input.pressed(KeyCode::KeyS) || keyboard_input.pressed(KeyCode::ArrowDown) {
+            movement.y -= 1.0;
+        }
+        for gamepad in gamepads.iter() {
+            let axis = |axis_type| gamepad_axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.0);
+            let stick = Vec2::new(axis(GamepadAxisType::LeftStickX), axis(GamepadAxisType::LeftStickY));
+            movement += apply_dead_zone(stick, settings.controls.stick_dead_zone).extend(0.0);
+        }
+        input.movement = movement.clamp_length_max(1.0);
+
+        for action in Action::ALL {
+            let key_pressed = keyboard_input.any_just_pressed(action.keys().iter().copied());
+            let button_pressed = gamepads.iter().any(|gamepad| {
+                action
+                    .gamepad_buttons()
+                    .iter()
+                    .any(|&button_type| gamepad_buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+            });
+            if key_pressed || button_pressed {
+                input.press(action);
+            }
+        }
+    }
+
+    fn clear_player_input(mut input: ResMut<PlayerInput>) {
+        *input = PlayerInput::default();
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_dead_zone_rescales_from_its_edge() {
+            assert_eq!(apply_dead_zone(Vec2::new(0.1, 0.1), 0.2), Vec2::ZERO);
+            assert!(apply_dead_zone(Vec2::new(0.21, 0.0), 0.2).x < 0.02);
+            assert_eq!(apply_dead_zone(Vec2::new(0.0, -1.0), 0.2), Vec2::new(0.0, -1.0));
+        }
+
+        #[test]
+        fn test_buffered_press_waits_until_consumed_or_expired() {
+            let mut input = PlayerInput::default();
+            input.press(Action::Dash);
+            input.age(0.1, 0.15);
+            assert!(input.consume(Action::Dash));
+            assert!(!input.consume(Action::Dash));
+
+            input.press(Action::Taunt);
+            input.age(0.2, 0.15);
+            assert!(!input.consume(Action::Taunt));
+        }
+    }
+}
+
+mod rumble {
+    use super::*;
+    use bevy::input::gamepad::{GamepadRumbleIntensity, GamepadRumbleRequest};
+    use std::time::Duration;
+
+    /// Contact damage arrives every frame; this keeps it from turning into one long buzz.
+    const HURT_RUMBLE_INTERVAL: f32 = 0.3;
+
+    /// Gamepad rumble on damage, boss arrivals and taunts, scaled by the
+    /// `controls.rumble_intensity` setting.
+    pub struct RumblePlugin;
+
+    impl Plugin for RumblePlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Update, rumble_on_events.run_if(in_state(GameState::Running)));
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn rumble_on_events(
+        mut hurt_events: EventReader<player::PlayerHurt>,
+        mut started_events: EventReader<waves::ScriptedEventStarted>,
+        mut taunt_events: EventReader<taunt::TauntUsed>,
+        mut rumble_requests: EventWriter<GamepadRumbleRequest>,
+        gamepads: Res<Gamepads>,
+        settings: Res<settings::Settings>,
+        real_time: Res<Time<Real>>,
+        mut last_hurt_rumble: Local<Option<Duration>>,
+    ) {
+        let mut pulses = Vec::new();
+        if hurt_events.read().count() > 0 {
+            let now = real_time.elapsed();
+            let due = last_hurt_rumble.is_none_or(|last| (now - last).as_secs_f32() >= HURT_RUMBLE_INTERVAL);
+            if due {
+                *last_hurt_rumble = Some(now);
+                pulses.push((GamepadRumbleIntensity::weak_motor(0.6), 0.15));
+            }
+        }
+        for started in started_events.read() {
+            if started.event == waves::ScriptedEvent::Boss {
+                pulses.push((GamepadRumbleIntensity::MAX, 0.8));
+            }
+        }
+        for _ in taunt_events.read() {
+            pulses.push((GamepadRumbleIntensity::strong_motor(0.8), 0.3));
+        }
+
+        let scale = settings.controls.rumble_intensity;
+        if scale <= 0.0 {
+            return;
+        }
+        for (intensity, seconds) in pulses {
+            let intensity = GamepadRumbleIntensity {
+                strong_motor: intensity.strong_motor * scale,
+                weak_motor: intensity.weak_motor * scale,
+            };
+            for gamepad in gamepads.iter() {
+                rumble_requests.send(GamepadRumbleRequest::Add {
+                    duration: Duration::from_secs_f32(seconds),
+                    intensity,
+                    gamepad,
+                });
+            }
+        }
+    }
+}
+
+mod bot {
+    use super::*;
+
+    pub struct BotPlugin;
+
+    impl Plugin for BotPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                (drive_bot.in_set(input::InputSet), bot_level_up).run_if(in_play),
+            );
+        }
+    }
+
+    const KITE_RADIUS: f32 = 350.0;
+    const DASH_RADIUS: f32 = 60.0;
+    const GEM_RADIUS: f32 = 500.0;
+    const HOME_RADIUS: f32 = 300.0;
+
+    /// A player driven by `drive_bot` rather than the keyboard, which also picks its own upgrades.
+    #[derive(Component)]
+    pub struct Bot;
+
+    fn drive_bot(
+        mut input: ResMut<input::PlayerInput>,
+        player_query: Query<&Transform, (With<player::Player>, With<Bot>)>,
+        enemy_query: Query<&Transform, With<enemy::Enemy>>,
+        gem_query: Query<&Transform, (With<leveling::XpGem>, Without<hibernation::Hibernating>)>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let position = player_transform.translation;
+        let enemies = enemy_query.iter().map(|enemy| enemy.translation).collect::<Vec<_>>();
+        let nearest_gem = gem_query
+            .iter()
+            .map(|gem| gem.translation)
+            .filter(|gem| gem.distance(position) < GEM_RADIUS)
+            .min_by(|a, b| a.distance_squared(position).total_cmp(&b.distance_squared(position)));
+        input.movement = steer(position, &enemies, nearest_gem);
+        // Dash out when something is about to land a hit. The bot never taunts; that's
+        // too risky to judge from a distance check alone.
+        if enemies.iter().any(|enemy| enemy.distance(position) < DASH_RADIUS) {
+            input.press(input::Action::Dash);
+        }
+    }
+
+    /// Moves away from nearby enemies, circling rather than backing straight off. With
+    /// nothing close it goes for `gem`, and it drifts back toward the origin when it strays.
+    pub fn steer(position: Vec3, enemies: &[Vec3], gem: Option<Vec3>) -> Vec3 {
+        let mut away = Vec2::ZERO;
+        for enemy in enemies {
+            let offset = (position - *enemy).truncate();
+            let distance = offset.length();
+            if distance < KITE_RADIUS && distance > 0.0 {
+                away += offset / distance * (1.0 - distance / KITE_RADIUS);
+            }
+        }
+        let circle = away.perp() * 0.5;
+        let greed = match gem {
+            Some(gem) if away.length() < 0.5 => (gem - position).truncate().normalize_or_zero(),
+            _ => Vec2::ZERO,
+        };
+        let home = -position.truncate();
+        let homeward = if home.length() > HOME_RADIUS {
+            home.normalize() * (home.length() / HOME_RADIUS - 1.0)
+        } else {
+            Vec2::ZERO
+        };
+        (away + circle + greed + homeward).normalize_or_zero().extend(0.0)
+    }
+
+    fn bot_level_up(
+        bot_query: Query<(), With<Bot>>,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+        mut current_run: ResMut<run::CurrentRun>,
+        planned_build: Res<build_code::PlannedBuild>,
+        mut chosen_events: EventWriter<ui::UpgradeChosen>,
+    ) {
+        if bot_query.is_empty() || !player_stats.advance() {
+            return;
+        }
+        let mut rng = current_run.next_draft_rng();
+        let options = ui::draft_upgrades(&current_run.build, &current_run.heat, &planned_build, &mut rng);
+        if let Some(upgrade) = pick_upgrade(&options, &current_run.build) {
+            chosen_events.send(ui::UpgradeChosen(upgrade));
+        }
+    }
+
+    /// Evolutions first, then weapons it doesn't have yet, then raw firepower; repeats
+    /// of something already owned are worth a little less each time.
+    pub fn pick_upgrade(options: &[ui::Upgrade], build: &[ui::Upgrade]) -> Option<ui::Upgrade> {
+        let score = |upgrade: &ui::Upgrade| {
+            let base = match upgrade {
+                ui::Upgrade::Evolve(_) => 100,
+                ui::Upgrade::Railgun
+                | ui::Upgrade::Whip
+                | ui::Upgrade::MineLayer
+                | ui::Upgrade::Turret
+                | ui::Upgrade::BlackHole
+                | ui::Upgrade::Shotgun
+                | ui::Upgrade::HomingMissile
+                | ui::Upgrade::ReflectiveShield
+                    if !build.contains(upgrade) =>
+                {
+                    50
+                }
+                ui::Upgrade::Multishot | ui::Upgrade::AttackSpeed => 30,
+                ui::Upgrade::Passive(_) => 20,
+                _ => 10,
+            };
+            let owned = build.iter().filter(|picked| *picked == upgrade).count() as i32;
+            base - 5 * owned
+        };
+        options.iter().copied().max_by_key(score)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_steer_backs_away_and_heads_home() {
+            let direction = steer(Vec3::ZERO, &[Vec3::new(100.0, 0.0, 0.0)], None);
+            assert!(direction.x < 0.0, "{:?}", direction);
+
+            let direction = steer(Vec3::new(1000.0, 0.0, 0.0), &[], None);
+            assert_eq!(direction, Vec3::NEG_X);
+        }
+
+        #[test]
+        fn test_pick_upgrade_prefers_evolutions_then_new_weapons() {
+            let evolution = ui::Upgrade::Evolve(passives::Evolution::StormCaller);
+            let options = [ui::Upgrade::BladeCount, ui::Upgrade::Railgun, evolution];
+            assert_eq!(pick_upgrade(&options, &[]), Some(evolution));
+
+            let options = [ui::Upgrade::BladeCount, ui::Upgrade::Railgun, ui::Upgrade::Multishot];
+            assert_eq!(pick_upgrade(&options, &[]), Some(ui::Upgrade::Railgun));
+        }
+    }
+}
+
+mod attract {
+    use super::*;
+
+    pub struct AttractPlugin;
+
+    impl Plugin for AttractPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                (tag_bot_player, restart_after_death).run_if(in_state(GameState::MainMenu)),
+            );
+        }
+    }
+
+    fn tag_bot_player(mut commands: Commands, query: Query<Entity, Added<player::Player>>) {
+        for entity in query.iter() {
+            commands.entity(entity).insert(bot::Bot);
+        }
+    }
+
+    /// The attract game never ends; when the bot goes down the world resets and it respawns.
+    fn restart_after_death(
+        mut commands: Commands,
+        query: Query<&combat::Health, With<player::Player>>,
+    ) {
+        if query.iter().any(|health| health.current <= 0.0) {
+            commands.add(run::reset_world);
+        }
+    }
+}
+
+mod pet {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const PET_SIZE: f32 = 14.0;
+    const PET_PICKUP_RADIUS: f32 = 40.0;
+    /// How far along the player's path the pet trails.
+    const PET_FOLLOW_DISTANCE: f32 = 60.0;
+    /// Higher is snappier; the pet closes ~1 - e^-k of the gap each second.
+    const PET_FOLLOW_SHARPNESS: f32 = 8.0;
+    const TRAIL_SPACING: f32 = 8.0;
+    const TRAIL_LENGTH: usize = 32;
+    /// Longest survival needed, across all recorded runs, to unlock the pet.
+    pub const PET_UNLOCK_TIME: f32 = 5.0 * 60.0;
+
+    /// A cosmetic companion that trails the player and picks up nearby gems.
+    pub struct PetPlugin;
+
+    impl Plugin for PetPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(
+                Update,
+                (
+                    spawn_pet,
+                    follow_player,
+                    collect_gems,
+                    animate_pet,
+                )
+                    .chain()
+                    .after(leveling::collect_xp_gems)
+                    .in_set(combat::CombatSet::Drops)
+                    .run_if(in_play),
+            );
+        }
+    }
+
+    #[derive(Component)]
+    pub struct Pet {
+        /// Where the pet is, before the bobbing animation is added on top.
+        position: Vec3,
+        /// The player's recent positions, newest first.
+        trail: VecDeque<Vec3>,
+        phase: f32,
+    }
+
+    pub fn is_unlocked(history: &history::RunHistory) -> bool {
+        stats::LifetimeStats::from_history(history).best_time >= PET_UNLOCK_TIME
+    }
+
+    /// The point `distance` back along `trail` (newest first), measured from `from`.
+    /// Falls back to the oldest point when the trail is shorter than that.
+    pub fn trail_point(from: Vec3, trail: &VecDeque<Vec3>, distance: f32) -> Vec3 {
+        let mut remaining = distance;
+        let mut previous = from;
+        for &point in trail {
+            let step = previous.distance(point);
+            if step >= remaining {
+                return previous.lerp(point, remaining / step);
+            }
+            remaining -= step;
+            previous = point;
+        }
+        previous
+    }
+
+    fn spawn_pet(
+        mut commands: Commands,
+        player_query: Query<&Transform, Added<player::Player>>,
+        pet_query: Query<(), With<Pet>>,
+        // Not present in the headless simulation, which never records history.
+        history: Option<Res<history::RunHistory>>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        if !pet_query.is_empty() || !history.is_some_and(|history| is_unlocked(&history)) {
+            return;
+        }
+        let position = (player_transform.translation - Vec3::Y * PET_FOLLOW_DISTANCE).truncate().extend(render_layer::RenderLayer::Player.z() - 0.2);
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::rgb(1.0, 0.85, 0.3),
+                    custom_size: Some(Vec2::splat(PET_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_translation(position),
+                ..default()
+            },
+            Pet {
+                position,
+                trail: VecDeque::with_capacity(TRAIL_LENGTH),
+                phase: 0.0,
+            },
+            DespawnOnExit::RUN,
+        ));
+    }
+
+    fn follow_player(
+        player_query: Query<&Transform, With<player::Player>>,
+        mut pet_query: Query<&mut Pet>,
+        time: Res<Time>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player_position = player_transform.translation;
+        for mut pet in pet_query.iter_mut() {
+            // Breadcrumbs rather than the player's live position, so the pet follows
+            // the path the player took instead of cutting corners.
+            let moved = pet.trail.front().is_none_or(|&last| last.distance(player_position) >= TRAIL_SPACING);
+            if moved {
+                pet.trail.push_front(player_position);
+                pet.trail.truncate(TRAIL_LENGTH);
+            }
+            let target = trail_point(player_position, &pet.trail, PET_FOLLOW_DISTANCE);
+            let blend = 1.0 - (-PET_FOLLOW_SHARPNESS * time.delta_seconds()).exp();
+            pet.position = pet.position.lerp(target.truncate().extend(pet.position.z), blend);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn collect_gems(
+        mut commands: Commands,
+        pet_query: Query<&Pet>,
+        gem_query: Query<
+            (Entity, &Transform, &leveling::XpGem),
+            (Without<combat::MarkedForDeath>, Without<hibernation::Hibernating>),
+        >,
+        mut player_stats: ResMut<leveling::PlayerStats>,
+        mut collected_events: EventWriter<leveling::XpGemCollected>,
+    ) {
+        for pet in pet_query.iter() {
+            for (gem_entity, gem_transform, gem) in gem_query.iter() {
+                if gem_transform.translation.truncate().distance(pet.position.truncate()) < PET_PICKUP_RADIUS {
+                    commands.entity(gem_entity).try_insert(combat::MarkedForDeath);
+                    player_stats.xp += gem.value;
+                    collected_events.send(leveling::XpGemCollected);
+                }
+            }
+        }
+    }
+
+    /// Hovers up and down, squashing a little at the bottom of each bob.
+    fn animate_pet(mut pet_query: Query<(&mut Pet, &mut Transform)>, time: Res<Time>) {
+        for (mut pet, mut transform) in pet_query.iter_mut() {
+            pet.phase = (pet.phase + time.delta_seconds() * 6.0) % std::f32::consts::TAU;
+            let bob = pet.phase.sin();
+            let squash = bob.min(0.0) * 0.15;
+            transform.translation = pet.position + Vec3::Y * bob * 4.0;
+            transform.scale = Vec3::new(1.0 - squash, 1.0 + squash, 1.0);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_trail_point_walks_back_along_the_path() {
+            // Player at the origin came down from (0, 100) after moving right from (-50, 100).
+            let trail = VecDeque::from([
+                Vec3::new(0.0, 50.0, 0.0),
+                Vec3::new(0.0, 100.0, 0.0),
+                Vec3::new(-50.0, 100.0, 0.0),
+            ]);
+            assert_eq!(trail_point(Vec3::ZERO, &trail, 75.0), Vec3::new(0.0, 75.0, 0.0));
+            assert_eq!(trail_point(Vec3::ZERO, &trail, 120.0), Vec3::new(-20.0, 100.0, 0.0));
+            assert_eq!(trail_point(Vec3::ZERO, &trail, 500.0), Vec3::new(-50.0, 100.0, 0.0));
+        }
+    }
+}
+
+mod cosmetics {
+    use super::*;
+    use rand::Rng;
+    use serde::{Deserialize, Serialize};
+
+    const WARDROBE_PATH: &str = "saves/wardrobe.ron";
+    /// Seconds between dash-trail ghosts, and how long each takes to fade.
+    const TRAIL_SPACING: f32 = 0.02;
+    const TRAIL_FADE: f32 = 0.3;
+
+    /// Player colours and dash trails bought with gold banked from finished runs, and
+    /// picked on the Character screen. Kept out of `GameplayPlugins` so simulated runs
+    /// don't bank gold; without a wardrobe the player spawns in the default look.
+    pub struct CosmeticsPlugin;
+
+    impl Plugin for CosmeticsPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(Wardrobe::load())
+                .add_systems(OnEnter(GameState::Character), setup_character_screen)
+                .add_systems(
+                    Update,
+                    (character_input, handle_cosmetic_buttons).run_if(in_state(GameState::Character)),
+                )
+                .add_systems(OnEnter(GameState::GameOver), bank_gold)
+                .add_systems(Update, (start_dash_trail, emit_dash_trail, fade_trail_ghosts).chain().run_if(in_play));
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Skin {
+        #[default]
+        Azure,
+        Crimson,
+        Emerald,
+        Gold,
+        Violet,
+    }
+
+    impl Skin {
+        pub fn color(&self) -> Color {
+            match self {
+                Skin::Azure => Color::rgb(0.2, 0.7, 0.9),
+                Skin::Crimson => Color::rgb(0.9, 0.2, 0.25),
+                Skin::Emerald => Color::rgb(0.2, 0.85, 0.4),
+                Skin::Gold => Color::rgb(1.0, 0.8, 0.2),
+                Skin::Violet => Color::rgb(0.65, 0.35, 0.95),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum DashTrail {
+        #[default]
+        None,
+        /// Fading copies of the player, in the player's colour.
+        Afterimage,
+        Embers,
+        Frost,
+    }
+
+    impl DashTrail {
+        /// Colour and size of each ghost left behind, if the trail leaves any.
+        fn ghost(&self, skin: Skin) -> Option<(Color, f32)> {
+            match self {
+                DashTrail::None => None,
+                DashTrail::Afterimage => Some((skin.color().with_a(0.5), PLAYER_SIZE)),
+                DashTrail::Embers => Some((Color::rgb(1.8, 0.6, 0.1), 8.0)),
+                DashTrail::Frost => Some((Color::rgb(0.6, 1.2, 1.8), 14.0)),
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Component, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Cosmetic {
+        Skin(Skin),
+        Trail(DashTrail),
+    }
+
+    impl Cosmetic {
+        const ALL: [Cosmetic; 9] = [
+            Cosmetic::Skin(Skin::Azure),
+            Cosmetic::Skin(Skin::Crimson),
+            Cosmetic::Skin(Skin::Emerald),
+            Cosmetic::Skin(Skin::Gold),
+            Cosmetic::Skin(Skin::Violet),
+            Cosmetic::Trail(DashTrail::None),
+            Cosmetic::Trail(DashTrail::Afterimage),
+            Cosmetic::Trail(DashTrail::Embers),
+            Cosmetic::Trail(DashTrail::Frost),
+        ];
+
+        fn label(&self) -> &'static str {
+            match self {
+                Cosmetic::Skin(Skin::Azure) => "Azure",
+                Cosmetic::Skin(Skin::Crimson) => "Crimson",
+                Cosmetic::Skin(Skin::Emerald) => "Emerald",
+                Cosmetic::Skin(Skin::Gold) => "Gold",
+                Cosmetic::Skin(Skin::Violet) => "Violet",
+                Cosmetic::Trail(DashTrail::None) => "No Trail",
+                Cosmetic::Trail(DashTrail::Afterimage) => "Afterimage Trail",
+                Cosmetic::Trail(DashTrail::Embers) => "Ember Trail",
+                Cosmetic::Trail(DashTrail::Frost) => "Frost Trail",
+            }
+        }
+
+        /// Banked gold to unlock it; the defaults are free.
+        fn price(&self) -> u32 {
+            match self {
+                Cosmetic::Skin(Skin::Azure) | Cosmetic::Trail(DashTrail::None) => 0,
+                Cosmetic::Skin(_) => 150,
+                Cosmetic::Trail(DashTrail::Afterimage) => 200,
+                Cosmetic::Trail(_) => 300,
+            }
+        }
+    }
+
+    /// The player's banked gold, what it has bought and what is being worn, persisted
+    /// to `WARDROBE_PATH`.
+    #[derive(Resource, Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+    #[serde(default)]
+    pub struct Wardrobe {
+        pub gold: u32,
+        pub owned: Vec<Cosmetic>,
+        pub skin: Skin,
+        pub trail: DashTrail,
+    }
+
+    impl Wardrobe {
+        pub fn load() -> Self {
+            persistence::load_ron(WARDROBE_PATH)
+        }
+
+        pub fn save(&self) {
+            persistence::save_ron(WARDROBE_PATH, self);
+        }
+
+        fn owns(&self, cosmetic: Cosmetic) -> bool {
+            cosmetic.price() == 0 || self.owned.contains(&cosmetic)
+        }
+
+        fn is_worn(&self, cosmetic: Cosmetic) -> bool {
+            match cosmetic {
+                Cosmetic::Skin(skin) => self.skin == skin,
+                Cosmetic::Trail(trail) => self.trail == trail,
+            }
+        }
+
+        /// Wears `cosmetic`, buying it first if need be. False if it isn't owned and
+        /// there isn't the gold for it.
+        fn select(&mut self, cosmetic: Cosmetic) -> bool {
+            if !self.owns(cosmetic) {
+                if self.gold < cosmetic.price() {
+                    return false;
+                }
+                self.gold -= cosmetic.price();
+                self.owned.push(cosmetic);
+            }
+            match cosmetic {
+                Cosmetic::Skin(skin) => self.skin = skin,
+                Cosmetic::Trail(trail) => self.trail = trail,
+            }
+            true
+        }
+    }
+
+    /// The player's colour: the worn skin, or the default without a wardrobe.
+    pub fn skin_color(wardrobe: Option<&Wardrobe>) -> Color {
+        wardrobe.map_or(Skin::default(), |wardrobe| wardrobe.skin).color()
+    }
+
+    fn bank_gold(
+        purse: Res<drops::Purse>,
+        current_run: Res<run::CurrentRun>,
+        mut wardrobe: ResMut<Wardrobe>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        if purse.gold == 0 || current_run.sandbox {
+            return;
+        }
+        wardrobe.gold += purse.gold;
+        wardrobe.save();
+        toasts.send(toasts::ToastEvent::new(
+            toasts::ToastKind::Saved,
+            format!("Banked {} gold for cosmetics", purse.gold),
+        ));
+    }
+
+    #[derive(Component)]
+    struct BankedGoldText;
+
+    fn cosmetic_text(cosmetic: Cosmetic, wardrobe: &Wardrobe) -> String {
+        if wardrobe.is_worn(cosmetic) {
+            format!("{}: Equipped", cosmetic.label())
+        } else if wardrobe.owns(cosmetic) {
+            cosmetic.label().to_string()
+        } else {
+            format!("{}: {} gold", cosmetic.label(), cosmetic.price())
+        }
+    }
+
+    fn banked_gold_text(wardrobe: &Wardrobe) -> String {
+        format!("Banked gold: {}", wardrobe.gold)
+    }
+
+    fn setup_character_screen(mut commands: Commands, wardrobe: Res<Wardrobe>) {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            DespawnOnExit(GameState::Character),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Character",
+                TextStyle { font_size: 50.0, ..default() },
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    banked_gold_text(&wardrobe),
+                    TextStyle { font_size: 24.0, color: Color::GOLD, ..default() },
+                ),
+                BankedGoldText,
+            ));
+            // Skins in one column, trails in the other.
+            parent.spawn(NodeBundle::default()).with_children(|parent| {
+                for is_skin in [true, false] {
+                    parent.spawn(NodeBundle {
+                        style: Style { flex_direction: FlexDirection::Column, ..default() },
+                        ..default()
+                    }).with_children(|parent| {
+                        for cosmetic in Cosmetic::ALL {
+                            if matches!(cosmetic, Cosmetic::Skin(_)) != is_skin {
+                                continue;
+                            }
+                            let swatch = match cosmetic {
+                                Cosmetic::Skin(skin) => skin.color(),
+                                Cosmetic::Trail(trail) => trail.ghost(wardrobe.skin).map_or(Color::GRAY, |(color, _)| color),
+                            };
+                            parent.spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(300.0),
+                                        height: Val::Px(50.0),
+                                        margin: UiRect::all(Val::Px(8.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        border: UiRect::left(Val::Px(6.0)),
+                                        ..default()
+                                    },
+                                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                                    border_color: swatch.into(),
+                                    ..default()
+                                },
+                                cosmetic,
+                            )).with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    cosmetic_text(cosmetic, &wardrobe),
+                                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                                ));
+                            });
+                        }
+                    });
+                }
+            });
+            parent.spawn(TextBundle::from_section(
+                "Finished runs bank the gold you picked up. Press Escape to return",
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+        });
+    }
+
+    fn character_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+    ) {
+        if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+            next_state.set(GameState::MainMenu);
+        }
+    }
+
+    fn handle_cosmetic_buttons(
+        interaction_query: Query<(&Interaction, &Cosmetic), Changed<Interaction>>,
+        button_query: Query<(&Cosmetic, &Children)>,
+        mut text_query: Query<&mut Text, Without<BankedGoldText>>,
+        mut gold_query: Query<&mut Text, With<BankedGoldText>>,
+        mut wardrobe: ResMut<Wardrobe>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        for (interaction, cosmetic) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            if !wardrobe.select(*cosmetic) {
+                toasts.send(toasts::ToastEvent::new(
+                    toasts::ToastKind::Error,
+                    format!("{} needs {} banked gold", cosmetic.label(), cosmetic.price()),
+                ));
+                continue;
+            }
+            wardrobe.save();
+
+            // Buying or wearing one can change how every other one in its column reads.
+            for (cosmetic, children) in button_query.iter() {
+                for &child in children.iter() {
+                    if let Ok(mut text) = text_query.get_mut(child) {
+                        text.sections[0].value = cosmetic_text(*cosmetic, &wardrobe);
+                    }
+                }
+            }
+            for mut text in gold_query.iter_mut() {
+                text.sections[0].value = banked_gold_text(&wardrobe);
+            }
+        }
+    }
+
+    /// Left on the player for as long as a dash lasts, dropping a ghost every
+    /// `TRAIL_SPACING` seconds.
+    #[derive(Component)]
+    struct TrailEmitter {
+        remaining: Timer,
+        spacing: Timer,
+    }
+
+    /// Fades out from `alpha` over its timer.
+    #[derive(Component)]
+    struct TrailGhost {
+        timer: Timer,
+        alpha: f32,
+    }
+
+    fn start_dash_trail(
+        mut commands: Commands,
+        mut dash_events: EventReader<player::PlayerDashed>,
+        player_query: Query<Entity, With<player::Player>>,
+        wardrobe: Res<Wardrobe>,
+    ) {
+        if dash_events.read().count() == 0 || wardrobe.trail == DashTrail::None {
+            return;
+        }
+        for entity in player_query.iter() {
+            commands.entity(entity).insert(TrailEmitter {
+                remaining: Timer::from_seconds(DASH_DURATION, TimerMode::Once),
+                spacing: Timer::from_seconds(TRAIL_SPACING, TimerMode::Repeating),
+            });
+        }
+    }
+
+    fn emit_dash_trail(
+        mut commands: Commands,
+        mut player_query: Query<(Entity, &Transform, &mut TrailEmitter)>,
+        wardrobe: Res<Wardrobe>,
+        time: Res<Time>,
+    ) {
+        let Some((color, size)) = wardrobe.trail.ghost(wardrobe.skin) else {
+            return;
+        };
+        let mut rng = rand::thread_rng();
+        for (entity, transform, mut emitter) in player_query.iter_mut() {
+            if emitter.remaining.tick(time.delta()).finished() {
+                commands.entity(entity).remove::<TrailEmitter>();
+            }
+            for _ in 0..emitter.spacing.tick(time.delta()).times_finished_this_tick() {
+                // Small ghosts scatter a little across the player's width.
+                let spread = (PLAYER_SIZE - size).max(0.0) / 2.0;
+                let offset = Vec2::new(rng.gen_range(-spread..=spread), rng.gen_range(-spread..=spread));
+                commands.spawn((
+                    SpriteBundle {
+                        sprite: Sprite { color, custom_size: Some(Vec2::splat(size)), ..default() },
+                        transform: Transform::from_translation(
+                            (transform.translation.truncate() + offset)
+                                .extend(render_layer::RenderLayer::Player.z() - 0.4),
+                        ),
+                        ..default()
+                    },
+                    TrailGhost { timer: Timer::from_seconds(TRAIL_FADE, TimerMode::Once), alpha: color.a() },
+                    DespawnOnExit::RUN,
+                ));
+            }
+        }
+    }
+
+    fn fade_trail_ghosts(
+        mut commands: Commands,
+        mut ghost_query: Query<(Entity, &mut TrailGhost, &mut Sprite)>,
+        time: Res<Time>,
+    ) {
+        for (entity, mut ghost, mut sprite) in ghost_query.iter_mut() {
+            if ghost.timer.tick(time.delta()).finished() {
+                commands.entity(entity).despawn();
+            } else {
+                sprite.color.set_a(ghost.alpha * ghost.timer.fraction_remaining());
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_selecting_buys_once_then_wears_for_free() {
+            let crimson = Cosmetic::Skin(Skin::Crimson);
+            let mut wardrobe = Wardrobe { gold: 100, ..default() };
+            assert!(!wardrobe.select(crimson));
+            assert_eq!(wardrobe.skin, Skin::Azure);
+
+            wardrobe.gold = 200;
+            assert!(wardrobe.select(crimson));
+            assert_eq!((wardrobe.gold, wardrobe.skin), (50, Skin::Crimson));
+
+            assert!(wardrobe.select(Cosmetic::Skin(Skin::Azure)));
+            assert!(wardrobe.select(crimson));
+            assert_eq!(wardrobe.gold, 50);
+            assert!(wardrobe.is_worn(crimson));
+        }
+    }
+}
+
+mod heat {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Enemy health multiplier under `HeatModifier::ToughEnemies`.
+    const TOUGH_ENEMY_HEALTH: f32 = 1.5;
+    /// Pickup radius multiplier under `HeatModifier::ShortReach`.
+    const SHORT_REACH_RADIUS: f32 = 0.5;
+
+    pub struct HeatPlugin;
+
+    impl Plugin for HeatPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(OnEnter(GameState::Heat), setup_heat_screen)
+                .add_systems(
+                    Update,
+                    (heat_input, handle_heat_buttons).run_if(in_state(GameState::Heat)),
+                )
+                .add_systems(OnEnter(GameState::Running), apply_heat.after(run::begin_run))
+                .add_systems(Update, toughen_enemies.run_if(in_play));
+        }
+    }
+
+    /// A challenge toggled on the Heat screen before a run. Each one makes the run
+    /// harder and raises the score multiplier it pays out.
+    #[derive(Component, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum HeatModifier {
+        NoBlades,
+        ToughEnemies,
+        ShortReach,
+    }
+
+    impl HeatModifier {
+        pub const ALL: [HeatModifier; 3] = [
+            HeatModifier::NoBlades,
+            HeatModifier::ToughEnemies,
+            HeatModifier::ShortReach,
+        ];
+
+        pub fn label(&self) -> &'static str {
+            match self {
+                HeatModifier::NoBlades => "No Blades",
+                HeatModifier::ToughEnemies => "Tough Enemies (+50% HP)",
+                HeatModifier::ShortReach => "Short Reach (half pickup radius)",
+            }
+        }
+
+        /// Added to the score multiplier while this modifier is on.
+        pub fn bonus(&self) -> f32 {
+            match self {
+                HeatModifier::NoBlades => 0.5,
+                HeatModifier::ToughEnemies => 0.3,
+                HeatModifier::ShortReach => 0.2,
+            }
+        }
+
+        fn allows(&self, upgrade: ui::Upgrade) -> bool {
+            match self {
+                HeatModifier::NoBlades => !matches!(
+                    upgrade,
+                    ui::Upgrade::BladeCount | ui::Upgrade::Evolve(passives::Evolution::RazorHalo)
+                ),
+                _ => true,
+            }
+        }
+    }
+
+    /// Score multiplier for a run played with `heat` on: one plus every modifier's bonus.
+    pub fn reward_multiplier(heat: &[HeatModifier]) -> f32 {
+        1.0 + heat.iter().map(HeatModifier::bonus).sum::<f32>()
+    }
+
+    /// Whether `upgrade` can be drafted in a run played with `heat` on.
+    pub fn allows(heat: &[HeatModifier], upgrade: ui::Upgrade) -> bool {
+        heat.iter().all(|modifier| modifier.allows(upgrade))
+    }
+
+    /// Player stat changes the run's heat imposes, folded in with every other modifier.
+    pub fn stat_modifiers(heat: &[HeatModifier]) -> impl Iterator<Item = attributes::StatModifier> + '_ {
+        heat.iter().filter_map(|modifier| match modifier {
+            HeatModifier::ShortReach => Some(attributes::StatModifier {
+                stat: attributes::Stat::PickupRadius,
+                op: attributes::ModifierOp::Multiply(SHORT_REACH_RADIUS),
+            }),
+            _ => None,
+        })
+    }
+
+    #[derive(Component)]
+    struct HeatScreen;
+
+    #[derive(Component)]
+    struct RewardText;
+
+    fn toggle_text(modifier: HeatModifier, heat: &[HeatModifier]) -> String {
+        let state = if heat.contains(&modifier) { "On" } else { "Off" };
+        format!("{}: {}  (+{:.0}%)", modifier.label(), state, modifier.bonus() * 100.0)
+    }
+
+    fn reward_text(heat: &[HeatModifier]) -> String {
+        format!("Score multiplier: x{:.1}", reward_multiplier(heat))
+    }
+
+    fn setup_heat_screen(mut commands: Commands, settings: Res<settings::Settings>) {
+        let heat = &settings.gameplay.heat;
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            HeatScreen,
+            DespawnOnExit(GameState::Heat),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Heat",
+                TextStyle { font_size: 50.0, ..default() },
+            ));
+            for modifier in HeatModifier::ALL {
+                parent.spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(420.0),
+                            height: Val::Px(50.0),
+                            margin: UiRect::all(Val::Px(8.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                        ..default()
+                    },
+                    modifier,
+                )).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        toggle_text(modifier, heat),
+                        TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                    ));
+                });
+            }
+            parent.spawn((
+                TextBundle::from_section(
+                    reward_text(heat),
+                    TextStyle { font_size: 24.0, color: Color::ORANGE_RED, ..default() },
+                ),
+                RewardText,
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Space or Enter to start, Escape to return",
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+        });
+    }
+
+    fn heat_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        focus: Res<buttons::ButtonFocus>,
+    ) {
+        // Enter presses the focused toggle instead, once the arrow keys have picked one.
+        let enter = keyboard_input.just_pressed(KeyCode::Enter) && focus.0.is_none();
+        if keyboard_input.just_pressed(KeyCode::Space) || enter {
+            next_state.set(GameState::Running);
+        } else if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+            next_state.set(GameState::MainMenu);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_heat_buttons(
+        interaction_query: Query<(&Interaction, &HeatModifier, &Children), (Changed<Interaction>, With<Button>)>,
+        mut text_query: Query<&mut Text, Without<RewardText>>,
+        mut reward_query: Query<&mut Text, With<RewardText>>,
+        mut settings: ResMut<settings::Settings>,
+    ) {
+        for (interaction, modifier, children) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            let heat = &mut settings.gameplay.heat;
+            match heat.iter().position(|active| active == modifier) {
+                Some(index) => {
+                    heat.remove(index);
+                }
+                None => heat.push(*modifier),
+            }
+            settings.save();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = toggle_text(*modifier, &settings.gameplay.heat);
+                }
+            }
+            for mut text in reward_query.iter_mut() {
+                text.sections[0].value = reward_text(&settings.gameplay.heat);
+            }
+        }
+    }
+
+    // Also runs when resuming from the level-up menu, so it only ever sets things
+    // to the values the heat calls for.
+    fn apply_heat(
+        current_run: Res<run::CurrentRun>,
+        mut weapon_stats: ResMut<combat::WeaponStats>,
+        mut stats_changed: EventWriter<attributes::StatsChanged>,
+    ) {
+        if current_run.heat.contains(&HeatModifier::NoBlades) && weapon_stats.blade_count != 0 {
+            weapon_stats.blade_count = 0;
+        }
+        stats_changed.send(attributes::StatsChanged);
+    }
+
+    fn toughen_enemies(
+        current_run: Res<run::CurrentRun>,
+        mut query: Query<&mut combat::Health, Added<enemy::Enemy>>,
+    ) {
+        if !current_run.heat.contains(&HeatModifier::ToughEnemies) {
+            return;
+        }
+        for mut health in query.iter_mut() {
+            *health = combat::Health::new(health.max * TOUGH_ENEMY_HEALTH);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_heat_raises_reward_and_narrows_the_pool() {
+            assert_eq!(reward_multiplier(&[]), 1.0);
+            assert!((reward_multiplier(&HeatModifier::ALL) - 2.0).abs() < 1e-4);
+
+            let heat = [HeatModifier::NoBlades];
+            assert!(!allows(&heat, ui::Upgrade::BladeCount));
+            assert!(!allows(&heat, ui::Upgrade::Evolve(passives::Evolution::RazorHalo)));
+            assert!(allows(&heat, ui::Upgrade::Multishot));
+            assert!(allows(&[], ui::Upgrade::BladeCount));
+
+            let stats = attributes::EffectiveStats::resolve(
+                &stat_modifiers(&[HeatModifier::ShortReach]).collect::<Vec<_>>(),
+            );
+            assert_eq!(stats.pickup_radius, XP_PICKUP_RADIUS * SHORT_REACH_RADIUS);
+        }
+    }
+}
+
+mod daily {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+    /// Mixed into the day number so neighbouring days get unrelated seeds.
+    const DAILY_SALT: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    pub struct DailyPlugin;
+
+    impl Plugin for DailyPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<PendingDaily>();
+        }
+    }
+
+    /// Set from the main menu so the next run starts as that day's Daily run.
+    #[derive(Resource, Default)]
+    pub struct PendingDaily(pub Option<u64>);
+
+    /// Today's day number, counted in whole UTC days since the Unix epoch.
+    pub fn today() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs() / SECONDS_PER_DAY)
+            .unwrap_or_default()
+    }
+
+    /// The run seed everyone plays on `day`.
+    pub fn seed(day: u64) -> u64 {
+        day.wrapping_add(1).wrapping_mul(DAILY_SALT)
+    }
+
+    /// The heat everyone plays with on `day`, picked from the day's seed.
+    pub fn heat(day: u64) -> Vec<heat::HeatModifier> {
+        let mut rng = StdRng::seed_from_u64(seed(day));
+        heat::HeatModifier::ALL
+            .into_iter()
+            .filter(|_| rng.gen_bool(0.5))
+            .collect()
+    }
+
+    /// `day` as a calendar date, e.g. "2026-10-17".
+    pub fn date_label(day: u64) -> String {
+        // Howard Hinnant's days-to-civil conversion, shifted so years start in March.
+        let days = day as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day_of_month = day_of_year - (153 * month_index + 2) / 5 + 1;
+        let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+        let year = year_of_era + era * 400 + (month <= 2) as i64;
+        format!("{}-{:02}-{:02}", year, month, day_of_month)
+    }
+
+    /// The best-scoring run of `day`'s Daily, if it has been played.
+    pub fn best_run(history: &history::RunHistory, day: u64) -> Option<&history::RunSummary> {
+        history
+            .runs
+            .iter()
+            .filter(|run| run.daily == Some(day))
+            .max_by_key(|run| run.score)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_daily_is_fixed_per_day() {
+            assert_eq!(date_label(0), "1970-01-01");
+            assert_eq!(date_label(20_743), "2026-10-17");
+            assert_eq!(date_label(11_016), "2000-02-29");
+
+            assert_eq!(seed(20_743), seed(20_743));
+            assert_ne!(seed(20_743), seed(20_744));
+            assert_eq!(heat(20_743), heat(20_743));
+        }
+    }
+}
+
+mod dummy {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Dummies never get anywhere near this before `keep_dummies_standing` tops them back up.
+    const DUMMY_HEALTH: f32 = 1.0e9;
+    /// Seconds of hits a dummy's DPS readout is averaged over.
+    const DPS_WINDOW: f32 = 3.0;
+    const DUMMY_RING_RADIUS: f32 = 260.0;
+    const DUMMIES_PER_RING: usize = 8;
+
+    /// Target dummies: static enemies that never die and show the damage they've taken
+    /// above themselves. Put down from the test range panel or the dev console.
+    pub struct DummyPlugin;
+
+    impl Plugin for DummyPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<DummyRequest>().add_systems(
+                Update,
+                (
+                    handle_dummy_requests,
+                    (track_dummy_damage, keep_dummies_standing)
+                        .after(combat::CombatSet::ApplyDamage)
+                        .before(combat::CombatSet::Death),
+                    update_dummy_readouts,
+                )
+                    .run_if(in_play),
+            );
+        }
+    }
+
+    #[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DummyRequest {
+        /// Puts one down in the next free spot around the player.
+        Spawn,
+        Clear,
+    }
+
+    /// A static enemy that can't die, keeping the hits it took in the last `DPS_WINDOW` seconds.
+    #[derive(Component, Default)]
+    pub struct TargetDummy {
+        total: f32,
+        recent: VecDeque<(f32, f32)>,
+    }
+
+    impl TargetDummy {
+        fn record(&mut self, now: f32, amount: f32) {
+            self.total += amount;
+            self.recent.push_back((now, amount));
+        }
+
+        /// Damage per second over the last `DPS_WINDOW` seconds, forgetting older hits.
+        fn dps(&mut self, now: f32) -> f32 {
+            while self.recent.front().is_some_and(|&(at, _)| now - at > DPS_WINDOW) {
+                self.recent.pop_front();
+            }
+            self.recent.iter().map(|&(_, amount)| amount).sum::<f32>() / DPS_WINDOW
+        }
+    }
+
+    #[derive(Component)]
+    struct DummyReadout;
+
+    /// Where the `placed`th dummy goes: evenly around a ring about the player, then the
+    /// next ring out once that one is full.
+    fn ring_position(player: Vec3, placed: usize) -> Vec3 {
+        let angle = (placed % DUMMIES_PER_RING) as f32 * std::f32::consts::TAU / DUMMIES_PER_RING as f32;
+        let distance = DUMMY_RING_RADIUS + (placed / DUMMIES_PER_RING) as f32 * ENEMY_SIZE * 3.0;
+        player + Vec3::new(angle.cos(), angle.sin(), 0.0) * distance
+    }
+
+    fn handle_dummy_requests(
+        mut commands: Commands,
+        mut requests: EventReader<DummyRequest>,
+        dummy_query: Query<Entity, With<TargetDummy>>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        // Counted here rather than per request, since dummies spawned this frame aren't in the query yet.
+        let mut placed = dummy_query.iter().count();
+        for request in requests.read() {
+            match request {
+                DummyRequest::Spawn => {
+                    let Ok(player_transform) = player_query.get_single() else {
+                        continue;
+                    };
+                    spawn_dummy(&mut commands, ring_position(player_transform.translation, placed));
+                    placed += 1;
+                }
+                DummyRequest::Clear => {
+                    for entity in dummy_query.iter() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                    placed = 0;
+                }
+            }
+        }
+    }
+
+    fn spawn_dummy(commands: &mut Commands, position: Vec3) {
+        let mut bundle = enemy::enemy_bundle(position, Color::rgb(0.85, 0.75, 0.55), enemy::EnemyKind::Grunt);
+        bundle.3 = combat::Health::new(DUMMY_HEALTH);
+        commands.spawn((bundle, enemy::Speed(0.0), TargetDummy::default())).with_children(|parent| {
+            parent.spawn((
+                Text2dBundle {
+                    text: Text::from_section("", TextStyle { font_size: 16.0, color: Color::WHITE, ..default() }),
+                    transform: Transform::from_xyz(0.0, ENEMY_SIZE, 1.0),
+                    ..default()
+                },
+                DummyReadout,
+            ));
+        });
+    }
+
+    fn track_dummy_damage(
+        mut damage_events: EventReader<enemy::EnemyDamaged>,
+        mut dummy_query: Query<&mut TargetDummy>,
+        time: Res<Time>,
+    ) {
+        let now = time.elapsed_seconds();
+        for event in damage_events.read() {
+            if let Ok(mut dummy) = dummy_query.get_mut(event.entity) {
+                dummy.record(now, event.amount);
+            }
+        }
+    }
+
+    fn keep_dummies_standing(mut query: Query<&mut combat::Health, With<TargetDummy>>) {
+        for mut health in query.iter_mut() {
+            health.current = health.max;
+        }
+    }
+
+    fn update_dummy_readouts(
+        mut dummy_query: Query<(&mut TargetDummy, &Children)>,
+        mut text_query: Query<&mut Text, With<DummyReadout>>,
+        time: Res<Time>,
+    ) {
+        let now = time.elapsed_seconds();
+        for (mut dummy, children) in dummy_query.iter_mut() {
+            let dps = dummy.dps(now);
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = format!("{:.0} dmg\n{:.0} DPS", dummy.total, dps);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_dummy_dps_only_counts_recent_hits() {
+            let mut dummy = TargetDummy::default();
+            dummy.record(0.0, 30.0);
+            dummy.record(2.0, 30.0);
+            dummy.record(2.5, 30.0);
+            assert_eq!(dummy.dps(2.5), 30.0);
+
+            // The first hit has aged out of the window, but still counts towards the total.
+            assert_eq!(dummy.dps(3.5), 20.0);
+            assert_eq!(dummy.total, 90.0);
+            assert_eq!(dummy.dps(10.0), 0.0);
+        }
+
+        #[test]
+        fn test_dummies_never_die() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+                .add_event::<enemy::EnemyDamaged>()
+                .add_systems(Update, (track_dummy_damage, keep_dummies_standing).chain());
+            let mut bundle = enemy::enemy_bundle(Vec3::ZERO, Color::WHITE, enemy::EnemyKind::Grunt);
+            bundle.3 = combat::Health::new(DUMMY_HEALTH);
+            let dummy = app.world.spawn((bundle, TargetDummy::default())).id();
+
+            app.world.get_mut::<combat::Health>(dummy).unwrap().current = -5.0;
+            app.world.send_event(enemy::EnemyDamaged { entity: dummy, amount: 40.0, shred: 0, split: None });
+            app.update();
+
+            assert_eq!(app.world.get::<combat::Health>(dummy).unwrap().current, DUMMY_HEALTH);
+            assert_eq!(app.world.get::<TargetDummy>(dummy).unwrap().total, 40.0);
+        }
+    }
+}
+
+mod sandbox {
+    use super::*;
+
+    /// The test range: a run with no spawners where the player can't die, with a panel
+    /// to grant any upgrade and put down target dummies.
+    pub struct SandboxPlugin;
+
+    impl Plugin for SandboxPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<PendingSandbox>()
+                .add_systems(OnEnter(GameState::Running), setup_sandbox_panel.after(run::begin_run).run_if(sandboxing))
+                .add_systems(
+                    Update,
+                    (
+                        handle_grant_buttons,
+                        handle_dummy_buttons,
+                        keep_player_alive
+                            .after(combat::CombatSet::ApplyDamage)
+                            .before(combat::CombatSet::Death),
+                    )
+                        .run_if(in_state(GameState::Running).and_then(sandboxing)),
+                );
+        }
+    }
+
+    /// Set from the main menu so the next run starts on the test range.
+    #[derive(Resource, Default)]
+    pub struct PendingSandbox(pub bool);
+
+    fn sandboxing(current_run: Res<run::CurrentRun>) -> bool {
+        current_run.sandbox
+    }
+
+    #[derive(Component)]
+    struct SandboxPanel;
+
+    #[derive(Component)]
+    struct GrantButton(ui::Upgrade);
+
+    #[derive(Component)]
+    struct DummyButton(dummy::DummyRequest);
+
+    impl DummyButton {
+        fn label(&self) -> &'static str {
+            match self.0 {
+                dummy::DummyRequest::Spawn => "Spawn Dummy",
+                dummy::DummyRequest::Clear => "Clear Dummies",
+            }
+        }
+    }
+
+    fn button(width: f32) -> ButtonBundle {
+        ButtonBundle {
+            style: Style {
+                width: Val::Px(width),
+                height: Val::Px(30.0),
+                margin: UiRect::all(Val::Px(3.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::rgba(0.15, 0.15, 0.15, 0.85).into(),
+            ..default()
+        }
+    }
+
+    fn setup_sandbox_panel(mut commands: Commands, query: Query<(), With<SandboxPanel>>) {
+        // OnEnter(Running) also fires when resuming from the level-up menu.
+        if !query.is_empty() {
+            return;
+        }
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(10.0),
+                    top: Val::Px(220.0),
+                    width: Val::Px(330.0),
+                    flex_direction: FlexDirection::Row,
+                    flex_wrap: FlexWrap::Wrap,
+                    ..default()
+                },
+                ..default()
+            },
+            SandboxPanel,
+            settings::SafeArea,
+            DespawnOnExit::RUN,
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Test Range",
+                TextStyle { font_size: 24.0, ..default() },
+            ).with_style(Style { width: Val::Percent(100.0), ..default() }));
+            for action in [DummyButton(dummy::DummyRequest::Spawn), DummyButton(dummy::DummyRequest::Clear)] {
+                let label = action.label();
+                parent.spawn((button(156.0), action)).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        label,
+                        TextStyle { font_size: 16.0, color: Color::GOLD, ..default() },
+                    ));
+                });
+            }
+            for upgrade in ui::Upgrade::ALL {
+                parent.spawn((button(156.0), GrantButton(upgrade))).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        upgrade.label(),
+                        TextStyle { font_size: 14.0, color: Color::WHITE, ..default() },
+                    ));
+                });
+            }
+        });
+    }
+
+    fn handle_grant_buttons(
+        interaction_query: Query<(&Interaction, &GrantButton), Changed<Interaction>>,
+        mut chosen_events: EventWriter<ui::UpgradeChosen>,
+    ) {
+        for (interaction, grant) in interaction_query.iter() {
+            if *interaction == Interaction::Pressed {
+                chosen_events.send(ui::UpgradeChosen(grant.0));
+            }
+        }
+    }
+
+    fn handle_dummy_buttons(
+        interaction_query: Query<(&Interaction, &DummyButton), Changed<Interaction>>,
+        mut requests: EventWriter<dummy::DummyRequest>,
+    ) {
+        for (interaction, button) in interaction_query.iter() {
+            if *interaction == Interaction::Pressed {
+                requests.send(button.0);
+            }
+        }
+    }
+
+    fn keep_player_alive(mut query: Query<&mut combat::Health, With<player::Player>>) {
+        for mut health in query.iter_mut() {
+            health.current = health.max;
+        }
+    }
+}
+
+mod run {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use serde::{Deserialize, Serialize};
+
+    pub struct RunPlugin;
+
+    impl Plugin for RunPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(CurrentRun::default())
+                .insert_resource(NextRunSeed::default())
+                .insert_resource(RunRng(StdRng::seed_from_u64(0)))
+                .init_schedule(ResetRun)
+                .add_systems(OnEnter(GameState::Running), begin_run)
+                .add_systems(Update, tick_run_clock.run_if(in_play))
+                .add_systems(
+                    Update,
+                    (
+                        ask_to_abandon.run_if(confirm::dialog_closed).before(confirm::ConfirmSet),
+                        abandon_run,
+                    )
+                        .run_if(in_state(GameState::Running)),
+                )
+                .add_systems(Update, count_kills.in_set(combat::CombatSet::Death))
+                .add_systems(OnEnter(GameState::GameOver), show_game_over)
+                .add_systems(Update, game_over_input.run_if(in_state(GameState::GameOver)))
+                .add_systems(OnExit(GameState::GameOver), reset_world)
+                // Leaving the menu ends the attract-mode game, so a real run starts clean.
+                .add_systems(OnExit(GameState::MainMenu), reset_world)
+                .add_systems(ResetRun, reset_run);
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    pub enum DeathCause {
+        Swarmed,
+        Abandoned,
+    }
+
+    impl DeathCause {
+        pub fn label(&self) -> &'static str {
+            match self {
+                DeathCause::Swarmed => "Swarmed",
+                DeathCause::Abandoned => "Abandoned",
+            }
+        }
+    }
+
+    /// Bookkeeping for the run in progress, turned into a `RunSummary` when it ends.
+    #[derive(Resource, Default, Debug)]
+    pub struct CurrentRun {
+        pub active: bool,
+        pub seed: u64,
+        pub elapsed: f32,
+        pub kills: u32,
+        pub build: Vec<ui::Upgrade>,
+        pub death_cause: Option<DeathCause>,
+        /// Challenge modifiers picked on the Heat screen, fixed when the run starts.
+        pub heat: Vec<heat::HeatModifier>,
+        /// The day this is the Daily run for, if it is one.
+        pub daily: Option<u64>,
+        /// Level-up cards come from the seed rather than fresh randomness, so everyone on
+        /// the same seed who picks the same cards is offered the same ones.
+        pub seeded_drafts: bool,
+        /// Level-ups drafted so far this run.
+        pub drafts: u32,
+        /// A test-range run: nothing spawns on its own, and it isn't kept in history.
+        pub sandbox: bool,
+    }
+
+    impl CurrentRun {
+        pub fn score(&self) -> u32 {
+            let base = self.kills * 10 + self.elapsed as u32;
+            (base as f32 * heat::reward_multiplier(&self.heat)).round() as u32
+        }
+
+        /// Randomness for the next level-up's cards. A seeded draft depends only on the
+        /// seed and how many drafts came before, never on the world RNG, which the
+        /// player's movement and kills pull from at a different rate in every run.
+        pub fn next_draft_rng(&mut self) -> StdRng {
+            self.drafts += 1;
+            if self.seeded_drafts {
+                StdRng::seed_from_u64(self.seed ^ u64::from(self.drafts).wrapping_mul(DRAFT_SALT))
+            } else {
+                StdRng::from_entropy()
+            }
+        }
+    }
+
+    const DRAFT_SALT: u64 = 0xD1B5_4A32_D192_ED03;
+
+    /// Seed to use for the next run instead of a random one (set by replays).
+    #[derive(Resource, Default)]
+    pub struct NextRunSeed(pub Option<u64>);
+
+    /// Seeded RNG for world generation, so a run can be replayed from its seed.
+    #[derive(Resource)]
+    pub struct RunRng(pub StdRng);
+
+    #[derive(Component)]
+    struct GameOverScreen;
+
+    #[derive(Component)]
+    struct BuildCodeText;
+
+    pub fn format_duration(seconds: f32) -> String {
+        let total = seconds.max(0.0) as u32;
+        format!("{}:{:02}", total / 60, total % 60)
+    }
+
+    pub fn begin_run(
+        mut current_run: ResMut<CurrentRun>,
+        mut next_seed: ResMut<NextRunSeed>,
+        mut run_rng: ResMut<RunRng>,
+        mut pending_daily: ResMut<daily::PendingDaily>,
+        mut pending_sandbox: ResMut<sandbox::PendingSandbox>,
+        settings: Res<settings::Settings>,
+    ) {
+        // OnEnter(Running) also fires when resuming from the level-up menu.
+        if current_run.active {
+            return;
+        }
+        *current_run = match pending_daily.0.take() {
+            // Everyone gets the same seed and heat on the same day, whatever their own heat is.
+            Some(day) => CurrentRun {
+                active: true,
+                seed: daily::seed(day),
+                heat: daily::heat(day),
+                daily: Some(day),
+                seeded_drafts: true,
+                ..default()
+            },
+            None => CurrentRun {
+                active: true,
+                seed: next_seed.0.take().unwrap_or_else(|| rand::thread_rng().gen()),
+                heat: settings.gameplay.heat.clone(),
+                seeded_drafts: settings.gameplay.seeded_drafts,
+                sandbox: std::mem::take(&mut pending_sandbox.0),
+                ..default()
+            },
+        };
+        let seed = current_run.seed;
+        run_rng.0 = StdRng::seed_from_u64(seed);
+        info!("Starting run with seed {}", seed);
+    }
+
+    /// Run condition for anything that spawns enemies on its own, which the test range turns off.
+    pub fn not_sandbox(current_run: Res<CurrentRun>) -> bool {
+        !current_run.sandbox
+    }
+
+    fn tick_run_clock(mut current_run: ResMut<CurrentRun>, time: Res<Time>) {
+        current_run.elapsed += time.delta_seconds();
+    }
+
+    fn count_kills(
+        mut events: EventReader<enemy::EnemyDied>,
+        mut current_run: ResMut<CurrentRun>,
+    ) {
+        current_run.kills += events.read().count() as u32;
+    }
+
+    fn ask_to_abandon(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut requests: EventWriter<confirm::ConfirmRequested>,
+    ) {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            requests.send(confirm::ConfirmRequested(confirm::ConfirmAction::AbandonRun));
+        }
+    }
+
+    fn abandon_run(
+        mut confirmed: EventReader<confirm::Confirmed>,
+        mut current_run: ResMut<CurrentRun>,
+        mut game_state: ResMut<NextState<GameState>>,
+    ) {
+        if confirmed.read().any(|confirmed| confirmed.0 == confirm::ConfirmAction::AbandonRun) {
+            current_run.death_cause = Some(DeathCause::Abandoned);
+            game_state.set(GameState::GameOver);
+        }
+    }
+
+    fn show_game_over(
+        mut commands: Commands,
+        current_run: Res<CurrentRun>,
+        player_stats: Res<leveling::PlayerStats>,
+        damage_log: Res<damage_log::DamageLog>,
+    ) {
+        let build_code = build_code::BuildCode::from_run(&current_run);
+        let title = match current_run.death_cause {
+            Some(DeathCause::Abandoned) => "Run Abandoned",
+            _ => "Game Over",
+        };
+
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                z_index: ZIndex::Global(100),
+                ..default()
+            },
+            GameOverScreen,
+            DespawnOnExit::RUN,
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                title,
+                TextStyle { font_size: 60.0, color: Color::WHITE, ..default() },
+            ));
+            if current_run.death_cause == Some(DeathCause::Swarmed) {
+                if let Some(hit) = damage_log.killing_blow() {
+                    parent.spawn(TextBundle::from_section(
+                        format!("Killed by: {}", hit.describe()),
+                        TextStyle { font_size: 25.0, color: Color::rgb(1.0, 0.4, 0.4), ..default() },
+                    ));
+                }
+            }
+            parent.spawn(TextBundle::from_section(
+                format!(
+                    "Survived {}  -  Level {}  -  {} kills  -  Score {}",
+                    format_duration(current_run.elapsed),
+                    player_stats.level,
+                    current_run.kills,
+                    current_run.score(),
+                ),
+                TextStyle { font_size: 25.0, color: Color::WHITE, ..default() },
+            ));
+            if let Some(day) = current_run.daily {
+                parent.spawn(TextBundle::from_section(
+                    format!("Daily run {}", daily::date_label(day)),
+                    TextStyle { font_size: 20.0, color: Color::GOLD, ..default() },
+                ));
+            }
+            if !current_run.heat.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    format!(
+                        "Heat: {}  (x{:.1} score)",
+                        current_run.heat.iter().map(|modifier| modifier.label()).collect::<Vec<_>>().join(", "),
+                        heat::reward_multiplier(&current_run.heat),
+                    ),
+                    TextStyle { font_size: 20.0, color: Color::ORANGE_RED, ..default() },
+                ));
+            }
+            parent.spawn(TextBundle::from_section(
+                format!("Seed: {}", current_run.seed),
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+            parent.spawn((
+                TextBundle::from_section(
+                    format!("Build code: {}  (C to copy)", build_code.encode()),
+                    TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+                ),
+                BuildCodeText,
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Space or Enter to return to the menu",
+                TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+            ));
+        });
+    }
+
+    fn game_over_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        current_run: Res<CurrentRun>,
+        mut code_text_query: Query<&mut Text, With<BuildCodeText>>,
+        // Kept alive so the copied text survives on platforms where the owner serves it.
+        mut clipboard: Local<Option<arboard::Clipboard>>,
+    ) {
+        if keyboard_input.any_just_pressed([KeyCode::Space, KeyCode::Enter]) {
+            next_state.set(GameState::MainMenu);
+        } else if keyboard_input.just_pressed(KeyCode::KeyC) {
+            let code = build_code::BuildCode::from_run(&current_run).encode();
+            if clipboard.is_none() {
+                *clipboard = arboard::Clipboard::new()
+                    .map_err(|err| warn!("Clipboard unavailable: {}", err))
+                    .ok();
+            }
+            let copied = clipboard
+                .as_mut()
+                .is_some_and(|clipboard| clipboard.set_text(code.clone()).is_ok());
+            let status = if copied {
+                format!("Copied build code {}", code)
+            } else {
+                format!("Could not access the clipboard, build code is {}", code)
+            };
+            for mut text in code_text_query.iter_mut() {
+                text.sections[0].value = status.clone();
+            }
+        }
+    }
+
+    fn reset_run(mut current_run: ResMut<CurrentRun>) {
+        *current_run = CurrentRun::default();
+    }
+
+    pub fn reset_world(world: &mut World) {
+        world.run_schedule(ResetRun);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_reset_clears_only_the_run_scope() {
+            let mut app = App::new();
+            app.add_plugins(MinimalPlugins)
+               .init_state::<GameState>()
+               .init_schedule(ResetRun)
+               .add_plugins(CleanupPlugin);
+            let in_run = app.world.spawn(DespawnOnExit::RUN).with_children(|parent| {
+                parent.spawn_empty();
+            }).id();
+            let child = app.world.entity(in_run).get::<Children>().unwrap()[0];
+            let on_menu = app.world.spawn(DespawnOnExit(GameState::MainMenu)).id();
+            let untagged = app.world.spawn_empty().id();
+
+            reset_world(&mut app.world);
+            assert!(app.world.get_entity(in_run).is_none());
+            assert!(app.world.get_entity(child).is_none());
+            assert!(app.world.get_entity(on_menu).is_some());
+            assert!(app.world.get_entity(untagged).is_some());
+
+            app.world.resource_mut::<NextState<GameState>>().set(GameState::Running);
+            app.update();
+            assert!(app.world.get_entity(on_menu).is_none());
+            assert!(app.world.get_entity(untagged).is_some());
+        }
+
+        #[test]
+        fn test_seeded_drafts_match_on_the_same_seed() {
+            let planned_build = build_code::PlannedBuild::default();
+            let drafts = |seed| {
+                let mut current_run = CurrentRun { seed, seeded_drafts: true, ..default() };
+                (0..5)
+                    .map(|_| {
+                        let mut rng = current_run.next_draft_rng();
+                        ui::draft_upgrades(&[], &[], &planned_build, &mut rng)
+                    })
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(drafts(42), drafts(42));
+            assert_ne!(drafts(42), drafts(43));
+        }
+    }
+}
+
+mod history {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const HISTORY_PATH: &str = "saves/run_history.ron";
+    const MAX_STORED_RUNS: usize = 100;
+    const MAX_LISTED_RUNS: usize = 10;
+
+    pub struct HistoryPlugin;
+
+    impl Plugin for HistoryPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(RunHistory::load())
+                .add_systems(OnEnter(GameState::GameOver), record_run)
+                .add_systems(OnEnter(GameState::RunHistory), setup_history_screen)
+                .add_systems(
+                    Update,
+                    (history_input, handle_history_buttons)
+                        .run_if(in_state(GameState::RunHistory)),
+                );
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    pub struct RunSummary {
+        pub seed: u64,
+        pub duration: f32,
+        pub level: u32,
+        pub kills: u32,
+        pub score: u32,
+        pub build: Vec<ui::Upgrade>,
+        pub death_cause: run::DeathCause,
+        /// Unix timestamp (seconds) of when the run ended.
+        pub ended_at: u64,
+        #[serde(default)]
+        pub heat: Vec<heat::HeatModifier>,
+        /// Set on Daily runs, which get their own leaderboard.
+        #[serde(default)]
+        pub daily: Option<u64>,
+        /// `build_info::label()` of the game that played the run; empty for runs from
+        /// before it was recorded.
+        #[serde(default)]
+        pub game_version: String,
+    }
+
+    impl RunSummary {
+        fn from_run(current_run: &run::CurrentRun, player_stats: &leveling::PlayerStats) -> Self {
+            Self {
+                seed: current_run.seed,
+                duration: current_run.elapsed,
+                level: player_stats.level,
+                kills: current_run.kills,
+                score: current_run.score(),
+                build: current_run.build.clone(),
+                death_cause: current_run.death_cause.unwrap_or(run::DeathCause::Swarmed),
+                ended_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|elapsed| elapsed.as_secs())
+                    .unwrap_or_default(),
+                heat: current_run.heat.clone(),
+                daily: current_run.daily,
+                game_version: build_info::label(),
+            }
+        }
+
+        /// Upgrade picks grouped by kind, e.g. "More Blades x2, Shotgun x1".
+        pub fn build_summary(&self) -> String {
+            let mut counts: Vec<(ui::Upgrade, u32)> = Vec::new();
+            for upgrade in &self.build {
+                match counts.iter_mut().find(|(picked, _)| picked == upgrade) {
+                    Some((_, count)) => *count += 1,
+                    None => counts.push((*upgrade, 1)),
+                }
+            }
+            if counts.is_empty() {
+                return "No upgrades".to_string();
+            }
+            counts
+                .iter()
+                .map(|(upgrade, count)| format!("{} x{}", upgrade.label(), count))
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+
+    /// Every completed run, oldest first, persisted to `HISTORY_PATH`.
+    #[derive(Resource, Serialize, Deserialize, Default, Debug)]
+    pub struct RunHistory {
+        pub runs: Vec<RunSummary>,
+    }
+
+    impl RunHistory {
+        pub fn load() -> Self {
+            persistence::load_ron(HISTORY_PATH)
+        }
+
+        pub fn save(&self) {
+            persistence::save_ron(HISTORY_PATH, self);
+        }
+
+        pub fn push(&mut self, summary: RunSummary) {
+            self.runs.push(summary);
+            if self.runs.len() > MAX_STORED_RUNS {
+                let excess = self.runs.len() - MAX_STORED_RUNS;
+                self.runs.drain(..excess);
+            }
+        }
+    }
+
+    #[derive(Component)]
+    struct HistoryScreen;
+    #[derive(Component)]
+    struct HistoryStatusText;
+
+    #[derive(Component, Clone, Copy, Debug)]
+    enum HistoryAction {
+        CopySeed(u64),
+        Replay(u64),
+    }
+
+    fn record_run(
+        current_run: Res<run::CurrentRun>,
+        player_stats: Res<leveling::PlayerStats>,
+        mut history: ResMut<RunHistory>,
+        mut toasts: EventWriter<toasts::ToastEvent>,
+    ) {
+        if current_run.sandbox {
+            return;
+        }
+        history.push(RunSummary::from_run(&current_run, &player_stats));
+        history.save();
+        toasts.send(toasts::ToastEvent::new(toasts::ToastKind::Saved, "Run saved to history"));
+    }
+
+    fn setup_history_screen(mut commands: Commands, history: Res<RunHistory>) {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            HistoryScreen,
+            DespawnOnExit(GameState::RunHistory),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Run History",
+                TextStyle { font_size: 50.0, ..default() },
+            ));
+
+            if history.runs.is_empty() {
+                parent.spawn(TextBundle::from_section(
+                    "No runs yet. Go get swarmed!",
+                    TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+                ));
+            }
+
+            for summary in history.runs.iter().rev().take(MAX_LISTED_RUNS) {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        align_items: AlignItems::Center,
+                        margin: UiRect::all(Val::Px(4.0)),
+                        ..default()
+                    },
+                    ..default()
+                }).with_children(|parent| {
+                    parent.spawn(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            width: Val::Px(700.0),
+                            ..default()
+                        },
+                        ..default()
+                    }).with_children(|parent| {
+                        let daily_tag = summary
+                            .daily
+                            .map(|day| format!("Daily {}  -  ", daily::date_label(day)))
+                            .unwrap_or_default();
+                        parent.spawn(TextBundle::from_section(
+                            format!(
+                                "{}{}  -  Level {}  -  {} kills  -  Score {}  -  {}  -  Seed {}",
+                                daily_tag,
+                                run::format_duration(summary.duration),
+                                summary.level,
+                                summary.kills,
+                                summary.score,
+                                summary.death_cause.label(),
+                                summary.seed,
+                            ),
+                            TextStyle { font_size: 18.0, color: Color::WHITE, ..default() },
+                        ));
+                        parent.spawn(TextBundle::from_section(
+                            summary.build_summary(),
+                            TextStyle { font_size: 14.0, color: Color::GRAY, ..default() },
+                        ));
+                    });
+
+                    for (action, label) in [
+                        (HistoryAction::CopySeed(summary.seed), "Copy Seed"),
+                        (HistoryAction::Replay(summary.seed), "Replay"),
+                    ] {
+                        parent.spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(120.0),
+                                    height: Val::Px(36.0),
+                                    margin: UiRect::horizontal(Val::Px(5.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                                ..default()
+                            },
+                            action,
+                        )).with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                label,
+                                TextStyle { font_size: 16.0, color: Color::WHITE, ..default() },
+                            ));
+                        });
+                    }
+                });
+            }
+
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle { font_size: 18.0, color: Color::rgb(0.1, 0.9, 0.1), ..default() },
+                ),
+                HistoryStatusText,
+            ));
+            parent.spawn(TextBundle::from_section(
+                "Press Escape to return",
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+        });
+    }
+
+    fn history_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+    ) {
+        if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+            next_state.set(GameState::MainMenu);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_history_buttons(
+        interaction_query: Query<(&Interaction, &HistoryAction), (Changed<Interaction>, With<Button>)>,
+        mut status_query: Query<&mut Text, With<HistoryStatusText>>,
+        mut next_seed: ResMut<run::NextRunSeed>,
+        mut game_state: ResMut<NextState<GameState>>,
+        // Kept alive so the copied text survives on platforms where the owner serves it.
+        mut clipboard: Local<Option<arboard::Clipboard>>,
+    ) {
+        for (interaction, action) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            match *action {
+                HistoryAction::CopySeed(seed) => {
+                    if clipboard.is_none() {
+                        *clipboard = arboard::Clipboard::new()
+                            .map_err(|err| warn!("Clipboard unavailable: {}", err))
+                            .ok();
+                    }
+                    let copied = clipboard
+                        .as_mut()
+                        .is_some_and(|clipboard| clipboard.set_text(seed.to_string()).is_ok());
+                    let status = if copied {
+                        format!("Copied seed {} to the clipboard", seed)
+                    } else {
+                        format!("Could not access the clipboard, seed is {}", seed)
+                    };
+                    for mut text in status_query.iter_mut() {
+                        text.sections[0].value = status.clone();
+                    }
+                }
+                HistoryAction::Replay(seed) => {
+                    next_seed.0 = Some(seed);
+                    game_state.set(GameState::Running);
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn summary(seed: u64) -> RunSummary {
+            RunSummary {
+                seed,
+                duration: 60.0,
+                level: 3,
+                kills: 42,
+                score: 480,
+                build: vec![ui::Upgrade::BladeCount, ui::Upgrade::Shotgun, ui::Upgrade::BladeCount],
+                death_cause: run::DeathCause::Swarmed,
+                ended_at: 0,
+                heat: Vec::new(),
+                daily: None,
+                game_version: String::new(),
+            }
+        }
+
+        #[test]
+        fn test_history_keeps_most_recent_runs() {
+            let mut history = RunHistory::default();
+            for seed in 0..(MAX_STORED_RUNS as u64 + 5) {
+                history.push(summary(seed));
+            }
+
+            assert_eq!(history.runs.len(), MAX_STORED_RUNS);
+            assert_eq!(history.runs.first().unwrap().seed, 5);
+            assert_eq!(history.runs.last().unwrap().seed, MAX_STORED_RUNS as u64 + 4);
+
+            let restored: RunHistory = ron::from_str(&ron::to_string(&history).unwrap()).unwrap();
+            assert_eq!(restored.runs.len(), MAX_STORED_RUNS);
+            assert_eq!(restored.runs[0].build_summary(), "More Blades x2, Shotgun x1");
+        }
+    }
+}
+
+mod stats {
+    use super::*;
+    use serde::Serialize;
+    use std::{
+        fs, io,
+        path::{Path, PathBuf},
+    };
+
+    pub const EXPORT_DIR: &str = "exports";
+
+    pub struct StatsPlugin;
+
+    impl Plugin for StatsPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_event::<ProfileReset>()
+                .add_systems(OnEnter(GameState::Stats), setup_stats_screen)
+                .add_systems(
+                    Update,
+                    (
+                        (stats_input, handle_export_button, handle_reset_button)
+                            .run_if(confirm::dialog_closed)
+                            .before(confirm::ConfirmSet),
+                        (reset_profile, setup_stats_screen.run_if(on_event::<ProfileReset>())).chain(),
+                    )
+                        .run_if(in_state(GameState::Stats)),
+                );
+        }
+    }
+
+    /// Totals across every recorded run, derived from the run history.
+    #[derive(Serialize, Default, Debug, PartialEq)]
+    pub struct LifetimeStats {
+        pub runs: u32,
+        pub total_kills: u32,
+        pub total_playtime: f32,
+        pub best_time: f32,
+        pub best_score: u32,
+        pub highest_level: u32,
+        pub upgrades_picked: u32,
+    }
+
+    impl LifetimeStats {
+        pub fn from_history(history: &history::RunHistory) -> Self {
+            history.runs.iter().fold(Self::default(), |mut stats, run| {
+                stats.runs += 1;
+                stats.total_kills += run.kills;
+                stats.total_playtime += run.duration;
+                stats.best_time = stats.best_time.max(run.duration);
+                stats.best_score = stats.best_score.max(run.score);
+                stats.highest_level = stats.highest_level.max(run.level);
+                stats.upgrades_picked += run.build.len() as u32;
+                stats
+            })
+        }
+    }
+
+    /// Writes the run history and lifetime stats as JSON and CSV into `dir`,
+    /// returning the paths of the files written.
+    pub fn export(history: &history::RunHistory, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        fs::create_dir_all(dir)?;
+        let lifetime = LifetimeStats::from_history(history);
+
+        let runs_json = dir.join("runs.json");
+        fs::write(&runs_json, serde_json::to_string_pretty(&history.runs)?)?;
+
+        let lifetime_json = dir.join("lifetime_stats.json");
+        fs::write(&lifetime_json, serde_json::to_string_pretty(&lifetime)?)?;
+
+        let mut csv = String::from("seed,duration,level,kills,score,death_cause,ended_at,daily,build,game_version\n");
+        for run in &history.runs {
+            let build = run.build.iter().map(|upgrade| upgrade.label()).collect::<Vec<_>>().join(";");
+            csv.push_str(&format!(
+                "{},{:.2},{},{},{},{},{},{},{},{}\n",
+                run.seed,
+                run.duration,
+                run.level,
+                run.kills,
+                run.score,
+                run.death_cause.label(),
+                run.ended_at,
+                run.daily.map(daily::date_label).unwrap_or_default(),
+                csv_field(&build),
+                csv_field(&run.game_version),
+            ));
+        }
+        let runs_csv = dir.join("runs.csv");
+        fs::write(&runs_csv, csv)?;
+
+        let lifetime_csv = dir.join("lifetime_stats.csv");
+        fs::write(
+            &lifetime_csv,
+            format!(
+                "runs,total_kills,total_playtime,best_time,best_score,highest_level,upgrades_picked\n{},{},{:.2},{:.2},{},{},{}\n",
+                lifetime.runs,
+                lifetime.total_kills,
+                lifetime.total_playtime,
+                lifetime.best_time,
+                lifetime.best_score,
+                lifetime.highest_level,
+                lifetime.upgrades_picked,
+            ),
+        )?;
+
+        Ok(vec![runs_json, runs_csv, lifetime_json, lifetime_csv])
+    }
+
+    fn csv_field(value: &str) -> String {
+        if value.contains([',', '"', '\n']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+
+    #[derive(Component)]
+    struct StatsScreen;
+    #[derive(Component)]
+    struct ExportButton;
+    #[derive(Component)]
+    struct ExportStatusText;
+    #[derive(Component)]
+    struct ResetProfileButton;
+
+    /// The history was just wiped; the screen is rebuilt from the empty one.
+    #[derive(Event)]
+    struct ProfileReset;
+
+    fn setup_stats_screen(mut commands: Commands, history: Res<history::RunHistory>) {
+        let lifetime = LifetimeStats::from_history(&history);
+        let today = daily::today();
+        let lines = [
+            format!("Runs played: {}", lifetime.runs),
+            format!("Total kills: {}", lifetime.total_kills),
+            format!("Total playtime: {}", run::format_duration(lifetime.total_playtime)),
+            format!("Longest survival: {}", run::format_duration(lifetime.best_time)),
+            format!("Best score: {}", lifetime.best_score),
+            format!("Highest level: {}", lifetime.highest_level),
+            format!("Upgrades picked: {}", lifetime.upgrades_picked),
+            match daily::best_run(&history, today) {
+                Some(best) => format!("Daily {}: best score {}", daily::date_label(today), best.score),
+                None => format!("Daily {}: not played yet", daily::date_label(today)),
+            },
+            if pet::is_unlocked(&history) {
+                "Pet companion: unlocked".to_string()
+            } else {
+                format!("Pet companion: survive {} to unlock", run::format_duration(pet::PET_UNLOCK_TIME))
+            },
+        ];
+
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            StatsScreen,
+            DespawnOnExit(GameState::Stats),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Lifetime Stats",
+                TextStyle { font_size: 50.0, ..default() },
+            ));
+            for line in lines {
+                parent.spawn(TextBundle::from_section(
+                    line,
+                    TextStyle { font_size: 22.0, color: Color::WHITE, ..default() },
+                ));
+            }
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(15.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                },
+                ExportButton,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Export JSON / CSV",
+                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                ));
+            });
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle { font_size: 18.0, color: Color::rgb(0.1, 0.9, 0.1), ..default() },
+                ),
+                ExportStatusText,
+            ));
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(250.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(15.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.4, 0.1, 0.1).into(),
+                    ..default()
+                },
+                ResetProfileButton,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Reset profile",
+                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                ));
+            });
+            parent.spawn(TextBundle::from_section(
+                "Press Escape to return",
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+        });
+    }
+
+    fn stats_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+    ) {
+        if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+            next_state.set(GameState::MainMenu);
+        }
+    }
+
+    fn handle_export_button(
+        interaction_query: Query<&Interaction, (Changed<Interaction>, With<ExportButton>)>,
+        mut status_query: Query<&mut Text, With<ExportStatusText>>,
+        history: Res<history::RunHistory>,
+    ) {
+        for interaction in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            let status = match export(&history, Path::new(EXPORT_DIR)) {
+                Ok(paths) => format!("Exported {} files to {}/", paths.len(), EXPORT_DIR),
+                Err(err) => {
+                    warn!("Stats export failed: {}", err);
+                    format!("Export failed: {}", err)
+                }
+            };
+            for mut text in status_query.iter_mut() {
+                text.sections[0].value = status.clone();
+            }
+        }
+    }
+
+    fn handle_reset_button(
+        interaction_query: Query<&Interaction, (Changed<Interaction>, With<ResetProfileButton>)>,
+        mut requests: EventWriter<confirm::ConfirmRequested>,
+    ) {
+        if interaction_query.iter().any(|interaction| *interaction == Interaction::Pressed) {
+            requests.send(confirm::ConfirmRequested(confirm::ConfirmAction::ResetProfile));
+        }
+    }
+
+    fn reset_profile(
+        mut commands: Commands,
+        mut confirmed: EventReader<confirm::Confirmed>,
+        mut history: ResMut<history::RunHistory>,
+        mut wardrobe: ResMut<cosmetics::Wardrobe>,
+        screen_query: Query<Entity, With<StatsScreen>>,
+        mut reset: EventWriter<ProfileReset>,
+    ) {
+        if !confirmed.read().any(|confirmed| confirmed.0 == confirm::ConfirmAction::ResetProfile) {
+            return;
+        }
+        *history = history::RunHistory::default();
+        history.save();
+        *wardrobe = cosmetics::Wardrobe::default();
+        wardrobe.save();
+        for entity in screen_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        reset.send(ProfileReset);
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_csv_field_escaping() {
+            assert_eq!(csv_field("More Blades;Shotgun"), "More Blades;Shotgun");
+            assert_eq!(csv_field("a,b"), "\"a,b\"");
+            assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        }
+    }
+}
+
+mod diagnostics {
+    use super::*;
+    use bevy::{
+        input::common_conditions::input_just_pressed,
+        log::{
+            tracing_subscriber::{layer::Context, layer::SubscriberExt, Layer},
+            BoxedSubscriber,
+        },
+        utils::tracing::{
+            field::{Field, Visit},
+            Event, Subscriber,
+        },
+    };
+    use std::{
+        collections::VecDeque,
+        fmt::Write as _,
+        fs,
+        path::Path,
+        sync::Mutex,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+
+    const DUMP_KEY: KeyCode = KeyCode::F8;
+    const RECENT_LOG_LINES: usize = 200;
+    const RECENT_SPAWNS: usize = 20;
+
+    /// The last `RECENT_LOG_LINES` log lines, kept by `RecentLogLayer` for the dump.
+    static RECENT_LOGS: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+    /// F8 writes a snapshot of the game's state to a text file in `exports/` for
+    /// attaching to bug reports, and says where on screen.
+    pub struct DiagnosticsPlugin;
+
+    impl Plugin for DiagnosticsPlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<SpawnLog>()
+                .add_systems(
+                    Update,
+                    (record_spawns, dump_diagnostics.run_if(input_just_pressed(DUMP_KEY))),
+                )
+                .add_systems(ResetRun, clear_spawn_log);
+        }
+    }
+
+    /// Enemies spawned this run, and where the last few appeared, so a dump taken
+    /// when something pops up on top of the player says what and where.
+    #[derive(Resource, Default)]
+    struct SpawnLog {
+        total: u32,
+        recent: VecDeque<(Entity, Vec3)>,
+    }
+
+    fn record_spawns(mut events: EventReader<enemy::EnemySpawned>, mut log: ResMut<SpawnLog>) {
+        for event in events.read() {
+            log.total += 1;
+            if log.recent.len() >= RECENT_SPAWNS {
+                log.recent.pop_front();
+            }
+            log.recent.push_back((event.entity, event.position));
+        }
+    }
+
+    fn clear_spawn_log(mut log: ResMut<SpawnLog>) {
+        *log = SpawnLog::default();
+    }
+
+    /// Hooked into `LogPlugin` to keep a copy of recent log lines.
+    pub fn capture_recent_logs(subscriber: BoxedSubscriber) -> BoxedSubscriber {
+        Box::new(subscriber.with(RecentLogLayer))
+    }
+
+    struct RecentLogLayer;
+
+    impl<S: Subscriber> Layer<S> for RecentLogLayer {
+        fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+            let metadata = event.metadata();
+            let mut line = format!("{} {}:", metadata.level(), metadata.target());
+            event.record(&mut LineVisitor(&mut line));
+            if let Ok(mut logs) = RECENT_LOGS.lock() {
+                if logs.len() >= RECENT_LOG_LINES {
+                    logs.pop_front();
+                }
+                logs.push_back(line);
+            }
+        }
+    }
+
+    /// Appends an event's message and fields to a log line.
+    struct LineVisitor<'a>(&'a mut String);
+
+    impl Visit for LineVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                let _ = write!(self.0, " {:?}", value);
+            } else {
+                let _ = write!(self.0, " {}={:?}", field.name(), value);
+            }
+        }
+    }
+
+    fn dump_diagnostics(world: &mut World) {
+        let report = report(world);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let path = Path::new(stats::EXPORT_DIR).join(format!("diagnostics-{}.txt", timestamp));
+        let toast = match fs::create_dir_all(stats::EXPORT_DIR).and_then(|_| fs::write(&path, report)) {
+            Ok(()) => {
+                info!("Wrote diagnostics to {}", path.display());
+                toasts::ToastEvent::new(toasts::ToastKind::Saved, format!("Diagnostics saved to {}", path.display()))
+            }
+            Err(err) => {
+                warn!("Failed to write diagnostics to {}: {}", path.display(), err);
+                toasts::ToastEvent::new(toasts::ToastKind::Error, format!("Couldn't save diagnostics: {}", err))
+            }
+        };
+        world.send_event(toast);
+    }
+
+    /// Everything a bug report needs that isn't in the player's description of it.
+    fn report(world: &mut World) -> String {
+        let mut report = String::new();
+        let _ = writeln!(report, "Swarm Heaven {}", build_info::label());
+        let _ = writeln!(report, "State: {:?}", world.resource::<State<GameState>>().get());
+        let _ = writeln!(report, "Seed: {}", world.resource::<run::CurrentRun>().seed);
+
+        let _ = writeln!(report, "\n[Entities]");
+        let _ = writeln!(report, "Total: {}", world.entities().len());
+        let _ = writeln!(report, "Enemies: {}", count::<With<enemy::Enemy>>(world));
+        let _ = writeln!(report, "Projectiles: {}", count::<With<combat::Projectile>>(world));
+        let _ = writeln!(report, "XP gems: {}", count::<With<leveling::XpGem>>(world));
+        let _ = writeln!(report, "Hibernating: {}", count::<With<hibernation::Hibernating>>(world));
+        let spawn_log = world.resource::<SpawnLog>();
+        let _ = writeln!(report, "Enemies spawned this run: {}", spawn_log.total);
+
+        let _ = writeln!(report, "\n[Recent spawns]");
+        for (entity, position) in &spawn_log.recent {
+            let _ = writeln!(report, "{:?} at ({:.0}, {:.0})", entity, position.x, position.y);
+        }
+
+        let _ = writeln!(report, "\n[Resources]");
+        let _ = writeln!(report, "{:#?}", world.resource::<run::CurrentRun>());
+        let _ = writeln!(report, "{:#?}", world.resource::<leveling::PlayerStats>());
+        let _ = writeln!(report, "{:#?}", world.resource::<combat::WeaponStats>());
+        let _ = writeln!(report, "{:#?}", world.resource::<attributes::EffectiveStats>());
+        let _ = writeln!(report, "{:#?}", world.resource::<drops::Purse>());
+        let _ = writeln!(report, "{:#?}", world.resource::<game_speed::GameSpeed>());
+        let _ = writeln!(report, "{:#?}", world.resource::<budget::FrameBudget>());
+
+        let _ = writeln!(report, "\n[Settings]");
+        let settings = ron::ser::to_string_pretty(world.resource::<settings::Settings>(), Default::default());
+        let _ = writeln!(report, "{}", settings.unwrap_or_else(|err| err.to_string()));
+
+        let _ = writeln!(report, "\n[Recent log]");
+        for line in recent_logs() {
+            let _ = writeln!(report, "{}", line);
+        }
+        report
+    }
+
+    /// A copy of the captured log lines, oldest first. Empty if the buffer is busy, which
+    /// only matters to the panic hook: it may run while the panicking thread holds it.
+    pub fn recent_logs() -> Vec<String> {
+        RECENT_LOGS
+            .try_lock()
+            .map(|logs| logs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn count<F: bevy::ecs::query::QueryFilter>(world: &mut World) -> usize {
+        world.query_filtered::<(), F>().iter(world).count()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use bevy::{log::tracing_subscriber::Registry, utils::tracing::subscriber::with_default};
+
+        #[test]
+        fn test_recent_logs_keep_the_last_lines_with_their_fields() {
+            with_default(Registry::default().with(RecentLogLayer), || {
+                for wave in 0..RECENT_LOG_LINES + 5 {
+                    warn!(wave, "Spawned wave");
+                }
+            });
+            let logs = RECENT_LOGS.lock().unwrap();
+            assert_eq!(logs.len(), RECENT_LOG_LINES);
+            assert!(logs.back().unwrap().ends_with("Spawned wave wave=204"), "{:?}", logs.back());
+            assert!(logs.front().unwrap().starts_with("WARN "));
+        }
+
+        #[test]
+        fn test_spawn_log_counts_every_spawn_and_keeps_the_last_few() {
+            let mut app = App::new();
+            app.add_event::<enemy::EnemySpawned>()
+               .init_resource::<SpawnLog>()
+               .add_systems(Update, record_spawns);
+
+            for i in 0..RECENT_SPAWNS + 3 {
+                let entity = app.world.spawn_empty().id();
+                app.world.send_event(enemy::EnemySpawned { entity, position: Vec3::X * i as f32 });
+            }
+            app.update();
+
+            let log = app.world.resource::<SpawnLog>();
+            assert_eq!(log.total, RECENT_SPAWNS as u32 + 3);
+            assert_eq!(log.recent.len(), RECENT_SPAWNS);
+            assert_eq!(log.recent.front().unwrap().1, Vec3::X * 3.0);
+        }
+    }
+}
+
+mod crash {
+    use super::*;
+    use bevy::time::common_conditions::on_real_timer;
+    use std::{
+        backtrace::Backtrace,
+        fmt::Write as _,
+        fs,
+        panic::PanicHookInfo,
+        path::Path,
+        sync::Mutex,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    /// What the game was doing as of the last second, for the panic hook to write out;
+    /// by the time it runs the world can't be looked at.
+    static CRASH_CONTEXT: Mutex<String> = Mutex::new(String::new());
+
+    /// Keeps `CRASH_CONTEXT` current. The hook itself is installed from `main`.
+    pub struct CrashPlugin;
+
+    impl Plugin for CrashPlugin {
+        fn build(&self, app: &mut App) {
+            app.add_systems(Update, record_crash_context.run_if(on_real_timer(Duration::from_secs(1))));
+        }
+    }
+
+    fn record_crash_context(
+        state: Res<State<GameState>>,
+        current_run: Res<run::CurrentRun>,
+        enemy_query: Query<(), With<enemy::Enemy>>,
+        budget: Res<budget::FrameBudget>,
+    ) {
+        if let Ok(mut context) = CRASH_CONTEXT.lock() {
+            *context = format!(
+                "State: {:?}\nSeed: {}\nRun time: {:.1}s\nKills: {}\nEnemies: {}\nFidelity: {:?}",
+                state.get(),
+                current_run.seed,
+                current_run.elapsed,
+                current_run.kills,
+                enemy_query.iter().count(),
+                budget.fidelity,
+            );
+        }
+    }
+
+    /// Writes every panic, with the last known game state and recent log lines, to a
+    /// crash log in `exports/`, then tells the player where it is instead of the window
+    /// just vanishing. The default hook still runs first, so the terminal sees it too.
+    pub fn install_panic_hook() {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+            let path = write_crash_log(info);
+            let message = match &path {
+                Some(path) => format!(
+                    "The swarm won (crash).\n\nA crash log was saved to {}.\nPlease attach it to a bug report.",
+                    path
+                ),
+                None => "The swarm won (crash).\n\nThe crash log couldn't be saved.".to_string(),
+            };
+            show_crash_message(&message);
+        }));
+    }
+
+    fn write_crash_log(info: &PanicHookInfo) -> Option<String> {
+        let mut log = String::new();
+        let _ = writeln!(log, "Swarm Heaven {} crashed", build_info::label());
+        let _ = writeln!(log, "{}", info);
+        let _ = writeln!(log, "\n[Backtrace]\n{}", Backtrace::force_capture());
+        let context = CRASH_CONTEXT.try_lock().map(|context| context.clone()).unwrap_or_default();
+        let _ = writeln!(log, "\n[Last known state]\n{}", context);
+        let _ = writeln!(log, "\n[Recent log]");
+        for line in diagnostics::recent_logs() {
+            let _ = writeln!(log, "{}", line);
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        let path = Path::new(stats::EXPORT_DIR).join(format!("crash-{}.txt", timestamp));
+        fs::create_dir_all(stats::EXPORT_DIR).and_then(|_| fs::write(&path, log)).ok()?;
+        Some(path.display().to_string())
+    }
+
+    #[cfg(windows)]
+    fn show_crash_message(message: &str) {
+        use std::ffi::c_void;
+
+        #[link(name = "user32")]
+        extern "system" {
+            fn MessageBoxW(window: *mut c_void, text: *const u16, caption: *const u16, kind: u32) -> i32;
+        }
+        const MB_ICONERROR: u32 = 0x10;
+
+        let wide = |text: &str| text.encode_utf16().chain(Some(0)).collect::<Vec<u16>>();
+        let (text, caption) = (wide(message), wide("Swarm Heaven"));
+        // SAFETY: both strings are NUL-terminated UTF-16 that outlive the call, and a null
+        // owner window is allowed.
+        unsafe {
+            MessageBoxW(std::ptr::null_mut(), text.as_ptr(), caption.as_ptr(), MB_ICONERROR);
+        }
+    }
+
+    /// Elsewhere the game is usually started from a terminal, which keeps the message.
+    #[cfg(not(windows))]
+    fn show_crash_message(message: &str) {
+        eprintln!("\n{}", message);
+    }
+}
+
+mod stress {
+    use super::*;
+    use bevy::diagnostic::EntityCountDiagnosticsPlugin;
+
+    const STRESS_PLAYER_HEALTH: f32 = 1.0e9;
+    const STRESS_INNER_RADIUS: f32 = 300.0;
+    const STRESS_OUTER_RADIUS: f32 = 2000.0;
+
+    /// Rendering/simulation stress scene, started with `--stress <enemy count>`.
+    /// Skips the menu, makes the player unkillable, and spawns the whole swarm at
+    /// once so frame times and entity counts can be read from the diagnostics log.
+    pub struct StressPlugin {
+        pub enemy_count: u32,
+    }
+
+    impl Plugin for StressPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(StressConfig { enemy_count: self.enemy_count })
+                .add_plugins(EntityCountDiagnosticsPlugin)
+                .add_systems(Startup, skip_main_menu)
+                .add_systems(OnEnter(GameState::Running), spawn_stress_swarm)
+                .add_systems(Update, make_player_unkillable.run_if(in_state(GameState::Running)));
+        }
+    }
+
+    #[derive(Resource)]
+    struct StressConfig {
+        enemy_count: u32,
+    }
+
+    pub fn enemy_count_from_args() -> Option<u32> {
+        let mut args = std::env::args().skip_while(|arg| arg != "--stress").skip(1);
+        args.next().and_then(|count| count.parse().ok())
+    }
+
+    fn skip_main_menu(mut next_state: ResMut<NextState<GameState>>) {
+        next_state.set(GameState::Running);
+    }
+
+    fn spawn_stress_swarm(
+        mut commands: Commands,
+        config: Res<StressConfig>,
+        mut spawned: Local<bool>,
+    ) {
+        if *spawned {
+            return;
+        }
+        *spawned = true;
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..config.enemy_count {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            let distance = rng.gen_range(STRESS_INNER_RADIUS..STRESS_OUTER_RADIUS);
+            let position = Vec3::new(angle.cos() * distance, angle.sin() * distance, 0.0);
+            commands.spawn(enemy::enemy_bundle(position, Color::rgb(0.9, 0.2, 0.2), enemy::EnemyKind::Grunt));
+        }
+        info!("Stress scene: spawned {} enemies", config.enemy_count);
+    }
+
+    fn make_player_unkillable(mut query: Query<&mut combat::Health, Added<player::Player>>) {
+        for mut health in query.iter_mut() {
+            *health = combat::Health::new(STRESS_PLAYER_HEALTH);
+        }
+    }
+}
+
+mod relay {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use std::io::ErrorKind;
+    use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+    const DEFAULT_PORT: u16 = 7878;
+    /// Seconds between status messages, which also keep the link alive.
+    const STATUS_INTERVAL: f32 = 1.0;
+    /// A friend not heard from for this long has gone.
+    const PEER_TIMEOUT: f32 = 5.0;
+    /// Every this many kills in a streak sends the friend a burst.
+    const STREAK_KILLS: u32 = 50;
+    /// A streak ends when this many seconds pass without a kill.
+    const STREAK_GAP: f32 = 2.0;
+    const BURST_SIZE: u32 = 15;
+    /// Caps what a burst can spawn, whatever the other side sends.
+    const MAX_BURST_SIZE: u32 = 50;
+    const BURST_RADIUS: f32 = 700.0;
+    const NOTICE_DURATION: f32 = 3.0;
+
+    /// Horde relay, started with `--relay-host [port]` or `--relay-join <address>`.
+    /// Two players each play their own run; every kill streak sends a burst of enemies
+    /// into the other's game. The two games talk over UDP, polled once a frame.
+    pub struct RelayPlugin {
+        pub role: Role,
+    }
+
+    #[derive(Clone, Debug)]
+    pub enum Role {
+        Host(u16),
+        Join(String),
+    }
+
+    pub fn role_from_args() -> Option<Role> {
+        let mut args = std::env::args().skip_while(|arg| arg != "--relay-host" && arg != "--relay-join");
+        match args.next()?.as_str() {
+            "--relay-host" => Some(Role::Host(args.next().and_then(|port| port.parse().ok()).unwrap_or(DEFAULT_PORT))),
+            _ => {
+                let address = args.next()?;
+                // A bare host joins on the default port.
+                if address.contains(':') {
+                    Some(Role::Join(address))
+                } else {
+                    Some(Role::Join(format!("{}:{}", address, DEFAULT_PORT)))
+                }
+            }
+        }
+    }
+
+    impl Plugin for RelayPlugin {
+        fn build(&self, app: &mut App) {
+            let relay = match Relay::open(&self.role) {
+                Ok(relay) => relay,
+                Err(err) => {
+                    warn!("Couldn't start the horde relay: {}", err);
+                    return;
+                }
+            };
+            app.insert_resource(relay)
+                .add_event::<BurstReceived>()
+                .add_systems(Startup, spawn_presence_text)
+                .add_systems(Update, (receive_messages, send_status, update_presence_text).chain())
+                .add_systems(
+                    Update,
+                    (track_streak.after(combat::CombatSet::Death), spawn_bursts).run_if(in_state(GameState::Running)),
+                );
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    struct FriendStatus {
+        playing: bool,
+        elapsed: f32,
+        level: u32,
+        kills: u32,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+    enum Message {
+        Status(FriendStatus),
+        Burst { size: u32 },
+    }
+
+    #[derive(Event)]
+    struct BurstReceived {
+        size: u32,
+    }
+
+    #[derive(Resource)]
+    struct Relay {
+        socket: UdpSocket,
+        /// Where the friend is. A joining game knows from the start; the host learns it
+        /// from the first message that arrives.
+        peer: Option<SocketAddr>,
+        /// The port a host is waiting on, to tell the player.
+        port: u16,
+        /// Real time the friend was last heard from.
+        last_heard: Option<f32>,
+        friend: Option<FriendStatus>,
+        status_timer: Timer,
+        /// A line about the last burst sent or received, and the real time it expires.
+        notice: Option<(String, f32)>,
+    }
+
+    impl Relay {
+        fn open(role: &Role) -> std::io::Result<Self> {
+            let (socket, peer) = match role {
+                Role::Host(port) => (UdpSocket::bind(("0.0.0.0", *port))?, None),
+                Role::Join(address) => {
+                    let peer = address
+                        .to_socket_addrs()?
+                        .next()
+                        .ok_or_else(|| std::io::Error::new(ErrorKind::NotFound, format!("no address for {}", address)))?;
+                    (UdpSocket::bind(("0.0.0.0", 0))?, Some(peer))
+                }
+            };
+            socket.set_nonblocking(true)?;
+            let port = socket.local_addr()?.port();
+            Ok(Self {
+                socket,
+                peer,
+                port,
+                last_heard: None,
+                friend: None,
+                status_timer: Timer::from_seconds(STATUS_INTERVAL, TimerMode::Repeating),
+                notice: None,
+            })
+        }
+
+        fn send(&self, message: &Message) {
+            let Some(peer) = self.peer else {
+                return;
+            };
+            let Ok(bytes) = serde_json::to_vec(message) else {
+                return;
+            };
+            // A lost datagram only costs one status update or one burst.
+            if let Err(err) = self.socket.send_to(&bytes, peer) {
+                debug!("Relay send to {} failed: {}", peer, err);
+            }
+        }
+
+        fn connected(&self, now: f32) -> bool {
+            self.last_heard.is_some_and(|heard| now - heard < PEER_TIMEOUT)
+        }
+    }
+
+    /// Kills in a row, none more than `STREAK_GAP` seconds after the one before.
+    #[derive(Default)]
+    struct Streak {
+        kills: u32,
+        last_kill: f32,
+    }
+
+    impl Streak {
+        /// Adds `kills` made at `now`, returning how many bursts they earned.
+        fn record(&mut self, kills: u32, now: f32) -> u32 {
+            if kills == 0 {
+                return 0;
+            }
+            if now - self.last_kill > STREAK_GAP {
+                self.kills = 0;
+            }
+            self.last_kill = now;
+            let before = self.kills / STREAK_KILLS;
+            self.kills += kills;
+            self.kills / STREAK_KILLS - before
+        }
+    }
+
+    fn receive_messages(
+        mut relay: ResMut<Relay>,
+        real_time: Res<Time<Real>>,
+        mut burst_events: EventWriter<BurstReceived>,
+    ) {
+        let mut buffer = [0u8; 512];
+        loop {
+            let (len, from) = match relay.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // Windows reports an unreachable peer on the next receive; keep polling.
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    warn!("Relay receive failed: {}", err);
+                    break;
+                }
+            };
+            if *relay.peer.get_or_insert(from) != from {
+                continue;
+            }
+            let Ok(message) = serde_json::from_slice::<Message>(&buffer[..len]) else {
+                continue;
+            };
+            relay.last_heard = Some(real_time.elapsed_seconds());
+            match message {
+                Message::Status(status) => relay.friend = Some(status),
+                Message::Burst { size } => {
+                    let size = size.min(MAX_BURST_SIZE);
+                    burst_events.send(BurstReceived { size });
+                    let expires = real_time.elapsed_seconds() + NOTICE_DURATION;
+                    relay.notice = Some((format!("Your friend sent {} enemies your way!", size), expires));
+                }
+            }
+        }
+    }
+
+    fn send_status(
+        mut relay: ResMut<Relay>,
+        real_time: Res<Time<Real>>,
+        state: Res<State<GameState>>,
+        current_run: Res<run::CurrentRun>,
+        player_stats: Res<leveling::PlayerStats>,
+    ) {
+        if !relay.status_timer.tick(real_time.delta()).just_finished() {
+            return;
+        }
+        let playing = matches!(state.get(), GameState::Running | GameState::Paused | GameState::Dying);
+        relay.send(&Message::Status(FriendStatus {
+            playing,
+            elapsed: current_run.elapsed,
+            level: player_stats.level,
+            kills: current_run.kills,
+        }));
+    }
+
+    fn track_streak(
+        mut relay: ResMut<Relay>,
+        mut streak: Local<Streak>,
+        mut death_events: EventReader<enemy::EnemyDied>,
+        time: Res<Time>,
+        real_time: Res<Time<Real>>,
+    ) {
+        let bursts = streak.record(death_events.read().count() as u32, time.elapsed_seconds());
+        if bursts == 0 || !relay.connected(real_time.elapsed_seconds()) {
+            return;
+        }
+        for _ in 0..bursts {
+            relay.send(&Message::Burst { size: BURST_SIZE });
+        }
+        let expires = real_time.elapsed_seconds() + NOTICE_DURATION;
+        relay.notice = Some((format!("Streak! Sent {} enemies to your friend", BURST_SIZE * bursts), expires));
+    }
+
+    fn spawn_bursts(
+        mut commands: Commands,
+        mut burst_events: EventReader<BurstReceived>,
+        settings: Res<settings::Settings>,
+        player_query: Query<&Transform, With<player::Player>>,
+    ) {
+        let Ok(player_transform) = player_query.get_single() else {
+            return;
+        };
+        let player_position = player_transform.translation;
+        let mut rng = rand::thread_rng();
+        for burst in burst_events.read() {
+            for _ in 0..burst.size {
+                let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                let position = player_position + Vec3::new(angle.cos(), angle.sin(), 0.0) * BURST_RADIUS;
+                let position = enemy::outside_safe_zone(position, player_position, settings.gameplay.spawn_safe_radius);
+                commands.spawn(enemy::enemy_bundle(position, Color::rgb(1.6, 0.3, 1.2), enemy::EnemyKind::Grunt));
+            }
+        }
+    }
+
+    #[derive(Component)]
+    struct PresenceText;
+
+    fn spawn_presence_text(mut commands: Commands) {
+        commands.spawn((
+            TextBundle::from_section("", TextStyle { font_size: 18.0, color: Color::rgb(1.0, 0.6, 0.9), ..default() })
+                .with_style(Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(10.0),
+                    left: Val::Px(10.0),
+                    ..default()
+                }),
+            PresenceText,
+        ));
+    }
+
+    fn update_presence_text(
+        relay: Res<Relay>,
+        real_time: Res<Time<Real>>,
+        mut text_query: Query<&mut Text, With<PresenceText>>,
+    ) {
+        let now = real_time.elapsed_seconds();
+        let status = match (relay.peer, relay.friend) {
+            (None, _) => format!("Relay: waiting for a friend on port {}", relay.port),
+            (Some(peer), _) if relay.last_heard.is_none() => format!("Relay: connecting to {}", peer),
+            _ if !relay.connected(now) => "Relay: friend disconnected".to_string(),
+            (_, Some(friend)) if friend.playing => format!(
+                "Friend: {}  -  Level {}  -  {} kills",
+                run::format_duration(friend.elapsed),
+                friend.level,
+                friend.kills,
+            ),
+            _ => "Friend: in the menus".to_string(),
+        };
+        let value = match &relay.notice {
+            Some((notice, expires)) if now < *expires => format!("{}\n{}", notice, status),
+            _ => status,
+        };
+        for mut text in text_query.iter_mut() {
+            if text.sections[0].value != value {
+                text.sections[0].value.clone_from(&value);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_streak_sends_a_burst_every_so_many_kills_in_a_row() {
+            let mut streak = Streak::default();
+            assert_eq!(streak.record(STREAK_KILLS - 1, 1.0), 0);
+            assert_eq!(streak.record(1, 2.0), 1);
+            // Too long a gap starts the count over.
+            assert_eq!(streak.record(STREAK_KILLS - 1, 2.0 + STREAK_GAP + 0.1), 0);
+            assert_eq!(streak.record(STREAK_KILLS * 2, 5.0), 2);
+        }
+    }
+}
+
+mod simulate {
+    use super::*;
+    use bevy::{app::AppExit, diagnostic::DiagnosticsPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    /// Game time advanced per frame; headless frames take far less than this to simulate.
+    const SIM_TIMESTEP: f32 = 1.0 / 30.0;
+    /// A run that lasts this long counts as survived and is ended there.
+    const SIM_TIME_LIMIT: f32 = 15.0 * 60.0;
+
+    /// Balance simulation, started with `--simulate <runs>`. Plays the given number of
+    /// bot runs headlessly on a fixed timestep, then prints aggregate results.
+    pub struct SimulatePlugin {
+        pub runs: u32,
+    }
+
+    impl Plugin for SimulatePlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(Simulation { target_runs: self.runs, results: Vec::new() })
+                .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(SIM_TIMESTEP)))
+                .insert_resource(budget::FrameBudget::fixed())
+                .add_systems(Startup, start_run)
+                .add_systems(Update, (tag_bot_player, end_at_time_limit).run_if(in_state(GameState::Running)))
+                .add_systems(OnEnter(GameState::GameOver), record_run);
+        }
+    }
+
+    pub fn runs_from_args() -> Option<u32> {
+        let mut args = std::env::args().skip_while(|arg| arg != "--simulate").skip(1);
+        args.next().and_then(|count| count.parse().ok())
+    }
+
+    /// Builds a windowless app with the gameplay plugins and runs it until every
+    /// simulated run has finished. Run history and lifetime stats are left untouched.
+    pub fn run(runs: u32) {
+        App::new()
+            .add_plugins((
+                MinimalPlugins,
+                AssetPlugin::default(),
+                bevy::input::InputPlugin,
+                DiagnosticsPlugin,
+                FrameTimeDiagnosticsPlugin,
+                bevy::transform::TransformPlugin,
+                bevy::hierarchy::HierarchyPlugin,
+            ))
+            .init_asset::<Mesh>()
+            .init_asset::<ColorMaterial>()
+            .init_asset::<Image>()
+            .init_asset::<Font>()
+            .init_asset::<TextureAtlasLayout>()
+            .init_state::<GameState>()
+            .init_resource::<post_process::ScreenEffects>()
+            .add_plugins(GameplayPlugins)
+            .add_plugins(SimulatePlugin { runs })
+            .run();
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct RunResult {
+        pub survived_for: f32,
+        pub kills: u32,
+        pub build: Vec<ui::Upgrade>,
+        pub won: bool,
+    }
+
+    #[derive(Resource)]
+    struct Simulation {
+        target_runs: u32,
+        results: Vec<RunResult>,
+    }
+
+    /// How often an upgrade was picked across all runs and how those runs went.
+    #[derive(Debug, PartialEq)]
+    pub struct UpgradeReport {
+        pub upgrade: ui::Upgrade,
+        pub runs: u32,
+        pub wins: u32,
+        pub mean_survival: f32,
+    }
+
+    impl UpgradeReport {
+        pub fn win_rate(&self) -> f32 {
+            self.wins as f32 / self.runs as f32
+        }
+    }
+
+    /// Per-upgrade results, counting each run once however many copies it took,
+    /// sorted by win rate and then by mean survival time.
+    pub fn upgrade_reports(results: &[RunResult]) -> Vec<UpgradeReport> {
+        let mut reports: Vec<UpgradeReport> = Vec::new();
+        for result in results {
+            let mut seen: Vec<ui::Upgrade> = Vec::new();
+            for &upgrade in &result.build {
+                if seen.contains(&upgrade) {
+                    continue;
+                }
+                seen.push(upgrade);
+                let index = match reports.iter().position(|report| report.upgrade == upgrade) {
+                    Some(index) => index,
+                    None => {
+                        reports.push(UpgradeReport { upgrade, runs: 0, wins: 0, mean_survival: 0.0 });
+                        reports.len() - 1
+                    }
+                };
+                let report = &mut reports[index];
+                report.runs += 1;
+                report.wins += result.won as u32;
+                // Running mean, so no second pass is needed.
+                report.mean_survival += (result.survived_for - report.mean_survival) / report.runs as f32;
+            }
+        }
+        reports.sort_by(|a, b| {
+            b.win_rate()
+                .total_cmp(&a.win_rate())
+                .then(b.mean_survival.total_cmp(&a.mean_survival))
+        });
+        reports
+    }
+
+    fn median(values: &mut [f32]) -> f32 {
+        if values.is_empty() {
+            return 0.0;
+        }
+        values.sort_by(f32::total_cmp);
+        let middle = values.len() / 2;
+        if values.len().is_multiple_of(2) {
+            (values[middle - 1] + values[middle]) / 2.0
+        } else {
+            values[middle]
+        }
+    }
+
+    fn start_run(mut next_state: ResMut<NextState<GameState>>) {
+        next_state.set(GameState::Running);
+    }
+
+    fn tag_bot_player(mut commands: Commands, query: Query<Entity, Added<player::Player>>) {
+        for entity in query.iter() {
+            commands.entity(entity).insert(bot::Bot);
+        }
+    }
+
+    fn end_at_time_limit(
+        current_run: Res<run::CurrentRun>,
+        mut next_state: ResMut<NextState<GameState>>,
+    ) {
+        if current_run.elapsed >= SIM_TIME_LIMIT {
+            next_state.set(GameState::GameOver);
+        }
+    }
+
+    fn record_run(
+        current_run: Res<run::CurrentRun>,
+        mut simulation: ResMut<Simulation>,
+        mut next_state: ResMut<NextState<GameState>>,
+        mut exit: EventWriter<AppExit>,
+    ) {
+        simulation.results.push(RunResult {
+            survived_for: current_run.elapsed,
+            kills: current_run.kills,
+            build: current_run.build.clone(),
+            won: current_run.death_cause.is_none(),
+        });
+        println!(
+            "Run {}/{}: {} survived, {} kills, {} upgrades",
+            simulation.results.len(),
+            simulation.target_runs,
+            run::format_duration(current_run.elapsed),
+            current_run.kills,
+            current_run.build.len(),
+        );
+
+        if simulation.results.len() as u32 >= simulation.target_runs {
+            print_summary(&simulation.results);
+            exit.send(AppExit);
+        } else {
+            // Leaving GameOver resets the world, and entering Running starts a fresh run.
+            next_state.set(GameState::Running);
+        }
+    }
+
+    fn print_summary(results: &[RunResult]) {
+        let runs = results.len().max(1) as f32;
+        let mut survival: Vec<f32> = results.iter().map(|result| result.survived_for).collect();
+        let mut kills: Vec<f32> = results.iter().map(|result| result.kills as f32).collect();
+        let wins = results.iter().filter(|result| result.won).count();
+
+        println!();
+        println!("{} runs, {} survived to {}", results.len(), wins, run::format_duration(SIM_TIME_LIMIT));
+        println!(
+            "Survival: mean {}, median {}",
+            run::format_duration(survival.iter().sum::<f32>() / runs),
+            run::format_duration(median(&mut survival)),
+        );
+        println!(
+            "Kills: mean {:.0}, median {:.0}",
+            kills.iter().sum::<f32>() / runs,
+            median(&mut kills),
+        );
+        println!();
+        println!("{:<28} {:>6} {:>8} {:>10}", "Upgrade", "Runs", "Win rate", "Survival");
+        for report in upgrade_reports(results) {
+            println!(
+                "{:<28} {:>6} {:>7.0}% {:>10}",
+                report.upgrade.label(),
+                report.runs,
+                report.win_rate() * 100.0,
+                run::format_duration(report.mean_survival),
+            );
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn result(survived_for: f32, won: bool, build: Vec<ui::Upgrade>) -> RunResult {
+            RunResult { survived_for, kills: 0, build, won }
+        }
+
+        #[test]
+        fn test_upgrade_reports_count_each_run_once() {
+            let results = vec![
+                result(900.0, true, vec![ui::Upgrade::Multishot, ui::Upgrade::Multishot]),
+                result(300.0, false, vec![ui::Upgrade::Multishot, ui::Upgrade::Whip]),
+            ];
+            let reports = upgrade_reports(&results);
+
+            assert_eq!(reports.len(), 2);
+            assert_eq!(reports[0].upgrade, ui::Upgrade::Multishot);
+            assert_eq!(reports[0].runs, 2);
+            assert_eq!(reports[0].wins, 1);
+            assert_eq!(reports[0].mean_survival, 600.0);
+            assert_eq!(reports[1].upgrade, ui::Upgrade::Whip);
+            assert_eq!(reports[1].win_rate(), 0.0);
+        }
+    }
+}
+
+mod build_info {
+    /// From Cargo.toml.
+    pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+    /// Short hash of the commit the binary was built from, or "unknown" outside a checkout.
+    pub const GIT_HASH: &str = env!("SWARM_HEAVEN_GIT_HASH");
+
+    /// e.g. "v0.1.0 (3701acc)", shown on the main menu and recorded with every run,
+    /// so bug reports and exported runs can be matched to a build.
+    pub fn label() -> String {
+        format!("v{} ({})", VERSION, GIT_HASH)
+    }
+}
+
+mod persistence {
+    use super::*;
+    use serde::{de::DeserializeOwned, Serialize};
+    use std::{fs, io::Write, path::Path};
+
+    /// Reads a RON file, falling back to defaults when it's missing or unreadable.
+    pub fn load_ron<T: DeserializeOwned + Default>(path: &str) -> T {
+        match fs::read_to_string(path) {
+            Ok(contents) => ron::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring unreadable {}: {}", path, err);
+                T::default()
+            }),
+            Err(_) => T::default(),
+        }
+    }
+
+    pub fn save_ron<T: Serialize>(path: &str, value: &T) {
+        let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| write_atomically(Path::new(path), contents.as_bytes()).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("Failed to save {}: {}", path, err);
+        }
+    }
+
+    /// Writes to a temporary file next to `path` and renames it over the original, so a
+    /// crash or power cut mid-write leaves the previous save intact instead of a truncated one.
+    fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = Path::new(&temp);
+        {
+            let mut file = fs::File::create(temp)?;
+            file.write_all(contents)?;
+            file.sync_all()?;
+        }
+        fs::rename(temp, path)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_save_ron_replaces_the_file_without_leaving_a_temp() {
+            let dir = std::env::temp_dir().join(format!("swarm_heaven_save_{}", std::process::id()));
+            let path = dir.join("saves/values.ron");
+            let path_str = path.to_str().unwrap();
+
+            save_ron(path_str, &vec![1u32, 2]);
+            save_ron(path_str, &vec![3u32]);
+            assert_eq!(load_ron::<Vec<u32>>(path_str), vec![3]);
+            assert!(!dir.join("saves/values.ron.tmp").exists());
+
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
+
+mod settings {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    const SETTINGS_PATH: &str = "saves/settings.ron";
+
+    pub struct SettingsPlugin;
+
+    impl Plugin for SettingsPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(Settings::load())
+                .add_systems(OnEnter(GameState::Settings), setup_settings_screen)
+                .add_systems(
+                    Update,
+                    (
+                        settings_input,
+                        handle_setting_buttons,
+                        handle_difficulty_button,
+                        handle_particle_density_button,
+                        handle_frame_cap_button,
+                        handle_stepper_buttons,
+                    )
+                        .run_if(in_state(GameState::Settings)),
+                )
+                // The headless simulation has no UI, and so no `UiScale`.
+                .add_systems(Update, (apply_ui_scale, apply_safe_area).run_if(resource_exists::<UiScale>));
+        }
+    }
+
+    /// Player preferences, persisted to `SETTINGS_PATH`. Missing fields fall back
+    /// to their defaults so older files keep loading as options are added.
+    #[derive(Resource, Serialize, Deserialize, Default, Clone, Debug)]
+    #[serde(default)]
+    pub struct Settings {
+        pub graphics: GraphicsSettings,
+        pub gameplay: GameplaySettings,
+        pub controls: ControlSettings,
+    }
+
+    /// Input tuning. Only rumble has a control on the options screen; the rest is
+    /// edited in the settings file.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(default)]
+    pub struct ControlSettings {
+        /// Fraction of the left stick's travel ignored around the centre.
+        pub stick_dead_zone: f32,
+        /// Seconds of play a dash or taunt press is held for before it's dropped.
+        pub buffer_window: f32,
+        /// Gamepad rumble strength, from 0 (off) to 1.
+        pub rumble_intensity: f32,
+    }
+
+    impl Default for ControlSettings {
+        fn default() -> Self {
+            Self {
+                stick_dead_zone: 0.2,
+                buffer_window: 0.15,
+                rumble_intensity: 1.0,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub enum Difficulty {
+        Easy,
+        #[default]
+        Normal,
+        Hard,
+    }
+
+    impl Difficulty {
+        fn label(&self) -> &'static str {
+            match self {
+                Difficulty::Easy => "Easy",
+                Difficulty::Normal => "Normal",
+                Difficulty::Hard => "Hard",
+            }
+        }
+
+        fn next(&self) -> Self {
+            match self {
+                Difficulty::Easy => Difficulty::Normal,
+                Difficulty::Normal => Difficulty::Hard,
+                Difficulty::Hard => Difficulty::Easy,
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(default)]
+    pub struct GameplaySettings {
+        pub difficulty: Difficulty,
+        pub xp_curves: XpCurves,
+        /// Nothing spawns closer to the player than this; edited in the settings file.
+        pub spawn_safe_radius: f32,
+        /// Challenge modifiers toggled on the Heat screen, applied to every run started.
+        pub heat: Vec<heat::HeatModifier>,
+        /// Fence the player in with each boss until it dies.
+        pub boss_arena: bool,
+        /// Draw level-up cards from the run seed, for seeded races. Daily runs always do.
+        pub seeded_drafts: bool,
+    }
+
+    impl Default for GameplaySettings {
+        fn default() -> Self {
+            Self {
+                difficulty: Difficulty::default(),
+                xp_curves: XpCurves::default(),
+                spawn_safe_radius: 450.0,
+                heat: Vec::new(),
+                boss_arena: true,
+                seeded_drafts: false,
+            }
+        }
+    }
+
+    /// The level curve used on each difficulty. Edit the settings file to swap in
+    /// a different preset or a custom table.
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(default)]
+    pub struct XpCurves {
+        pub easy: leveling::XpCurve,
+        pub normal: leveling::XpCurve,
+        pub hard: leveling::XpCurve,
+    }
+
+    impl Default for XpCurves {
+        fn default() -> Self {
+            Self {
+                easy: leveling::XpCurve::Linear { base: 80, step: 40 },
+                normal: leveling::XpCurve::default(),
+                hard: leveling::XpCurve::Exponential {
+                    base: 120,
+                    growth: 1.6,
+                },
+            }
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    #[serde(default)]
+    pub struct GraphicsSettings {
+        pub bloom: bool,
+        pub damage_vignette: bool,
+        pub chromatic_flash: bool,
+        pub projectile_cap_warning: bool,
+        pub corpse_decals: bool,
+        pub ambient_particles: ambience::ParticleDensity,
+        /// Size of the whole interface, from 0.75 to 1.5.
+        pub ui_scale: f32,
+        /// Fraction of the screen's width and height kept clear of the HUD along each
+        /// edge, for displays that crop or round off their corners.
+        pub safe_area: f32,
+        pub frame_cap: frame_limit::FrameCap,
+        /// Update at a trickle while the window is unfocused or minimized.
+        pub background_throttle: bool,
+    }
+
+    impl Default for GraphicsSettings {
+        fn default() -> Self {
+            Self {
+                bloom: true,
+                damage_vignette: true,
+                chromatic_flash: true,
+                projectile_cap_warning: true,
+                corpse_decals: true,
+                ambient_particles: ambience::ParticleDensity::default(),
+                ui_scale: 1.0,
+                safe_area: 0.0,
+                frame_cap: frame_limit::FrameCap::default(),
+                background_throttle: true,
+            }
+        }
+    }
+
+    impl Settings {
+        pub fn load() -> Self {
+            persistence::load_ron(SETTINGS_PATH)
+        }
+
+        pub fn save(&self) {
+            persistence::save_ron(SETTINGS_PATH, self);
+        }
+
+        pub fn xp_curve(&self) -> &leveling::XpCurve {
+            let curves = &self.gameplay.xp_curves;
+            match self.gameplay.difficulty {
+                Difficulty::Easy => &curves.easy,
+                Difficulty::Normal => &curves.normal,
+                Difficulty::Hard => &curves.hard,
+            }
+        }
+    }
+
+    #[derive(Component, Clone, Copy, Debug)]
+    enum SettingToggle {
+        Bloom,
+        DamageVignette,
+        ChromaticFlash,
+        ProjectileCapWarning,
+        CorpseDecals,
+        BackgroundThrottle,
+        BossArena,
+        SeededDrafts,
+    }
+
+    impl SettingToggle {
+        const ALL: [SettingToggle; 8] = [
+            SettingToggle::Bloom,
+            SettingToggle::DamageVignette,
+            SettingToggle::ChromaticFlash,
+            SettingToggle::ProjectileCapWarning,
+            SettingToggle::CorpseDecals,
+            SettingToggle::BackgroundThrottle,
+            SettingToggle::BossArena,
+            SettingToggle::SeededDrafts,
+        ];
+
+        fn label(&self) -> &'static str {
+            match self {
+                SettingToggle::Bloom => "Bloom",
+                SettingToggle::DamageVignette => "Damage Vignette",
+                SettingToggle::ChromaticFlash => "Chromatic Flash",
+                SettingToggle::ProjectileCapWarning => "Projectile Cap Warning",
+                SettingToggle::CorpseDecals => "Corpse Decals",
+                SettingToggle::BackgroundThrottle => "Throttle In Background",
+                SettingToggle::BossArena => "Boss Arena",
+                SettingToggle::SeededDrafts => "Seeded Drafts",
+            }
+        }
+
+        fn value_mut<'a>(&self, settings: &'a mut Settings) -> &'a mut bool {
+            match self {
+                SettingToggle::Bloom => &mut settings.graphics.bloom,
+                SettingToggle::DamageVignette => &mut settings.graphics.damage_vignette,
+                SettingToggle::ChromaticFlash => &mut settings.graphics.chromatic_flash,
+                SettingToggle::ProjectileCapWarning => &mut settings.graphics.projectile_cap_warning,
+                SettingToggle::CorpseDecals => &mut settings.graphics.corpse_decals,
+                SettingToggle::BackgroundThrottle => &mut settings.graphics.background_throttle,
+                SettingToggle::BossArena => &mut settings.gameplay.boss_arena,
+                SettingToggle::SeededDrafts => &mut settings.gameplay.seeded_drafts,
+            }
+        }
+
+        fn button_text(&self, settings: &mut Settings) -> String {
+            let state = if *self.value_mut(settings) { "On" } else { "Off" };
+            format!("{}: {}", self.label(), state)
+        }
+    }
+
+    #[derive(Component)]
+    struct SettingsScreen;
+
+    #[derive(Component)]
+    struct DifficultyButton;
+
+    #[derive(Component)]
+    struct ParticleDensityButton;
+
+    #[derive(Component)]
+    struct FrameCapButton;
+
+    /// A number on the options screen, nudged up and down by - / + buttons either side
+    /// of its readout.
+    #[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+    enum Stepper {
+        Rumble,
+        UiScale,
+        SafeArea,
+    }
+
+    impl Stepper {
+        const ALL: [Stepper; 3] = [Stepper::Rumble, Stepper::UiScale, Stepper::SafeArea];
+
+        fn value_mut<'a>(&self, settings: &'a mut Settings) -> &'a mut f32 {
+            match self {
+                Stepper::Rumble => &mut settings.controls.rumble_intensity,
+                Stepper::UiScale => &mut settings.graphics.ui_scale,
+                Stepper::SafeArea => &mut settings.graphics.safe_area,
+            }
+        }
+
+        /// The step between settings, and the lowest and highest value allowed.
+        fn range(&self) -> (f32, f32, f32) {
+            match self {
+                Stepper::Rumble => (0.1, 0.0, 1.0),
+                Stepper::UiScale => (0.05, 0.75, 1.5),
+                Stepper::SafeArea => (0.01, 0.0, 0.1),
+            }
+        }
+
+        fn step(&self, settings: &mut Settings, direction: f32) {
+            let (step, min, max) = self.range();
+            let value = self.value_mut(settings);
+            // Rounded so repeated steps land on whole steps rather than drifting.
+            *value = ((*value / step + direction).round() * step).clamp(min, max);
+        }
+
+        fn text(&self, settings: &mut Settings) -> String {
+            let value = *self.value_mut(settings);
+            match self {
+                Stepper::Rumble if value <= 0.0 => "Rumble: Off".to_string(),
+                Stepper::Rumble => format!("Rumble: {:.0}%", value * 100.0),
+                Stepper::UiScale => format!("UI Scale: {:.0}%", value * 100.0),
+                Stepper::SafeArea => format!("Safe Area: {:.0}%", value * 100.0),
+            }
+        }
+    }
+
+    /// The - (-1.0) or + (1.0) button of a stepper.
+    #[derive(Component)]
+    struct StepButton(Stepper, f32);
+
+    #[derive(Component)]
+    struct StepperText(Stepper);
+
+    /// A HUD root kept inside the safe area set in the options.
+    #[derive(Component)]
+    pub struct SafeArea;
+
+    fn difficulty_text(settings: &Settings) -> String {
+        format!("Difficulty: {}", settings.gameplay.difficulty.label())
+    }
+
+    fn particle_density_text(settings: &Settings) -> String {
+        format!("Ambient Particles: {}", settings.graphics.ambient_particles.label())
+    }
+
+    fn frame_cap_text(settings: &Settings) -> String {
+        format!("Frame Rate Cap: {}", settings.graphics.frame_cap.label())
+    }
+
+    fn setup_settings_screen(mut commands: Commands, mut settings: ResMut<Settings>) {
+        // Read through `bypass_change_detection` so opening the screen doesn't
+        // look like a settings change to the systems that apply them.
+        let settings = settings.bypass_change_detection();
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                ..default()
+            },
+            SettingsScreen,
+            DespawnOnExit(GameState::Settings),
+            ui_anim::Tween::fade_in(),
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Options",
+                TextStyle { font_size: 50.0, ..default() },
+            ));
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                },
+                DifficultyButton,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    difficulty_text(settings),
+                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                ));
+            });
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                },
+                ParticleDensityButton,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    particle_density_text(settings),
+                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                ));
+            });
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                    ..default()
+                },
+                FrameCapButton,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    frame_cap_text(settings),
+                    TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                ));
+            });
+            for stepper in Stepper::ALL {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(300.0),
+                        height: Val::Px(50.0),
+                        margin: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::SpaceBetween,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    ..default()
+                }).with_children(|parent| {
+                    spawn_step_button(parent, StepButton(stepper, -1.0), "-");
+                    parent.spawn((
+                        TextBundle::from_section(
+                            stepper.text(settings),
+                            TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                        ),
+                        StepperText(stepper),
+                    ));
+                    spawn_step_button(parent, StepButton(stepper, 1.0), "+");
+                });
+            }
+            // Toggles sit two to a row so the whole screen fits at 720p.
+            parent.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(640.0),
+                    flex_wrap: FlexWrap::Wrap,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            }).with_children(|parent| {
+                for toggle in SettingToggle::ALL {
+                    parent.spawn((
+                        ButtonBundle {
+                            style: Style {
+                                width: Val::Px(300.0),
+                                height: Val::Px(50.0),
+                                margin: UiRect::all(Val::Px(8.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                            ..default()
+                        },
+                        toggle,
+                    )).with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            toggle.button_text(settings),
+                            TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
+                        ));
+                    });
+                }
+            });
+            parent.spawn(TextBundle::from_section(
+                "Press Escape to return",
+                TextStyle { font_size: 20.0, color: Color::GRAY, ..default() },
+            ));
+        });
+    }
+
+    fn spawn_step_button(parent: &mut ChildBuilder, button: StepButton, label: &str) {
+        parent.spawn((
+            ButtonBundle {
+                style: Style {
+                    width: Val::Px(50.0),
+                    height: Val::Px(50.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: Color::rgb(0.15, 0.15, 0.15).into(),
+                ..default()
+            },
+            button,
+        )).with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                label,
+                TextStyle { font_size: 24.0, color: Color::WHITE, ..default() },
+            ));
+        });
+    }
+
+    fn settings_input(
+        mut next_state: ResMut<NextState<GameState>>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+    ) {
+        if keyboard_input.any_just_pressed([KeyCode::Escape, KeyCode::Backspace]) {
+            next_state.set(GameState::MainMenu);
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_setting_buttons(
+        interaction_query: Query<(&Interaction, &SettingToggle, &Children), (Changed<Interaction>, With<Button>)>,
+        mut text_query: Query<&mut Text>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for (interaction, toggle, children) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            let value = toggle.value_mut(&mut settings);
+            *value = !*value;
+            settings.save();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = toggle.button_text(&mut settings);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_difficulty_button(
+        interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<DifficultyButton>)>,
+        mut text_query: Query<&mut Text>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for (interaction, children) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            settings.gameplay.difficulty = settings.gameplay.difficulty.next();
+            settings.save();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = difficulty_text(&settings);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_particle_density_button(
+        interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<ParticleDensityButton>)>,
+        mut text_query: Query<&mut Text>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for (interaction, children) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            settings.graphics.ambient_particles = settings.graphics.ambient_particles.next();
+            settings.save();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = particle_density_text(&settings);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_frame_cap_button(
+        interaction_query: Query<(&Interaction, &Children), (Changed<Interaction>, With<FrameCapButton>)>,
+        mut text_query: Query<&mut Text>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for (interaction, children) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            settings.graphics.frame_cap = settings.graphics.frame_cap.next();
+            settings.save();
+
+            for &child in children.iter() {
+                if let Ok(mut text) = text_query.get_mut(child) {
+                    text.sections[0].value = frame_cap_text(&settings);
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn handle_stepper_buttons(
+        interaction_query: Query<(&Interaction, &StepButton), (Changed<Interaction>, With<Button>)>,
+        mut text_query: Query<(&mut Text, &StepperText)>,
+        mut settings: ResMut<Settings>,
+    ) {
+        for (interaction, StepButton(stepper, direction)) in interaction_query.iter() {
+            if *interaction != Interaction::Pressed {
+                continue;
+            }
+            stepper.step(&mut settings, *direction);
+            settings.save();
+
+            for (mut text, StepperText(shown)) in text_query.iter_mut() {
+                if shown == stepper {
+                    text.sections[0].value = stepper.text(&mut settings);
+                }
+            }
+        }
+    }
+
+    fn apply_ui_scale(settings: Res<Settings>, mut ui_scale: ResMut<UiScale>) {
+        if settings.is_changed() {
+            ui_scale.0 = settings.graphics.ui_scale;
+        }
+    }
+
+    /// Insets every `SafeArea` root from the screen edges, so the HUD inside it stays
+    /// clear of whatever the display cuts off. Uses a transparent border rather than
+    /// padding, because absolutely positioned children are placed inside the border
+    /// but ignore padding.
+    fn apply_safe_area(settings: Res<Settings>, mut query: Query<(Ref<SafeArea>, &mut Style)>) {
+        let margin = settings.graphics.safe_area * 100.0;
+        for (safe_area, mut style) in query.iter_mut() {
+            if settings.is_changed() || safe_area.is_added() {
+                style.border = UiRect::axes(Val::Vw(margin), Val::Vh(margin));
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_steppers_land_on_whole_steps_within_range() {
+            let mut settings = Settings::default();
+            for _ in 0..3 {
+                Stepper::UiScale.step(&mut settings, 1.0);
+            }
+            assert_eq!(Stepper::UiScale.text(&mut settings), "UI Scale: 115%");
+            for _ in 0..20 {
+                Stepper::UiScale.step(&mut settings, 1.0);
+            }
+            assert_eq!(settings.graphics.ui_scale, 1.5);
+            for _ in 0..20 {
+                Stepper::UiScale.step(&mut settings, -1.0);
+            }
+            assert_eq!(settings.graphics.ui_scale, 0.75);
+
+            settings.controls.rumble_intensity = 0.1;
+            Stepper::Rumble.step(&mut settings, -1.0);
+            assert_eq!(Stepper::Rumble.text(&mut settings), "Rumble: Off");
+        }
+    }
+}
+
+#[cfg(feature = "dev")]
+mod console {
+    use super::*;
+    use bevy::window::ReceivedCharacter;
+
+    /// Lines of earlier output kept above the prompt.
+    const CONSOLE_HISTORY: usize = 6;
+    /// Most dummies one command will put down.
+    const MAX_DUMMIES_PER_COMMAND: u32 = 32;
+
+    /// A developer console, opened with the backquote key, for balance work. Game time is
+    /// paused while it's open so typing doesn't steer the player.
+    pub struct ConsolePlugin;
+
+    impl Plugin for ConsolePlugin {
+        fn build(&self, app: &mut App) {
+            app.init_resource::<Console>()
+                .add_systems(Startup, spawn_console)
+                .add_systems(Update, (toggle_console, type_into_console, show_console).chain());
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ConsoleCommand {
+        Help,
+        /// Puts down this many target dummies around the player.
+        Dummy(u32),
+        ClearDummies,
+    }
+
+    impl ConsoleCommand {
+        pub fn parse(line: &str) -> Result<Self, String> {
+            let mut words = line.split_whitespace();
+            let command = match (words.next(), words.next()) {
+                (Some("help"), None) => ConsoleCommand::Help,
+                (Some("dummy"), None) => ConsoleCommand::Dummy(1),
+                (Some("dummy"), Some("clear")) => ConsoleCommand::ClearDummies,
+                (Some("dummy"), Some(count)) => match count.parse::<u32>() {
+                    Ok(count @ 1..=MAX_DUMMIES_PER_COMMAND) => ConsoleCommand::Dummy(count),
+                    _ => return Err(format!("dummy takes a count from 1 to {}, or clear", MAX_DUMMIES_PER_COMMAND)),
+                },
+                (Some(other), _) => return Err(format!("Unknown command '{}', try help", other)),
+                (None, _) => return Err(String::new()),
+            };
+            if words.next().is_some() {
+                return Err(format!("Too many arguments: {}", line.trim()));
+            }
+            Ok(command)
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct Console {
+        open: bool,
+        input: String,
+        output: Vec<String>,
+    }
+
+    impl Console {
+        fn print(&mut self, line: impl Into<String>) {
+            self.output.push(line.into());
+            let excess = self.output.len().saturating_sub(CONSOLE_HISTORY);
+            self.output.drain(..excess);
+        }
+    }
+
+    #[derive(Component)]
+    struct ConsoleRoot;
+
+    #[derive(Component)]
+    struct ConsoleText;
+
+    fn spawn_console(mut commands: Commands) {
+        commands.spawn((
+            NodeBundle {
+                style: Style {
+                    display: Display::None,
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    bottom: Val::Px(0.0),
+                    padding: UiRect::all(Val::Px(8.0)),
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.8).into(),
+                z_index: ZIndex::Global(400),
+                ..default()
+            },
+            ConsoleRoot,
+        )).with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", TextStyle { font_size: 18.0, color: Color::rgb(0.6, 1.0, 0.6), ..default() }),
+                ConsoleText,
+            ));
+        });
+    }
+
+    fn toggle_console(
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut console: ResMut<Console>,
+        mut virtual_time: ResMut<Time<Virtual>>,
+    ) {
+        if !keyboard_input.just_pressed(KeyCode::Backquote) {
+            return;
+        }
+        console.open = !console.open;
+        if console.open {
+            virtual_time.pause();
+        } else {
+            virtual_time.unpause();
+        }
+    }
+
+    fn type_into_console(
+        mut characters: EventReader<ReceivedCharacter>,
+        keyboard_input: Res<ButtonInput<KeyCode>>,
+        mut console: ResMut<Console>,
+        mut dummy_requests: EventWriter<dummy::DummyRequest>,
+    ) {
+        if !console.open {
+            characters.clear();
+            return;
+        }
+        for event in characters.read() {
+            for char in event.char.chars().filter(|char| !char.is_control() && *char != '`') {
+                console.input.push(char);
+            }
+        }
+        if keyboard_input.just_pressed(KeyCode::Backspace) {
+            console.input.pop();
+        }
+        if !keyboard_input.just_pressed(KeyCode::Enter) {
+            return;
+        }
+        let line = std::mem::take(&mut console.input);
+        match ConsoleCommand::parse(&line) {
+            Ok(ConsoleCommand::Help) => console.print("dummy [count] | dummy clear | help"),
+            Ok(ConsoleCommand::Dummy(count)) => {
+                for _ in 0..count {
+                    dummy_requests.send(dummy::DummyRequest::Spawn);
+                }
+                console.print(format!("Spawned {} target dumm{}", count, if count == 1 { "y" } else { "ies" }));
+            }
+            Ok(ConsoleCommand::ClearDummies) => {
+                dummy_requests.send(dummy::DummyRequest::Clear);
+                console.print("Cleared target dummies");
+            }
+            Err(message) if message.is_empty() => {}
+            Err(message) => console.print(message),
+        }
+    }
+
+    fn show_console(
+        console: Res<Console>,
+        mut root_query: Query<&mut Style, With<ConsoleRoot>>,
+        mut text_query: Query<&mut Text, With<ConsoleText>>,
+    ) {
+        if !console.is_changed() {
+            return;
+        }
+        for mut style in root_query.iter_mut() {
+            style.display = if console.open { Display::Flex } else { Display::None };
+        }
+        for mut text in text_query.iter_mut() {
+            let mut lines = console.output.clone();
+            lines.push(format!("> {}_", console.input));
+            text.sections[0].value = lines.join("\n");
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_console_parses_dummy_commands() {
+            assert_eq!(ConsoleCommand::parse("dummy"), Ok(ConsoleCommand::Dummy(1)));
+            assert_eq!(ConsoleCommand::parse("  dummy 4 "), Ok(ConsoleCommand::Dummy(4)));
+            assert_eq!(ConsoleCommand::parse("dummy clear"), Ok(ConsoleCommand::ClearDummies));
+            assert_eq!(ConsoleCommand::parse("help"), Ok(ConsoleCommand::Help));
+            assert!(ConsoleCommand::parse("dummy 0").is_err());
+            assert!(ConsoleCommand::parse("dummy 4 5").is_err());
+            assert!(ConsoleCommand::parse("spawn boss").is_err());
+        }
+    }
+}
+
+#[cfg(feature = "twitch")]
+mod twitch {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Environment variable holding the Twitch channel whose chat gets to vote.
+    const CHANNEL_VAR: &str = "SWARM_HEAVEN_TWITCH_CHANNEL";
+    const IRC_ADDRESS: &str = "irc.chat.twitch.tv:6667";
+    /// Twitch lets anyone read chat under a `justinfan` nick, with no token.
+    const ANONYMOUS_NICK: &str = "justinfan31337";
+    const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    /// Real seconds chat has to vote once the level-up menu opens.
+    const VOTE_DURATION: f32 = 20.0;
+
+    /// Lets Twitch chat vote on level-up cards. Reading chat blocks, so it happens on
+    /// its own thread; `forward_chat` hands the messages to Bevy as `ChatMessage` events.
+    pub struct TwitchPlugin;
+
+    impl Plugin for TwitchPlugin {
+        fn build(&self, app: &mut App) {
+            let Ok(channel) = std::env::var(CHANNEL_VAR) else {
+                info!("{} not set, Twitch chat voting disabled", CHANNEL_VAR);
+                return;
+            };
+            let (sender, receiver) = mpsc::channel();
+            let channel = channel.trim_start_matches('#').to_lowercase();
+            std::thread::spawn(move || read_chat(&channel, sender));
+            app.insert_resource(ChatInbox(Mutex::new(receiver)))
+                .add_event::<ChatMessage>()
+                .add_systems(First, forward_chat)
+                .add_systems(OnEnter(GameState::Paused), start_vote)
+                .add_systems(Update, (count_votes, update_vote_overlay).chain().run_if(in_state(GameState::Paused)))
+                .add_systems(OnExit(GameState::Paused), end_vote);
+        }
+    }
+
+    #[derive(Event, Debug)]
+    struct ChatMessage {
+        user: String,
+        text: String,
+    }
+
+    #[derive(Resource)]
+    struct ChatInbox(Mutex<Receiver<ChatMessage>>);
+
+    /// Keeps a connection to the channel's chat open until the game exits.
+    fn read_chat(channel: &str, sender: Sender<ChatMessage>) {
+        loop {
+            match stream_chat(channel, &sender) {
+                // The game is gone and nobody is listening.
+                Ok(()) => return,
+                Err(err) => warn!("Twitch chat connection lost: {}", err),
+            }
+            std::thread::sleep(RECONNECT_DELAY);
         }
     }
 
-    fn collect_xp_gems(
-        mut commands: Commands,
-        player_query: Query<&Transform, With<player::Player>>,
-        gem_query: Query<(Entity, &Transform), With<XpGem>>,
-        mut player_stats: ResMut<PlayerStats>,
-    ) {
-        if let Ok(player_transform) = player_query.get_single() {
-            for (gem_entity, gem_transform) in gem_query.iter() {
-                if player_transform
-                    .translation
-                    .distance(gem_transform.translation)
-                    < (PLAYER_SIZE / 2.0 + 50.0) // Increased collection radius
-                {
-                    commands.entity(gem_entity).despawn();
-                    player_stats.xp += 10;
+    fn stream_chat(channel: &str, sender: &Sender<ChatMessage>) -> std::io::Result<()> {
+        let mut stream = TcpStream::connect(IRC_ADDRESS)?;
+        write!(stream, "NICK {}\r\nJOIN #{}\r\n", ANONYMOUS_NICK, channel)?;
+        info!("Reading Twitch chat for #{}", channel);
+        let reader = BufReader::new(stream.try_clone()?);
+        for line in reader.lines() {
+            let line = line?;
+            if let Some(server) = line.strip_prefix("PING ") {
+                write!(stream, "PONG {}\r\n", server)?;
+            } else if let Some(message) = parse_privmsg(&line) {
+                if sender.send(message).is_err() {
+                    return Ok(());
                 }
             }
         }
+        Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "chat closed the connection"))
     }
 
-    fn check_level_up(
-        mut player_stats: ResMut<PlayerStats>,
-        mut game_state: ResMut<NextState<GameState>>,
-    ) {
-        if player_stats.xp >= player_stats.xp_to_next_level {
-            player_stats.level += 1;
-            player_stats.xp -= player_stats.xp_to_next_level;
-            player_stats.xp_to_next_level = (player_stats.xp_to_next_level as f32 * 1.5) as u32;
-            game_state.set(GameState::Paused);
-        }
+    /// Pulls the sender and text out of a chat line such as
+    /// `:name!name@name.tmi.twitch.tv PRIVMSG #channel :hello`.
+    fn parse_privmsg(line: &str) -> Option<ChatMessage> {
+        let (prefix, rest) = line.strip_prefix(':')?.split_once(' ')?;
+        let (_, text) = rest.strip_prefix("PRIVMSG ")?.split_once(" :")?;
+        let user = prefix.split('!').next()?;
+        Some(ChatMessage { user: user.to_string(), text: text.to_string() })
     }
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
-
-        #[test]
-        fn test_level_up_logic() {
-            let mut app = App::new();
-            app.add_plugins(MinimalPlugins)
-               .init_state::<GameState>()
-               .insert_resource(PlayerStats {
-                   xp: 100,
-                   level: 1,
-                   xp_to_next_level: 100,
-               })
-               .add_systems(Update, check_level_up);
-
-            app.update();
+    fn forward_chat(inbox: Res<ChatInbox>, mut chat_events: EventWriter<ChatMessage>) {
+        let Ok(receiver) = inbox.0.lock() else {
+            return;
+        };
+        chat_events.send_batch(receiver.try_iter());
+    }
 
-            let stats = app.world.resource::<PlayerStats>();
-            assert_eq!(stats.level, 2);
-            assert_eq!(stats.xp, 0);
-            assert_eq!(stats.xp_to_next_level, 150); // 100 * 1.5
+    /// Which card a message votes for, from 0: "1", "!2" or "!vote 3".
+    fn parse_vote(text: &str, cards: usize) -> Option<usize> {
+        let text = text.trim().trim_start_matches('!');
+        let text = text.strip_prefix("vote").unwrap_or(text).trim();
+        let card = text.parse::<usize>().ok()?.checked_sub(1)?;
+        (card < cards).then_some(card)
+    }
 
-            let _state = app.world.resource::<State<GameState>>();
-            // State transitions are applied at the start of the next frame.
-            // But next_state is in NextState resource.
-            let next_state = app.world.resource::<NextState<GameState>>();
+    /// The open vote. Each viewer has one ballot; voting again changes it.
+    #[derive(Resource)]
+    struct Vote {
+        timer: Timer,
+        ballots: HashMap<String, usize>,
+    }
 
-            // To verify state transition, we need to apply state transitions.
-            // But we can just check if NextState was set.
-            if let Some(s) = next_state.0 {
-                assert_eq!(s, GameState::Paused);
+    impl Vote {
+        fn counts(&self, cards: usize) -> Vec<u32> {
+            let mut counts = vec![0; cards];
+            for &card in self.ballots.values() {
+                counts[card] += 1;
             }
+            counts
         }
-    }
-}
-
-mod ui {
-    use super::*;
-    use bevy::diagnostic::DiagnosticsStore;
-    use rand::seq::SliceRandom;
-
-    pub struct UiPlugin;
 
-    impl Plugin for UiPlugin {
-        fn build(&self, app: &mut App) {
-            app.add_systems(OnEnter(GameState::Running), setup_game_ui)
-                .add_systems(
-                    Update,
-                    (update_game_ui, handle_upgrade_buttons)
-                )
-                .add_systems(OnEnter(GameState::Paused), show_level_up_menu)
-                .add_systems(OnExit(GameState::Paused), hide_level_up_menu)
-                .add_systems(OnExit(GameState::Running), hide_level_up_menu);
+        /// The card with the most votes, the leftmost on a tie; none if nobody voted.
+        fn winner(&self, cards: usize) -> Option<usize> {
+            let counts = self.counts(cards);
+            let most = *counts.iter().max()?;
+            (most > 0).then(|| counts.iter().position(|&count| count == most)).flatten()
         }
     }
 
     #[derive(Component)]
-    struct FpsText;
-    #[derive(Component)]
-    struct EnemyCountText;
-    #[derive(Component)]
-    struct TimerText;
-    #[derive(Component)]
-    struct LevelUpMenu;
+    struct VoteText;
+
     #[derive(Component)]
-    struct GameUi;
+    struct VoteTimerBar;
 
-    fn setup_game_ui(mut commands: Commands, query: Query<&GameUi>) {
-        if !query.is_empty() {
-            return;
-        }
-        commands.spawn((
-            NodeBundle {
-                style: Style {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
-                    justify_content: JustifyContent::SpaceBetween,
-                    ..default()
-                },
-                ..default()
-            },
-            GameUi,
-        )).with_children(|parent| {
-            parent.spawn(NodeBundle {
-                style: Style {
-                    flex_direction: FlexDirection::Column,
-                    margin: UiRect::all(Val::Px(10.0)),
-                    ..default()
-                },
-                ..default()
-            }).with_children(|parent| {
-                parent.spawn((TextBundle::from_section(
-                    "FPS: ",
-                    TextStyle { font_size: 20.0, ..default() },
-                ), FpsText));
-                parent.spawn((TextBundle::from_section(
-                    "Enemies: ",
-                    TextStyle { font_size: 20.0, ..default() },
-                ), EnemyCountText));
-            });
-            parent.spawn((
-                TextBundle::from_section(
-                    "Time: 0.0",
-                    TextStyle { font_size: 30.0, ..default() },
-                ).with_style(Style {
-                    margin: UiRect::all(Val::Px(10.0)),
-                    ..default()
-                }),
-                TimerText,
-            ));
+    fn start_vote(mut commands: Commands) {
+        commands.insert_resource(Vote {
+            timer: Timer::from_seconds(VOTE_DURATION, TimerMode::Once),
+            ballots: HashMap::new(),
         });
-
         commands.spawn((
             NodeBundle {
                 style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(20.0),
                     width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
-                    justify_content: JustifyContent::Center,
-                    display: Display::None,
                     ..default()
                 },
-                background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
-                z_index: ZIndex::Global(100),
+                z_index: ZIndex::Global(101),
                 ..default()
             },
-            LevelUpMenu,
+            DespawnOnExit(GameState::Paused),
         )).with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section("", TextStyle { font_size: 20.0, color: Color::rgb(0.75, 0.6, 1.0), ..default() }),
+                VoteText,
+            ));
             parent.spawn(NodeBundle {
                 style: Style {
-                    flex_direction: FlexDirection::Column,
-                    align_items: AlignItems::Center,
+                    width: Val::Px(300.0),
+                    height: Val::Px(6.0),
+                    margin: UiRect::top(Val::Px(6.0)),
                     ..default()
                 },
+                background_color: Color::rgba(1.0, 1.0, 1.0, 0.2).into(),
                 ..default()
             }).with_children(|parent| {
-                parent.spawn(TextBundle::from_section(
-                    "Level Up!",
-                    TextStyle { font_size: 50.0, color: Color::WHITE, ..default() },
+                parent.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: Color::rgb(0.57, 0.27, 1.0).into(),
+                        ..default()
+                    },
+                    VoteTimerBar,
                 ));
             });
         });
     }
 
-    fn update_game_ui(
-        diagnostics: Res<DiagnosticsStore>,
-        mut fps_query: Query<&mut Text, With<FpsText>>,
-        mut enemy_query: Query<&mut Text, (With<EnemyCountText>, Without<FpsText>)>,
-        enemy_count_query: Query<(), With<enemy::Enemy>>,
-        time: Res<Time>,
-        mut timer_query: Query<&mut Text, (With<TimerText>, Without<FpsText>, Without<EnemyCountText>)>,
+    /// Records ballots and, once time is up, takes the winning card for the streamer.
+    /// The streamer can still click a card themselves before then.
+    fn count_votes(
+        vote: Option<ResMut<Vote>>,
+        mut chat_events: EventReader<ChatMessage>,
+        real_time: Res<Time<Real>>,
+        offer: Res<ui::LevelUpOffer>,
+        mut chosen_events: EventWriter<ui::UpgradeChosen>,
+        mut game_state: ResMut<NextState<GameState>>,
     ) {
-        if let Some(fps) = diagnostics.get(&FrameTimeDiagnosticsPlugin::FPS) {
-            if let Some(value) = fps.smoothed() {
-                for mut text in fps_query.iter_mut() {
-                    text.sections[0].value = format!("FPS: {:.0}", value);
-                }
+        let Some(mut vote) = vote else {
+            return;
+        };
+        if vote.timer.finished() {
+            return;
+        }
+        for message in chat_events.read() {
+            if let Some(card) = parse_vote(&message.text, offer.0.len()) {
+                vote.ballots.insert(message.user.clone(), card);
             }
         }
-
-        for mut text in enemy_query.iter_mut() {
-            text.sections[0].value = format!("Enemies: {}", enemy_count_query.iter().count());
+        if !vote.timer.tick(real_time.delta()).just_finished() {
+            return;
         }
-
-        for mut text in timer_query.iter_mut() {
-            text.sections[0].value = format!("Time: {:.1}", time.elapsed_seconds());
+        if let Some(card) = vote.winner(offer.0.len()) {
+            chosen_events.send(ui::UpgradeChosen(offer.0[card]));
+            game_state.set(GameState::Running);
         }
     }
 
-    #[derive(Component, Clone, Copy, Debug)]
-    enum Upgrade {
-        Multishot,
-        ChainLightning,
-        BladeCount,
-        AttackSpeed,
-        Shotgun,
-        HomingMissile,
-    }
-
-    fn show_level_up_menu(
-        mut commands: Commands,
-        mut menu_query: Query<(Entity, &mut Style), With<LevelUpMenu>>,
+    fn update_vote_overlay(
+        vote: Option<Res<Vote>>,
+        offer: Res<ui::LevelUpOffer>,
+        mut text_query: Query<&mut Text, With<VoteText>>,
+        mut bar_query: Query<&mut Style, With<VoteTimerBar>>,
     ) {
-        if let Ok((menu_entity, mut style)) = menu_query.get_single_mut() {
-            style.display = Display::Flex;
-
-            let all_upgrades = vec![
-                (Upgrade::Multishot, "More Projectiles"),
-                (Upgrade::ChainLightning, "Chain Lightning"),
-                (Upgrade::BladeCount, "More Blades"),
-                (Upgrade::AttackSpeed, "Faster Attacks"),
-                (Upgrade::Shotgun, "Shotgun"),
-                (Upgrade::HomingMissile, "Homing Missiles"),
-            ];
-            
-            let mut rng = rand::thread_rng();
-            let chosen_upgrades = all_upgrades.choose_multiple(&mut rng, 3).cloned().collect::<Vec<_>>();
-
-            commands.entity(menu_entity).with_children(|parent| {
-                for (upgrade, label) in chosen_upgrades {
-                    parent.spawn((
-                        ButtonBundle {
-                            style: Style {
-                                width: Val::Px(250.0),
-                                height: Val::Px(60.0),
-                                margin: UiRect::all(Val::Px(10.0)),
-                                justify_content: JustifyContent::Center,
-                                align_items: AlignItems::Center,
-                                ..default()
-                            },
-                            background_color: Color::rgb(0.15, 0.15, 0.15).into(),
-                            ..default()
-                        },
-                        upgrade,
-                    )).with_children(|parent| {
-                        parent.spawn(TextBundle::from_section(
-                            label,
-                            TextStyle { font_size: 20.0, color: Color::WHITE, ..default() },
-                        ));
-                    });
-                }
-            });
+        let Some(vote) = vote else {
+            return;
+        };
+        let mut lines = vec![if vote.timer.finished() {
+            "Chat didn't vote - your pick".to_string()
+        } else {
+            format!("Chat vote: type 1-{} ({:.0}s left)", offer.0.len(), vote.timer.remaining_secs().ceil())
+        }];
+        for (card, (upgrade, count)) in offer.0.iter().zip(vote.counts(offer.0.len())).enumerate() {
+            lines.push(format!("{}. {}  -  {}", card + 1, upgrade.label(), count));
+        }
+        for mut text in text_query.iter_mut() {
+            text.sections[0].value = lines.join("\n");
+        }
+        for mut style in bar_query.iter_mut() {
+            style.width = Val::Percent(vote.timer.fraction_remaining() * 100.0);
         }
     }
 
-    fn hide_level_up_menu(
-        mut commands: Commands,
-        mut menu_query: Query<&mut Style, With<LevelUpMenu>>,
-        button_query: Query<Entity, With<Button>>,
-    ) {
-        if let Ok(mut style) = menu_query.get_single_mut() {
-            style.display = Display::None;
-            for entity in button_query.iter() {
-                commands.entity(entity).despawn_recursive();
-            }
-        }
+    fn end_vote(mut commands: Commands) {
+        commands.remove_resource::<Vote>();
     }
 
-    fn handle_upgrade_buttons(
-        interaction_query: Query<(&Interaction, &Upgrade), (Changed<Interaction>, With<Button>)>,
-        mut weapon_stats: ResMut<combat::WeaponStats>,
-        mut game_state: ResMut<NextState<GameState>>,
-    ) {
-        for (interaction, upgrade) in interaction_query.iter() {
-            if *interaction == Interaction::Pressed {
-                match upgrade {
-                    Upgrade::Multishot => weapon_stats.multishot += 1,
-                    Upgrade::ChainLightning => weapon_stats.chain_lightning += 1,
-                    Upgrade::BladeCount => weapon_stats.blade_count += 1,
-                    Upgrade::AttackSpeed => weapon_stats.fire_rate *= 0.9,
-                    Upgrade::Shotgun => weapon_stats.shotgun_count += 1,
-                    Upgrade::HomingMissile => weapon_stats.homing_count += 1,
-                }
-                game_state.set(GameState::Running);
-            }
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_chat_lines_become_ballots_and_the_leftmost_tie_wins() {
+            let message = parse_privmsg(":alice!alice@alice.tmi.twitch.tv PRIVMSG #streamer :!vote 2").unwrap();
+            assert_eq!((message.user.as_str(), message.text.as_str()), ("alice", "!vote 2"));
+            assert!(parse_privmsg(":tmi.twitch.tv 001 justinfan31337 :Welcome, GLHF!").is_none());
+
+            assert_eq!(parse_vote(&message.text, 3), Some(1));
+            assert_eq!(parse_vote(" 3 ", 3), Some(2));
+            assert_eq!(parse_vote("!1", 3), Some(0));
+            assert_eq!(parse_vote("4", 3), None);
+            assert_eq!(parse_vote("0", 3), None);
+            assert_eq!(parse_vote("gg", 3), None);
+
+            let mut vote = Vote {
+                timer: Timer::from_seconds(VOTE_DURATION, TimerMode::Once),
+                ballots: HashMap::new(),
+            };
+            assert_eq!(vote.winner(3), None);
+            vote.ballots.insert("alice".into(), 2);
+            vote.ballots.insert("bob".into(), 1);
+            assert_eq!(vote.winner(3), Some(1));
+            vote.ballots.insert("alice".into(), 0);
+            assert_eq!(vote.counts(3), vec![1, 1, 0]);
+            assert_eq!(vote.winner(3), Some(0));
         }
     }
 }
 
-mod waves {
+#[cfg(feature = "discord")]
+mod presence {
     use super::*;
+    use discord_rich_presence::{activity, DiscordIpc, DiscordIpcClient};
 
-    pub struct WavePlugin;
+    /// Environment variable holding the Discord application id to publish under.
+    const APP_ID_VAR: &str = "SWARM_HEAVEN_DISCORD_APP_ID";
 
-    impl Plugin for WavePlugin {
+    pub struct PresencePlugin;
+
+    impl Plugin for PresencePlugin {
         fn build(&self, app: &mut App) {
-            app.insert_resource(MegaWaveTimer(Timer::from_seconds(60.0, TimerMode::Repeating)))
-                .add_systems(Update, mega_wave_spawner.run_if(in_state(GameState::Running)));
+            app.insert_resource(Presence::connect())
+                .add_systems(Update, update_presence);
         }
     }
 
     #[derive(Resource)]
-    struct MegaWaveTimer(Timer);
+    struct Presence {
+        client: Option<DiscordIpcClient>,
+        last_sent: Option<(String, String)>,
+    }
 
-    fn mega_wave_spawner(
-        mut commands: Commands,
-        time: Res<Time>,
-        mut timer: ResMut<MegaWaveTimer>,
-        player_query: Query<&Transform, With<player::Player>>,
-    ) {
-        if timer.0.tick(time.delta()).just_finished() {
-            if let Ok(player_transform) = player_query.get_single() {
-                let mut rng = rand::thread_rng();
-                let direction = match rng.gen_range(0..4) {
-                    0 => Vec3::new(0.0, 1.0, 0.0),  // North
-                    1 => Vec3::new(0.0, -1.0, 0.0), // South
-                    2 => Vec3::new(1.0, 0.0, 0.0),  // East
-                    _ => Vec3::new(-1.0, 0.0, 0.0), // West
-                };
+    impl Presence {
+        fn connect() -> Self {
+            let client = match std::env::var(APP_ID_VAR) {
+                Ok(app_id) => {
+                    let mut client = DiscordIpcClient::new(app_id);
+                    match client.connect() {
+                        Ok(()) => Some(client),
+                        Err(err) => {
+                            warn!("Discord Rich Presence unavailable: {}", err);
+                            None
+                        }
+                    }
+                }
+                Err(_) => {
+                    info!("{} not set, Discord Rich Presence disabled", APP_ID_VAR);
+                    None
+                }
+            };
+            Self { client, last_sent: None }
+        }
+    }
 
-                let spawn_center = player_transform.translation + direction * 1200.0;
+    /// Details/state lines for the current activity, e.g. ("Level 4", "Minute 3").
+    fn describe(
+        state: &GameState,
+        current_run: &run::CurrentRun,
+        player_stats: &leveling::PlayerStats,
+    ) -> (String, String) {
+        let minute = current_run.elapsed as u32 / 60 + 1;
+        match state {
+            GameState::Running => (format!("Level {}", player_stats.level), format!("Minute {}", minute)),
+            GameState::Paused => (format!("Level {}", player_stats.level), "Choosing an upgrade".to_string()),
+            GameState::Dying | GameState::GameOver => (
+                format!("Survived {}", run::format_duration(current_run.elapsed)),
+                "Game over".to_string(),
+            ),
+            _ => ("In the menus".to_string(), String::new()),
+        }
+    }
 
-                for _ in 0..100 {
-                    let offset = Vec3::new(
-                        rng.gen_range(-100.0..100.0),
-                        rng.gen_range(-100.0..100.0),
-                        0.0,
-                    );
-                    commands.spawn((
-                        SpriteBundle {
-                            sprite: Sprite {
-                                color: Color::rgb(0.9, 0.2, 0.2),
-                                custom_size: Some(Vec2::new(ENEMY_SIZE, ENEMY_SIZE)),
-                                ..default()
-                            },
-                            transform: Transform::from_translation(spawn_center + offset),
-                            ..default()
-                        },
-                        enemy::Enemy,
-                    ));
-                }
+    // Only pushes to Discord when the text changes, which happens on state
+    // transitions, level-ups and once per in-run minute.
+    fn update_presence(
+        mut presence: ResMut<Presence>,
+        state: Res<State<GameState>>,
+        current_run: Res<run::CurrentRun>,
+        player_stats: Res<leveling::PlayerStats>,
+    ) {
+        let lines = describe(state.get(), &current_run, &player_stats);
+        if presence.last_sent.as_ref() == Some(&lines) {
+            return;
+        }
+
+        let Presence { client, last_sent } = &mut *presence;
+        if let Some(ipc) = client.as_mut() {
+            let mut activity = activity::Activity::new().details(lines.0.as_str());
+            if !lines.1.is_empty() {
+                activity = activity.state(lines.1.as_str());
+            }
+            if let Err(err) = ipc.set_activity(activity) {
+                warn!("Failed to update Discord Rich Presence: {}", err);
+                *client = None;
             }
         }
+        *last_sent = Some(lines);
     }
 }
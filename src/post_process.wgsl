@@ -0,0 +1,33 @@
+#import bevy_core_pipeline::fullscreen_vertex_shader::FullscreenVertexOutput
+
+@group(0) @binding(0) var screen_texture: texture_2d<f32>;
+@group(0) @binding(1) var texture_sampler: sampler;
+
+struct PostProcessSettings {
+    vignette: f32,
+    chromatic_aberration: f32,
+    desaturation: f32,
+}
+@group(0) @binding(2) var<uniform> settings: PostProcessSettings;
+
+const VIGNETTE_COLOR: vec3<f32> = vec3<f32>(0.6, 0.0, 0.0);
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
+    let from_center = in.uv - vec2<f32>(0.5, 0.5);
+
+    // Chromatic aberration: split red and blue outwards from the screen center.
+    let offset = from_center * settings.chromatic_aberration * 0.02;
+    let base = textureSample(screen_texture, texture_sampler, in.uv);
+    let red = textureSample(screen_texture, texture_sampler, in.uv + offset).r;
+    let blue = textureSample(screen_texture, texture_sampler, in.uv - offset).b;
+    var color = vec3<f32>(red, base.g, blue);
+
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    color = mix(color, vec3<f32>(luma), settings.desaturation);
+
+    let edge = smoothstep(0.35, 0.75, length(from_center) * 1.414);
+    color = mix(color, VIGNETTE_COLOR, edge * settings.vignette);
+
+    return vec4<f32>(color, base.a);
+}